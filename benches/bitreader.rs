@@ -0,0 +1,43 @@
+//! Measures the speed of `BitCursor` reads of common widths.
+//!
+//! Run with `cargo bench --bench bitreader`.
+
+extern crate alac;
+
+use alac::bitreader::BitCursor;
+use std::hint::black_box;
+use std::time::Instant;
+
+const BUF_LEN: usize = 1 << 20;
+const ITERATIONS: u32 = 20;
+
+fn bench<F: Fn(&mut BitCursor) -> u32>(name: &str, bits: usize, read: F) {
+    let buf: Vec<u8> = (0..BUF_LEN as u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect();
+
+    let mut reads = 0;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut cursor = BitCursor::new(black_box(&buf)).unwrap();
+        while cursor.remaining_bits() >= bits {
+            black_box(read(&mut cursor));
+            reads += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{}: {:.2} ns per read",
+        name,
+        elapsed.as_nanos() as f64 / reads as f64
+    );
+}
+
+fn main() {
+    bench("read_u8(5)", 5, |c| c.read_u8(5).unwrap() as u32);
+    bench("read_u16(12)", 12, |c| c.read_u16(12).unwrap() as u32);
+    bench("read_u16(16)", 16, |c| c.read_u16(16).unwrap() as u32);
+    bench("read_u32(23)", 23, |c| c.read_u32(23).unwrap());
+    bench("read_u32(32)", 32, |c| c.read_u32(32).unwrap());
+}
@@ -0,0 +1,81 @@
+//! Compares decoding a typical packet with decoding a quiet one made up of zero blocks.
+//!
+//! Run with `cargo bench --bench decode`.
+
+extern crate alac;
+#[macro_use]
+extern crate criterion;
+
+use alac::{Decoder, StreamInfo};
+use criterion::{black_box, Criterion};
+
+const COOKIE: &[u8] = include_bytes!("../tests/data/magic_cookie.bin");
+const PACKET: &[u8] = include_bytes!("../tests/data/packet_16_bit.bin");
+
+/// Packs `(value, bits)` fields into bytes, most significant bit first.
+fn pack(fields: &[(u32, usize)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    let mut len = 0;
+    for &(value, bits) in fields {
+        for i in (0..bits).rev() {
+            if len % 8 == 0 {
+                buf.push(0);
+            }
+            buf[len / 8] |= (((value >> i) & 1) as u8) << (7 - len % 8);
+            len += 1;
+        }
+    }
+    buf
+}
+
+/// Returns a packet of 4096 silent stereo frames for the stream described by `COOKIE`.
+///
+/// Each channel is coded as a zero followed by a zero block covering the rest of the packet.
+fn quiet_packet() -> Vec<u8> {
+    let mut fields = vec![
+        // A channel pair element.
+        (1, 3),
+        (0, 4),
+        (0, 12),
+        // Not a partial frame, with no sample shift and not uncompressed.
+        (0, 1),
+        (0, 2),
+        (0, 1),
+        // No stereo mixing.
+        (0, 8),
+        (0, 8),
+    ];
+    for _ in 0..2 {
+        // Order 4 prediction with zero coefficients.
+        fields.extend_from_slice(&[(0, 4), (9, 4), (4, 3), (4, 5)]);
+        fields.extend_from_slice(&[(0, 16); 4]);
+    }
+    for _ in 0..2 {
+        // The zero block length is escaped as it has a quotient greater than 8.
+        fields.extend_from_slice(&[(0, 1), (0x1ff, 9), (4095, 16)]);
+    }
+    fields.push((7, 3));
+    pack(&fields)
+}
+
+fn decode(c: &mut Criterion) {
+    let stream_info = StreamInfo::from_cookie(COOKIE).unwrap();
+    let mut decoder = Decoder::new(stream_info.clone());
+    let mut out = vec![0i32; stream_info.max_samples_per_packet() as usize];
+
+    let quiet = quiet_packet();
+    let samples = decoder.decode_packet(&quiet, &mut out).unwrap();
+    assert_eq!(samples.len(), 4096 * 2);
+    assert!(samples.iter().all(|&sample| sample == 0));
+
+    let mut bench_packet = |name: &str, packet: &[u8]| {
+        c.bench_function(name, |b| {
+            b.iter(|| decoder.decode_packet(black_box(packet), &mut out).is_ok())
+        });
+    };
+    bench_packet("decode_packet", PACKET);
+    bench_packet("decode_packet_quiet", &quiet);
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
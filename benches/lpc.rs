@@ -0,0 +1,45 @@
+//! Measures decoding speed on files from Apple's encoder, which predicts almost every channel with
+//! an LPC order of 4 or 8.
+//!
+//! Each file is decoded repeatedly to simulate a long file. Run with `cargo bench --bench lpc`.
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs;
+use std::io::Cursor;
+use std::time::Instant;
+
+const PATHS: &[&str] = &[
+    "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf",
+    "tests/data/decode_comparison/synth_44100_24_bit_afconvert.caf",
+];
+
+const ITERATIONS: u32 = 200;
+
+fn main() {
+    for path in PATHS {
+        let data = fs::read(path).unwrap();
+
+        let mut frames = 0;
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            let reader = Reader::new(Cursor::new(&data[..])).unwrap();
+            let stream_info = reader.stream_info().clone();
+            let channels = stream_info.channels() as usize;
+
+            let mut packets = reader.into_packets::<i32>();
+            let mut buf = vec![0; stream_info.max_samples_per_packet() as usize];
+            while let Some(samples) = packets.next_into(&mut buf).unwrap() {
+                frames += samples.len() / channels;
+            }
+        }
+        let elapsed = start.elapsed();
+
+        println!(
+            "{}: {:.2} ns per frame",
+            path,
+            elapsed.as_nanos() as f64 / frames as f64
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Builders for minimal CAF and MP4 files wrapping fuzzer provided packet data.
+
+#![allow(dead_code)]
+
+/// The magic cookie used for all synthetic containers: 16-bit stereo, 4096 frames per packet.
+pub const COOKIE: &[u8] = b"\x00\x00\x10\x00\x00\x10\x28\x0a\x0e\x02\x00\xff\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xac\x44";
+
+/// Splits fuzzer input into a packet size table and the packet data that follows it.
+///
+/// The first byte gives the number of packets. Each packet size is stored as a big-endian `u16`.
+pub fn split_input(data: &[u8]) -> Option<(Vec<u32>, &[u8])> {
+    let (&count, rest) = data.split_first()?;
+    let table_len = count as usize * 2;
+    if rest.len() < table_len {
+        return None;
+    }
+    let (table, packets) = rest.split_at(table_len);
+    let sizes = table
+        .chunks(2)
+        .map(|s| ((s[0] as u32) << 8) | s[1] as u32)
+        .collect();
+    Some((sizes, packets))
+}
+
+/// Builds a CAF file with a packet table describing `sizes` followed by `packets`.
+pub fn caf(sizes: &[u32], packets: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(b"caff");
+    out.extend_from_slice(&1u16.to_be_bytes());
+    out.extend_from_slice(&0u16.to_be_bytes());
+
+    let mut desc = Vec::new();
+    desc.extend_from_slice(&44100f64.to_bits().to_be_bytes());
+    desc.extend_from_slice(b"alac");
+    desc.extend_from_slice(&1u32.to_be_bytes()); // format flags
+    desc.extend_from_slice(&0u32.to_be_bytes()); // bytes per packet
+    desc.extend_from_slice(&4096u32.to_be_bytes()); // frames per packet
+    desc.extend_from_slice(&2u32.to_be_bytes()); // channels per frame
+    desc.extend_from_slice(&0u32.to_be_bytes()); // bits per channel
+    caf_chunk(&mut out, b"desc", &desc);
+
+    caf_chunk(&mut out, b"kuki", COOKIE);
+
+    let mut pakt = Vec::new();
+    pakt.extend_from_slice(&(sizes.len() as i64).to_be_bytes());
+    pakt.extend_from_slice(&(sizes.len() as i64 * 4096).to_be_bytes());
+    pakt.extend_from_slice(&0i32.to_be_bytes()); // priming frames
+    pakt.extend_from_slice(&0i32.to_be_bytes()); // remainder frames
+    for &size in sizes {
+        write_varint(&mut pakt, size);
+    }
+    caf_chunk(&mut out, b"pakt", &pakt);
+
+    let mut data = Vec::new();
+    data.extend_from_slice(&0u32.to_be_bytes()); // edit count
+    data.extend_from_slice(packets);
+    caf_chunk(&mut out, b"data", &data);
+
+    out
+}
+
+fn caf_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(data.len() as i64).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    value >>= 7;
+    while value != 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.reverse();
+    out.extend_from_slice(&bytes);
+}
+
+/// Builds an MP4 file with a single ALAC track of `sizes` packets stored in one chunk.
+pub fn mp4(sizes: &[u32], packets: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    let mut ftyp = Vec::new();
+    ftyp.extend_from_slice(b"M4A ");
+    ftyp.extend_from_slice(&0u32.to_be_bytes());
+    ftyp.extend_from_slice(b"M4A mp42isom");
+    mp4_box(&mut out, b"ftyp", &ftyp);
+
+    // The sample table refers to the mdat payload which follows the moov box. Build moov once to
+    // learn its size, then again with the correct chunk offset.
+    let moov_len = moov(sizes, 0).len() as u64;
+    let chunk_offset = out.len() as u64 + moov_len + 8;
+    let moov = moov(sizes, chunk_offset);
+    out.extend_from_slice(&moov);
+
+    mp4_box(&mut out, b"mdat", packets);
+
+    out
+}
+
+fn moov(sizes: &[u32], chunk_offset: u64) -> Vec<u8> {
+    let mut mvhd = full_box_header(0);
+    mvhd.extend_from_slice(&0u32.to_be_bytes()); // creation time
+    mvhd.extend_from_slice(&0u32.to_be_bytes()); // modification time
+    mvhd.extend_from_slice(&44100u32.to_be_bytes()); // timescale
+    mvhd.extend_from_slice(&(sizes.len() as u32 * 4096).to_be_bytes()); // duration
+    mvhd.extend_from_slice(&0x0001_0000u32.to_be_bytes()); // rate
+    mvhd.extend_from_slice(&0x0100u16.to_be_bytes()); // volume
+    mvhd.extend_from_slice(&[0; 10]); // reserved
+    for &m in &[0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        mvhd.extend_from_slice(&m.to_be_bytes()); // matrix
+    }
+    mvhd.extend_from_slice(&[0; 24]); // pre-defined
+    mvhd.extend_from_slice(&2u32.to_be_bytes()); // next track id
+
+    let mut tkhd = full_box_header(7);
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // creation time
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // modification time
+    tkhd.extend_from_slice(&1u32.to_be_bytes()); // track id
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // reserved
+    tkhd.extend_from_slice(&(sizes.len() as u32 * 4096).to_be_bytes()); // duration
+    tkhd.extend_from_slice(&[0; 8]); // reserved
+    tkhd.extend_from_slice(&0u16.to_be_bytes()); // layer
+    tkhd.extend_from_slice(&0u16.to_be_bytes()); // alternate group
+    tkhd.extend_from_slice(&0x0100u16.to_be_bytes()); // volume
+    tkhd.extend_from_slice(&0u16.to_be_bytes()); // reserved
+    for &m in &[0x0001_0000u32, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000] {
+        tkhd.extend_from_slice(&m.to_be_bytes()); // matrix
+    }
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // width
+    tkhd.extend_from_slice(&0u32.to_be_bytes()); // height
+
+    let mut mdhd = full_box_header(0);
+    mdhd.extend_from_slice(&0u32.to_be_bytes()); // creation time
+    mdhd.extend_from_slice(&0u32.to_be_bytes()); // modification time
+    mdhd.extend_from_slice(&44100u32.to_be_bytes()); // timescale
+    mdhd.extend_from_slice(&(sizes.len() as u32 * 4096).to_be_bytes()); // duration
+    mdhd.extend_from_slice(&0x55c4u16.to_be_bytes()); // language
+    mdhd.extend_from_slice(&0u16.to_be_bytes()); // pre-defined
+
+    let mut hdlr = full_box_header(0);
+    hdlr.extend_from_slice(&0u32.to_be_bytes()); // pre-defined
+    hdlr.extend_from_slice(b"soun");
+    hdlr.extend_from_slice(&[0; 12]); // reserved
+    hdlr.extend_from_slice(b"\0"); // name
+
+    let mut alac = full_box_header(0);
+    alac.extend_from_slice(COOKIE);
+
+    let mut entry = Vec::new();
+    entry.extend_from_slice(&[0; 6]); // reserved
+    entry.extend_from_slice(&1u16.to_be_bytes()); // data reference index
+    entry.extend_from_slice(&0u16.to_be_bytes()); // version
+    entry.extend_from_slice(&0u16.to_be_bytes()); // revision
+    entry.extend_from_slice(&0u32.to_be_bytes()); // vendor
+    entry.extend_from_slice(&2u16.to_be_bytes()); // channels
+    entry.extend_from_slice(&16u16.to_be_bytes()); // sample size
+    entry.extend_from_slice(&0u16.to_be_bytes()); // compression id
+    entry.extend_from_slice(&0u16.to_be_bytes()); // packet size
+    entry.extend_from_slice(&(44100u32 << 16).to_be_bytes()); // sample rate
+    mp4_box(&mut entry, b"alac", &alac);
+
+    let mut stsd = full_box_header(0);
+    stsd.extend_from_slice(&1u32.to_be_bytes());
+    mp4_box(&mut stsd, b"alac", &entry);
+
+    let mut stts = full_box_header(0);
+    stts.extend_from_slice(&1u32.to_be_bytes());
+    stts.extend_from_slice(&(sizes.len() as u32).to_be_bytes());
+    stts.extend_from_slice(&4096u32.to_be_bytes());
+
+    let mut stsc = full_box_header(0);
+    stsc.extend_from_slice(&1u32.to_be_bytes());
+    stsc.extend_from_slice(&1u32.to_be_bytes()); // first chunk
+    stsc.extend_from_slice(&(sizes.len() as u32).to_be_bytes()); // samples per chunk
+    stsc.extend_from_slice(&1u32.to_be_bytes()); // sample description index
+
+    let mut stsz = full_box_header(0);
+    stsz.extend_from_slice(&0u32.to_be_bytes()); // sample size
+    stsz.extend_from_slice(&(sizes.len() as u32).to_be_bytes());
+    for &size in sizes {
+        stsz.extend_from_slice(&size.to_be_bytes());
+    }
+
+    let mut stco = full_box_header(0);
+    stco.extend_from_slice(&1u32.to_be_bytes());
+    stco.extend_from_slice(&(chunk_offset as u32).to_be_bytes());
+
+    let mut stbl = Vec::new();
+    mp4_box(&mut stbl, b"stsd", &stsd);
+    mp4_box(&mut stbl, b"stts", &stts);
+    mp4_box(&mut stbl, b"stsc", &stsc);
+    mp4_box(&mut stbl, b"stsz", &stsz);
+    mp4_box(&mut stbl, b"stco", &stco);
+
+    let mut minf = Vec::new();
+    mp4_box(&mut minf, b"stbl", &stbl);
+
+    let mut mdia = Vec::new();
+    mp4_box(&mut mdia, b"mdhd", &mdhd);
+    mp4_box(&mut mdia, b"hdlr", &hdlr);
+    mp4_box(&mut mdia, b"minf", &minf);
+
+    let mut trak = Vec::new();
+    mp4_box(&mut trak, b"tkhd", &tkhd);
+    mp4_box(&mut trak, b"mdia", &mdia);
+
+    let mut moov = Vec::new();
+    mp4_box(&mut moov, b"mvhd", &mvhd);
+    mp4_box(&mut moov, b"trak", &trak);
+
+    let mut out = Vec::new();
+    mp4_box(&mut out, b"moov", &moov);
+    out
+}
+
+fn full_box_header(flags: u32) -> Vec<u8> {
+    // Version 0 in the high byte followed by 24 bits of flags.
+    flags.to_be_bytes().to_vec()
+}
+
+fn mp4_box(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+}
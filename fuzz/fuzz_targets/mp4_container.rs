@@ -0,0 +1,26 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate alac;
+
+mod containers;
+
+use alac::*;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let (sizes, packets) = if let Some(split) = containers::split_input(data) {
+        split
+    } else {
+        return;
+    };
+
+    let file = containers::mp4(&sizes, packets);
+    let reader = Reader::new(Cursor::new(file)).expect("failed to open synthetic mp4 file");
+    for sample in reader.into_samples::<i32>() {
+        // IO errors are not recoverable but the reader continues after decoder errors.
+        if let Err(ReadError::Io(_)) = sample {
+            break;
+        }
+    }
+});
@@ -0,0 +1,30 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate alac;
+
+use alac::*;
+use std::io::Cursor;
+
+// Limit the number of samples decoded from each input so slow inputs don't time out.
+const MAX_SAMPLES: usize = 1024 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    // Whole files are mutated directly to exercise container parsing.
+    let reader = if let Ok(r) = Reader::new(Cursor::new(data)) {
+        r
+    } else {
+        return;
+    };
+
+    if reader.stream_info().max_samples_per_packet() > 1024 * 50 {
+        return;
+    }
+
+    for sample in reader.into_samples::<i32>().take(MAX_SAMPLES) {
+        // IO errors are not recoverable but the reader continues after decoder errors.
+        if let Err(ReadError::Io(_)) = sample {
+            break;
+        }
+    }
+});
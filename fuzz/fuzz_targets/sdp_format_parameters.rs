@@ -0,0 +1,21 @@
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate alac;
+
+use alac::*;
+use std::str;
+
+fuzz_target!(|data: &[u8]| {
+    let params = if let Ok(s) = str::from_utf8(data) {
+        s
+    } else {
+        return;
+    };
+
+    if let Ok(stream_info) = StreamInfo::from_sdp_format_parameters(params) {
+        // Anything accepted by the parser must be safe to query.
+        let _ = stream_info.max_samples_per_packet();
+        let _ = stream_info.max_frames_per_packet();
+    }
+});
@@ -0,0 +1,133 @@
+#ifndef ALAC_H
+#define ALAC_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The function succeeded.
+ */
+#define ALAC_OK 0
+
+/**
+ * A pointer was null or a buffer was too short.
+ */
+#define ALAC_ERROR_INVALID_ARGUMENT -1
+
+/**
+ * The magic cookie or packet is invalid.
+ */
+#define ALAC_ERROR_INVALID_DATA -2
+
+/**
+ * The decoder panicked.
+ */
+#define ALAC_ERROR_PANIC -3
+
+/**
+ * An ALAC packet decoder.
+ */
+typedef struct AlacDecoder AlacDecoder;
+
+/**
+ * Codec parameters of an ALAC stream.
+ */
+typedef struct AlacStreamInfo {
+  uint32_t frame_length;
+  uint8_t bit_depth;
+  uint8_t channels;
+  uint32_t sample_rate;
+  /**
+   * The minimum length of the buffer passed to the `alac_decode_packet` functions.
+   */
+  uint32_t max_samples_per_packet;
+} AlacStreamInfo;
+
+/**
+ * Parses a magic cookie into `out`.
+ *
+ * Returns `ALAC_OK` on success.
+ *
+ * # Safety
+ *
+ * `cookie` must point to `cookie_len` readable bytes and `out` must be valid for writes.
+ */
+int32_t alac_stream_info_from_cookie(const uint8_t *cookie,
+                                     size_t cookie_len,
+                                     struct AlacStreamInfo *out);
+
+/**
+ * Creates a decoder from a magic cookie.
+ *
+ * Returns null if the cookie is invalid or the decoder panicked. The decoder must be freed with
+ * `alac_decoder_free`.
+ *
+ * # Safety
+ *
+ * `cookie` must point to `cookie_len` readable bytes.
+ */
+struct AlacDecoder *alac_decoder_new(const uint8_t *cookie, size_t cookie_len);
+
+/**
+ * Writes the parameters of the stream being decoded to `out`.
+ *
+ * Returns `ALAC_OK` on success.
+ *
+ * # Safety
+ *
+ * `decoder` must have been returned by `alac_decoder_new` and not freed, and `out` must be valid
+ * for writes.
+ */
+int32_t alac_decoder_stream_info(const struct AlacDecoder *decoder, struct AlacStreamInfo *out);
+
+/**
+ * Decodes a packet into `out` as interleaved 32 bit samples, left-aligned so that the most
+ * significant bit of each sample is bit 31.
+ *
+ * `out_len` must be at least `max_samples_per_packet`. Returns the number of samples written, or
+ * a negative error code.
+ *
+ * # Safety
+ *
+ * `decoder` must have been returned by `alac_decoder_new` and not freed, `packet` must point to
+ * `packet_len` readable bytes and `out` must point to `out_len` writable samples.
+ */
+ptrdiff_t alac_decode_packet(struct AlacDecoder *decoder,
+                             const uint8_t *packet,
+                             size_t packet_len,
+                             int32_t *out,
+                             size_t out_len);
+
+/**
+ * Decodes a packet from a stream with a bit depth of 16 into `out` as interleaved 16 bit
+ * samples.
+ *
+ * `out_len` must be at least `max_samples_per_packet`. Returns the number of samples written, or
+ * a negative error code.
+ *
+ * # Safety
+ *
+ * `decoder` must have been returned by `alac_decoder_new` and not freed, `packet` must point to
+ * `packet_len` readable bytes and `out` must point to `out_len` writable samples.
+ */
+ptrdiff_t alac_decode_packet_s16(struct AlacDecoder *decoder,
+                                 const uint8_t *packet,
+                                 size_t packet_len,
+                                 int16_t *out,
+                                 size_t out_len);
+
+/**
+ * Frees a decoder. Passing null does nothing.
+ *
+ * # Safety
+ *
+ * `decoder` must be null or have been returned by `alac_decoder_new` and not already freed.
+ */
+void alac_decoder_free(struct AlacDecoder *decoder);
+
+#endif  /* ALAC_H */
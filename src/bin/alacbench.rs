@@ -0,0 +1,116 @@
+//! Measures decoding throughput on the current machine.
+//!
+//! Each file is read into memory and decoded repeatedly so that only decoding is measured.
+//!
+//! Usage: `alacbench [--iterations N] FILE...`
+
+extern crate alac;
+
+use alac::Reader;
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::process;
+use std::time::{Duration, Instant};
+
+fn main() {
+    let mut iterations = 10;
+    let mut paths = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &arg[..] {
+            "--iterations" | "-n" => {
+                iterations = match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) if n > 0 => n,
+                    _ => usage(),
+                }
+            }
+            "--help" | "-h" => usage(),
+            _ => paths.push(arg),
+        }
+    }
+    if paths.is_empty() {
+        usage();
+    }
+
+    for path in &paths {
+        if let Err(err) = bench(path, iterations) {
+            eprintln!("{}: {}", path, err);
+            process::exit(1);
+        }
+    }
+
+    if let Some(peak) = peak_memory_kb() {
+        println!("peak memory: {} KiB", peak);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: alacbench [--iterations N] FILE...");
+    process::exit(2);
+}
+
+fn bench(path: &str, iterations: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let data = fs::read(path)?;
+
+    let mut latencies = Vec::new();
+    let mut frames = 0;
+    let mut elapsed = Duration::from_secs(0);
+    let mut sample_rate = 0;
+
+    for _ in 0..iterations {
+        let reader = Reader::new(Cursor::new(&data[..]))?;
+        let stream_info = reader.stream_info().clone();
+        sample_rate = stream_info.sample_rate();
+
+        let mut packets = reader.into_packets::<i32>();
+        let mut buf = vec![0; stream_info.max_samples_per_packet() as usize];
+        loop {
+            let start = Instant::now();
+            let result = packets.next_into(&mut buf)?;
+            latencies.push(start.elapsed());
+            if result.is_none() {
+                // The final call only detects the end of the stream.
+                latencies.pop();
+                break;
+            }
+        }
+
+        let throughput = packets.throughput();
+        frames += throughput.frames_out();
+        elapsed += throughput.elapsed();
+    }
+
+    latencies.sort();
+    let audio_secs = frames as f64 / sample_rate as f64;
+
+    println!("{}", path);
+    println!("  packets decoded: {}", latencies.len());
+    println!(
+        "  realtime factor: {:.1}x",
+        audio_secs / elapsed.as_secs_f64()
+    );
+    if !latencies.is_empty() {
+        println!(
+            "  packet latency:  p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            percentile(&latencies, 50),
+            percentile(&latencies, 90),
+            percentile(&latencies, 99),
+            latencies[latencies.len() - 1],
+        );
+    }
+    Ok(())
+}
+
+/// Returns the `p`th percentile of sorted `values`.
+fn percentile(values: &[Duration], p: usize) -> Duration {
+    values[(values.len() - 1) * p / 100]
+}
+
+/// Returns the peak resident set size of this process if the platform reports it.
+fn peak_memory_kb() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
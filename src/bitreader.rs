@@ -0,0 +1,399 @@
+//! An MSB-first bit reader.
+//!
+//! This is the bit reader used by the ALAC decoder. It is exposed for use by companion crates that
+//! need to parse bit-oriented data such as custom container formats.
+
+use std::cmp;
+#[cfg(feature = "std")]
+use std::error;
+use std::fmt;
+
+const U32_BITS: usize = 32;
+const U64_BITS: usize = 64;
+
+/// Reads bits from a byte slice, most significant bit first.
+#[derive(Clone, Debug)]
+pub struct BitCursor<'a> {
+    buf: &'a [u8],
+    len_bits: usize,
+    // The index in `buf` of the first byte that has not been loaded into `acc`.
+    next_byte: usize,
+    // The next bits to be read, starting with the most significant bit.
+    acc: u64,
+    // The number of bits in `acc` that have not been read.
+    acc_len: u8,
+}
+
+/// An error returned when reading more bits than remain in the buffer.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct NotEnoughData;
+
+#[cfg(feature = "std")]
+impl error::Error for NotEnoughData {}
+
+impl fmt::Display for NotEnoughData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("not enough data")
+    }
+}
+
+/// An error returned when a buffer is too long for its length in bits to fit in a `usize`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BufferTooLong;
+
+#[cfg(feature = "std")]
+impl error::Error for BufferTooLong {}
+
+impl fmt::Display for BufferTooLong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("buffer is too long")
+    }
+}
+
+impl<'a> BitCursor<'a> {
+    /// Creates a `BitCursor` positioned at the first bit of `buf`.
+    pub fn new(buf: &'a [u8]) -> Result<BitCursor<'a>, BufferTooLong> {
+        if buf.len() > usize::MAX >> 3 {
+            return Err(BufferTooLong);
+        }
+
+        let mut cursor = BitCursor {
+            buf,
+            len_bits: buf.len() << 3,
+            next_byte: 0,
+            acc: 0,
+            acc_len: 0,
+        };
+        cursor.refill();
+        Ok(cursor)
+    }
+
+    /// Reads a single bit.
+    #[inline]
+    pub fn read_bit(&mut self) -> Result<bool, NotEnoughData> {
+        Ok(match self.read_u32(1)? {
+            0 => false,
+            1 => true,
+            _ => unreachable!(),
+        })
+    }
+
+    /// Reads `bits` bits as an unsigned integer.
+    ///
+    /// Panics if `bits` is greater than 8.
+    #[inline]
+    pub fn read_u8(&mut self, bits: usize) -> Result<u8, NotEnoughData> {
+        assert!(bits <= 8);
+        debug_assert!(bits > 0);
+
+        Ok(self.read_u32(bits)? as u8)
+    }
+
+    /// Reads `bits` bits as an unsigned integer.
+    ///
+    /// Panics if `bits` is greater than 16.
+    #[inline]
+    pub fn read_u16(&mut self, bits: usize) -> Result<u16, NotEnoughData> {
+        assert!(bits <= 16);
+        debug_assert!(bits > 0);
+
+        Ok(self.read_u32(bits)? as u16)
+    }
+
+    /// Reads `bits` bits as an unsigned integer.
+    ///
+    /// Panics if `bits` is greater than 32.
+    #[inline]
+    pub fn read_u32(&mut self, bits: usize) -> Result<u32, NotEnoughData> {
+        assert!(bits <= 32);
+        debug_assert!(bits > 0);
+
+        if bits > self.acc_len as usize {
+            self.refill();
+            if bits > self.acc_len as usize {
+                return Err(NotEnoughData);
+            }
+        }
+
+        // Shifting in two steps doesn't overflow when `bits` is zero.
+        let val = (self.acc >> 1 >> (U64_BITS - 1 - bits)) as u32;
+        self.consume(bits);
+        Ok(val)
+    }
+
+    /// Reads `bits` bits as a two's complement signed integer, sign extending it.
+    ///
+    /// Panics if `bits` is greater than 16.
+    #[inline]
+    pub fn read_i16(&mut self, bits: usize) -> Result<i16, NotEnoughData> {
+        assert!(bits <= 16);
+
+        Ok(self.read_i32(bits)? as i16)
+    }
+
+    /// Reads `bits` bits as a two's complement signed integer, sign extending it.
+    ///
+    /// Panics if `bits` is greater than 32.
+    #[inline]
+    pub fn read_i32(&mut self, bits: usize) -> Result<i32, NotEnoughData> {
+        let val = self.read_u32(bits)?;
+        let shift = U32_BITS - bits;
+        Ok(((val << shift) as i32) >> shift)
+    }
+
+    /// Reads a unary coded value of up to `max` one bits terminated by a zero bit, returning the
+    /// number of one bits. The terminating zero is not read if `max` one bits are read.
+    ///
+    /// Panics if `max` is greater than 31.
+    #[inline]
+    pub fn read_unary(&mut self, max: usize) -> Result<u32, NotEnoughData> {
+        assert!(max < U32_BITS);
+
+        if (self.acc_len as usize) < U32_BITS {
+            self.refill();
+        }
+
+        // Bits past the end of the buffer are zero so a terminating zero found there is rejected
+        // by `skip` below.
+        let ones = cmp::min(self.peek_aligned().leading_ones() as usize, max);
+        let len = if ones == max { ones } else { ones + 1 };
+        self.skip(len)?;
+        Ok(ones as u32)
+    }
+
+    /// Returns the next `bits` bits as an unsigned integer without advancing the cursor.
+    ///
+    /// Panics if `bits` is greater than 32.
+    #[inline]
+    pub fn peek_u32(&self, bits: usize) -> Result<u32, NotEnoughData> {
+        assert!(bits <= 32);
+
+        self.check_enough_bits(bits)?;
+        Ok((self.peek_aligned() as u64 >> (U32_BITS - bits)) as u32)
+    }
+
+    /// Skips `bits` bits.
+    #[inline]
+    pub fn skip(&mut self, bits: usize) -> Result<(), NotEnoughData> {
+        self.check_enough_bits(bits)?;
+
+        if bits <= self.acc_len as usize {
+            self.consume(bits);
+            Ok(())
+        } else {
+            let position = self.position_bits() + bits;
+            self.seek_bits(position)
+        }
+    }
+
+    /// Moves the cursor to `position` bits from the start of the buffer, either forwards or
+    /// backwards.
+    ///
+    /// Returns an error if `position` is past the end of the buffer.
+    #[inline]
+    pub fn seek_bits(&mut self, position: usize) -> Result<(), NotEnoughData> {
+        if position > self.len_bits {
+            return Err(NotEnoughData);
+        }
+
+        // Whole bytes are skipped without loading them into the accumulator.
+        self.next_byte = position >> 3;
+        self.acc = 0;
+        self.acc_len = 0;
+        self.refill();
+        self.consume(position & 7);
+        Ok(())
+    }
+
+    /// Skips to the start of the next byte unless already at the start of a byte.
+    #[inline]
+    pub fn skip_to_byte(&mut self) -> Result<(), NotEnoughData> {
+        let pos_into_byte = self.position_bits() & 7;
+        if pos_into_byte != 0 {
+            self.skip(8 - pos_into_byte)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the number of bits read or skipped since the start of the buffer.
+    #[inline]
+    pub fn position_bits(&self) -> usize {
+        self.len_bits - self.remaining_bits()
+    }
+
+    /// Returns the number of bits left to read.
+    #[inline]
+    pub fn remaining_bits(&self) -> usize {
+        ((self.buf.len() - self.next_byte) << 3) + self.acc_len as usize
+    }
+
+    #[inline]
+    fn check_enough_bits(&self, bits: usize) -> Result<(), NotEnoughData> {
+        if bits <= self.remaining_bits() {
+            Ok(())
+        } else {
+            Err(NotEnoughData)
+        }
+    }
+
+    /// Returns the next 32 bits with the first in the most significant bit, padded with zeros past
+    /// the end of the buffer.
+    #[inline]
+    fn peek_aligned(&self) -> u32 {
+        let mut acc = self.acc;
+        if (self.acc_len as usize) < U32_BITS {
+            let buf = &self.buf[self.next_byte..];
+            let bytes_to_read = cmp::min(4, buf.len());
+            let mut bytes = [0; 4];
+            bytes[..bytes_to_read].copy_from_slice(&buf[..bytes_to_read]);
+            acc |= (u32::from_be_bytes(bytes) as u64) << (U32_BITS - self.acc_len as usize);
+        }
+        (acc >> U32_BITS) as u32
+    }
+
+    /// Discards `bits` bits from the accumulator.
+    #[inline]
+    fn consume(&mut self, bits: usize) {
+        debug_assert!(bits <= self.acc_len as usize);
+
+        self.acc = self.acc.checked_shl(bits as u32).unwrap_or(0);
+        self.acc_len -= bits as u8;
+    }
+
+    /// Loads whole bytes into the accumulator until it holds at least 56 bits or the buffer is
+    /// empty.
+    #[inline]
+    fn refill(&mut self) {
+        let buf = &self.buf[self.next_byte..];
+        if buf.len() >= 8 {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&buf[..8]);
+            let next = u64::from_be_bytes(bytes);
+
+            // Only whole bytes are counted as loaded. The bits of the next byte that partly fit
+            // are also set but they are the same bits the next refill would load in their place.
+            let bytes_loaded = (U64_BITS - self.acc_len as usize) >> 3;
+            self.acc |= next >> self.acc_len;
+            self.acc_len += bytes_loaded as u8 * 8;
+            self.next_byte += bytes_loaded;
+        } else {
+            for &byte in buf {
+                if self.acc_len > 56 {
+                    break;
+                }
+                self.acc |= (byte as u64) << (56 - self.acc_len);
+                self.acc_len += 8;
+                self.next_byte += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BitCursor;
+
+    #[test]
+    fn read_across_bytes() {
+        let data = &[0xde, 0xad, 0xbe, 0xef, 0x12];
+        let mut reader = BitCursor::new(data).unwrap();
+        assert_eq!(reader.read_u8(4).unwrap(), 0xd);
+        assert_eq!(reader.read_u32(32).unwrap(), 0xeadbeef1);
+        assert_eq!(reader.position_bits(), 36);
+        assert_eq!(reader.remaining_bits(), 4);
+        assert!(reader.read_u8(5).is_err());
+        assert_eq!(reader.read_u8(4).unwrap(), 0x2);
+    }
+
+    #[test]
+    fn read_across_refills() {
+        let mut data = [0u8; 128];
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = (i * 157 + 31) as u8;
+        }
+        let bit = |pos: usize| (data[pos >> 3] >> (7 - (pos & 7))) & 1 == 1;
+
+        // Reads of every width and skips of whole and partial bytes land at each bit offset of the
+        // accumulator.
+        let mut reader = BitCursor::new(&data).unwrap();
+        let mut pos = 0;
+        for bits in (1..33).chain(1..33).cycle().take(40) {
+            if bits % 5 == 0 {
+                reader.skip(bits * 3).unwrap();
+                pos += bits * 3;
+                continue;
+            }
+            let expected = (pos..pos + bits).fold(0, |val, pos| val << 1 | bit(pos) as u32);
+            assert_eq!(reader.read_u32(bits).unwrap(), expected);
+            pos += bits;
+            assert_eq!(reader.position_bits(), pos);
+        }
+        assert_eq!(reader.remaining_bits(), data.len() * 8 - pos);
+    }
+
+    #[test]
+    fn peek_and_seek() {
+        let data = &[0xde, 0xad, 0xbe, 0xef, 0x12];
+        let mut reader = BitCursor::new(data).unwrap();
+        reader.skip(4).unwrap();
+        assert_eq!(reader.peek_u32(32).unwrap(), 0xeadbeef1);
+        assert_eq!(reader.peek_u32(12).unwrap(), 0xead);
+        assert_eq!(reader.position_bits(), 4);
+        assert!(reader.peek_u32(32).is_ok());
+        reader.skip(8).unwrap();
+        assert!(reader.peek_u32(29).is_err());
+        assert_eq!(reader.peek_u32(28).unwrap(), 0xdbeef12);
+
+        reader.seek_bits(36).unwrap();
+        assert_eq!(reader.read_u8(4).unwrap(), 0x2);
+        reader.seek_bits(8).unwrap();
+        assert_eq!(reader.read_u8(8).unwrap(), 0xad);
+        reader.seek_bits(40).unwrap();
+        assert_eq!(reader.remaining_bits(), 0);
+        assert!(reader.seek_bits(41).is_err());
+    }
+
+    #[test]
+    fn read_signed() {
+        let data = &[0xf0, 0x7f, 0xff, 0x80, 0x00];
+        let mut reader = BitCursor::new(data).unwrap();
+        assert_eq!(reader.read_i16(4).unwrap(), -1);
+        assert_eq!(reader.read_i16(4).unwrap(), 0);
+        assert_eq!(reader.read_i16(16).unwrap(), i16::MAX);
+        assert_eq!(reader.read_i32(1).unwrap(), -1);
+        assert_eq!(reader.read_i32(15).unwrap(), 0);
+
+        let data = &[0x80, 0x00, 0x00, 0x01];
+        let mut reader = BitCursor::new(data).unwrap();
+        assert_eq!(reader.read_i32(32).unwrap(), i32::MIN + 1);
+    }
+
+    #[test]
+    fn read_unary() {
+        let data = &[0b1101_1111, 0b1111_1110, 0b1000_0000];
+        let mut reader = BitCursor::new(data).unwrap();
+        assert_eq!(reader.read_unary(9).unwrap(), 2);
+        assert_eq!(reader.position_bits(), 3);
+        // The terminating zero is not read once `max` ones have been read.
+        assert_eq!(reader.read_unary(9).unwrap(), 9);
+        assert_eq!(reader.position_bits(), 12);
+        assert_eq!(reader.read_unary(9).unwrap(), 3);
+        assert_eq!(reader.read_unary(0).unwrap(), 0);
+        assert_eq!(reader.position_bits(), 16);
+        assert_eq!(reader.read_unary(9).unwrap(), 1);
+        // The remaining bits are all zeros but the reader must not run past the end of the buffer.
+        assert_eq!(reader.read_unary(9).unwrap(), 0);
+        let mut reader = BitCursor::new(&[0xff]).unwrap();
+        assert!(reader.read_unary(9).is_err());
+    }
+
+    #[test]
+    fn skip_to_byte() {
+        let data = &[0xde, 0xad];
+        let mut reader = BitCursor::new(data).unwrap();
+        reader.read_u8(5).unwrap();
+        reader.skip_to_byte().unwrap();
+        assert_eq!(reader.read_u8(8).unwrap(), 0xad);
+    }
+}
@@ -1,57 +1,610 @@
-extern crate caf;
-
-use self::caf::chunks::CafChunk;
-use self::caf::{CafError, ChunkType, FormatType};
-use std::io::{ErrorKind, Read, Seek};
+use std::cmp;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
 use std::mem;
 
-use {invalid_data, Format, ReadError};
+use index::PacketEntry;
+use {invalid_data, read_be_u32, Format, GaplessInfo, Metadata, ReadError};
+
+/// The longest information or channel layout chunk read.
+const MAX_HEADER_CHUNK_LEN: u64 = 1024 * 1024;
+
+// Channel layout tags meaning the layout is given by a channel bitmap or channel descriptions.
+const CHANNEL_LAYOUT_USE_DESCRIPTIONS: u32 = 0;
+const CHANNEL_LAYOUT_USE_BITMAP: u32 = 1 << 16;
 
 fn caf_error(msg: &'static str) -> ReadError {
-    ReadError::Format(Format::Caf, invalid_data(msg))
+    ReadError::Format(Format::Caf, invalid_data(::ErrorKind::InvalidContainer, msg))
 }
 
-impl From<CafError> for ReadError {
-    fn from(err: CafError) -> ReadError {
-        match err {
-            CafError::Io(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
-                caf_error("unexpected end of stream")
-            }
-            CafError::Io(err) => ReadError::Io(err),
-            CafError::FromUtf8(_) => caf_error("bytes are not valid utf8"),
-            CafError::NotCaf => caf_error("not a caf file"),
-            CafError::UnsupportedChunkType(_) => caf_error("unsupported chunk type"),
-        }
+/// Fills `buf` from `reader`, treating the end of the stream as a malformed file.
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<(), ReadError> {
+    reader.read_exact(buf).map_err(|err| match err.kind() {
+        ErrorKind::UnexpectedEof => caf_error("unexpected end of stream"),
+        _ => err.into(),
+    })
+}
+
+/// The contents of an audio description (`desc`) chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CafAudioDescription {
+    pub sample_rate: f64,
+    /// The four character code of the audio format, `alac` for ALAC files.
+    pub format_id: [u8; 4],
+    pub format_flags: u32,
+    pub bytes_per_packet: u32,
+    pub frames_per_packet: u32,
+    pub channels_per_frame: u32,
+    pub bits_per_channel: u32,
+}
+
+/// The contents of a packet table (`pakt`) chunk.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CafPacketTable {
+    pub valid_frames: i64,
+    pub priming_frames: i32,
+    pub remainder_frames: i32,
+    /// The length in bytes of each packet.
+    pub lengths: Vec<u64>,
+}
+
+/// The contents of a channel layout (`chan`) chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CafChannelLayout {
+    /// The Core Audio channel layout tag.
+    pub tag: u32,
+    /// The channel bitmap, used when `tag` is `1 << 16`.
+    pub bitmap: u32,
+    /// The channel descriptions, used when `tag` is zero.
+    pub descriptions: Vec<CafChannelDescription>,
+}
+
+/// A channel description from a channel layout (`chan`) chunk.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CafChannelDescription {
+    pub label: u32,
+    pub flags: u32,
+    pub coordinates: [f32; 3],
+}
+
+/// The type and location of a chunk in a CAF file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CafChunk {
+    /// The four character code of the chunk type.
+    pub chunk_type: [u8; 4],
+    /// The stream position of the chunk's content, following its header.
+    pub offset: u64,
+    /// The length of the chunk's content, or `None` if it extends to the end of the file.
+    pub len: Option<u64>,
+}
+
+/// The parsed header chunks of a CAF file.
+#[cfg(feature = "container-context")]
+#[derive(Clone, Debug)]
+pub struct CafContext {
+    audio_description: CafAudioDescription,
+    packet_table: CafPacketTable,
+    magic_cookie: Vec<u8>,
+    channel_layout: Option<CafChannelLayout>,
+    chunks: Vec<CafChunk>,
+    edit_count: u32,
+}
+
+#[cfg(feature = "container-context")]
+impl CafContext {
+    /// Returns the contents of the audio description (`desc`) chunk.
+    pub fn audio_description(&self) -> &CafAudioDescription {
+        &self.audio_description
+    }
+
+    /// Returns the contents of the packet table (`pakt`) chunk.
+    pub fn packet_table(&self) -> &CafPacketTable {
+        &self.packet_table
+    }
+
+    /// Returns the contents of the magic cookie (`kuki`) chunk.
+    pub fn magic_cookie(&self) -> &[u8] {
+        &self.magic_cookie
+    }
+
+    /// Returns the contents of the channel layout (`chan`) chunk, if the file has one.
+    pub fn channel_layout(&self) -> Option<&CafChannelLayout> {
+        self.channel_layout.as_ref()
+    }
+
+    /// Returns every chunk read while looking for the chunks above, including unknown chunks, in
+    /// the order they appear in the file.
+    pub fn chunks(&self) -> &[CafChunk] {
+        &self.chunks
+    }
+
+    /// Returns the edit count stored at the start of the audio data (`data`) chunk.
+    pub fn edit_count(&self) -> u32 {
+        self.edit_count
     }
 }
 
-pub struct CafPacketReader<R: Read + Seek> {
-    reader: caf::CafPacketReader<R>,
+pub struct CafPacketReader<R> {
+    reader: R,
+    packet_lengths: Vec<u64>,
+    gapless_info: Option<GaplessInfo>,
+    metadata: Metadata,
+    data_start: u64,
+    // The end of the audio data chunk, or `None` if it extends to the end of the file.
+    data_end: Option<u64>,
+    packet_idx: usize,
+    packet_offset: u64,
+    // The position of `reader`, or `None` if it is unknown after a failed read.
+    position: Option<u64>,
+    // Packets longer than this are rejected rather than read into memory.
+    packet_len_limit: u64,
+    #[cfg(feature = "container-context")]
+    context: CafContext,
 }
 
 impl<R: Read + Seek> CafPacketReader<R> {
-    pub fn new(reader: R) -> Result<(CafPacketReader<R>, Vec<u8>), ReadError> {
-        let mut reader = caf::CafPacketReader::new(reader, vec![ChunkType::MagicCookie])?;
-        if reader.audio_desc.format_id != FormatType::AppleLossless {
+    pub fn new(mut reader: R) -> Result<(CafPacketReader<R>, Vec<u8>), ReadError> {
+        let chunks = read_chunks(&mut reader)?;
+        let audio_description = chunks
+            .audio_description
+            .ok_or(caf_error("missing audio description chunk"))?;
+        if &audio_description.format_id != b"alac" {
             return Err(caf_error("does not contain alac data"));
         }
-        let magic_cookie = mem::replace(&mut reader.chunks, Vec::new())
-            .into_iter()
-            .filter_map(|c| match c {
-                CafChunk::MagicCookie(d) => Some(d),
-                _ => None,
-            }).next()
+        let magic_cookie = chunks
+            .magic_cookie
             .ok_or(caf_error("missing magic cookie"))?;
-        Ok((CafPacketReader { reader }, magic_cookie))
+        let packet_table = chunks
+            .packet_table
+            .ok_or(caf_error("missing packet table"))?;
+        let (data_start, data_end) = chunks.data.ok_or(caf_error("missing audio data chunk"))?;
+
+        let gapless_info = if packet_table.valid_frames >= 0 && packet_table.priming_frames >= 0 {
+            Some(GaplessInfo::new(
+                packet_table.priming_frames as u64,
+                packet_table.valid_frames as u64,
+            ))
+        } else {
+            None
+        };
+
+        let cookie = match chunks.channel_layout.as_ref().map(|layout| layout.tag) {
+            Some(CHANNEL_LAYOUT_USE_DESCRIPTIONS) | Some(CHANNEL_LAYOUT_USE_BITMAP) | None => {
+                magic_cookie.clone()
+            }
+            Some(tag) => ::cookie_with_channel_layout(magic_cookie.clone(), tag),
+        };
+
+        #[cfg(feature = "container-context")]
+        let context = CafContext {
+            audio_description,
+            packet_table: packet_table.clone(),
+            magic_cookie,
+            channel_layout: chunks.channel_layout,
+            chunks: chunks.chunks,
+            edit_count: chunks.edit_count,
+        };
+
+        Ok((
+            CafPacketReader {
+                reader,
+                packet_lengths: packet_table.lengths,
+                gapless_info,
+                metadata: chunks.metadata,
+                data_start,
+                data_end,
+                packet_idx: 0,
+                packet_offset: data_start,
+                position: None,
+                packet_len_limit: u64::MAX,
+                #[cfg(feature = "container-context")]
+                context,
+            },
+            cookie,
+        ))
+    }
+
+    #[cfg(feature = "container-context")]
+    pub fn context(&self) -> &CafContext {
+        &self.context
+    }
+
+    pub fn packet_count(&self) -> u64 {
+        self.packet_lengths.len() as u64
+    }
+
+    pub fn packet_index(&self) -> u64 {
+        self.packet_idx as u64
+    }
+
+    /// Returns the length of the longest packet in the packet table.
+    pub fn max_packet_len(&self) -> Option<u64> {
+        self.packet_lengths.iter().cloned().max()
+    }
+
+    /// Returns the total number of frames in all packets, or `None` if the packet table is invalid.
+    pub fn total_frames(&self) -> Option<u64> {
+        // Priming frames are decoded along with the valid frames. Remainder frames are not as the
+        // final ALAC packet only contains the valid frames.
+        self.gapless_info
+            .map(|info| info.priming_frames() + info.valid_frames())
+    }
+
+    /// Returns the priming and valid frame counts from the packet table, or `None` if the packet
+    /// table is invalid.
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.gapless_info
+    }
+
+    /// Returns the tags read from the file, leaving empty metadata in their place.
+    pub fn take_metadata(&mut self) -> Metadata {
+        mem::take(&mut self.metadata)
+    }
+
+    /// Returns the offset and length of every packet that lies within the data chunk.
+    pub fn packet_entries(&self) -> Vec<PacketEntry> {
+        let mut entries = Vec::with_capacity(self.packet_lengths.len());
+        let mut offset = self.data_start;
+        for &len in &self.packet_lengths {
+            let end = offset.saturating_add(len);
+            if self.data_end.is_some_and(|data_end| end > data_end) {
+                break;
+            }
+            entries.push(PacketEntry { offset, len });
+            offset = end;
+        }
+        entries
+    }
+
+    /// Positions the reader so the next packet read is `packet`.
+    pub fn seek_to_packet(&mut self, packet: u64) {
+        let packet_idx = cmp::min(packet, self.packet_lengths.len() as u64) as usize;
+        let lengths = &self.packet_lengths[..packet_idx];
+        self.packet_offset = lengths
+            .iter()
+            .fold(self.data_start, |offset, &len| offset.saturating_add(len));
+        self.packet_idx = packet_idx;
+    }
+
+    /// Consumes the reader returning the underlying reader positioned at the start of the next
+    /// concatenated CAF file, or `None` if there isn't one.
+    pub fn into_next_file(mut self) -> Result<Option<R>, ReadError> {
+        // Without a data chunk size the data extends to the end of the file.
+        let data_end = match self.data_end {
+            Some(data_end) => data_end,
+            None => return Ok(None),
+        };
+
+        // Skip any chunks following the data chunk.
+        self.reader.seek(SeekFrom::Start(data_end))?;
+        loop {
+            let mut chunk_type = [0; 4];
+            match self.reader.read_exact(&mut chunk_type) {
+                Ok(()) => (),
+                Err(ref err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(err) => return Err(err.into()),
+            }
+            if &chunk_type == b"caff" {
+                self.reader.seek(SeekFrom::Current(-4))?;
+                return Ok(Some(self.reader));
+            }
+
+            let mut chunk_size = [0; 8];
+            self.reader.read_exact(&mut chunk_size)?;
+            let chunk_size = i64::from_be_bytes(chunk_size);
+            if chunk_size < 0 {
+                return Ok(None);
+            }
+            self.reader.seek(SeekFrom::Current(chunk_size))?;
+        }
+    }
+
+    /// Returns the stream position following the packet most recently read, if it is known.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Sets the length of the longest packet that will be read.
+    pub fn set_packet_len_limit(&mut self, limit: u64) {
+        self.packet_len_limit = limit;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
     }
 
     pub fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
-        if let Some(packet_len) = self.reader.next_packet_size() {
-            buf.resize(packet_len, 0);
-            self.reader.read_packet_into(&mut buf[..])?;
-        } else {
-            buf.clear();
+        let entry = match self.next_packet_entry() {
+            Some(entry) => entry,
+            None => {
+                buf.clear();
+                return Ok(());
+            }
+        };
+
+        if entry.len > self.packet_len_limit {
+            return Err(caf_error("packet is longer than the stream allows"));
         }
+
+        // The packet index and offset are only advanced once the whole packet has been read so a
+        // failed read can be retried. The stream position is unknown until the read succeeds.
+        if self.position.take() != Some(entry.offset) {
+            self.reader.seek(SeekFrom::Start(entry.offset))?;
+        }
+        buf.resize(entry.len as usize, 0);
+        self.reader.read_exact(&mut buf[..])?;
+
+        self.skip_packet();
+        self.position = Some(self.packet_offset);
         Ok(())
     }
+
+    /// Moves past the next packet without reading it, returning its location in the file or
+    /// `None` if there are no more packets.
+    pub fn skip_packet(&mut self) -> Option<PacketEntry> {
+        let entry = self.next_packet_entry()?;
+        self.packet_idx += 1;
+        self.packet_offset = entry.offset.saturating_add(entry.len);
+        Some(entry)
+    }
+
+    fn next_packet_entry(&self) -> Option<PacketEntry> {
+        let len = *self.packet_lengths.get(self.packet_idx)?;
+
+        // A packet table describing more data than the data chunk contains ends the stream early.
+        let end = self.packet_offset.saturating_add(len);
+        if self.data_end.is_some_and(|data_end| end > data_end) {
+            return None;
+        }
+        Some(PacketEntry {
+            offset: self.packet_offset,
+            len,
+        })
+    }
+}
+
+/// The chunks of a CAF file needed to read its audio.
+#[derive(Default)]
+struct Chunks {
+    audio_description: Option<CafAudioDescription>,
+    packet_table: Option<CafPacketTable>,
+    magic_cookie: Option<Vec<u8>>,
+    channel_layout: Option<CafChannelLayout>,
+    metadata: Metadata,
+    // The start and end of the audio data.
+    data: Option<(u64, Option<u64>)>,
+    edit_count: u32,
+    chunks: Vec<CafChunk>,
+}
+
+/// Reads the chunks of the CAF file starting at the current stream position.
+///
+/// Chunks are read in a single pass up to the start of the audio data (`data`) chunk, so the
+/// stream only needs to seek forwards, unless chunks needed to read the audio follow the audio
+/// data. Unknown chunks are skipped.
+fn read_chunks<R: Read + Seek>(reader: &mut R) -> Result<Chunks, ReadError> {
+    let file_start = reader.stream_position()?;
+    let mut header = [0; 8];
+    read_exact(reader, &mut header)?;
+    // The file type is followed by a 16 bit version, which must be 1, and 16 bits of flags.
+    if &header[0..4] != b"caff" || header[4..6] != [0, 1] {
+        return Err(caf_error("not a caf file"));
+    }
+
+    let mut chunks = Chunks::default();
+    let mut offset = file_start + 8;
+    loop {
+        // Chunks following the audio data are only read if chunks needed to read it are missing.
+        if let Some((_, data_end)) = chunks.data {
+            let found_all = chunks.audio_description.is_some()
+                && chunks.packet_table.is_some()
+                && chunks.magic_cookie.is_some();
+            if found_all || data_end.is_none() {
+                break;
+            }
+        }
+
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 12];
+        if let Err(err) = reader.read_exact(&mut header) {
+            // Once the audio data has been found the file may end after any chunk.
+            return match err.kind() {
+                ErrorKind::UnexpectedEof if chunks.data.is_some() => Ok(chunks),
+                ErrorKind::UnexpectedEof => Err(caf_error("unexpected end of stream")),
+                _ => Err(err.into()),
+            };
+        }
+        let chunk_type = [header[0], header[1], header[2], header[3]];
+        let mut chunk_size = [0; 8];
+        chunk_size.copy_from_slice(&header[4..12]);
+        let chunk_size = i64::from_be_bytes(chunk_size);
+        // Only the audio data chunk may have an unknown size, meaning it extends to the end of the
+        // file.
+        let len = match chunk_size {
+            -1 if &chunk_type == b"data" => None,
+            len if len >= 0 => Some(len as u64),
+            _ => return Err(caf_error("invalid chunk size")),
+        };
+        // A following concatenated file ends the chunks of this one.
+        if &chunk_type == b"caff" && chunks.data.is_some() {
+            break;
+        }
+
+        let content_start = offset + 12;
+        chunks.chunks.push(CafChunk {
+            chunk_type,
+            offset: content_start,
+            len,
+        });
+        let chunk_len = len.unwrap_or(0);
+        offset = content_start.saturating_add(chunk_len);
+        match &chunk_type {
+            b"desc" => {
+                let desc = read_chunk(reader, chunk_len, MAX_HEADER_CHUNK_LEN)?;
+                chunks.audio_description = Some(parse_desc(&desc)?);
+            }
+            b"pakt" => {
+                let pakt = read_chunk(reader, chunk_len, chunk_len)?;
+                chunks.packet_table = Some(parse_pakt(&pakt)?);
+            }
+            b"kuki" => {
+                chunks.magic_cookie = Some(read_chunk(reader, chunk_len, MAX_HEADER_CHUNK_LEN)?);
+            }
+            // Chunks that aren't needed to decode the stream are skipped if they are too long.
+            b"chan" if chunk_len <= MAX_HEADER_CHUNK_LEN => {
+                let chan = read_chunk(reader, chunk_len, chunk_len)?;
+                chunks.channel_layout = parse_chan(&chan);
+            }
+            b"info" if chunk_len <= MAX_HEADER_CHUNK_LEN => {
+                let info = read_chunk(reader, chunk_len, chunk_len)?;
+                chunks.metadata = parse_info(&info);
+            }
+            b"data" if chunks.data.is_none() => {
+                // The audio data follows a 32 bit edit count.
+                if len.is_some_and(|len| len < 4) {
+                    return Err(caf_error("audio data chunk is too short"));
+                }
+                let mut edit_count = [0; 4];
+                read_exact(reader, &mut edit_count)?;
+                chunks.data = Some((content_start + 4, len.map(|len| content_start + len)));
+                chunks.edit_count = u32::from_be_bytes(edit_count);
+            }
+            _ => (),
+        }
+    }
+    Ok(chunks)
+}
+
+/// Reads the `len` byte content of the chunk at the current stream position.
+fn read_chunk<R: Read>(reader: &mut R, len: u64, max_len: u64) -> Result<Vec<u8>, ReadError> {
+    if len > max_len {
+        return Err(caf_error("chunk is too long"));
+    }
+    // The content is read incrementally so a corrupt size can't cause a huge allocation.
+    let mut chunk = Vec::new();
+    if reader.take(len).read_to_end(&mut chunk)? as u64 != len {
+        return Err(caf_error("unexpected end of stream"));
+    }
+    Ok(chunk)
+}
+
+fn parse_desc(desc: &[u8]) -> Result<CafAudioDescription, ReadError> {
+    if desc.len() < 32 {
+        return Err(caf_error("audio description chunk is too short"));
+    }
+    let field = |i: usize| read_be_u32(&desc[i..i + 4]);
+    let mut sample_rate = [0; 8];
+    sample_rate.copy_from_slice(&desc[0..8]);
+    Ok(CafAudioDescription {
+        sample_rate: f64::from_be_bytes(sample_rate),
+        format_id: [desc[8], desc[9], desc[10], desc[11]],
+        format_flags: field(12),
+        bytes_per_packet: field(16),
+        frames_per_packet: field(20),
+        channels_per_frame: field(24),
+        bits_per_channel: field(28),
+    })
+}
+
+fn parse_pakt(pakt: &[u8]) -> Result<CafPacketTable, ReadError> {
+    if pakt.len() < 24 {
+        return Err(caf_error("packet table chunk is too short"));
+    }
+    let mut packet_count = [0; 8];
+    packet_count.copy_from_slice(&pakt[0..8]);
+    let packet_count = i64::from_be_bytes(packet_count);
+    let mut valid_frames = [0; 8];
+    valid_frames.copy_from_slice(&pakt[8..16]);
+
+    // Each packet length is a variable length integer of at least one byte.
+    let mut entries = &pakt[24..];
+    if packet_count < 0 || packet_count as u64 > entries.len() as u64 {
+        return Err(caf_error("packet table chunk is too short"));
+    }
+    let mut lengths = Vec::with_capacity(packet_count as usize);
+    for _ in 0..packet_count {
+        lengths.push(read_vlq(&mut entries)?);
+    }
+
+    Ok(CafPacketTable {
+        valid_frames: i64::from_be_bytes(valid_frames),
+        priming_frames: read_be_u32(&pakt[16..20]) as i32,
+        remainder_frames: read_be_u32(&pakt[20..24]) as i32,
+        lengths,
+    })
+}
+
+/// Reads a variable length integer stored as big endian groups of 7 bits, where the high bit of
+/// each byte is set if another byte follows.
+fn read_vlq(buf: &mut &[u8]) -> Result<u64, ReadError> {
+    let mut value = 0u64;
+    loop {
+        let (&byte, rest) = buf
+            .split_first()
+            .ok_or(caf_error("packet table chunk is too short"))?;
+        *buf = rest;
+        if value.leading_zeros() < 7 {
+            return Err(caf_error("packet table entry is too long"));
+        }
+        value = (value << 7) | (byte & 0x7f) as u64;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+/// Parses a channel layout chunk, returning `None` if it is malformed.
+fn parse_chan(chan: &[u8]) -> Option<CafChannelLayout> {
+    let field = |i: usize| chan.get(i..i + 4).map(read_be_u32);
+    let (tag, bitmap, count) = (field(0)?, field(4)?, field(8)?);
+    // Each description is a label, flags and three coordinates.
+    let descriptions = chan.get(12..)?.chunks_exact(20);
+    if (descriptions.len() as u64) < count as u64 {
+        return None;
+    }
+    let descriptions = descriptions.take(count as usize).map(|d| {
+        let coordinate = |i: usize| f32::from_bits(read_be_u32(&d[i..i + 4]));
+        CafChannelDescription {
+            label: read_be_u32(&d[0..4]),
+            flags: read_be_u32(&d[4..8]),
+            coordinates: [coordinate(8), coordinate(12), coordinate(16)],
+        }
+    });
+    Some(CafChannelLayout {
+        tag,
+        bitmap,
+        descriptions: descriptions.collect(),
+    })
+}
+
+fn parse_info(info: &[u8]) -> Metadata {
+    let mut metadata = Metadata::default();
+
+    // The entry count is followed by pairs of null terminated UTF-8 keys and values.
+    let entries = match info.get(0..4) {
+        Some(count) => u32::from_be_bytes([count[0], count[1], count[2], count[3]]),
+        None => return metadata,
+    };
+    let mut strings = info[4..]
+        .split(|&b| b == 0)
+        .take(entries as usize * 2)
+        .map(String::from_utf8_lossy);
+    while let (Some(key), Some(value)) = (strings.next(), strings.next()) {
+        match &*key {
+            "title" => metadata.title = Some(value.into_owned()),
+            "artist" => metadata.artist = Some(value.into_owned()),
+            "album" => metadata.album = Some(value.into_owned()),
+            "track number" => {
+                // The number may be followed by the number of tracks, for example "3/12".
+                let mut numbers = value.split('/').map(|n| n.trim().parse().ok());
+                metadata.track_number = numbers.next().and_then(|n| n);
+                metadata.track_count = numbers.next().and_then(|n| n);
+            }
+            _ => (),
+        }
+    }
+    metadata
 }
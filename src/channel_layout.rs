@@ -0,0 +1,269 @@
+/// The position of a speaker that a channel is intended for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Speaker {
+    Left,
+    Right,
+    Center,
+    LowFrequency,
+    LeftSurround,
+    RightSurround,
+    CenterSurround,
+    LeftCenter,
+    RightCenter,
+    RearLeftSurround,
+    RearRightSurround,
+}
+
+/// The speaker arrangement of a stream's channels.
+///
+/// Layouts correspond to Core Audio channel layout tags, as stored in ALAC magic cookies and CAF
+/// files. The speakers each channel is intended for are returned by `speakers`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ChannelLayout {
+    /// C
+    Mono,
+    /// L R
+    Stereo,
+    /// L R C
+    Mpeg3_0A,
+    /// C L R
+    Mpeg3_0B,
+    /// L R C Cs
+    Mpeg4_0A,
+    /// C L R Cs
+    Mpeg4_0B,
+    /// L R C Ls Rs
+    Mpeg5_0A,
+    /// L R Ls Rs C
+    Mpeg5_0B,
+    /// L C R Ls Rs
+    Mpeg5_0C,
+    /// C L R Ls Rs
+    Mpeg5_0D,
+    /// L R C LFE Ls Rs
+    Mpeg5_1A,
+    /// L R Ls Rs C LFE
+    Mpeg5_1B,
+    /// L C R Ls Rs LFE
+    Mpeg5_1C,
+    /// C L R Ls Rs LFE
+    Mpeg5_1D,
+    /// L R C LFE Ls Rs Cs
+    Mpeg6_1A,
+    /// C L R Ls Rs Cs LFE
+    Aac6_1,
+    /// L R C LFE Ls Rs Lc Rc
+    Mpeg7_1A,
+    /// C Lc Rc L R Ls Rs LFE
+    Mpeg7_1B,
+    /// L R C LFE Ls Rs Rls Rrs
+    Mpeg7_1C,
+    /// A Core Audio channel layout tag without a known speaker arrangement.
+    Other(u32),
+}
+
+use self::ChannelLayout::*;
+use self::Speaker::*;
+
+// Each layout's Core Audio tag and speakers. The tag's upper 16 bits identify the layout and its
+// lower 16 bits give the channel count.
+const LAYOUTS: &[(ChannelLayout, u32, &[Speaker])] = &[
+    (Mono, 100 << 16 | 1, &[Center]),
+    (Stereo, 101 << 16 | 2, &[Left, Right]),
+    (Mpeg3_0A, 113 << 16 | 3, &[Left, Right, Center]),
+    (Mpeg3_0B, 114 << 16 | 3, &[Center, Left, Right]),
+    (
+        Mpeg4_0A,
+        115 << 16 | 4,
+        &[Left, Right, Center, CenterSurround],
+    ),
+    (
+        Mpeg4_0B,
+        116 << 16 | 4,
+        &[Center, Left, Right, CenterSurround],
+    ),
+    (
+        Mpeg5_0A,
+        117 << 16 | 5,
+        &[Left, Right, Center, LeftSurround, RightSurround],
+    ),
+    (
+        Mpeg5_0B,
+        118 << 16 | 5,
+        &[Left, Right, LeftSurround, RightSurround, Center],
+    ),
+    (
+        Mpeg5_0C,
+        119 << 16 | 5,
+        &[Left, Center, Right, LeftSurround, RightSurround],
+    ),
+    (
+        Mpeg5_0D,
+        120 << 16 | 5,
+        &[Center, Left, Right, LeftSurround, RightSurround],
+    ),
+    (
+        Mpeg5_1A,
+        121 << 16 | 6,
+        &[
+            Left,
+            Right,
+            Center,
+            LowFrequency,
+            LeftSurround,
+            RightSurround,
+        ],
+    ),
+    (
+        Mpeg5_1B,
+        122 << 16 | 6,
+        &[
+            Left,
+            Right,
+            LeftSurround,
+            RightSurround,
+            Center,
+            LowFrequency,
+        ],
+    ),
+    (
+        Mpeg5_1C,
+        123 << 16 | 6,
+        &[
+            Left,
+            Center,
+            Right,
+            LeftSurround,
+            RightSurround,
+            LowFrequency,
+        ],
+    ),
+    (
+        Mpeg5_1D,
+        124 << 16 | 6,
+        &[
+            Center,
+            Left,
+            Right,
+            LeftSurround,
+            RightSurround,
+            LowFrequency,
+        ],
+    ),
+    (
+        Mpeg6_1A,
+        125 << 16 | 7,
+        &[
+            Left,
+            Right,
+            Center,
+            LowFrequency,
+            LeftSurround,
+            RightSurround,
+            CenterSurround,
+        ],
+    ),
+    (
+        Aac6_1,
+        142 << 16 | 7,
+        &[
+            Center,
+            Left,
+            Right,
+            LeftSurround,
+            RightSurround,
+            CenterSurround,
+            LowFrequency,
+        ],
+    ),
+    (
+        Mpeg7_1A,
+        126 << 16 | 8,
+        &[
+            Left,
+            Right,
+            Center,
+            LowFrequency,
+            LeftSurround,
+            RightSurround,
+            LeftCenter,
+            RightCenter,
+        ],
+    ),
+    (
+        Mpeg7_1B,
+        127 << 16 | 8,
+        &[
+            Center,
+            LeftCenter,
+            RightCenter,
+            Left,
+            Right,
+            LeftSurround,
+            RightSurround,
+            LowFrequency,
+        ],
+    ),
+    (
+        Mpeg7_1C,
+        128 << 16 | 8,
+        &[
+            Left,
+            Right,
+            Center,
+            LowFrequency,
+            LeftSurround,
+            RightSurround,
+            RearLeftSurround,
+            RearRightSurround,
+        ],
+    ),
+];
+
+impl ChannelLayout {
+    /// Returns the layout identified by a Core Audio channel layout tag.
+    pub fn from_tag(tag: u32) -> ChannelLayout {
+        LAYOUTS
+            .iter()
+            .find(|&&(_, layout_tag, _)| layout_tag == tag)
+            .map_or(Other(tag), |&(layout, _, _)| layout)
+    }
+
+    /// Returns the layout ALAC encoders use for `channels` channels when none is given, or `None`
+    /// if there is no standard layout for that many channels.
+    pub fn default_for_channels(channels: u8) -> Option<ChannelLayout> {
+        match channels {
+            1 => Some(Mono),
+            2 => Some(Stereo),
+            3 => Some(Mpeg3_0B),
+            4 => Some(Mpeg4_0B),
+            5 => Some(Mpeg5_0D),
+            6 => Some(Mpeg5_1D),
+            7 => Some(Aac6_1),
+            8 => Some(Mpeg7_1B),
+            _ => None,
+        }
+    }
+
+    /// Returns the Core Audio channel layout tag identifying the layout.
+    pub fn tag(&self) -> u32 {
+        match *self {
+            Other(tag) => tag,
+            layout => LAYOUTS
+                .iter()
+                .find(|&&(l, _, _)| l == layout)
+                .map_or(0, |&(_, tag, _)| tag),
+        }
+    }
+
+    /// Returns the speaker each channel is intended for in channel order, or `None` if the layout
+    /// is not known.
+    pub fn speakers(&self) -> Option<&'static [Speaker]> {
+        LAYOUTS
+            .iter()
+            .find(|&&(layout, _, _)| layout == *self)
+            .map(|&(_, _, speakers)| speakers)
+    }
+}
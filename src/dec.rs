@@ -3,17 +3,42 @@
 // overflow but in tight loops, performance critical code, and where overflow is not necessarily
 // incorrect wrapping arithmetic is used.
 
+use alloc::boxed::Box;
+use alloc::vec::Vec;
 use std::cmp::min;
-
-use bitcursor::BitCursor;
-use {invalid_data, InvalidData, StreamInfo};
+use std::mem;
+use std::slice;
+use std::ops::{BitOr, Mul, Range, Shl, Shr};
+
+use bitreader::BitCursor;
+use float::FloatScale;
+#[cfg(feature = "simd")]
+use simd;
+use {
+    invalid_data, invalid_data_mismatch, ChannelLayout, DecodeError, ErrorKind, InvalidData,
+    StreamInfo, MAX_COMPATIBLE_VERSION, MAX_SAMPLES_PER_PACKET,
+};
 
 /// A type that can be used to represent audio samples.
+///
+/// This trait is sealed unless the `unsealed-sample` feature is enabled, which allows it to be
+/// implemented for other types such as fixed point or SIMD samples.
 pub trait Sample: Copy + private::Sealed {
-    /// Constructs `Self` from a right-aligned sample with bit depth `bits`.
+    /// Constructs `Self` from a right-aligned, sign extended sample with bit depth `bits`.
     fn from_decoder(sample: i32, bits: u8) -> Self;
 
+    /// Returns the number of bits of precision the type holds. Streams with a greater bit depth
+    /// are requantized to this many bits, if requantization is enabled, before being passed to
+    /// `from_decoder`.
     fn bits() -> u8;
+
+    /// Constructs `Self` in the same way as `from_decoder`, mapping the sample to a floating point
+    /// value using `scale`. Integer types ignore `scale`.
+    #[inline(always)]
+    fn from_decoder_scaled(sample: i32, bits: u8, scale: FloatScale) -> Self {
+        let _ = scale;
+        Self::from_decoder(sample, bits)
+    }
 }
 
 impl Sample for i16 {
@@ -40,17 +65,345 @@ impl Sample for i32 {
     }
 }
 
+/// A 32 bit sample that is right-aligned rather than left-aligned like `i32` samples.
+///
+/// Right-aligned samples keep the values they were encoded with, e.g. a 24 bit stream produces
+/// values in the range `[-2^23, 2^23)` as most WAV readers do.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct I32Raw(pub i32);
+
+impl From<I32Raw> for i32 {
+    fn from(sample: I32Raw) -> i32 {
+        sample.0
+    }
+}
+
+impl Sample for I32Raw {
+    #[inline(always)]
+    fn from_decoder(sample: i32, _: u8) -> Self {
+        I32Raw(sample)
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        32
+    }
+}
+
+/// Samples are scaled using the decoder's `FloatScale`, by default `FloatScale::FullScale` which
+/// maps them to the range `[-1.0, 1.0)`.
+impl Sample for f32 {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        sample as f32 / (1u32 << (bits - 1)) as f32
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        32
+    }
+
+    #[inline(always)]
+    fn from_decoder_scaled(sample: i32, bits: u8, scale: FloatScale) -> Self {
+        match scale {
+            FloatScale::FullScale => Self::from_decoder(sample, bits),
+            // The same conversion as `FloatScale::convert_f32` so the results are identical.
+            FloatScale::UnityAtMax => (sample as f64 * (1.0 / scale.divisor(bits))) as f32,
+        }
+    }
+}
+
+/// Samples are scaled using the decoder's `FloatScale`, by default `FloatScale::FullScale` which
+/// maps them to the range `[-1.0, 1.0)`.
+impl Sample for f64 {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        sample as f64 / (1u32 << (bits - 1)) as f64
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        32
+    }
+
+    #[inline(always)]
+    fn from_decoder_scaled(sample: i32, bits: u8, scale: FloatScale) -> Self {
+        match scale {
+            FloatScale::FullScale => Self::from_decoder(sample, bits),
+            // The same conversion as `FloatScale::convert_f64` so the results are identical.
+            FloatScale::UnityAtMax => sample as f64 * (1.0 / scale.divisor(bits)),
+        }
+    }
+}
+
+/// The sample format of a byte buffer passed to `Decoder::decode_packet_bytes`.
+///
+/// Formats are named after their ALSA equivalents.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SampleFormat {
+    /// Little-endian 16 bit samples with the same values as `i16` samples.
+    S16Le,
+    /// Little-endian 24 bit samples in the low three bytes of four.
+    S24Le,
+    /// Packed little-endian 24 bit samples of three bytes each.
+    S24Le3,
+    /// Little-endian 32 bit samples with the same values as `i32` samples.
+    S32Le,
+    /// Little-endian 32 bit floating point samples with the same values as `f32` samples.
+    F32Le,
+}
+
+impl SampleFormat {
+    /// Returns the number of bytes each sample occupies.
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleFormat::S16Le => 2,
+            SampleFormat::S24Le3 => 3,
+            SampleFormat::S24Le | SampleFormat::S32Le | SampleFormat::F32Le => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+struct S16Le([u8; 2]);
+
+impl Sample for S16Le {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        S16Le(i16::from_decoder(sample, bits).to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        16
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+struct S24Le([u8; 4]);
+
+impl Sample for S24Le {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        S24Le((sample << (24 - bits)).to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        24
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+struct S24Le3([u8; 3]);
+
+impl Sample for S24Le3 {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        let sample = sample << (24 - bits);
+        let bytes = sample.to_le_bytes();
+        S24Le3([bytes[0], bytes[1], bytes[2]])
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        24
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+struct S32Le([u8; 4]);
+
+impl Sample for S32Le {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        S32Le(i32::from_decoder(sample, bits).to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        32
+    }
+}
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
+struct F32Le([u8; 4]);
+
+impl Sample for F32Le {
+    #[inline(always)]
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        F32Le(f32::from_decoder(sample, bits).to_le_bytes())
+    }
+
+    #[inline(always)]
+    fn bits() -> u8 {
+        32
+    }
+
+    #[inline(always)]
+    fn from_decoder_scaled(sample: i32, bits: u8, scale: FloatScale) -> Self {
+        F32Le(f32::from_decoder_scaled(sample, bits, scale).to_le_bytes())
+    }
+}
+
+/// Reinterprets a byte buffer as a buffer of byte array samples, ignoring any trailing bytes that
+/// do not make up a whole sample.
+fn byte_samples<S: Sample>(out: &mut [u8]) -> &mut [S] {
+    // Only byte array samples have an alignment of one.
+    debug_assert_eq!(mem::align_of::<S>(), 1);
+    let len = out.len() / mem::size_of::<S>();
+    // Safe as `S` is a byte array with an alignment of one for which any bytes are valid.
+    unsafe { slice::from_raw_parts_mut(out.as_mut_ptr() as *mut S, len) }
+}
+
+#[cfg(feature = "unsealed-sample")]
+mod private {
+    pub trait Sealed {}
+    impl<T> Sealed for T {}
+}
+
+#[cfg(not(feature = "unsealed-sample"))]
 mod private {
     /// Sealed prevents other crates from implementing any traits that use it.
     pub trait Sealed {}
     impl Sealed for i16 {}
     impl Sealed for i32 {}
+    impl Sealed for super::I32Raw {}
+    impl Sealed for super::S16Le {}
+    impl Sealed for super::S24Le {}
+    impl Sealed for super::S24Le3 {}
+    impl Sealed for super::S32Le {}
+    impl Sealed for super::F32Le {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// How `Decoder::conceal_lost_packet` fills the gap left by a lost packet.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Concealment {
+    /// Lost packets are replaced with silence.
+    #[default]
+    Silence,
+    /// Lost packets are replaced with the last packet decoded, or silence if there is none.
+    RepeatLastPacket,
+}
+
+/// How samples are requantized when decoding into a sample type with fewer bits than the stream.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Requantize {
+    /// The least significant bits of each sample are discarded.
+    Truncate,
+    /// Triangular probability density function (TPDF) dither of one output least significant bit
+    /// is added before rounding. This avoids truncation distortion at the cost of a slightly
+    /// higher noise floor.
+    Dither,
+}
+
+/// Options controlling how a `Decoder` validates packets and converts samples.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct DecodeOptions {
+    strict: bool,
+    requantize: Option<Requantize>,
+    // `None` if the limit is `MAX_SAMPLES_PER_PACKET`.
+    samples_per_packet_limit: Option<u32>,
+    float_scale: FloatScale,
+}
+
+impl DecodeOptions {
+    /// Creates `DecodeOptions` with the default lenient settings.
+    pub fn new() -> DecodeOptions {
+        DecodeOptions::default()
+    }
+
+    /// Sets whether packets containing data after their end element, and streams with a compatible
+    /// version newer than `MAX_COMPATIBLE_VERSION`, are rejected. Defaults to `false`.
+    pub fn strict(mut self, strict: bool) -> DecodeOptions {
+        self.strict = strict;
+        self
+    }
+
+    /// Returns `true` if packets containing data after their end element, and streams with an
+    /// unknown compatible version, are rejected.
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Allows decoding into a sample type with fewer bits than the stream's bit depth, e.g. a 24
+    /// bit stream into `i16`, requantizing samples as given. By default this is not allowed.
+    pub fn requantize(mut self, requantize: Requantize) -> DecodeOptions {
+        self.requantize = Some(requantize);
+        self
+    }
+
+    /// Returns how samples are requantized for narrower sample types, or `None` if decoding into
+    /// them is not allowed.
+    pub fn requantization(&self) -> Option<Requantize> {
+        self.requantize
+    }
+
+    /// Sets the largest number of samples per packet, across all channels, of a stream the
+    /// decoder accepts. Defaults to `MAX_SAMPLES_PER_PACKET`, which is also the highest limit.
+    ///
+    /// Lowering the limit bounds the memory used to decode streams from untrusted sources.
+    pub fn limit_samples_per_packet(mut self, limit: u32) -> DecodeOptions {
+        self.samples_per_packet_limit = Some(min(limit, MAX_SAMPLES_PER_PACKET));
+        self
+    }
+
+    /// Returns the largest number of samples per packet of a stream the decoder accepts.
+    pub fn samples_per_packet_limit(&self) -> u32 {
+        self.samples_per_packet_limit.unwrap_or(MAX_SAMPLES_PER_PACKET)
+    }
+
+    /// Sets how samples are mapped to floating point values when decoding into `f32` or `f64`.
+    /// Defaults to `FloatScale::FullScale`.
+    pub fn scale_floats(mut self, scale: FloatScale) -> DecodeOptions {
+        self.float_scale = scale;
+        self
+    }
+
+    /// Returns how samples are mapped to floating point values.
+    pub fn float_scale(&self) -> FloatScale {
+        self.float_scale
+    }
+}
+
+/// The result of decoding a single packet.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DecodedPacket {
+    /// The number of samples decoded.
+    pub num_samples: usize,
+    /// The number of bytes of the packet consumed up to and including the end element.
+    pub consumed: usize,
+    /// Whether any element was stored uncompressed using the escape flag.
+    #[cfg_attr(not(any(feature = "caf", feature = "mp4")), allow(dead_code))]
+    pub uncompressed: bool,
 }
 
 /// An ALAC packet decoder.
+///
+/// A `Decoder` is `Send` and `Sync`. Cloning it creates an independent decoder with its own
+/// buffers and the same options, channel gains and concealment state, so each thread of a worker
+/// pool can decode packets of the same stream.
+#[derive(Clone)]
 pub struct Decoder {
     config: StreamInfo,
+    options: DecodeOptions,
     buf: Box<[i32]>,
+    // Only allocated for 32 bit streams, whose stereo elements can need 33 bit intermediates.
+    wide_buf: Box<[i64]>,
+    requantizer: Requantizer,
+    // The output channel of each channel in element order, or `None` if they are the same.
+    channel_order: Option<Box<[u8]>>,
+    gains: Option<Box<[f64]>>,
+    concealment: Concealment,
+    // The last packet decoded, kept when lost packets are concealed by repeating it.
+    last_packet: Vec<u8>,
 }
 
 const ID_SCE: u8 = 0; // Single Channel Element
@@ -65,9 +418,80 @@ const ID_END: u8 = 7; // frame end
 impl Decoder {
     /// Creates a `Decoder` for a stream described by the `StreamInfo`.
     pub fn new(config: StreamInfo) -> Decoder {
-        Decoder {
+        Decoder::with_options(config, DecodeOptions::default())
+    }
+
+    /// Creates a `Decoder` for a stream described by the `StreamInfo` using the given options.
+    ///
+    /// Panics if the stream has more samples per packet than
+    /// `DecodeOptions::samples_per_packet_limit`, or if strict decoding is enabled and the stream
+    /// has an unknown compatible version. Use `try_with_options` to handle these as errors.
+    pub fn with_options(config: StreamInfo, options: DecodeOptions) -> Decoder {
+        match Decoder::try_with_options(config, options) {
+            Ok(decoder) => decoder,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// Creates a `Decoder` for a stream described by the `StreamInfo` using the given options,
+    /// returning an error if the stream has more samples per packet than
+    /// `DecodeOptions::samples_per_packet_limit`, or if strict decoding is enabled and
+    /// `StreamInfo::is_known_compatible_version` is `false`.
+    pub fn try_with_options(
+        config: StreamInfo,
+        options: DecodeOptions,
+    ) -> Result<Decoder, InvalidData> {
+        if config.max_samples_per_packet() > options.samples_per_packet_limit() {
+            return Err(invalid_data(
+                ErrorKind::InvalidStreamInfo,
+                "stream has more samples per packet than the decoder's limit",
+            ));
+        }
+        if options.strict && !config.is_known_compatible_version() {
+            return Err(invalid_data_mismatch(
+                ErrorKind::InvalidStreamInfo,
+                "stream has an unknown compatible version",
+                MAX_COMPATIBLE_VERSION as u64,
+                config.compatible_version() as u64,
+            ));
+        }
+        Ok(Decoder {
             buf: vec![0; config.frame_length as usize * 2].into_boxed_slice(),
+            wide_buf: if config.bit_depth == 32 && config.num_channels > 1 {
+                vec![0; config.frame_length as usize * 2].into_boxed_slice()
+            } else {
+                Box::new([])
+            },
+            channel_order: channel_order(&config),
+            requantizer: Requantizer::new(options.requantize, options.float_scale),
             config,
+            options,
+            gains: None,
+            concealment: Concealment::Silence,
+            last_packet: Vec::new(),
+        })
+    }
+
+    /// Returns the output channel of the channel at `channel_index` in element order.
+    fn output_channel(&self, channel_index: u8) -> usize {
+        match self.channel_order {
+            Some(ref order) => order[channel_index as usize] as usize,
+            None => channel_index as usize,
+        }
+    }
+
+    /// Sets a linear gain to apply to each channel as samples are decoded.
+    ///
+    /// Gains are given in output channel order. Gained samples are rounded and saturate at the
+    /// limits of the stream's bit depth. Pass an empty slice to remove any gains.
+    ///
+    /// Panics if `gains` is not empty and its length is not the number of channels in the stream.
+    pub fn set_channel_gains(&mut self, gains: &[f64]) {
+        if gains.is_empty() {
+            self.gains = None;
+        } else {
+            assert_eq!(gains.len(), self.config.num_channels as usize);
+            self.gains = Some(gains.into());
         }
     }
 
@@ -76,24 +500,285 @@ impl Decoder {
         &self.config
     }
 
+    /// Returns the options used to create this decoder.
+    pub fn options(&self) -> DecodeOptions {
+        self.options
+    }
+
+    #[cfg(any(feature = "caf", feature = "mp4"))]
+    pub(crate) fn set_stream_info(&mut self, config: StreamInfo) {
+        // Only the sample rate can be changed without reallocating the decode buffer.
+        debug_assert_eq!(config.frame_length, self.config.frame_length);
+        self.config = config;
+    }
+
+    #[cfg(any(feature = "caf", feature = "mp4"))]
+    pub(crate) fn set_requantize(&mut self, requantize: Requantize) {
+        self.options.requantize = Some(requantize);
+        self.requantizer.requantize = Some(requantize);
+    }
+
+    /// Sets how `conceal_lost_packet` replaces lost packets. Defaults to `Concealment::Silence`.
+    pub fn set_concealment(&mut self, concealment: Concealment) {
+        self.concealment = concealment;
+        self.last_packet.clear();
+    }
+
+    /// Returns the decoder to the state it was created in, apart from its channel gains and
+    /// concealment mode.
+    ///
+    /// ALAC packets are decoded independently so this only forgets the packet repeated by
+    /// `conceal_lost_packet`. It should be called after a discontinuity such as a seek.
+    pub fn reset(&mut self) {
+        self.last_packet.clear();
+    }
+
     /// Decodes an ALAC packet into `out`.
     ///
     /// Channels are interleaved, e.g. for a stereo packet `out` would contains samples in the
-    /// order `[left, right, left, right, ..]`.
+    /// order `[left, right, left, right, ..]`. Multichannel audio is output in the order of
+    /// `StreamInfo::channel_layout`, which may differ from the order channels are stored in.
     ///
-    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    /// With strict decoding enabled an error is returned if `packet` contains data after its end
+    /// element.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet` or `S` has fewer bits
+    /// than the stream's bit depth and requantization is not enabled in the decoder's options.
+    /// `try_decode_packet` returns an error instead.
     pub fn decode_packet<'a, S: Sample>(
         &mut self,
         packet: &[u8],
         out: &'a mut [S],
     ) -> Result<&'a [S], InvalidData> {
+        let decoded = self.decode_packet_detailed(packet, out)?;
+        Ok(&out[..decoded.num_samples])
+    }
+
+    /// Decodes an ALAC packet into `out` as packed little-endian 24 bit samples of three bytes
+    /// each, returning the bytes written.
+    ///
+    /// This is the format expected by S24_3LE audio devices. Streams with a lower bit depth are
+    /// left-aligned while 32 bit streams must have requantization enabled in the decoder's
+    /// options. See `decode_packet` for details of the output and of strict decoding.
+    ///
+    /// Panics if `out` is shorter than three times `StreamInfo::max_samples_per_packet`.
+    pub fn decode_packet_s24le<'a>(
+        &mut self,
+        packet: &[u8],
+        out: &'a mut [u8],
+    ) -> Result<&'a [u8], InvalidData> {
+        self.decode_packet_bytes(packet, out, SampleFormat::S24Le3)
+    }
+
+    /// Decodes an ALAC packet into `out` as samples of the given format, returning the bytes
+    /// written.
+    ///
+    /// This allows samples to be written straight into an audio device's buffer. Formats with
+    /// fewer bits than the stream's bit depth require requantization to be enabled in the
+    /// decoder's options. See `decode_packet` for details of the output and of strict decoding.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet` samples of the format.
+    pub fn decode_packet_bytes<'a>(
+        &mut self,
+        packet: &[u8],
+        out: &'a mut [u8],
+        format: SampleFormat,
+    ) -> Result<&'a [u8], InvalidData> {
+        let len = match format {
+            SampleFormat::S16Le => self.decode_packet_as::<S16Le>(packet, out)?,
+            SampleFormat::S24Le => self.decode_packet_as::<S24Le>(packet, out)?,
+            SampleFormat::S24Le3 => self.decode_packet_as::<S24Le3>(packet, out)?,
+            SampleFormat::S32Le => self.decode_packet_as::<S32Le>(packet, out)?,
+            SampleFormat::F32Le => self.decode_packet_as::<F32Le>(packet, out)?,
+        };
+        Ok(&out[..len * format.bytes_per_sample()])
+    }
+
+    /// Decodes a packet into a byte buffer of byte array samples, returning the number of samples
+    /// decoded.
+    fn decode_packet_as<S: Sample>(
+        &mut self,
+        packet: &[u8],
+        out: &mut [u8],
+    ) -> Result<usize, InvalidData> {
+        Ok(self.decode_packet(packet, byte_samples::<S>(out))?.len())
+    }
+
+    /// Decodes the packet at the start of `data` into `out`, returning the decoded samples and the
+    /// number of bytes of `data` the packet occupies.
+    ///
+    /// Packets end with an end element, so this can be used to decode a byte stream of
+    /// back-to-back packets without any external framing. `data` may extend past the end of the
+    /// packet, even when strict decoding is enabled. See `decode_packet` for details of the
+    /// output.
+    pub fn decode_packet_with_len<'a, S: Sample>(
+        &mut self,
+        data: &[u8],
+        out: &'a mut [S],
+    ) -> Result<(&'a [S], usize), InvalidData> {
+        let decoded = self.decode_packet_prefix(data, out)?;
+        Ok((&out[..decoded.num_samples], decoded.consumed))
+    }
+
+    /// Decodes a packet into `out` in the same way as `decode_packet`, returning details of the
+    /// packet rather than the decoded samples.
+    pub(crate) fn decode_packet_detailed<S: Sample>(
+        &mut self,
+        packet: &[u8],
+        out: &mut [S],
+    ) -> Result<DecodedPacket, InvalidData> {
+        let decoded = self.decode_packet_prefix(packet, out)?;
+        if self.options.strict && decoded.consumed != packet.len() {
+            return Err(invalid_data_mismatch(
+                ErrorKind::TrailingData,
+                "packet contains data after its end element",
+                decoded.consumed as u64,
+                packet.len() as u64,
+            ));
+        }
+        Ok(decoded)
+    }
+
+    /// Decodes the packet at the start of `data`, remembering it for concealment.
+    fn decode_packet_prefix<S: Sample>(
+        &mut self,
+        data: &[u8],
+        out: &mut [S],
+    ) -> Result<DecodedPacket, InvalidData> {
+        let decoded = self.decode_packet_inner(data, out)?;
+        if self.concealment == Concealment::RepeatLastPacket {
+            self.last_packet.clear();
+            self.last_packet.extend_from_slice(&data[..decoded.consumed]);
+        }
+        Ok(decoded)
+    }
+
+    /// Decodes an ALAC packet into `out` in the same way as `decode_packet`, including rejecting
+    /// trailing data when strict decoding is enabled, returning an error rather than panicking if
+    /// `out` is too short or `S` is too narrow for the stream.
+    pub fn try_decode_packet<'a, S: Sample>(
+        &mut self,
+        packet: &[u8],
+        out: &'a mut [S],
+    ) -> Result<&'a [S], DecodeError> {
+        let required = self.config.max_samples_per_packet() as usize;
+        if out.len() < required {
+            return Err(DecodeError::OutputTooShort {
+                required,
+                len: out.len(),
+            });
+        }
+        if S::bits() < self.config.bit_depth && self.options.requantize.is_none() {
+            return Err(DecodeError::SampleTooNarrow {
+                sample_bits: S::bits(),
+                bit_depth: self.config.bit_depth,
+            });
+        }
+        Ok(self.decode_packet(packet, out)?)
+    }
+
+    /// Writes a full packet of samples into `out` in place of a packet that was lost, so that
+    /// the output stays in step with the stream's clock.
+    ///
+    /// The samples are silence or a repeat of the last packet depending on the concealment mode.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    pub fn conceal_lost_packet<'a, S: Sample>(&mut self, out: &'a mut [S]) -> &'a [S] {
+        let num_samples = self.config.max_samples_per_packet() as usize;
+        assert!(out.len() >= num_samples);
+
+        // Packets are decoded independently so decoding the last packet again repeats it.
+        let last_packet = mem::take(&mut self.last_packet);
+        let decoded = if last_packet.is_empty() {
+            0
+        } else {
+            self.decode_packet_inner(&last_packet, out)
+                .map_or(0, |decoded| decoded.num_samples)
+        };
+        self.last_packet = last_packet;
+
+        // A repeated partial packet is padded with silence.
+        for sample in &mut out[decoded..num_samples] {
+            *sample = S::from_decoder(0, self.config.bit_depth);
+        }
+        &out[..num_samples]
+    }
+
+    /// Decodes a sequence of ALAC packets into a single buffer of interleaved samples.
+    ///
+    /// This is useful for packets obtained from a transport other than a supported container. An
+    /// error is returned if any packet fails to decode, including packets with trailing data when
+    /// strict decoding is enabled.
+    pub fn decode_all<'a, I, S>(&mut self, packets: I) -> Result<Vec<S>, InvalidData>
+    where
+        I: IntoIterator<Item = &'a [u8]>,
+        S: Sample,
+    {
+        let max_samples = self.config.max_samples_per_packet() as usize;
+        let mut out = Vec::new();
+        for packet in packets {
+            // Decode directly into the end of the output to avoid copying each packet.
+            let len = out.len();
+            out.resize(len + max_samples, S::from_decoder(0, 16));
+            let decoded = self.decode_packet_detailed(packet, &mut out[len..])?;
+            out.truncate(len + decoded.num_samples);
+        }
+        Ok(out)
+    }
+
+    /// Searches `data` for the first position at which a whole packet can be decoded and decodes
+    /// it into `out`.
+    ///
+    /// This is intended for recovering audio from damaged streams where packet boundaries are
+    /// unknown. Returns the byte range of the packet within `data` along with the decoded samples,
+    /// or `None` if no decodable packet is found. Data after the packet is not an error, even when
+    /// strict decoding is enabled, as it is expected to hold the following packets.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    pub fn decode_next_valid_packet<'a, S: Sample>(
+        &mut self,
+        data: &[u8],
+        out: &'a mut [S],
+    ) -> Option<(Range<usize>, &'a [S])> {
+        let (offset, decoded) = self.find_packet(data, data.len(), out)?;
+        Some((offset..offset + decoded.consumed, &out[..decoded.num_samples]))
+    }
+
+    /// Returns the offset of the first packet starting before `max_offset` in `data` along with
+    /// details of the decoded packet.
+    pub(crate) fn find_packet<S: Sample>(
+        &mut self,
+        data: &[u8],
+        max_offset: usize,
+        out: &mut [S],
+    ) -> Option<(usize, DecodedPacket)> {
+        for offset in 0..min(max_offset, data.len()) {
+            let packet = &data[offset..];
+            if !is_plausible_packet_start(packet) {
+                continue;
+            }
+
+            match self.decode_packet_inner(packet, out) {
+                Ok(decoded) if decoded.num_samples > 0 => return Some((offset, decoded)),
+                _ => (),
+            }
+        }
+        None
+    }
+
+    fn decode_packet_inner<S: Sample>(
+        &mut self,
+        packet: &[u8],
+        out: &mut [S],
+    ) -> Result<DecodedPacket, InvalidData> {
         let mut reader = BitCursor::new(packet)?;
 
         let mut channel_index = 0;
         let mut frame_samples = None;
+        let mut uncompressed = false;
 
         assert!(out.len() >= self.config.max_samples_per_packet() as usize);
-        assert!(S::bits() >= self.config.bit_depth);
+        assert!(S::bits() >= self.config.bit_depth || self.options.requantize.is_some());
 
         loop {
             let tag = reader.read_u8(3)?;
@@ -107,24 +792,35 @@ impl Decoder {
                         _ => unreachable!(),
                     };
 
-                    // Check that there aren't too many channels in this packet.
-                    if channel_index + element_channels > self.config.num_channels {
-                        return Err(invalid_data("packet contains more channels than expected"));
+                    // Check that there aren't too many channels in this packet. Streams may have up
+                    // to 255 channels so widen before adding to avoid overflow.
+                    let next_channel_index = channel_index as u32 + element_channels as u32;
+                    if next_channel_index > self.config.num_channels as u32 {
+                        return Err(invalid_data_mismatch(
+                            ErrorKind::ChannelCountMismatch,
+                            "packet contains more channels than expected",
+                            self.config.num_channels as u64,
+                            next_channel_index as u64,
+                        ));
                     }
 
-                    let element_samples = decode_audio_element(
+                    let (element_samples, element_uncompressed) = decode_audio_element(
                         self,
                         &mut reader,
                         out,
                         channel_index,
                         element_channels,
                     )?;
+                    uncompressed |= element_uncompressed;
 
                     // Check that the number of samples are consistent within elements of a frame.
                     if let Some(frame_samples) = frame_samples {
                         if frame_samples != element_samples {
-                            return Err(invalid_data(
+                            return Err(invalid_data_mismatch(
+                                ErrorKind::SampleCountMismatch,
                                 "all channels in a packet must contain the same number of samples",
+                                frame_samples as u64,
+                                element_samples as u64,
                             ));
                         }
                     } else {
@@ -134,7 +830,10 @@ impl Decoder {
                     channel_index += element_channels;
                 }
                 ID_CCE | ID_PCE => {
-                    return Err(invalid_data("packet cce and pce elements are unsupported"));
+                    return Err(invalid_data(
+                        ErrorKind::UnsupportedElement,
+                        "packet cce and pce elements are unsupported",
+                    ));
                 }
                 ID_DSE => {
                     // data stream element -- parse but ignore
@@ -174,17 +873,25 @@ impl Decoder {
                 }
                 ID_END => {
                     // We've finished decoding the frame. Skip to the end of this byte. There may
-                    // be data left in the packet.
-                    // TODO: Should we throw an error about leftover data.
+                    // be data left in the packet, which is only an error when decoding strictly.
                     reader.skip_to_byte()?;
 
                     // Check that there were as many channels in the packet as there ought to be.
                     if channel_index != self.config.num_channels {
-                        return Err(invalid_data("packet contains fewer channels than expected"));
+                        return Err(invalid_data_mismatch(
+                            ErrorKind::ChannelCountMismatch,
+                            "packet contains fewer channels than expected",
+                            self.config.num_channels as u64,
+                            channel_index as u64,
+                        ));
                     }
 
                     let frame_samples = frame_samples.unwrap_or(self.config.frame_length);
-                    return Ok(&out[..frame_samples as usize * channel_index as usize]);
+                    return Ok(DecodedPacket {
+                        num_samples: frame_samples as usize * channel_index as usize,
+                        consumed: packet.len() - reader.remaining_bits() / 8,
+                        uncompressed,
+                    });
                 }
                 // `tag` is 3 bits long and we've exhaused all 8 options.
                 _ => unreachable!(),
@@ -193,19 +900,55 @@ impl Decoder {
     }
 }
 
+/// Returns the number of frames in `packet` given by the header of its first audio element, or
+/// `None` if it does not start with one.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub(crate) fn packet_frames(packet: &[u8], frame_length: u32) -> Option<u32> {
+    if !is_plausible_packet_start(packet) {
+        return None;
+    }
+
+    // Skip the element and instance tags and unused bits, then read the partial frame flag and
+    // skip the sample shift and escape flag.
+    let mut reader = BitCursor::new(packet).ok()?;
+    reader.skip(19).ok()?;
+    let partial_frame = reader.read_bit().ok()?;
+    reader.skip(3).ok()?;
+    if partial_frame {
+        reader.read_u32(32).ok()
+    } else {
+        Some(frame_length)
+    }
+}
+
+/// Returns `false` if `packet` cannot possibly start with an audio element.
+fn is_plausible_packet_start(packet: &[u8]) -> bool {
+    if packet.len() < 3 {
+        return false;
+    }
+
+    // A 3-bit element tag and 4-bit instance tag are followed by 12 bits which must be zero.
+    let tag = packet[0] >> 5;
+    let unused_is_zero = packet[0] & 1 == 0 && packet[1] == 0 && packet[2] >> 5 == 0;
+    (tag == ID_SCE || tag == ID_CPE || tag == ID_LFE) && unused_is_zero
+}
+
 fn decode_audio_element<'a, S: Sample>(
     this: &mut Decoder,
     reader: &mut BitCursor<'a>,
     out: &mut [S],
     channel_index: u8,
     element_channels: u8,
-) -> Result<u32, InvalidData> {
+) -> Result<(u32, bool), InvalidData> {
     // Unused
     let _element_instance_tag = reader.read_u8(4)?;
 
     let unused = reader.read_u16(12)?;
     if unused != 0 {
-        return Err(invalid_data("unused channel header bits must be zero"));
+        return Err(invalid_data(
+            ErrorKind::InvalidChannelHeader,
+            "unused channel header bits must be zero",
+        ));
     }
 
     // read the 1-bit "partial frame" flag, 2-bit "shift-off" flag & 1-bit "escape" flag
@@ -214,6 +957,7 @@ fn decode_audio_element<'a, S: Sample>(
     let sample_shift_bytes = reader.read_u8(2)?;
     if sample_shift_bytes > 2 {
         return Err(invalid_data(
+            ErrorKind::InvalidChannelHeader,
             "channel sample shift must not be greater than 16",
         ));
     }
@@ -227,7 +971,12 @@ fn decode_audio_element<'a, S: Sample>(
         let num_samples = reader.read_u32(32)?;
 
         if num_samples > this.config.frame_length {
-            return Err(invalid_data("channel contains more samples than expected"));
+            return Err(invalid_data_mismatch(
+                ErrorKind::SampleCountMismatch,
+                "channel contains more samples than expected",
+                this.config.frame_length as u64,
+                num_samples as u64,
+            ));
         }
 
         num_samples as usize
@@ -235,23 +984,24 @@ fn decode_audio_element<'a, S: Sample>(
         this.config.frame_length as usize
     };
 
-    if !is_uncompressed {
-        let (buf_u, buf_v) = this.buf.split_at_mut(this.config.frame_length as usize);
-        let mut mix_buf = [&mut buf_u[..num_samples], &mut buf_v[..num_samples]];
+    let channels = element_output_channels(this, channel_index, element_channels);
 
+    if !is_uncompressed {
+        // Stereo elements need one more bit than the stream's bit depth for the difference
+        // between their channels. The bit depth is at most 32 so this is at most 33.
         let chan_bits =
             this.config.bit_depth as i16 - sample_shift as i16 + element_channels as i16 - 1;
-        if chan_bits > 32 {
-            // unimplemented - could in theory be 33
-            return Err(invalid_data("channel bit depth cannot be greater than 32"));
-        } else if chan_bits < 1 {
-            return Err(invalid_data("channel bit depth must be greater than zero"));
+        if chan_bits < 1 {
+            return Err(invalid_data(
+                ErrorKind::InvalidChannelHeader,
+                "channel bit depth must be greater than zero",
+            ));
         }
         let chan_bits = chan_bits as u8;
 
         // compressed frame, read rest of parameters
         let mix_bits: u8 = reader.read_u8(8)?;
-        let mix_res: i8 = reader.read_u8(8)? as i8;
+        let mix_res = reader.read_i16(8)? as i8;
 
         let mut lpc_mode = [0; 2]; //u8
         let mut lpc_quant = [0; 2]; //u32
@@ -267,20 +1017,22 @@ fn decode_audio_element<'a, S: Sample>(
 
             // Coefficients are used in reverse order of storage for prediction
             for j in (0..lpc_order[i] as usize).rev() {
-                lpc_coefs[i][j] = reader.read_u16(16)? as i16;
+                lpc_coefs[i][j] = reader.read_i16(16)?;
             }
         }
 
-        let extra_bits_reader = if sample_shift != 0 {
-            let extra_bits_reader = reader.clone();
+        // The extra bits precede the rice coded samples so they are skipped and read afterwards.
+        let extra_bits_position = if sample_shift != 0 {
+            let extra_bits_position = reader.position_bits();
             let skip_len = (sample_shift as usize)
                 .checked_mul(num_samples)
                 .and_then(|v| v.checked_mul(element_channels as usize));
             if let Some(skip_len) = skip_len {
                 reader.skip(skip_len)?;
-                Some(extra_bits_reader)
+                Some(extra_bits_position)
             } else {
                 return Err(invalid_data(
+                    ErrorKind::InvalidChannelHeader,
                     "integer overflow when calculating extra_bits length",
                 ));
             }
@@ -288,81 +1040,424 @@ fn decode_audio_element<'a, S: Sample>(
             None
         };
 
-        // TODO: Tidy and comment these steps see below for an example
-        // https://github.com/ruud-v-a/claxon/blob/master/src/subframe.rs
-        // It should be possible to it without allocating buffers quite easily
-        for i in 0..(element_channels as usize) {
-            rice_decompress(
+        let params = ElementParams {
+            element_channels,
+            chan_bits,
+            sample_shift,
+            mix_bits,
+            mix_res,
+            lpc_mode,
+            lpc_quant,
+            pb_factor,
+            lpc_order,
+        };
+        let gains = element_gains(&this.gains, channels);
+        let frame_length = this.config.frame_length as usize;
+        if chan_bits > 32 {
+            let (buf_u, buf_v) = this.wide_buf.split_at_mut(frame_length);
+            let mix_buf = [&mut buf_u[..num_samples], &mut buf_v[..num_samples]];
+            decode_compressed(
+                reader,
+                extra_bits_position,
+                &this.config,
+                &params,
+                &mut lpc_coefs,
+                mix_buf,
+                gains,
+                channels,
+                &mut this.requantizer,
+                out,
+            )?;
+        } else {
+            let (buf_u, buf_v) = this.buf.split_at_mut(frame_length);
+            let mix_buf = [&mut buf_u[..num_samples], &mut buf_v[..num_samples]];
+            decode_compressed(
                 reader,
+                extra_bits_position,
                 &this.config,
-                &mut mix_buf[i],
-                chan_bits,
-                pb_factor[i],
+                &params,
+                &mut lpc_coefs,
+                mix_buf,
+                gains,
+                channels,
+                &mut this.requantizer,
+                out,
             )?;
+        }
+    } else {
+        // uncompressed frame, copy data into the mix buffers to use common output code
+
+        // Here we deviate here from the reference implementation and just copy
+        // straight to the output buffer.
 
-            if lpc_mode[i as usize] == 15 {
-                // the special "numActive == 31" mode can be done in-place
-                lpc_predict_order_31(mix_buf[i], chan_bits);
-            } else if lpc_mode[i as usize] == 0 {
-                if lpc_order[i] == 31 {
-                    return Err(invalid_data("lpc_mode must be 15 if lpc_order is 31"));
+        if sample_shift != 0 {
+            return Err(invalid_data(
+                ErrorKind::InvalidChannelHeader,
+                "sample shift cannot be greater than zero for uncompressed channels",
+            ));
+        }
+
+        let gains = element_gains(&this.gains, channels);
+        let frames = out.chunks_exact_mut(this.config.num_channels as usize);
+        for frame in frames.take(num_samples) {
+            for j in 0..element_channels as usize {
+                let mut sample = reader.read_i32(this.config.bit_depth as usize)?;
+                if let Some(gains) = gains {
+                    sample = apply_gain(sample, gains[j], this.config.bit_depth);
                 }
 
-                let lpc_coefs = &mut lpc_coefs[i][..lpc_order[i] as usize];
-                lpc_predict(mix_buf[i], chan_bits, lpc_coefs, lpc_quant[i])?;
-            } else {
-                return Err(invalid_data("lpc_mode must be 0 or 15"));
+                frame[channels[j]] = this.requantizer.convert(sample, this.config.bit_depth);
             }
         }
+    }
+
+    Ok((num_samples as u32, is_uncompressed))
+}
+
+/// Converts decoded samples to the output sample type, requantizing them if it is narrower than the
+/// stream's bit depth.
+#[derive(Clone)]
+struct Requantizer {
+    requantize: Option<Requantize>,
+    float_scale: FloatScale,
+    // The state of the xorshift generator used for dither.
+    rng: u32,
+}
 
-        if element_channels == 2 && mix_res != 0 {
-            unmix_stereo(&mut mix_buf, mix_bits, mix_res);
+impl Requantizer {
+    fn new(requantize: Option<Requantize>, float_scale: FloatScale) -> Requantizer {
+        Requantizer {
+            requantize,
+            float_scale,
+            rng: 0x9e37_79b9,
         }
+    }
 
-        // now read the shifted values into the shift buffer
-        // We directly apply the shifts to avoid needing a buffer
-        if let Some(mut extra_bits_reader) = extra_bits_reader {
-            append_extra_bits(
-                &mut extra_bits_reader,
-                &mut mix_buf,
-                element_channels,
-                sample_shift,
-            )?;
+    #[inline(always)]
+    fn convert<S: Sample>(&mut self, sample: i32, bit_depth: u8) -> S {
+        if S::bits() >= bit_depth {
+            return S::from_decoder_scaled(sample, bit_depth, self.float_scale);
         }
 
-        for i in 0..num_samples {
-            for j in 0..element_channels as usize {
-                let sample = mix_buf[j][i];
+        let sample = sample as i64;
+        let shift = bit_depth - S::bits();
+        let sample = match self.requantize {
+            Some(Requantize::Dither) => {
+                // The difference of two uniformly distributed values has a triangular
+                // distribution. The shift is at most 16 so both come from a single random word.
+                let rand = self.next_u32();
+                let mask = (1 << shift) - 1;
+                let noise = (rand & mask) as i64 - ((rand >> 16) & mask) as i64;
+                let max = (1 << (S::bits() - 1)) - 1;
+                let rounded = (sample + noise + (1 << (shift - 1))) >> shift;
+                rounded.clamp(-max - 1, max)
+            }
+            _ => sample >> shift,
+        };
+        S::from_decoder_scaled(sample as i32, S::bits(), self.float_scale)
+    }
 
-                let idx = i * this.config.num_channels as usize + channel_index as usize + j;
+    fn next_u32(&mut self) -> u32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        self.rng
+    }
+}
 
-                out[idx] = S::from_decoder(sample, this.config.bit_depth);
-            }
-        }
-    } else {
-        // uncompressed frame, copy data into the mix buffers to use common output code
+/// The parameters of a compressed audio element read from its header.
+struct ElementParams {
+    element_channels: u8,
+    chan_bits: u8,
+    sample_shift: u8,
+    mix_bits: u8,
+    mix_res: i8,
+    lpc_mode: [u8; 2],
+    lpc_quant: [u32; 2],
+    pb_factor: [u16; 2],
+    lpc_order: [u8; 2],
+}
 
-        // Here we deviate here from the reference implementation and just copy
-        // straight to the output buffer.
+/// Decodes the channels of a compressed audio element into `mix_buf` and writes them to `out`.
+#[allow(clippy::too_many_arguments)]
+fn decode_compressed<'a, T: Word, S: Sample>(
+    reader: &mut BitCursor<'a>,
+    extra_bits_position: Option<usize>,
+    config: &StreamInfo,
+    params: &ElementParams,
+    lpc_coefs: &mut [[i16; 32]; 2],
+    mut mix_buf: [&mut [T]; 2],
+    gains: Option<[f64; 2]>,
+    channels: [usize; 2],
+    requantizer: &mut Requantizer,
+    out: &mut [S],
+) -> Result<(), InvalidData> {
+    let element_channels = params.element_channels as usize;
+    let chan_bits = params.chan_bits;
+
+    // TODO: Tidy and comment these steps see below for an example
+    // https://github.com/ruud-v-a/claxon/blob/master/src/subframe.rs
+    // It should be possible to it without allocating buffers quite easily
+    for i in 0..element_channels {
+        rice_decompress(reader, config, mix_buf[i], chan_bits, params.pb_factor[i])?;
+
+        if params.lpc_mode[i] == 15 {
+            // the special "numActive == 31" mode can be done in-place
+            lpc_predict_order_31(mix_buf[i], chan_bits);
+        } else if params.lpc_mode[i] == 0 {
+            if params.lpc_order[i] == 31 {
+                return Err(invalid_data(
+                    ErrorKind::InvalidLpcMode,
+                    "lpc_mode must be 15 if lpc_order is 31",
+                ));
+            }
 
-        if sample_shift != 0 {
+            let lpc_coefs = &mut lpc_coefs[i][..params.lpc_order[i] as usize];
+            lpc_predict(mix_buf[i], chan_bits, lpc_coefs, params.lpc_quant[i])?;
+        } else {
             return Err(invalid_data(
-                "sample shift cannot be greater than zero for uncompressed channels",
+                ErrorKind::InvalidLpcMode,
+                "lpc_mode must be 0 or 15",
             ));
         }
+    }
 
-        for i in 0..num_samples {
-            for j in 0..element_channels as usize {
-                let sample = reader.read_u32(this.config.bit_depth as usize)? as i32;
+    if element_channels == 2 && params.mix_res != 0 {
+        unmix_stereo(&mut mix_buf, params.mix_bits, params.mix_res);
+    }
 
-                let idx = i * this.config.num_channels as usize + channel_index as usize + j;
+    // now read the shifted values into the shift buffer
+    // We directly apply the shifts to avoid needing a buffer
+    if let Some(extra_bits_position) = extra_bits_position {
+        let end = reader.position_bits();
+        reader.seek_bits(extra_bits_position)?;
+        append_extra_bits(
+            reader,
+            &mut mix_buf,
+            params.element_channels,
+            params.sample_shift,
+        )?;
+        reader.seek_bits(end)?;
+    }
 
-                out[idx] = S::from_decoder(sample, this.config.bit_depth);
-            }
+    let mut output_sample = |sample: T, j: usize| {
+        // Unmixed samples fit in the stream's bit depth.
+        let mut sample = sample.to_i32();
+        if let Some(gains) = gains {
+            sample = apply_gain(sample, gains[j], config.bit_depth);
+        }
+        requantizer.convert(sample, config.bit_depth)
+    };
+
+    // Iterating over whole frames lets the bounds checks on the channel indices be hoisted out of
+    // the loops.
+    let frames = out.chunks_exact_mut(config.num_channels as usize);
+    let [ref u, ref v] = mix_buf;
+    if element_channels == 2 {
+        for (frame, (&u, &v)) in frames.zip(u.iter().zip(v.iter())) {
+            frame[channels[0]] = output_sample(u, 0);
+            frame[channels[1]] = output_sample(v, 1);
+        }
+    } else {
+        for (frame, &u) in frames.zip(u.iter()) {
+            frame[channels[0]] = output_sample(u, 0);
         }
     }
 
-    Ok(num_samples as u32)
+    Ok(())
+}
+
+/// An integer type used for intermediate samples while decoding.
+///
+/// `i32` is used for everything except stereo elements of 32 bit streams without a sample shift,
+/// whose channel difference needs 33 bits.
+trait Word:
+    Copy
+    + Default
+    + PartialEq
+    + From<i16>
+    + Mul<Output = Self>
+    + Shl<usize, Output = Self>
+    + Shr<u32, Output = Self>
+    + BitOr<Output = Self>
+{
+    /// Decodes a rice coded value whose least significant bit is its sign.
+    fn from_rice(val: u64) -> Self;
+    fn from_extra_bits(bits: u16) -> Self;
+    fn to_i32(self) -> i32;
+    fn wrapping_add(self, rhs: Self) -> Self;
+    fn wrapping_sub(self, rhs: Self) -> Self;
+    fn wrapping_mul(self, rhs: Self) -> Self;
+    fn wrapping_shr(self, rhs: u32) -> Self;
+    fn signum(self) -> Self;
+    fn sign_extend(self, bits: u8) -> Self;
+    /// Returns `buf` as a slice of `i32` if `Self` is `i32`, allowing the SIMD loops to be used.
+    #[cfg(feature = "simd")]
+    fn as_i32_slice(buf: &mut [Self]) -> Option<&mut [i32]>;
+}
+
+impl Word for i32 {
+    #[inline(always)]
+    fn from_rice(val: u64) -> i32 {
+        ((val >> 1) as i32) ^ -((val & 1) as i32)
+    }
+
+    #[inline(always)]
+    fn from_extra_bits(bits: u16) -> i32 {
+        bits as i32
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self
+    }
+
+    #[inline(always)]
+    fn wrapping_add(self, rhs: i32) -> i32 {
+        i32::wrapping_add(self, rhs)
+    }
+
+    #[inline(always)]
+    fn wrapping_sub(self, rhs: i32) -> i32 {
+        i32::wrapping_sub(self, rhs)
+    }
+
+    #[inline(always)]
+    fn wrapping_mul(self, rhs: i32) -> i32 {
+        i32::wrapping_mul(self, rhs)
+    }
+
+    #[inline(always)]
+    fn wrapping_shr(self, rhs: u32) -> i32 {
+        i32::wrapping_shr(self, rhs)
+    }
+
+    #[inline(always)]
+    fn signum(self) -> i32 {
+        i32::signum(self)
+    }
+
+    #[inline(always)]
+    fn sign_extend(self, bits: u8) -> i32 {
+        sign_extend(self, bits)
+    }
+
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn as_i32_slice(buf: &mut [i32]) -> Option<&mut [i32]> {
+        Some(buf)
+    }
+}
+
+impl Word for i64 {
+    #[inline(always)]
+    fn from_rice(val: u64) -> i64 {
+        ((val >> 1) as i64) ^ -((val & 1) as i64)
+    }
+
+    #[inline(always)]
+    fn from_extra_bits(bits: u16) -> i64 {
+        bits as i64
+    }
+
+    #[inline(always)]
+    fn to_i32(self) -> i32 {
+        self as i32
+    }
+
+    #[inline(always)]
+    fn wrapping_add(self, rhs: i64) -> i64 {
+        i64::wrapping_add(self, rhs)
+    }
+
+    #[inline(always)]
+    fn wrapping_sub(self, rhs: i64) -> i64 {
+        i64::wrapping_sub(self, rhs)
+    }
+
+    #[inline(always)]
+    fn wrapping_mul(self, rhs: i64) -> i64 {
+        i64::wrapping_mul(self, rhs)
+    }
+
+    #[inline(always)]
+    fn wrapping_shr(self, rhs: u32) -> i64 {
+        i64::wrapping_shr(self, rhs)
+    }
+
+    #[inline(always)]
+    fn signum(self) -> i64 {
+        i64::signum(self)
+    }
+
+    #[inline(always)]
+    fn sign_extend(self, bits: u8) -> i64 {
+        debug_assert!(bits <= 64);
+
+        let shift = 64 - bits;
+        (self << shift as usize) >> shift as u32
+    }
+
+    #[cfg(feature = "simd")]
+    #[inline(always)]
+    fn as_i32_slice(_: &mut [i64]) -> Option<&mut [i32]> {
+        None
+    }
+}
+
+/// Returns the output channels of an element's channels. The second channel of a single channel
+/// element repeats the first.
+fn element_output_channels(this: &Decoder, channel_index: u8, element_channels: u8) -> [usize; 2] {
+    let first = this.output_channel(channel_index);
+    if element_channels == 2 {
+        [first, this.output_channel(channel_index + 1)]
+    } else {
+        [first, first]
+    }
+}
+
+fn element_gains(gains: &Option<Box<[f64]>>, channels: [usize; 2]) -> Option<[f64; 2]> {
+    gains
+        .as_ref()
+        .map(|gains| [gains[channels[0]], gains[channels[1]]])
+}
+
+/// Returns the output channel of each channel in element order, or `None` if the stream's
+/// channel layout matches the order of its elements.
+///
+/// ALAC stores channels in elements ordered by the default layout for the channel count, such as
+/// C, L/R, Ls/Rs, LFE for 5.1. Streams can declare a different layout using the same speakers.
+fn channel_order(config: &StreamInfo) -> Option<Box<[u8]>> {
+    let element_layout = ChannelLayout::default_for_channels(config.num_channels)?;
+    let layout = config.channel_layout()?;
+    if layout == element_layout {
+        return None;
+    }
+
+    let element_speakers = element_layout.speakers()?;
+    let speakers = layout.speakers()?;
+    if speakers.len() != element_speakers.len() {
+        return None;
+    }
+    element_speakers
+        .iter()
+        .map(|speaker| speakers.iter().position(|s| s == speaker).map(|i| i as u8))
+        .collect()
+}
+
+/// Applies `gain` to a right-aligned sample, saturating at the limits of `bits`.
+#[inline(always)]
+fn apply_gain(sample: i32, gain: f64, bits: u8) -> i32 {
+    let max = ((1i64 << (bits - 1)) - 1) as f64;
+    let min = -(1i64 << (bits - 1)) as f64;
+    let sample = sample as f64 * gain;
+    let sample = sample.max(min).min(max);
+    // `f64::round` is not available without `std`. Rounds half away from zero in the same way.
+    if sample < 0.0 {
+        (sample - 0.5) as i32
+    } else {
+        (sample + 0.5) as i32
+    }
 }
 
 #[inline]
@@ -371,7 +1466,7 @@ fn decode_rice_symbol<'a>(
     m: u32,
     k: u8,
     bps: u8,
-) -> Result<u32, InvalidData> {
+) -> Result<u64, InvalidData> {
     // Rice coding encodes a symbol S as the product of a quotient Q and a
     // modulus M added to a remainder R. Q is encoded in unary (Q 1s followed
     // by a 0) and R in binary in K bits.
@@ -390,13 +1485,15 @@ fn decode_rice_symbol<'a>(
     // First we need to try to read Q which is encoded in unary and is at most
     // 9. If it is greater than 8 the entire symbol is simply encoded in binary
     // after Q.
-    let mut q = 0;
-    while q != 9 && reader.read_bit()? == true {
-        q += 1;
-    }
+    let q = reader.read_unary(9)?;
 
     if q == 9 {
-        return Ok(reader.read_u32(bps as usize)?);
+        // Escaped values from 33 bit channels are too wide for a single read.
+        if bps > 32 {
+            let high = reader.read_u32(bps as usize - 32)? as u64;
+            return Ok(high << 32 | reader.read_u32(32)? as u64);
+        }
+        return Ok(reader.read_u32(bps as usize)? as u64);
     }
 
     // A modulus of 2^K - 1 is used instead of 2^K. Therefore if K = 1 then
@@ -404,7 +1501,7 @@ fn decode_rice_symbol<'a>(
     // log_2 which cannot be 0). This is presumably an optimisation that aims
     // to store small numbers more efficiently.
     if k == 1 {
-        return Ok(q);
+        return Ok(q as u64);
     }
 
     // Next we read the remainder which is at most K bits. If it is zero it is
@@ -421,13 +1518,13 @@ fn decode_rice_symbol<'a>(
     // M is still at most 2^K - 1 so this cannot overflow.
     let s = q * m + r;
 
-    Ok(s)
+    Ok(s as u64)
 }
 
-fn rice_decompress<'a>(
+fn rice_decompress<'a, T: Word>(
     reader: &mut BitCursor<'a>,
     config: &StreamInfo,
-    buf: &mut [i32],
+    buf: &mut [T],
     bps: u8,
     pb_factor: u16,
 ) -> Result<(), InvalidData> {
@@ -458,14 +1555,14 @@ fn rice_decompress<'a>(
         let val = val + sign_modifier;
         sign_modifier = 0;
         // As lsb sign bit right shift by 1
-        buf[i] = ((val >> 1) as i32) ^ -((val & 1) as i32);
+        buf[i] = T::from_rice(val);
 
         // Update the history value
         if val > 0xffff {
             rice_history = 0xffff;
         } else {
             // Avoid += as that has a tendency to underflow
-            rice_history = (rice_history + val * rice_history_mult)
+            rice_history = (rice_history + val as u32 * rice_history_mult)
                 - ((rice_history * rice_history_mult) >> 9);
         }
 
@@ -473,39 +1570,22 @@ fn rice_decompress<'a>(
         if (rice_history < 128) && (i + 1 < buf.len()) {
             // calculate rice param and decode block size
             let k = rice_history.leading_zeros() - 24 + ((rice_history + 16) >> 6);
-            // The maximum value k above can take is 7. The rice limit seems to always be higher
-            // than this. This is called infrequently enough that the if statement below should
-            // have a minimal effect on performance.
-            if k as u8 > k_max {
-                debug_assert!(
-                    false,
-                    "k ({}) greater than rice limit ({}). Unsure how to continue.",
-                    k, k_max
-                );
-            }
-
-            // Apple version
-            let k = k as u8;
-            let wb_local = (1 << k_max) - 1;
-            let m = ((1 << k) - 1) & wb_local;
-            // FFMPEG version
-            // let k = min(k as u8, k_max);
-            // let mz = ((1 << k) - 1);
-            // End versions
+            // The maximum value k above can take is 7, which is below the rice limit used by
+            // encoders. Smaller rice limits clamp k, as FFmpeg does, so that malformed streams
+            // decode deterministically. Apple's decoder instead masks only the modulus.
+            let k = min(k as u8, k_max);
+            let m = (1 << k) - 1;
 
             let zero_block_len = decode_rice_symbol(reader, m, k, 16)? as usize;
 
             if zero_block_len > 0 {
                 if zero_block_len >= buf.len() - i {
                     return Err(invalid_data(
+                        ErrorKind::SampleCountMismatch,
                         "zero block contains too many samples for channel",
                     ));
                 }
-                // TODO: Use memset equivalent here.
-                let buf = &mut buf[i + 1..];
-                for j in 0..zero_block_len {
-                    buf[j] = 0;
-                }
+                buf[i + 1..i + 1 + zero_block_len].fill(T::default());
                 i += zero_block_len;
             }
             if zero_block_len <= 0xffff {
@@ -527,83 +1607,54 @@ fn sign_extend(val: i32, bits: u8) -> i32 {
     (val << shift) >> shift
 }
 
-fn lpc_predict_order_31(buf: &mut [i32], bps: u8) {
-    debug_assert!(bps <= 32);
+fn lpc_predict_order_31<T: Word>(buf: &mut [T], bps: u8) {
+    debug_assert!(bps <= 33);
 
     // When lpc_order is 31 samples are encoded using differential coding. Samples values are the
     // sum of the previous and the difference between the previous and current sample.
     for i in 1..buf.len() {
-        buf[i] = sign_extend(buf[i].wrapping_add(buf[i - 1]), bps);
+        buf[i] = buf[i].wrapping_add(buf[i - 1]).sign_extend(bps);
     }
 }
 
-fn lpc_predict(
-    buf: &mut [i32],
+fn lpc_predict<T: Word>(
+    buf: &mut [T],
     bps: u8,
     lpc_coefs: &mut [i16],
     lpc_quant: u32,
 ) -> Result<(), InvalidData> {
     // Avoid integer underflow by checking lpc_quant is at least one.
-    if lpc_quant <= 0 {
-        return Err(invalid_data("lpc_quant must be at least one"));
+    if lpc_quant == 0 {
+        return Err(invalid_data(
+            ErrorKind::InvalidChannelHeader,
+            "lpc_quant must be at least one",
+        ));
     }
 
     let lpc_order = lpc_coefs.len();
 
-    debug_assert!(bps <= 32);
+    debug_assert!(bps <= 33);
     debug_assert!(lpc_order < 32);
     debug_assert!(lpc_quant < 16);
 
     // Prediction needs lpc_order + 1 previous decoded samples.
     for i in 1..min(lpc_order + 1, buf.len()) {
-        buf[i] = sign_extend(buf[i].wrapping_add(buf[i - 1]), bps);
-    }
-
-    for i in (lpc_order + 1)..buf.len() {
-        // The (lpc_order - 1)'th predicted sample is used as the mean signal value for this
-        // prediction.
-        let mean = buf[i - lpc_order - 1];
-
-        // The previous lpc_order samples are used to predict this sample.
-        let buf = &mut buf[i - lpc_order..i + 1];
-
-        // Predict the next sample using linear predictive coding.
-        let mut predicted: i32 = 0;
-        for (x, coef) in buf.iter().zip(lpc_coefs.iter()) {
-            let term = x.wrapping_sub(mean).wrapping_mul(*coef as i32);
-            predicted = predicted.wrapping_add(term);
-        }
-
-        // Round up to and then truncate by lpc_quant bits.
-        // 1 << (lpc_quant - 1) sets the (lpc_quant - 1)'th bit.
-        let predicted = (predicted.wrapping_add(1 << (lpc_quant - 1))) >> lpc_quant;
-
-        // Store the sample for output and to be used in the next prediction.
-        let prediction_error = buf[lpc_order];
-        let sample = predicted.wrapping_add(mean).wrapping_add(prediction_error);
-        buf[lpc_order] = sign_extend(sample, bps);
-
-        if prediction_error != 0 {
-            // The prediction was not exact so adjust LPC coefficients to try to reduce the size
-            // of the next prediction error. Add or subtract 1 from each coefficient until the
-            // sign of error has changed or we run out of coefficients to adjust.
-            let error_sign = prediction_error.signum();
-
-            // This implementation always uses a positive prediction error.
-            let mut prediction_error = error_sign * prediction_error;
-
-            for j in 0..lpc_order {
-                let predicted = buf[j].wrapping_sub(mean);
-                let sign = predicted.signum() * error_sign;
-                lpc_coefs[j] = lpc_coefs[j].wrapping_add(sign as i16);
-                // Update the prediction error now we have changed a coefficient.
-                let error_update =
-                    error_sign * (predicted * sign >> lpc_quant).wrapping_mul(j as i32 + 1);
-                prediction_error = prediction_error.wrapping_sub(error_update);
-                // Stop updating coefficients if the prediction error changes sign.
-                if prediction_error <= 0 {
-                    break;
-                }
+        buf[i] = buf[i].wrapping_add(buf[i - 1]).sign_extend(bps);
+    }
+
+    // Apple's encoder almost always uses an order of 4 or 8 so these have their own kernels.
+    match lpc_order {
+        4 => lpc_predict_fixed::<T, 4>(buf, bps, lpc_coefs, lpc_quant),
+        8 => lpc_predict_fixed::<T, 8>(buf, bps, lpc_coefs, lpc_quant),
+        _ => {
+            for i in (lpc_order + 1)..buf.len() {
+                // The (lpc_order - 1)'th predicted sample is used as the mean signal value for
+                // this prediction.
+                let mean = buf[i - lpc_order - 1];
+
+                // The previous lpc_order samples are used to predict this sample.
+                let (history, rest) = buf[i - lpc_order..].split_at_mut(lpc_order);
+                rest[0] = lpc_predict_sample(history, mean, rest[0], lpc_coefs, bps, lpc_quant);
             }
         }
     }
@@ -611,17 +1662,109 @@ fn lpc_predict(
     Ok(())
 }
 
-fn unmix_stereo(buf: &mut [&mut [i32]; 2], mix_bits: u8, mix_res: i8) {
+/// Runs the prediction loop of `lpc_predict` for a fixed order, which allows the compiler to
+/// unroll the loops over the previous samples and coefficients.
+fn lpc_predict_fixed<T: Word, const ORDER: usize>(
+    buf: &mut [T],
+    bps: u8,
+    lpc_coefs: &mut [i16],
+    lpc_quant: u32,
+) {
+    let mut coefs = [0; ORDER];
+    coefs.copy_from_slice(lpc_coefs);
+
+    for i in (ORDER + 1)..buf.len() {
+        let mean = buf[i - ORDER - 1];
+        let mut history = [T::default(); ORDER];
+        history.copy_from_slice(&buf[i - ORDER..i]);
+        buf[i] = lpc_predict_sample(&history, mean, buf[i], &mut coefs, bps, lpc_quant);
+    }
+
+    lpc_coefs.copy_from_slice(&coefs);
+}
+
+/// Predicts a sample from the previous `lpc_coefs.len()` samples in `history` and returns it
+/// corrected by `prediction_error`, adapting `lpc_coefs` if the prediction was not exact.
+#[inline(always)]
+fn lpc_predict_sample<T: Word>(
+    history: &[T],
+    mean: T,
+    prediction_error: T,
+    lpc_coefs: &mut [i16],
+    bps: u8,
+    lpc_quant: u32,
+) -> T {
+    let zero = T::default();
+
+    // Predict the next sample using linear predictive coding.
+    let mut predicted = zero;
+    for (x, coef) in history.iter().zip(lpc_coefs.iter()) {
+        let term = x.wrapping_sub(mean).wrapping_mul(T::from(*coef));
+        predicted = predicted.wrapping_add(term);
+    }
+
+    // Round up to and then truncate by lpc_quant bits.
+    // 1 << (lpc_quant - 1) sets the (lpc_quant - 1)'th bit.
+    let round = T::from(1) << (lpc_quant as usize - 1);
+    let predicted = predicted.wrapping_add(round) >> lpc_quant;
+
+    // The sample is returned for output and to be used in the next prediction.
+    let sample = predicted.wrapping_add(mean).wrapping_add(prediction_error);
+
+    if prediction_error != zero {
+        // The prediction was not exact so adjust LPC coefficients to try to reduce the size
+        // of the next prediction error. Add or subtract 1 from each coefficient until the
+        // sign of error has changed or we run out of coefficients to adjust.
+        let error_sign = prediction_error.signum();
+
+        // This implementation always uses a positive prediction error.
+        let mut prediction_error = error_sign * prediction_error;
+
+        for (j, (x, coef)) in history.iter().zip(lpc_coefs.iter_mut()).enumerate() {
+            let predicted = x.wrapping_sub(mean);
+            let sign = predicted.signum() * error_sign;
+            // `sign` is -1, 0 or 1.
+            *coef = coef.wrapping_add(sign.to_i32() as i16);
+            // Update the prediction error now we have changed a coefficient.
+            let error_update =
+                error_sign * ((predicted * sign) >> lpc_quant).wrapping_mul(T::from(j as i16 + 1));
+            prediction_error = prediction_error.wrapping_sub(error_update);
+            // Stop updating coefficients if the prediction error changes sign.
+            if prediction_error.signum() != T::from(1) {
+                break;
+            }
+        }
+    }
+
+    sample.sign_extend(bps)
+}
+
+#[allow(clippy::needless_range_loop)]
+fn unmix_stereo<T: Word>(buf: &mut [&mut [T]; 2], mix_bits: u8, mix_res: i8) {
     debug_assert_eq!(buf[0].len(), buf[1].len());
 
     let num_samples = min(buf[0].len(), buf[1].len());
 
-    for i in 0..num_samples {
+    #[cfg(feature = "simd")]
+    let start = {
+        let [ref mut u, ref mut v] = *buf;
+        match (T::as_i32_slice(u), T::as_i32_slice(v)) {
+            (Some(u), Some(v)) => simd::unmix_stereo(u, v, mix_bits, mix_res),
+            _ => 0,
+        }
+    };
+    #[cfg(not(feature = "simd"))]
+    let start = 0;
+
+    for i in start..num_samples {
         let u = buf[0][i];
         let v = buf[1][i];
 
         // `mix_bits` can be 0..255 and is never further validated in the reference decoder.
-        let r = u.wrapping_sub(v.wrapping_mul(mix_res as i32).wrapping_shr(mix_bits as u32));
+        let r = u.wrapping_sub(
+            v.wrapping_mul(T::from(mix_res as i16))
+                .wrapping_shr(mix_bits as u32),
+        );
         let l = r.wrapping_add(v);
 
         buf[0][i] = l;
@@ -629,9 +1772,10 @@ fn unmix_stereo(buf: &mut [&mut [i32]; 2], mix_bits: u8, mix_res: i8) {
     }
 }
 
-fn append_extra_bits<'a>(
+#[allow(clippy::needless_range_loop)]
+fn append_extra_bits<'a, T: Word>(
     reader: &mut BitCursor<'a>,
-    buf: &mut [&mut [i32]; 2],
+    buf: &mut [&mut [T]; 2],
     channels: u8,
     sample_shift: u8,
 ) -> Result<(), InvalidData> {
@@ -640,14 +1784,42 @@ fn append_extra_bits<'a>(
 
     let channels = min(channels as usize, buf.len());
     let num_samples = min(buf[0].len(), buf[1].len());
-    let sample_shift = sample_shift as usize;
 
-    for i in 0..num_samples {
+    // The extra bits of each channel are interleaved so they are read a block at a time before
+    // being appended to the samples of each channel.
+    let mut extra_bits = [[0; EXTRA_BITS_BLOCK_LEN]; 2];
+    let mut start = 0;
+    while start < num_samples {
+        let len = min(num_samples - start, EXTRA_BITS_BLOCK_LEN);
+        for i in 0..len {
+            for j in 0..channels {
+                extra_bits[j][i] = reader.read_u16(sample_shift as usize)?;
+            }
+        }
+
         for j in 0..channels {
-            let extra_bits = reader.read_u16(sample_shift)? as i32;
-            buf[j][i] = (buf[j][i] << sample_shift) | extra_bits as i32;
+            let samples = &mut buf[j][start..start + len];
+            shift_in_extra_bits(samples, &extra_bits[j][..len], sample_shift);
         }
+        start += len;
     }
 
     Ok(())
 }
+
+/// The number of samples per channel whose extra bits are read at once by `append_extra_bits`.
+const EXTRA_BITS_BLOCK_LEN: usize = 64;
+
+fn shift_in_extra_bits<T: Word>(buf: &mut [T], extra_bits: &[u16], sample_shift: u8) {
+    #[cfg(feature = "simd")]
+    let start = match T::as_i32_slice(buf) {
+        Some(buf) => simd::append_extra_bits(buf, extra_bits, sample_shift),
+        None => 0,
+    };
+    #[cfg(not(feature = "simd"))]
+    let start = 0;
+
+    for (sample, &bits) in buf[start..].iter_mut().zip(&extra_bits[start..]) {
+        *sample = (*sample << sample_shift as usize) | T::from_extra_bits(bits);
+    }
+}
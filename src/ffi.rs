@@ -0,0 +1,201 @@
+//! A C interface to the decoder.
+//!
+//! The C header is `include/alac.h`, generated with `cbindgen` using `cbindgen.toml`. A static or
+//! shared library can be built with
+//! `cargo rustc --release --features ffi --crate-type staticlib` (or `cdylib`).
+//!
+//! Functions never unwind into C. Invalid arguments, including output buffers that are too short,
+//! are reported as errors, and a panic, which would be a bug in the decoder, is caught and reported
+//! as `ALAC_ERROR_PANIC`.
+
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+
+use {DecodeError, Decoder, Sample, StreamInfo};
+
+/// The function succeeded.
+pub const ALAC_OK: i32 = 0;
+/// A pointer was null or a buffer was too short.
+pub const ALAC_ERROR_INVALID_ARGUMENT: i32 = -1;
+/// The magic cookie or packet is invalid.
+pub const ALAC_ERROR_INVALID_DATA: i32 = -2;
+/// The decoder panicked.
+pub const ALAC_ERROR_PANIC: i32 = -3;
+
+/// Codec parameters of an ALAC stream.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AlacStreamInfo {
+    pub frame_length: u32,
+    pub bit_depth: u8,
+    pub channels: u8,
+    pub sample_rate: u32,
+    /// The minimum length of the buffer passed to the `alac_decode_packet` functions.
+    pub max_samples_per_packet: u32,
+}
+
+impl From<&StreamInfo> for AlacStreamInfo {
+    fn from(info: &StreamInfo) -> AlacStreamInfo {
+        AlacStreamInfo {
+            frame_length: info.max_frames_per_packet(),
+            bit_depth: info.bit_depth(),
+            channels: info.channels(),
+            sample_rate: info.sample_rate(),
+            max_samples_per_packet: info.max_samples_per_packet(),
+        }
+    }
+}
+
+/// Calls `f`, returning `on_panic` rather than unwinding if it panics.
+fn catch_panic<T, F: FnOnce() -> T>(on_panic: T, f: F) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(on_panic)
+}
+
+unsafe fn cookie_stream_info(cookie: *const u8, cookie_len: usize) -> Option<StreamInfo> {
+    if cookie.is_null() {
+        return None;
+    }
+    StreamInfo::from_cookie(slice::from_raw_parts(cookie, cookie_len)).ok()
+}
+
+/// Parses a magic cookie into `out`.
+///
+/// Returns `ALAC_OK` on success.
+///
+/// # Safety
+///
+/// `cookie` must point to `cookie_len` readable bytes and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn alac_stream_info_from_cookie(
+    cookie: *const u8,
+    cookie_len: usize,
+    out: *mut AlacStreamInfo,
+) -> i32 {
+    if out.is_null() {
+        return ALAC_ERROR_INVALID_ARGUMENT;
+    }
+    catch_panic(ALAC_ERROR_PANIC, || {
+        match cookie_stream_info(cookie, cookie_len) {
+            Some(info) => {
+                ptr::write(out, AlacStreamInfo::from(&info));
+                ALAC_OK
+            }
+            None if cookie.is_null() => ALAC_ERROR_INVALID_ARGUMENT,
+            None => ALAC_ERROR_INVALID_DATA,
+        }
+    })
+}
+
+/// Creates a decoder from a magic cookie.
+///
+/// Returns null if the cookie is invalid or the decoder panicked. The decoder must be freed with
+/// `alac_decoder_free`.
+///
+/// # Safety
+///
+/// `cookie` must point to `cookie_len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn alac_decoder_new(cookie: *const u8, cookie_len: usize) -> *mut Decoder {
+    catch_panic(ptr::null_mut(), || {
+        match cookie_stream_info(cookie, cookie_len) {
+            Some(info) => Box::into_raw(Box::new(Decoder::new(info))),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+/// Writes the parameters of the stream being decoded to `out`.
+///
+/// Returns `ALAC_OK` on success.
+///
+/// # Safety
+///
+/// `decoder` must have been returned by `alac_decoder_new` and not freed, and `out` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn alac_decoder_stream_info(
+    decoder: *const Decoder,
+    out: *mut AlacStreamInfo,
+) -> i32 {
+    if decoder.is_null() || out.is_null() {
+        return ALAC_ERROR_INVALID_ARGUMENT;
+    }
+    ptr::write(out, AlacStreamInfo::from((*decoder).stream_info()));
+    ALAC_OK
+}
+
+unsafe fn decode_packet<S: Sample>(
+    decoder: *mut Decoder,
+    packet: *const u8,
+    packet_len: usize,
+    out: *mut S,
+    out_len: usize,
+) -> isize {
+    if decoder.is_null() || packet.is_null() || out.is_null() {
+        return ALAC_ERROR_INVALID_ARGUMENT as isize;
+    }
+    let packet = slice::from_raw_parts(packet, packet_len);
+    let out = slice::from_raw_parts_mut(out, out_len);
+    catch_panic(ALAC_ERROR_PANIC as isize, || {
+        match (*decoder).try_decode_packet(packet, out) {
+            Ok(samples) => samples.len() as isize,
+            Err(DecodeError::InvalidData(_)) => ALAC_ERROR_INVALID_DATA as isize,
+            Err(_) => ALAC_ERROR_INVALID_ARGUMENT as isize,
+        }
+    })
+}
+
+/// Decodes a packet into `out` as interleaved 32 bit samples, left-aligned so that the most
+/// significant bit of each sample is bit 31.
+///
+/// `out_len` must be at least `max_samples_per_packet`. Returns the number of samples written, or
+/// a negative error code.
+///
+/// # Safety
+///
+/// `decoder` must have been returned by `alac_decoder_new` and not freed, `packet` must point to
+/// `packet_len` readable bytes and `out` must point to `out_len` writable samples.
+#[no_mangle]
+pub unsafe extern "C" fn alac_decode_packet(
+    decoder: *mut Decoder,
+    packet: *const u8,
+    packet_len: usize,
+    out: *mut i32,
+    out_len: usize,
+) -> isize {
+    decode_packet(decoder, packet, packet_len, out, out_len)
+}
+
+/// Decodes a packet from a stream with a bit depth of 16 into `out` as interleaved 16 bit
+/// samples.
+///
+/// `out_len` must be at least `max_samples_per_packet`. Returns the number of samples written, or
+/// a negative error code.
+///
+/// # Safety
+///
+/// `decoder` must have been returned by `alac_decoder_new` and not freed, `packet` must point to
+/// `packet_len` readable bytes and `out` must point to `out_len` writable samples.
+#[no_mangle]
+pub unsafe extern "C" fn alac_decode_packet_s16(
+    decoder: *mut Decoder,
+    packet: *const u8,
+    packet_len: usize,
+    out: *mut i16,
+    out_len: usize,
+) -> isize {
+    decode_packet(decoder, packet, packet_len, out, out_len)
+}
+
+/// Frees a decoder. Passing null does nothing.
+///
+/// # Safety
+///
+/// `decoder` must be null or have been returned by `alac_decoder_new` and not already freed.
+#[no_mangle]
+pub unsafe extern "C" fn alac_decoder_free(decoder: *mut Decoder) {
+    if !decoder.is_null() {
+        drop(Box::from_raw(decoder));
+    }
+}
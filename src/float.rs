@@ -0,0 +1,77 @@
+//! Conversion of decoded samples to floating point.
+//!
+//! There are two common conventions for mapping integer samples to floating point values and
+//! different DSP libraries disagree on which to use. The difference is at most one LSB but is
+//! enough to break bit-exact comparisons, so the convention is chosen explicitly with `FloatScale`.
+//!
+//! Decoding directly to `f32` or `f64` samples uses the convention set with
+//! `DecodeOptions::scale_floats`, which defaults to `FloatScale::FullScale`. Samples decoded to
+//! `i32` can also be converted with `FloatScale`.
+
+/// How integer samples are mapped to floating point values.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FloatScale {
+    /// Samples are divided by `2^(bits - 1)`.
+    ///
+    /// The most negative sample maps to `-1.0` and the most positive sample to just below `1.0`.
+    /// This is the convention used by most audio APIs.
+    #[default]
+    FullScale,
+    /// Samples are divided by `2^(bits - 1) - 1`.
+    ///
+    /// The most positive sample maps to `1.0` and the most negative sample to just below `-1.0`.
+    UnityAtMax,
+}
+
+impl FloatScale {
+    /// Returns the value right-aligned samples of bit depth `bits` are divided by.
+    pub fn divisor(self, bits: u8) -> f64 {
+        let full_scale = (1u64 << (bits - 1)) as f64;
+        match self {
+            FloatScale::FullScale => full_scale,
+            FloatScale::UnityAtMax => full_scale - 1.0,
+        }
+    }
+
+    /// Converts left-aligned samples, as produced by `Sample for i32`, from a stream of bit depth
+    /// `bits` to `f32`.
+    ///
+    /// Panics if `out` is shorter than `samples`.
+    pub fn convert_f32(self, samples: &[i32], bits: u8, out: &mut [f32]) {
+        let scale = 1.0 / self.divisor(bits);
+        let shift = 32 - bits as u32;
+        for (out, &sample) in out[..samples.len()].iter_mut().zip(samples) {
+            *out = ((sample >> shift) as f64 * scale) as f32;
+        }
+    }
+
+    /// Converts left-aligned samples, as produced by `Sample for i32`, from a stream of bit depth
+    /// `bits` to `f64`.
+    ///
+    /// Panics if `out` is shorter than `samples`.
+    pub fn convert_f64(self, samples: &[i32], bits: u8, out: &mut [f64]) {
+        let scale = 1.0 / self.divisor(bits);
+        let shift = 32 - bits as u32;
+        for (out, &sample) in out[..samples.len()].iter_mut().zip(samples) {
+            *out = (sample >> shift) as f64 * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FloatScale;
+
+    #[test]
+    fn scales() {
+        let samples = [i16::MIN as i32, 0, i16::MAX as i32].map(|s| s << 16);
+
+        let mut out = [0.0f64; 3];
+        FloatScale::FullScale.convert_f64(&samples, 16, &mut out);
+        assert_eq!(out, [-1.0, 0.0, 32767.0 / 32768.0]);
+
+        let mut out = [0.0f32; 3];
+        FloatScale::UnityAtMax.convert_f32(&samples, 16, &mut out);
+        assert_eq!(out, [-32768.0 / 32767.0, 0.0, 1.0]);
+    }
+}
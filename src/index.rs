@@ -0,0 +1,404 @@
+use std::cmp;
+use std::io::{Read, Seek, SeekFrom};
+
+use reader::max_packet_len;
+use {invalid_data, ErrorKind, Format, GaplessInfo, InvalidData, ReadError, StreamInfo};
+
+const MAGIC: &[u8; 8] = b"ALACIDX1";
+
+/// The location of every packet in an ALAC file along with its magic cookie.
+///
+/// Opening a file requires parsing its container's sample tables which can be slow for very large
+/// files or remote sources. A `PacketIndex` can be obtained from an open `Reader` with
+/// `Reader::packet_index`, serialized with `to_bytes` and later used to construct a `Reader` for
+/// the same file without parsing the container with `Reader::from_packet_index`.
+///
+/// Packet `n` starts at frame `n * StreamInfo::max_frames_per_packet()`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PacketIndex {
+    format: Format,
+    magic_cookie: Vec<u8>,
+    total_frames: Option<u64>,
+    gapless_info: Option<GaplessInfo>,
+    packets: Vec<PacketEntry>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct PacketEntry {
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl PacketIndex {
+    pub(crate) fn new(
+        format: Format,
+        magic_cookie: Vec<u8>,
+        total_frames: Option<u64>,
+        gapless_info: Option<GaplessInfo>,
+        packets: Vec<PacketEntry>,
+    ) -> Self {
+        PacketIndex {
+            format,
+            magic_cookie,
+            total_frames,
+            gapless_info,
+            packets,
+        }
+    }
+
+    /// Returns the format of the indexed file.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns the number of packets in the index.
+    pub fn packet_count(&self) -> u64 {
+        self.packets.len() as u64
+    }
+
+    /// Returns the byte offset and length of a packet in the indexed file.
+    pub fn packet(&self, packet: u64) -> Option<(u64, u64)> {
+        let entry = self.packets.get(packet as usize)?;
+        Some((entry.offset, entry.len))
+    }
+
+    /// Serializes the index to a compact binary form.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(32 + self.magic_cookie.len() + self.packets.len() * 3);
+        buf.extend_from_slice(MAGIC);
+        buf.push(format_id(self.format));
+        write_varint(&mut buf, self.magic_cookie.len() as u64);
+        buf.extend_from_slice(&self.magic_cookie);
+        // Zero means the total is unknown.
+        write_varint(&mut buf, self.total_frames.map_or(0, |frames| frames + 1));
+        match self.gapless_info {
+            Some(info) => {
+                write_varint(&mut buf, info.priming_frames() + 1);
+                write_varint(&mut buf, info.valid_frames());
+            }
+            None => write_varint(&mut buf, 0),
+        }
+        write_varint(&mut buf, self.packets.len() as u64);
+
+        // Packets are usually contiguous so each offset is stored as the signed distance from the
+        // end of the previous packet, which is almost always zero.
+        let mut next_offset = 0u64;
+        for entry in &self.packets {
+            let gap = entry.offset.wrapping_sub(next_offset) as i64;
+            write_varint(&mut buf, ((gap << 1) ^ (gap >> 63)) as u64);
+            write_varint(&mut buf, entry.len);
+            next_offset = entry.offset.wrapping_add(entry.len);
+        }
+        buf
+    }
+
+    /// Deserializes an index produced by `to_bytes`.
+    ///
+    /// An error is returned if the magic cookie is invalid or a packet is longer than any packet
+    /// of the stream it describes can be.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<PacketIndex, InvalidData> {
+        if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(invalid_data(
+                ErrorKind::InvalidContainer,
+                "not a packet index",
+            ));
+        }
+        let format = format_from_id(bytes[MAGIC.len()])?;
+        bytes = &bytes[MAGIC.len() + 1..];
+
+        let cookie_len = read_varint(&mut bytes)?;
+        if cookie_len > bytes.len() as u64 {
+            return Err(invalid_data(
+                ErrorKind::InvalidContainer,
+                "packet index is truncated",
+            ));
+        }
+        let (magic_cookie, mut bytes) = bytes.split_at(cookie_len as usize);
+        let max_len = max_packet_len(&StreamInfo::from_cookie(magic_cookie)?) as u64;
+        let total_frames = read_varint(&mut bytes)?.checked_sub(1);
+        let gapless_info = match read_varint(&mut bytes)?.checked_sub(1) {
+            Some(priming_frames) => Some(GaplessInfo::new(priming_frames, read_varint(&mut bytes)?)),
+            None => None,
+        };
+
+        // Each packet takes at least two bytes which bounds the allocation below.
+        let packet_count = read_varint(&mut bytes)?;
+        if packet_count > bytes.len() as u64 / 2 {
+            return Err(invalid_data(
+                ErrorKind::InvalidContainer,
+                "packet index is truncated",
+            ));
+        }
+
+        let mut packets = Vec::with_capacity(packet_count as usize);
+        let mut next_offset = 0u64;
+        for _ in 0..packet_count {
+            let gap = read_varint(&mut bytes)?;
+            let gap = ((gap >> 1) as i64) ^ -((gap & 1) as i64);
+            let len = read_varint(&mut bytes)?;
+            if len > max_len {
+                return Err(invalid_data(
+                    ErrorKind::InvalidContainer,
+                    "packet index contains a packet longer than the stream allows",
+                ));
+            }
+            let offset = next_offset.wrapping_add(gap as u64);
+            packets.push(PacketEntry { offset, len });
+            next_offset = offset.wrapping_add(len);
+        }
+
+        if !bytes.is_empty() {
+            return Err(invalid_data(
+                ErrorKind::InvalidContainer,
+                "packet index contains trailing data",
+            ));
+        }
+
+        Ok(PacketIndex {
+            format,
+            magic_cookie: magic_cookie.to_vec(),
+            total_frames,
+            gapless_info,
+            packets,
+        })
+    }
+
+    pub(crate) fn magic_cookie(&self) -> &[u8] {
+        &self.magic_cookie
+    }
+
+    pub(crate) fn total_frames(&self) -> Option<u64> {
+        self.total_frames
+    }
+
+    pub(crate) fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.gapless_info
+    }
+}
+
+fn format_id(format: Format) -> u8 {
+    match format {
+        #[cfg(feature = "caf")]
+        Format::Caf => 1,
+        #[cfg(feature = "mp4")]
+        Format::Mp4 => 2,
+        // Raw streams and packet sources cannot be indexed.
+        Format::Raw | Format::Custom => 0,
+    }
+}
+
+fn format_from_id(id: u8) -> Result<Format, InvalidData> {
+    match id {
+        #[cfg(feature = "caf")]
+        1 => Ok(Format::Caf),
+        #[cfg(feature = "mp4")]
+        2 => Ok(Format::Mp4),
+        _ => Err(invalid_data(
+            ErrorKind::InvalidContainer,
+            "packet index format is not supported",
+        )),
+    }
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, InvalidData> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or(invalid_data(
+            ErrorKind::InvalidContainer,
+            "packet index is truncated",
+        ))?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data(
+        ErrorKind::InvalidContainer,
+        "packet index contains an invalid integer",
+    ))
+}
+
+/// Reads packets from the locations given in a `PacketIndex`.
+pub struct IndexedPacketReader<R> {
+    reader: R,
+    index: PacketIndex,
+    packet_idx: usize,
+    // The position of `reader`, or `None` if it is unknown after a failed read.
+    position: Option<u64>,
+    // Packets longer than this are rejected rather than read into memory.
+    packet_len_limit: u64,
+}
+
+impl<R: Read + Seek> IndexedPacketReader<R> {
+    pub fn new(reader: R, index: PacketIndex) -> IndexedPacketReader<R> {
+        IndexedPacketReader {
+            reader,
+            index,
+            packet_idx: 0,
+            position: None,
+            packet_len_limit: u64::MAX,
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        self.index.format
+    }
+
+    pub fn packet_entries(&self) -> Vec<PacketEntry> {
+        self.index.packets.clone()
+    }
+
+    pub fn packet_count(&self) -> u64 {
+        self.index.packet_count()
+    }
+
+    pub fn total_frames(&self) -> Option<u64> {
+        self.index.total_frames()
+    }
+
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.index.gapless_info()
+    }
+
+    pub fn max_packet_len(&self) -> Option<u64> {
+        self.index.packets.iter().map(|entry| entry.len).max()
+    }
+
+    pub fn packet_index(&self) -> u64 {
+        self.packet_idx as u64
+    }
+
+    pub fn seek_to_packet(&mut self, packet: u64) {
+        self.packet_idx = cmp::min(packet, self.index.packet_count()) as usize;
+    }
+
+    /// Returns the stream position following the packet most recently read, if it is known.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Sets the length of the longest packet that will be read.
+    pub fn set_packet_len_limit(&mut self, limit: u64) {
+        self.packet_len_limit = limit;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        let entry = match self.index.packets.get(self.packet_idx) {
+            Some(&entry) => entry,
+            None => {
+                buf.clear();
+                return Ok(());
+            }
+        };
+
+        if entry.len > self.packet_len_limit {
+            return Err(ReadError::Format(
+                self.index.format,
+                invalid_data(
+                    ErrorKind::InvalidContainer,
+                    "packet is longer than the stream allows",
+                ),
+            ));
+        }
+
+        // The packet index is only advanced once the whole packet has been read so a failed read
+        // can be retried. The stream position is unknown until the read succeeds.
+        if self.position.take() != Some(entry.offset) {
+            self.reader.seek(SeekFrom::Start(entry.offset))?;
+        }
+        buf.resize(entry.len as usize, 0);
+        self.reader.read_exact(&mut buf[..])?;
+
+        self.packet_idx += 1;
+        self.position = Some(entry.offset + entry.len);
+        Ok(())
+    }
+
+    /// Moves past the next packet without reading it, returning its location in the file or
+    /// `None` if there are no more packets.
+    #[cfg_attr(not(feature = "mmap"), allow(dead_code))]
+    pub fn skip_packet(&mut self) -> Option<PacketEntry> {
+        let entry = *self.index.packets.get(self.packet_idx)?;
+        self.packet_idx += 1;
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PacketEntry, PacketIndex};
+    use {ErrorKind, Format, GaplessInfo};
+
+    #[cfg(feature = "mp4")]
+    fn format() -> Format {
+        Format::Mp4
+    }
+
+    #[cfg(not(feature = "mp4"))]
+    fn format() -> Format {
+        Format::Caf
+    }
+
+    #[test]
+    fn round_trip() {
+        let packets = vec![
+            PacketEntry {
+                offset: 4092,
+                len: 8581,
+            },
+            PacketEntry {
+                offset: 12673,
+                len: 300,
+            },
+            PacketEntry {
+                offset: 1 << 40,
+                len: 16_000,
+            },
+            PacketEntry { offset: 64, len: 0 },
+        ];
+        let gapless_info = Some(GaplessInfo::new(2112, 1 << 20));
+        let cookie = include_bytes!("../tests/data/magic_cookie.bin").to_vec();
+        let index = PacketIndex::new(format(), cookie, Some(1 << 20), gapless_info, packets);
+
+        let bytes = index.to_bytes();
+        assert_eq!(PacketIndex::from_bytes(&bytes).unwrap(), index);
+
+        for len in 0..bytes.len() {
+            assert!(PacketIndex::from_bytes(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_packets() {
+        let cookie = include_bytes!("../tests/data/magic_cookie.bin").to_vec();
+        let packets = vec![PacketEntry {
+            offset: 4092,
+            len: 1 << 40,
+        }];
+        let index = PacketIndex::new(format(), cookie, None, None, packets);
+        let err = PacketIndex::from_bytes(&index.to_bytes()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidContainer);
+
+        // The packet length limit depends on the stream so the magic cookie must be valid.
+        let index = PacketIndex::new(format(), vec![1, 2, 3], None, None, Vec::new());
+        let err = PacketIndex::from_bytes(&index.to_bytes()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidStreamInfo);
+    }
+}
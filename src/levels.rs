@@ -0,0 +1,166 @@
+//! Per-channel peak and RMS level measurement.
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use std::io::{Read, Seek};
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use {ReadError, Samples};
+
+const FULL_SCALE: f64 = 2_147_483_648.0;
+
+/// The peak and RMS level of a single channel.
+///
+/// Levels are relative to full scale, i.e. a full scale square wave has a peak and RMS of `1.0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelLevels {
+    peak: f64,
+    rms: f64,
+}
+
+impl ChannelLevels {
+    /// Returns the absolute peak sample value relative to full scale.
+    pub fn peak(&self) -> f64 {
+        self.peak
+    }
+
+    /// Returns the root mean square sample value relative to full scale.
+    pub fn rms(&self) -> f64 {
+        self.rms
+    }
+
+    /// Returns the peak level in dBFS.
+    pub fn peak_dbfs(&self) -> f64 {
+        20.0 * self.peak.log10()
+    }
+
+    /// Returns the RMS level in dBFS.
+    pub fn rms_dbfs(&self) -> f64 {
+        20.0 * self.rms.log10()
+    }
+}
+
+/// Accumulates per-channel peak and RMS levels over a stream of samples.
+#[derive(Clone, Debug)]
+pub struct LevelMeter {
+    peaks: Vec<u32>,
+    sums: Vec<f64>,
+    frames: u64,
+    channel_pos: usize,
+}
+
+impl LevelMeter {
+    /// Creates a `LevelMeter` for a stream with `channels` interleaved channels.
+    pub fn new(channels: u8) -> LevelMeter {
+        LevelMeter {
+            peaks: vec![0; channels as usize],
+            sums: vec![0.0; channels as usize],
+            frames: 0,
+            channel_pos: 0,
+        }
+    }
+
+    /// Adds interleaved samples to the measurement.
+    ///
+    /// Samples must be left-aligned as produced by `Sample for i32`. Samples do not need to contain
+    /// whole frames.
+    pub fn add_samples(&mut self, samples: &[i32]) {
+        for &sample in samples {
+            self.add_sample(sample);
+        }
+    }
+
+    #[inline]
+    fn add_sample(&mut self, sample: i32) {
+        if self.peaks.is_empty() {
+            return;
+        }
+
+        let i = self.channel_pos;
+        self.peaks[i] = self.peaks[i].max(sample.unsigned_abs());
+        let x = sample as f64 / FULL_SCALE;
+        self.sums[i] += x * x;
+
+        self.channel_pos += 1;
+        if self.channel_pos == self.peaks.len() {
+            self.channel_pos = 0;
+            self.frames += 1;
+        }
+    }
+
+    /// Returns the number of whole frames measured.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the levels of each channel measured so far.
+    pub fn levels(&self) -> Vec<ChannelLevels> {
+        let frames = self.frames.max(1) as f64;
+        self.peaks
+            .iter()
+            .zip(&self.sums)
+            .map(|(&peak, &sum)| ChannelLevels {
+                peak: peak as f64 / FULL_SCALE,
+                rms: (sum / frames).sqrt(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+impl<R: Read + Seek> Samples<R, i32> {
+    /// Returns an iterator that measures the level of each channel as samples are yielded.
+    pub fn with_level_meter(self) -> MeteredSamples<R> {
+        let meter = LevelMeter::new(self.stream_info().channels());
+        MeteredSamples {
+            samples: self,
+            meter,
+        }
+    }
+}
+
+/// An iterator that yields samples read from a `Reader` while measuring their levels.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub struct MeteredSamples<R: Read + Seek> {
+    samples: Samples<R, i32>,
+    meter: LevelMeter,
+}
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+impl<R: Read + Seek> MeteredSamples<R> {
+    /// Returns the levels measured over the samples yielded so far.
+    pub fn level_meter(&self) -> &LevelMeter {
+        &self.meter
+    }
+}
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+impl<R: Read + Seek> Iterator for MeteredSamples<R> {
+    type Item = Result<i32, ReadError>;
+
+    fn next(&mut self) -> Option<Result<i32, ReadError>> {
+        let sample = self.samples.next()?;
+        if let Ok(sample) = sample {
+            self.meter.add_sample(sample);
+        }
+        Some(sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LevelMeter;
+
+    #[test]
+    fn peak_and_rms() {
+        let mut meter = LevelMeter::new(2);
+        meter.add_samples(&[1 << 30, 0, -(1 << 30), 0, 1 << 30]);
+        meter.add_samples(&[1 << 29]);
+
+        let levels = meter.levels();
+        assert_eq!(meter.frames(), 3);
+        assert_eq!(levels[0].peak(), 0.5);
+        assert_eq!(levels[0].rms(), 0.5);
+        assert_eq!(levels[1].peak(), 0.25);
+        assert!((levels[1].rms_dbfs() + 12.041 + 4.771).abs() < 0.01);
+    }
+}
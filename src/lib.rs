@@ -1,16 +1,97 @@
-mod bitcursor;
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// Without `std` the modules that only need `core` import it under its usual name.
+#[cfg(not(feature = "std"))]
+extern crate core as std;
+#[macro_use]
+extern crate alloc;
+
+pub mod bitreader;
 #[cfg(feature = "caf")]
 mod caf;
+mod channel_layout;
 mod dec;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod float;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+mod index;
+#[cfg(feature = "std")]
+pub mod levels;
+#[cfg(feature = "loudness")]
+pub mod loudness;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+mod metadata;
+#[cfg(all(feature = "mmap", any(feature = "caf", feature = "mp4")))]
+mod mmap;
 #[cfg(feature = "mp4")]
 mod mp4;
 #[cfg(any(feature = "caf", feature = "mp4"))]
 mod reader;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+mod raw;
+pub mod rtp;
+#[cfg(feature = "std")]
+pub mod silence;
+#[cfg(feature = "simd")]
+mod simd;
+pub mod slicer;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+mod source;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+mod streaming;
+#[cfg(all(feature = "threaded", any(feature = "caf", feature = "mp4")))]
+mod threaded;
+#[cfg(all(feature = "async", any(feature = "caf", feature = "mp4")))]
+pub mod tokio;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use dec::{Decoder, Sample};
+pub use channel_layout::{ChannelLayout, Speaker};
+pub use dec::{
+    Concealment, DecodeOptions, Decoder, I32Raw, Requantize, Sample, SampleFormat,
+};
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub use index::PacketIndex;
+#[cfg(all(feature = "mmap", any(feature = "caf", feature = "mp4")))]
+pub use mmap::MappedFile;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub use metadata::Metadata;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub use raw::write_raw_packet;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub use reader::{
+    probe, ErrorPolicy, Format, Frames, GaplessInfo, PacketLocation, PacketStats, Packets,
+    RawPacket, ReadError, Reader, Samples, Throughput, TrackInfo,
+};
+pub use slicer::PacketSlicer;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub use source::{PacketSource, ReadSeek};
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub use streaming::Streaming;
+#[cfg(all(feature = "threaded", any(feature = "caf", feature = "mp4")))]
+pub use threaded::{ThreadedPackets, ThreadedSamples};
 #[cfg(any(feature = "caf", feature = "mp4"))]
-pub use reader::{Format, Packets, ReadError, Reader, Samples};
+pub use validate::{validate_file, validate_file_with_progress, Issue, ValidationReport};
 
+/// Access to the parsed container of an ALAC file.
+#[cfg(all(feature = "container-context", any(feature = "caf", feature = "mp4")))]
+pub mod container {
+    #[cfg(feature = "caf")]
+    pub use caf::{
+        CafAudioDescription, CafChannelDescription, CafChannelLayout, CafChunk, CafContext,
+        CafPacketTable,
+    };
+    #[cfg(feature = "mp4")]
+    pub use mp4::{Mp4Context, Mp4Track};
+    pub use reader::ContainerContext;
+}
+
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
 use std::error;
 use std::fmt;
 
@@ -20,37 +101,195 @@ use std::fmt;
 /// been truncated.
 #[derive(Debug)]
 pub struct InvalidData {
+    kind: ErrorKind,
     message: &'static str,
+    mismatch: Option<(u64, u64)>,
+}
+
+/// The category of an `InvalidData` error.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// A packet ended before all of its elements were read.
+    TruncatedPacket,
+    /// A packet is longer than the decoder can read.
+    PacketTooLong,
+    /// A packet contains a different number of channels to the stream.
+    ChannelCountMismatch,
+    /// A packet or channel contains more or fewer samples than expected.
+    SampleCountMismatch,
+    /// A packet contains an element type the decoder does not support.
+    UnsupportedElement,
+    /// A packet contains data after its end element and strict decoding is enabled.
+    TrailingData,
+    /// A channel uses a prediction mode other than 0 or 15.
+    InvalidLpcMode,
+    /// A channel header contains an invalid value.
+    InvalidChannelHeader,
+    /// A magic cookie, SDP format parameters or stream configuration is invalid.
+    InvalidStreamInfo,
+    /// A stream configuration has no channels.
+    InvalidChannelCount,
+    /// A stream configuration's frame length is zero or larger than 65536.
+    InvalidFrameLength,
+    /// A stream configuration's bit depth is zero or greater than 32.
+    InvalidBitDepth,
+    /// A stream configuration's sample rate is zero.
+    InvalidSampleRate,
+    /// A stream configuration's rice parameter limit (`kb`) is zero or 32 or greater.
+    InvalidRiceLimit,
+    /// A container file, packet index or RTP packet is malformed.
+    InvalidContainer,
+}
+
+impl ErrorKind {
+    /// Returns `true` if the error affects a single packet, so decoding can continue with the next
+    /// packet, and `false` if the stream's configuration or container is invalid.
+    pub fn is_packet_error(&self) -> bool {
+        !matches!(
+            *self,
+            ErrorKind::InvalidStreamInfo
+                | ErrorKind::InvalidChannelCount
+                | ErrorKind::InvalidFrameLength
+                | ErrorKind::InvalidBitDepth
+                | ErrorKind::InvalidSampleRate
+                | ErrorKind::InvalidRiceLimit
+                | ErrorKind::InvalidContainer
+        )
+    }
 }
 
-impl error::Error for InvalidData {
-    fn description(&self) -> &str {
+impl InvalidData {
+    /// Returns the category of the problem.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns a description of the problem.
+    pub fn message(&self) -> &'static str {
         self.message
     }
+
+    /// Returns the value that was expected if the error was caused by a value mismatch.
+    pub fn expected(&self) -> Option<u64> {
+        self.mismatch.map(|(expected, _)| expected)
+    }
+
+    /// Returns the value that was found if the error was caused by a value mismatch.
+    pub fn found(&self) -> Option<u64> {
+        self.mismatch.map(|(_, found)| found)
+    }
 }
 
+#[cfg(feature = "std")]
+impl error::Error for InvalidData {}
+
 impl fmt::Display for InvalidData {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        f.write_str(self.message)
+        f.write_str(self.message)?;
+        if let Some((expected, found)) = self.mismatch {
+            write!(f, " (expected {}, found {})", expected, found)?;
+        }
+        Ok(())
+    }
+}
+
+/// An error returned by `Decoder::try_decode_packet`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DecodeError {
+    /// The packet could not be decoded.
+    InvalidData(InvalidData),
+    /// The output buffer is shorter than `StreamInfo::max_samples_per_packet`.
+    OutputTooShort { required: usize, len: usize },
+    /// The sample type has fewer bits than the stream's bit depth.
+    SampleTooNarrow { sample_bits: u8, bit_depth: u8 },
+}
+
+#[cfg(feature = "std")]
+impl error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            DecodeError::InvalidData(ref err) => Some(err),
+            DecodeError::OutputTooShort { .. } | DecodeError::SampleTooNarrow { .. } => None,
+        }
     }
 }
 
-impl From<bitcursor::NotEnoughData> for InvalidData {
-    fn from(_: bitcursor::NotEnoughData) -> InvalidData {
-        invalid_data("packet is not long enough")
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::InvalidData(ref err) => write!(f, "invalid data: {}", err),
+            DecodeError::OutputTooShort { required, len } => write!(
+                f,
+                "output buffer holds {} samples but {} are required",
+                len, required
+            ),
+            DecodeError::SampleTooNarrow {
+                sample_bits,
+                bit_depth,
+            } => write!(
+                f,
+                "{} bit samples cannot hold a {} bit stream",
+                sample_bits, bit_depth
+            ),
+        }
     }
 }
 
-impl From<bitcursor::BufferTooLong> for InvalidData {
-    fn from(_: bitcursor::BufferTooLong) -> InvalidData {
-        invalid_data("packet is too long")
+impl From<InvalidData> for DecodeError {
+    fn from(err: InvalidData) -> DecodeError {
+        DecodeError::InvalidData(err)
     }
 }
 
-fn invalid_data(message: &'static str) -> InvalidData {
-    InvalidData { message }
+impl From<bitreader::NotEnoughData> for InvalidData {
+    fn from(_: bitreader::NotEnoughData) -> InvalidData {
+        invalid_data(ErrorKind::TruncatedPacket, "packet is not long enough")
+    }
+}
+
+impl From<bitreader::BufferTooLong> for InvalidData {
+    fn from(_: bitreader::BufferTooLong) -> InvalidData {
+        invalid_data(ErrorKind::PacketTooLong, "packet is too long")
+    }
 }
 
+fn invalid_data(kind: ErrorKind, message: &'static str) -> InvalidData {
+    InvalidData {
+        kind,
+        message,
+        mismatch: None,
+    }
+}
+
+fn invalid_data_mismatch(
+    kind: ErrorKind,
+    message: &'static str,
+    expected: u64,
+    found: u64,
+) -> InvalidData {
+    InvalidData {
+        kind,
+        message,
+        mismatch: Some((expected, found)),
+    }
+}
+
+/// The newest ALAC format version supported by the decoder.
+pub const MAX_COMPATIBLE_VERSION: u8 = 0;
+
+// Apple's encoder uses 4096 frames per packet and AirPlay uses 352. Anything much larger than this
+// is almost certainly corrupt and would cause the decoder to allocate excessively large buffers.
+const MAX_FRAME_LENGTH: u32 = 1 << 16;
+
+/// The largest number of samples per packet, across all channels, of a stream accepted by
+/// `StreamInfo`.
+///
+/// This bounds the buffers allocated to decode a stream so that a hostile magic cookie can't demand
+/// gigabytes of memory. A lower limit can be set with `DecodeOptions::limit_samples_per_packet`.
+pub const MAX_SAMPLES_PER_PACKET: u32 = 1 << 20;
+
 /// Codec initialisation parameters for an ALAC stream.
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct StreamInfo {
@@ -65,6 +304,7 @@ pub struct StreamInfo {
     max_frame_bytes: u32,
     avg_bit_rate: u32,
     sample_rate: u32,
+    channel_layout_tag: Option<u32>,
 }
 
 impl StreamInfo {
@@ -79,7 +319,12 @@ impl StreamInfo {
 
         // Make sure we stay in bounds
         if cookie.len() < 24 {
-            return Err(invalid_data("magic cookie is not the correct length"));
+            return Err(invalid_data_mismatch(
+                ErrorKind::InvalidStreamInfo,
+                "magic cookie is not the correct length",
+                24,
+                cookie.len() as u64,
+            ));
         };
 
         // skip format ('frma') atom if present
@@ -94,9 +339,25 @@ impl StreamInfo {
 
         // Make sure cookie is long enough
         if cookie.len() < 24 {
-            return Err(invalid_data("magic cookie is not the correct length"));
+            return Err(invalid_data_mismatch(
+                ErrorKind::InvalidStreamInfo,
+                "magic cookie is not the correct length",
+                24,
+                cookie.len() as u64,
+            ));
         }
 
+        // An ALACChannelLayoutInfo structure may follow the ALACSpecificConfig. It is 24 bytes
+        // long and consists of its size, the 'chan' id, version flags, the channel layout tag, a
+        // channel bitmap and the number of channel descriptions.
+        let layout = &cookie[24..];
+        let channel_layout_tag =
+            if layout.len() >= 24 && read_be_u32(&layout[0..4]) == 24 && &layout[4..8] == b"chan" {
+                Some(read_be_u32(&layout[12..16]))
+            } else {
+                None
+            };
+
         StreamInfo {
             frame_length: read_be_u32(&cookie[0..4]),
             compatible_version: cookie[4],
@@ -109,6 +370,7 @@ impl StreamInfo {
             max_frame_bytes: read_be_u32(&cookie[12..16]),
             avg_bit_rate: read_be_u32(&cookie[16..20]),
             sample_rate: read_be_u32(&cookie[20..24]),
+            channel_layout_tag,
         }.validate()
     }
 
@@ -117,9 +379,22 @@ impl StreamInfo {
         use std::str::FromStr;
 
         fn parse<T: FromStr>(val: Option<&str>) -> Result<T, InvalidData> {
-            let val = val.ok_or(invalid_data("too few sdp format parameters"))?;
-            val.parse()
-                .map_err(|_| invalid_data("invalid sdp format parameter"))
+            let val = val.ok_or(invalid_data(
+                ErrorKind::InvalidStreamInfo,
+                "too few sdp format parameters",
+            ))?;
+            val.parse().map_err(|_| {
+                invalid_data(ErrorKind::InvalidStreamInfo, "invalid sdp format parameter")
+            })
+        }
+
+        // These parameters are often received from untrusted peers. No valid parameter string is
+        // anywhere near this long so reject long strings before doing any work.
+        if params.len() > 256 {
+            return Err(invalid_data(
+                ErrorKind::InvalidStreamInfo,
+                "sdp format parameters are too long",
+            ));
         }
 
         let mut params = params.split_whitespace();
@@ -136,16 +411,72 @@ impl StreamInfo {
             max_frame_bytes: parse(params.next())?,
             avg_bit_rate: parse(params.next())?,
             sample_rate: parse(params.next())?,
+            channel_layout_tag: None,
         };
 
         // Check we haven't been passed too many values
         if params.next().is_some() {
-            return Err(invalid_data("too many sdp format parameters"));
+            return Err(invalid_data(
+                ErrorKind::InvalidStreamInfo,
+                "too many sdp format parameters",
+            ));
         }
 
         info.validate()
     }
 
+    /// Returns the magic cookie describing the stream, i.e. the 24 byte `ALACSpecificConfig`
+    /// expected by `from_cookie` and stored by MP4 and CAF files.
+    ///
+    /// If the stream has a channel layout tag it is followed by a 24 byte `ALACChannelLayoutInfo`.
+    pub fn to_cookie(&self) -> Vec<u8> {
+        let mut cookie = Vec::with_capacity(48);
+        cookie.extend_from_slice(&self.frame_length.to_be_bytes());
+        cookie.extend_from_slice(&[
+            self.compatible_version,
+            self.bit_depth,
+            self.pb,
+            self.mb,
+            self.kb,
+            self.num_channels,
+        ]);
+        cookie.extend_from_slice(&self.max_run.to_be_bytes());
+        cookie.extend_from_slice(&self.max_frame_bytes.to_be_bytes());
+        cookie.extend_from_slice(&self.avg_bit_rate.to_be_bytes());
+        cookie.extend_from_slice(&self.sample_rate.to_be_bytes());
+
+        if let Some(tag) = self.channel_layout_tag {
+            cookie.extend_from_slice(&24u32.to_be_bytes());
+            cookie.extend_from_slice(b"chan");
+            // Version flags, the channel layout tag, channel bitmap and number of descriptions.
+            cookie.extend_from_slice(&0u32.to_be_bytes());
+            cookie.extend_from_slice(&tag.to_be_bytes());
+            cookie.extend_from_slice(&[0; 8]);
+        }
+        cookie
+    }
+
+    /// Returns SDP format specific parameters describing the stream, as expected by
+    /// `from_sdp_format_parameters`.
+    ///
+    /// The channel layout tag cannot be represented and is omitted.
+    pub fn to_sdp_format_parameters(&self) -> String {
+        format!(
+            "{} {} {} {} {} {} {} {} {} {} {}",
+            self.frame_length,
+            self.compatible_version,
+            self.bit_depth,
+            self.pb,
+            self.mb,
+            self.kb,
+            self.num_channels,
+            self.max_run,
+            self.max_frame_bytes,
+            self.avg_bit_rate,
+            self.sample_rate
+        )
+    }
+
     pub fn sample_rate(&self) -> u32 {
         self.sample_rate
     }
@@ -154,10 +485,34 @@ impl StreamInfo {
         self.bit_depth
     }
 
+    /// Returns the number of channels in the stream.
+    ///
+    /// Streams with more than 8 channels have no standard channel layout. Their channels are
+    /// output in the order of the audio elements in each packet.
     pub fn channels(&self) -> u8 {
         self.num_channels
     }
 
+    /// Returns the Core Audio channel layout tag from the magic cookie, or `None` if the cookie
+    /// does not contain a channel layout.
+    ///
+    /// Multichannel files usually include a channel layout describing their speaker arrangement.
+    /// The tag's upper 16 bits identify the layout and its lower 16 bits give the channel count.
+    pub fn channel_layout_tag(&self) -> Option<u32> {
+        self.channel_layout_tag
+    }
+
+    /// Returns the speaker arrangement of the stream's channels.
+    ///
+    /// Streams without a channel layout tag use the default ALAC layout for their channel count,
+    /// or `None` if they have more than 8 channels.
+    pub fn channel_layout(&self) -> Option<ChannelLayout> {
+        match self.channel_layout_tag {
+            Some(tag) => Some(ChannelLayout::from_tag(tag)),
+            None => ChannelLayout::default_for_channels(self.num_channels),
+        }
+    }
+
     pub fn max_frames_per_packet(&self) -> u32 {
         self.frame_length
     }
@@ -166,32 +521,267 @@ impl StreamInfo {
         self.frame_length * self.num_channels as u32
     }
 
+    /// Returns a copy of this `StreamInfo` with its sample rate replaced by `sample_rate`.
+    ///
+    /// This is useful for files whose magic cookie contains the wrong sample rate. The sample rate
+    /// does not affect decoding.
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Result<StreamInfo, InvalidData> {
+        self.sample_rate = sample_rate;
+        self.validate()
+    }
+
+    /// Returns the version of the ALAC format the stream is compatible with.
+    ///
+    /// Streams with a version greater than `MAX_COMPATIBLE_VERSION` are decoded as if they were
+    /// version 0 streams, which may produce incorrect output, unless strict decoding is enabled in
+    /// which case creating a `Decoder` for them fails.
+    pub fn compatible_version(&self) -> u8 {
+        self.compatible_version
+    }
+
+    /// Returns `true` if the stream's compatible version is one this decoder understands.
+    // `MAX_COMPATIBLE_VERSION` is currently zero but will grow if newer versions are supported.
+    #[allow(clippy::absurd_extreme_comparisons)]
+    pub fn is_known_compatible_version(&self) -> bool {
+        self.compatible_version <= MAX_COMPATIBLE_VERSION
+    }
+
+    /// Returns the maximum size of a compressed packet in bytes, or `0` if it is unknown.
+    pub fn max_frame_bytes(&self) -> u32 {
+        self.max_frame_bytes
+    }
+
+    /// Returns the average bit rate of the stream in bits per second, or `0` if it is unknown.
+    pub fn avg_bit_rate(&self) -> u32 {
+        self.avg_bit_rate
+    }
+
+    /// Returns the maximum run length used by the encoder. Unused by the decoder.
+    pub fn max_run(&self) -> u16 {
+        self.max_run
+    }
+
+    /// Returns the rice history multiplier (`pb`) used by the adaptive rice coder.
+    pub fn pb(&self) -> u8 {
+        self.pb
+    }
+
+    /// Returns the initial rice history value (`mb`) used by the adaptive rice coder.
+    pub fn mb(&self) -> u8 {
+        self.mb
+    }
+
+    /// Returns the rice parameter limit (`kb`) used by the adaptive rice coder.
+    pub fn kb(&self) -> u8 {
+        self.kb
+    }
+
     // TODO: Consider moving this validation to Decoder::new() on next major version bump
     fn validate(self) -> Result<StreamInfo, InvalidData> {
         if self.num_channels == 0 {
-            return Err(invalid_data("stream must contain one or more channels"));
+            return Err(invalid_data(
+                ErrorKind::InvalidChannelCount,
+                "stream must contain one or more channels",
+            ));
+        }
+
+        if self.frame_length == 0 {
+            return Err(invalid_data(
+                ErrorKind::InvalidFrameLength,
+                "frame length must be one or greater",
+            ));
         }
 
-        if self
-            .frame_length
-            .checked_mul(self.num_channels as u32)
-            .is_none()
-        {
-            return Err(invalid_data("overflow calculating max_samples_per_packet"));
+        // This also ensures `max_samples_per_packet` and the decoder's buffer length can't overflow.
+        if self.frame_length > MAX_FRAME_LENGTH {
+            return Err(invalid_data(
+                ErrorKind::InvalidFrameLength,
+                "frame length is too large",
+            ));
+        }
+
+        if self.max_samples_per_packet() > MAX_SAMPLES_PER_PACKET {
+            return Err(invalid_data(
+                ErrorKind::InvalidStreamInfo,
+                "stream has too many samples per packet",
+            ));
         }
 
         if self.bit_depth == 0 {
-            return Err(invalid_data("bit depth must be one or greater"));
+            return Err(invalid_data(
+                ErrorKind::InvalidBitDepth,
+                "bit depth must be one or greater",
+            ));
+        }
+
+        if self.bit_depth > 32 {
+            return Err(invalid_data(
+                ErrorKind::InvalidBitDepth,
+                "bit depth must be 32 or less",
+            ));
+        }
+
+        if self.sample_rate == 0 {
+            return Err(invalid_data(
+                ErrorKind::InvalidSampleRate,
+                "sample rate must be one or greater",
+            ));
+        }
+
+        // A rice limit of zero forces every rice parameter to zero, leaving no way to code
+        // non-zero residuals.
+        if self.kb == 0 {
+            return Err(invalid_data(
+                ErrorKind::InvalidRiceLimit,
+                "rice limit must be one or greater",
+            ));
         }
 
         if self.kb >= 32 {
-            return Err(invalid_data("rice limit must be less than 32"));
+            return Err(invalid_data(
+                ErrorKind::InvalidRiceLimit,
+                "rice limit must be less than 32",
+            ));
         }
 
         Ok(self)
     }
 }
 
+/// Builds a `StreamInfo` from individual parameters.
+///
+/// This is useful when ALAC packets are received without a magic cookie. Parameters that are not
+/// set default to those used by Apple's encoder for 16-bit stereo audio at 44.1 kHz.
+#[derive(Clone, Debug)]
+pub struct StreamInfoBuilder {
+    info: StreamInfo,
+}
+
+impl StreamInfoBuilder {
+    /// Creates a `StreamInfoBuilder` with default parameters.
+    pub fn new() -> StreamInfoBuilder {
+        StreamInfoBuilder {
+            info: StreamInfo {
+                frame_length: 4096,
+                compatible_version: 0,
+                bit_depth: 16,
+                pb: 40,
+                mb: 10,
+                kb: 14,
+                num_channels: 2,
+                max_run: 255,
+                max_frame_bytes: 0,
+                avg_bit_rate: 0,
+                sample_rate: 44100,
+                channel_layout_tag: None,
+            },
+        }
+    }
+
+    /// Sets the maximum number of frames in each packet. Defaults to 4096.
+    pub fn frame_length(mut self, frame_length: u32) -> StreamInfoBuilder {
+        self.info.frame_length = frame_length;
+        self
+    }
+
+    /// Sets the bit depth of the decoded samples. Defaults to 16.
+    pub fn bit_depth(mut self, bit_depth: u8) -> StreamInfoBuilder {
+        self.info.bit_depth = bit_depth;
+        self
+    }
+
+    /// Sets the number of channels. Defaults to 2.
+    pub fn channels(mut self, channels: u8) -> StreamInfoBuilder {
+        self.info.num_channels = channels;
+        self
+    }
+
+    /// Sets the sample rate. Defaults to 44100.
+    pub fn sample_rate(mut self, sample_rate: u32) -> StreamInfoBuilder {
+        self.info.sample_rate = sample_rate;
+        self
+    }
+
+    /// Sets the adaptive rice coder parameters `pb`, `mb` and `kb`. Defaults to 40, 10 and 14.
+    pub fn rice_params(mut self, pb: u8, mb: u8, kb: u8) -> StreamInfoBuilder {
+        self.info.pb = pb;
+        self.info.mb = mb;
+        self.info.kb = kb;
+        self
+    }
+
+    /// Sets the Core Audio channel layout tag. Defaults to none.
+    pub fn channel_layout_tag(mut self, tag: Option<u32>) -> StreamInfoBuilder {
+        self.info.channel_layout_tag = tag;
+        self
+    }
+
+    /// Returns the `StreamInfo`, or an error if the parameters are invalid.
+    pub fn build(self) -> Result<StreamInfo, InvalidData> {
+        self.info.validate()
+    }
+}
+
+impl Default for StreamInfoBuilder {
+    fn default() -> StreamInfoBuilder {
+        StreamInfoBuilder::new()
+    }
+}
+
+impl fmt::Display for StreamInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} Hz, {} ch, {}-bit, {} frames/packet",
+            self.sample_rate, self.num_channels, self.bit_depth, self.frame_length
+        )
+    }
+}
+
+/// Progress through a long running operation, reported after each packet.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Progress {
+    packets_done: u64,
+    frames_done: u64,
+    frames_total: Option<u64>,
+    bytes_read: u64,
+}
+
+impl Progress {
+    /// Returns the number of packets processed so far.
+    pub fn packets_done(&self) -> u64 {
+        self.packets_done
+    }
+
+    /// Returns the number of frames decoded so far.
+    pub fn frames_done(&self) -> u64 {
+        self.frames_done
+    }
+
+    /// Returns the total number of frames in the stream if it is known.
+    pub fn frames_total(&self) -> Option<u64> {
+        self.frames_total
+    }
+
+    /// Returns the number of compressed bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+}
+
+/// Returns `cookie` with a channel layout read from its container appended, unless the cookie is
+/// invalid or already has a channel layout.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+fn cookie_with_channel_layout(cookie: Vec<u8>, tag: u32) -> Vec<u8> {
+    match StreamInfo::from_cookie(&cookie) {
+        Ok(mut info) if info.channel_layout_tag.is_none() => {
+            info.channel_layout_tag = Some(tag);
+            info.to_cookie()
+        }
+        _ => cookie,
+    }
+}
+
 fn read_be_u16(buf: &[u8]) -> u16 {
     assert_eq!(buf.len(), 2);
     ((buf[0] as u16) << 8) | (buf[1] as u16)
@@ -204,7 +794,11 @@ fn read_be_u32(buf: &[u8]) -> u32 {
 
 #[cfg(test)]
 mod tests {
-    use super::StreamInfo;
+    use super::{
+        ChannelLayout, DecodeOptions, Decoder, ErrorKind, Speaker, StreamInfo, StreamInfoBuilder,
+        MAX_SAMPLES_PER_PACKET,
+    };
+    use alloc::string::ToString;
 
     #[test]
     fn test_from_cookie() {
@@ -223,11 +817,206 @@ mod tests {
             max_frame_bytes: 0,
             avg_bit_rate: 0,
             sample_rate: 44100,
+            channel_layout_tag: None,
         };
 
         assert_eq!(cookie, comparison);
     }
 
+    #[test]
+    fn samples_per_packet_limit() {
+        // 65536 frames of 17 channels is over the limit.
+        let params = "65536 0 16 40 10 14 17 255 0 0 44100";
+        let err = StreamInfo::from_sdp_format_parameters(params).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidStreamInfo);
+        let params = "65536 0 16 40 10 14 8 255 0 0 44100";
+        let info = StreamInfo::from_sdp_format_parameters(params).unwrap();
+        assert_eq!(info.max_samples_per_packet(), MAX_SAMPLES_PER_PACKET / 2);
+
+        let options = DecodeOptions::new().limit_samples_per_packet(8192);
+        assert!(Decoder::try_with_options(info.clone(), options).is_err());
+        let options = options.limit_samples_per_packet(info.max_samples_per_packet());
+        assert!(Decoder::try_with_options(info, options).is_ok());
+    }
+
+    #[test]
+    fn test_accessors() {
+        let params = "352 0 24 40 10 14 2 255 12000 1411200 44100";
+        let info = StreamInfo::from_sdp_format_parameters(params).unwrap();
+
+        assert_eq!(info.max_frames_per_packet(), 352);
+        assert_eq!(info.compatible_version(), 0);
+        assert_eq!(info.bit_depth(), 24);
+        assert_eq!((info.pb(), info.mb(), info.kb()), (40, 10, 14));
+        assert_eq!(info.channels(), 2);
+        assert_eq!(info.max_run(), 255);
+        assert_eq!(info.max_frame_bytes(), 12000);
+        assert_eq!(info.avg_bit_rate(), 1411200);
+        assert_eq!(info.sample_rate(), 44100);
+    }
+
+    #[test]
+    fn channel_layout_tag() {
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let mut cookie = cookie_bytes[..24].to_vec();
+        cookie[9] = 6;
+        assert_eq!(StreamInfo::from_cookie(&cookie).unwrap().channel_layout_tag(), None);
+
+        // kAudioChannelLayoutTag_MPEG_5_1_D
+        let layout_tag: u32 = (124 << 16) | 6;
+        cookie.extend_from_slice(&24u32.to_be_bytes());
+        cookie.extend_from_slice(b"chan");
+        cookie.extend_from_slice(&0u32.to_be_bytes());
+        cookie.extend_from_slice(&layout_tag.to_be_bytes());
+        cookie.extend_from_slice(&[0; 8]);
+        let info = StreamInfo::from_cookie(&cookie).unwrap();
+        assert_eq!(info.channel_layout_tag(), Some(layout_tag));
+        assert_eq!(info.channel_layout(), Some(ChannelLayout::Mpeg5_1D));
+    }
+
+    #[test]
+    fn channel_layout() {
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+        assert_eq!(info.channel_layout(), Some(ChannelLayout::Stereo));
+
+        // Streams without a tag use ALAC's default layouts.
+        let mut cookie = cookie_bytes[..24].to_vec();
+        cookie[9] = 8;
+        let layout = StreamInfo::from_cookie(&cookie).unwrap().channel_layout();
+        assert_eq!(layout, Some(ChannelLayout::Mpeg7_1B));
+        cookie[9] = 9;
+        assert_eq!(StreamInfo::from_cookie(&cookie).unwrap().channel_layout(), None);
+
+        let layout = ChannelLayout::Aac6_1;
+        assert_eq!(ChannelLayout::from_tag(layout.tag()), layout);
+        let speakers = layout.speakers().unwrap();
+        assert_eq!(speakers.len(), 7);
+        assert_eq!(speakers[6], Speaker::LowFrequency);
+
+        // kAudioChannelLayoutTag_DVD_0
+        let other = ChannelLayout::from_tag(133 << 16 | 1);
+        assert_eq!(other, ChannelLayout::Other(133 << 16 | 1));
+        assert_eq!(other.tag(), 133 << 16 | 1);
+        assert_eq!(other.speakers(), None);
+    }
+
+    #[test]
+    fn to_cookie_round_trip() {
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+        assert_eq!(info.to_cookie(), &cookie_bytes[..24]);
+
+        let params = info.to_sdp_format_parameters();
+        let info = StreamInfo::from_sdp_format_parameters(&params).unwrap();
+        assert_eq!(info.to_cookie(), &cookie_bytes[..24]);
+
+        let params = "352 0 24 40 10 14 2 255 12000 1411200 44100";
+        let info = StreamInfo::from_sdp_format_parameters(params).unwrap();
+        assert_eq!(info.to_sdp_format_parameters(), params);
+
+        // The channel layout is kept.
+        let mut cookie = cookie_bytes[..24].to_vec();
+        cookie.extend_from_slice(&24u32.to_be_bytes());
+        cookie.extend_from_slice(b"chan");
+        cookie.extend_from_slice(&[0, 0, 0, 0, 0, 0x65, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+        let info = StreamInfo::from_cookie(&cookie).unwrap();
+        assert_eq!(info.to_cookie(), cookie);
+    }
+
+    #[test]
+    fn stream_info_builder() {
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let info = StreamInfoBuilder::new().build().unwrap();
+        assert_eq!(info, StreamInfo::from_cookie(cookie_bytes).unwrap());
+
+        let info = StreamInfoBuilder::new()
+            .frame_length(352)
+            .bit_depth(24)
+            .channels(1)
+            .sample_rate(48000)
+            .rice_params(40, 10, 10)
+            .build()
+            .unwrap();
+        let params = "352 0 24 40 10 10 1 255 0 0 48000";
+        assert_eq!(info, StreamInfo::from_sdp_format_parameters(params).unwrap());
+
+        assert!(StreamInfoBuilder::new().channels(0).build().is_err());
+        assert!(StreamInfoBuilder::new().rice_params(40, 10, 0).build().is_err());
+    }
+
+    #[test]
+    fn invalid_data_carries_mismatched_values() {
+        let err = StreamInfo::from_cookie(&[0; 20]).unwrap_err();
+        assert_eq!((err.expected(), err.found()), (Some(24), Some(20)));
+        assert_eq!(err.kind(), ErrorKind::InvalidStreamInfo);
+        assert!(!err.kind().is_packet_error());
+
+        // A stereo packet containing only an end element.
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let mut decoder = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+        let mut out = vec![0i16; 8192];
+        let err = decoder.decode_packet(&[0xe0], &mut out).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "packet contains fewer channels than expected (expected 2, found 0)"
+        );
+        assert_eq!(err.kind(), ErrorKind::ChannelCountMismatch);
+        assert!(err.kind().is_packet_error());
+
+        let err = decoder.decode_packet(&[0x00], &mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TruncatedPacket);
+    }
+
+    #[test]
+    fn newer_compatible_versions_are_accepted() {
+        let params = "4096  1   16  40  10  14  2   255 0   0   44100";
+        let info = StreamInfo::from_sdp_format_parameters(params).unwrap();
+        assert_eq!(info.compatible_version(), 1);
+        assert!(!info.is_known_compatible_version());
+        assert!(Decoder::try_with_options(info.clone(), DecodeOptions::new()).is_ok());
+
+        // Strict decoders reject them.
+        let options = DecodeOptions::new().strict(true);
+        let err = Decoder::try_with_options(info, options).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidStreamInfo);
+        assert_eq!((err.expected(), err.found()), (Some(0), Some(1)));
+    }
+
+    #[test]
+    fn invalid_parameters_have_specific_kinds() {
+        let cases = [
+            ("4096 0 16 40 10 14 0 255 0 0 44100", ErrorKind::InvalidChannelCount),
+            ("0 0 16 40 10 14 2 255 0 0 44100", ErrorKind::InvalidFrameLength),
+            ("65537 0 16 40 10 14 1 255 0 0 44100", ErrorKind::InvalidFrameLength),
+            ("4096 0 0 40 10 14 2 255 0 0 44100", ErrorKind::InvalidBitDepth),
+            ("4096 0 33 40 10 14 2 255 0 0 44100", ErrorKind::InvalidBitDepth),
+            ("4096 0 16 40 10 14 2 255 0 0 0", ErrorKind::InvalidSampleRate),
+            ("4096 0 16 40 10 0 2 255 0 0 44100", ErrorKind::InvalidRiceLimit),
+            ("4096 0 16 40 10 32 2 255 0 0 44100", ErrorKind::InvalidRiceLimit),
+        ];
+        for &(params, kind) in &cases {
+            let err = StreamInfo::from_sdp_format_parameters(params).unwrap_err();
+            assert_eq!(err.kind(), kind, "{}", params);
+            assert!(!err.kind().is_packet_error());
+        }
+    }
+
+    #[test]
+    fn sample_rate_override() {
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+        assert_eq!(info.clone().with_sample_rate(48000).unwrap().sample_rate(), 48000);
+        assert!(info.with_sample_rate(0).is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let cookie_bytes = include_bytes!("../tests/data/magic_cookie.bin");
+        let info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+        assert_eq!(info.to_string(), "44100 Hz, 2 ch, 16-bit, 4096 frames/packet");
+    }
+
     #[test]
     fn cookie_must_have_one_or_more_channels() {
         let params = "4096  0   16  40  10  14  0   255 0   0   44100";
@@ -240,6 +1029,45 @@ mod tests {
         assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
     }
 
+    #[test]
+    fn sdp_format_parameters_must_not_overflow() {
+        let params = "4294967295  0   16  40  10  14  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+
+        let params = "4096  0   256  40  10  14  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+
+        let params = "4096  0   -16  40  10  14  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+    }
+
+    #[test]
+    fn cookie_must_have_sensible_frame_length() {
+        let params = "0  0   16  40  10  14  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+
+        let params = "1000000  0   16  40  10  14  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+    }
+
+    #[test]
+    fn cookie_must_have_bit_depth_of_32_or_less() {
+        let params = "4096  0   33  40  10  14  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+    }
+
+    #[test]
+    fn cookie_must_have_nonzero_sample_rate() {
+        let params = "4096  0   16  40  10  14  2   255 0   0   0";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+    }
+
+    #[test]
+    fn cookie_must_have_nonzero_rice_limit() {
+        let params = "4096  0   16  40  10  0  2   255 0   0   44100";
+        assert!(StreamInfo::from_sdp_format_parameters(params).is_err());
+    }
+
     #[test]
     fn test_from_sdp_format_parameters() {
         let params = "4096  0   16  40  10  14  2   255 0   0   44100";
@@ -257,6 +1085,7 @@ mod tests {
             max_frame_bytes: 0,
             avg_bit_rate: 0,
             sample_rate: 44100,
+            channel_layout_tag: None,
         };
 
         assert_eq!(cookie, comparison);
@@ -0,0 +1,302 @@
+//! Integrated loudness and true-peak measurement as specified by ITU-R BS.1770 and EBU R128.
+//!
+//! A `LoudnessMeter` is fed interleaved samples as they are decoded and only keeps a single energy
+//! value for every 100ms of audio, so a whole file can be measured in one streaming pass.
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use std::io::{Read, Seek};
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use {ReadError, Reader};
+
+// Gating blocks are 400ms long and overlap by 75%, so block energies are accumulated in 100ms steps.
+const STEPS_PER_BLOCK: usize = 4;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+// Taps per phase of the true-peak interpolation filter.
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// Measures the integrated loudness and true peak of an audio stream.
+pub struct LoudnessMeter {
+    channels: Vec<Channel>,
+    step_len: usize,
+    step_pos: usize,
+    // Weighted mean square of each completed 100ms step.
+    steps: Vec<f64>,
+    oversampling: usize,
+    interpolation: Vec<f64>,
+    sample_peak: f64,
+    true_peak: f64,
+}
+
+struct Channel {
+    weight: f64,
+    shelf: Biquad,
+    high_pass: Biquad,
+    step_energy: f64,
+    history: [f64; TRUE_PEAK_TAPS],
+}
+
+impl LoudnessMeter {
+    /// Creates a `LoudnessMeter` for a stream with `channels` interleaved channels.
+    ///
+    /// Channels are assumed to be in ALAC channel order, e.g. `[C, L, R, Ls, Rs, LFE]` for 5.1
+    /// audio, which determines the weighting of surround and LFE channels.
+    pub fn new(channels: u8, sample_rate: u32) -> LoudnessMeter {
+        let fs = sample_rate as f64;
+
+        let oversampling = match sample_rate {
+            0..=95_999 => 4,
+            96_000..=191_999 => 2,
+            _ => 1,
+        };
+
+        LoudnessMeter {
+            channels: (0..channels)
+                .map(|i| Channel {
+                    weight: channel_weight(channels, i),
+                    shelf: Biquad::shelf(fs),
+                    high_pass: Biquad::high_pass(fs),
+                    step_energy: 0.0,
+                    history: [0.0; TRUE_PEAK_TAPS],
+                })
+                .collect(),
+            step_len: (sample_rate as usize / 10).max(1),
+            step_pos: 0,
+            steps: Vec::new(),
+            oversampling,
+            interpolation: interpolation_filter(oversampling),
+            sample_peak: 0.0,
+            true_peak: 0.0,
+        }
+    }
+
+    /// Adds interleaved samples to the measurement.
+    ///
+    /// Samples must be left-aligned as produced by `Sample for i32`, i.e. full scale is
+    /// `i32::MIN..=i32::MAX` regardless of the stream bit depth.
+    pub fn add_samples(&mut self, samples: &[i32]) {
+        let num_channels = self.channels.len();
+        if num_channels == 0 {
+            return;
+        }
+
+        for frame in samples.chunks(num_channels) {
+            for (channel, &sample) in self.channels.iter_mut().zip(frame) {
+                let x = sample as f64 / 2_147_483_648.0;
+
+                let y = channel.high_pass.process(channel.shelf.process(x));
+                channel.step_energy += y * y;
+
+                self.sample_peak = self.sample_peak.max(x.abs());
+
+                // Shift the sample into the interpolation history and compute each of the
+                // oversampled phases.
+                channel.history.rotate_right(1);
+                channel.history[0] = x;
+                if self.oversampling > 1 {
+                    for phase in 0..self.oversampling {
+                        let mut interpolated = 0.0;
+                        for (k, x) in channel.history.iter().enumerate() {
+                            interpolated += self.interpolation[k * self.oversampling + phase] * x;
+                        }
+                        self.true_peak = self.true_peak.max(interpolated.abs());
+                    }
+                }
+            }
+
+            self.step_pos += 1;
+            if self.step_pos == self.step_len {
+                let energy = self
+                    .channels
+                    .iter_mut()
+                    .map(|c| c.weight * std::mem::replace(&mut c.step_energy, 0.0))
+                    .sum::<f64>();
+                self.steps.push(energy / self.step_len as f64);
+                self.step_pos = 0;
+            }
+        }
+    }
+
+    /// Returns the gated integrated loudness of the samples added so far in LUFS.
+    ///
+    /// Returns `None` if less than 400ms of audio has been added or if all of it is below the
+    /// -70 LUFS absolute gate.
+    pub fn integrated_loudness(&self) -> Option<f64> {
+        if self.steps.len() < STEPS_PER_BLOCK {
+            return None;
+        }
+
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(STEPS_PER_BLOCK)
+            .map(|w| w.iter().sum::<f64>() / STEPS_PER_BLOCK as f64)
+            .filter(|&energy| loudness(energy) > ABSOLUTE_GATE)
+            .collect();
+
+        let relative_gate = loudness(mean(&blocks)?) + RELATIVE_GATE;
+
+        let gated: Vec<f64> = blocks
+            .into_iter()
+            .filter(|&energy| loudness(energy) > relative_gate)
+            .collect();
+
+        mean(&gated).map(loudness)
+    }
+
+    /// Returns the maximum true peak of the samples added so far in dBTP.
+    pub fn true_peak(&self) -> f64 {
+        20.0 * self.sample_peak.max(self.true_peak).log10()
+    }
+}
+
+/// Measures the integrated loudness and true peak of the ALAC stream in `reader`.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub fn scan<R: Read + Seek>(reader: Reader<R>) -> Result<LoudnessMeter, ReadError> {
+    let stream_info = reader.stream_info().clone();
+    let mut meter = LoudnessMeter::new(stream_info.channels(), stream_info.sample_rate());
+
+    let mut packets = reader.into_packets::<i32>();
+    let mut buf = vec![0; stream_info.max_samples_per_packet() as usize];
+    while let Some(samples) = packets.next_into(&mut buf)? {
+        meter.add_samples(samples);
+    }
+
+    Ok(meter)
+}
+
+fn loudness(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+fn channel_weight(channels: u8, channel: u8) -> f64 {
+    // Surround channels are weighted by +1.5dB and LFE channels are excluded. The positions follow
+    // the default ALAC channel layouts for each channel count.
+    const SURROUND: f64 = 1.41;
+    match (channels, channel) {
+        (5, 3) | (5, 4) | (6, 3) | (6, 4) | (7, 3) | (7, 4) | (8, 5) | (8, 6) => SURROUND,
+        (6, 5) | (7, 6) | (8, 7) => 0.0,
+        _ => 1.0,
+    }
+}
+
+fn interpolation_filter(oversampling: usize) -> Vec<f64> {
+    // A windowed-sinc low-pass filter with a cutoff at the original Nyquist frequency, stored such
+    // that `filter[k * oversampling + phase]` is the k'th tap of the given phase.
+    let len = TRUE_PEAK_TAPS * oversampling;
+    let centre = (len - 1) as f64 / 2.0;
+    (0..len)
+        .map(|n| {
+            let t = (n as f64 - centre) / oversampling as f64;
+            let sinc = if t == 0.0 {
+                1.0
+            } else {
+                (std::f64::consts::PI * t).sin() / (std::f64::consts::PI * t)
+            };
+            let window =
+                0.5 - 0.5 * (2.0 * std::f64::consts::PI * (n as f64 + 0.5) / len as f64).cos();
+            sinc * window
+        })
+        .collect()
+}
+
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// The first stage of the K-weighting filter modelling the acoustic effect of the head.
+    fn shelf(fs: f64) -> Biquad {
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+
+        let k = (std::f64::consts::PI * f0 / fs).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// The second stage of the K-weighting filter, a simple high-pass filter.
+    fn high_pass(fs: f64) -> Biquad {
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+
+        let k = (std::f64::consts::PI * f0 / fs).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, x: f64) -> f64 {
+        // Transposed direct form II.
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LoudnessMeter;
+
+    fn sine(channels: u8, sample_rate: u32, freq: f64, amplitude: f64, secs: f64) -> Vec<i32> {
+        let frames = (sample_rate as f64 * secs) as usize;
+        let mut out = Vec::with_capacity(frames * channels as usize);
+        for i in 0..frames {
+            let t = i as f64 / sample_rate as f64;
+            let x = amplitude * (2.0 * std::f64::consts::PI * freq * t).sin();
+            for _ in 0..channels {
+                out.push((x * i32::MAX as f64) as i32);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn stereo_sine_loudness() {
+        // A 1kHz stereo sine measures approximately its peak level in LUFS.
+        let mut meter = LoudnessMeter::new(2, 48000);
+        meter.add_samples(&sine(2, 48000, 997.0, 0.1, 5.0));
+
+        let loudness = meter.integrated_loudness().unwrap();
+        assert!((loudness + 20.0).abs() < 0.1, "loudness {}", loudness);
+
+        let true_peak = meter.true_peak();
+        assert!((true_peak + 20.0).abs() < 0.1, "true peak {}", true_peak);
+    }
+
+    #[test]
+    fn silence_is_gated() {
+        let mut meter = LoudnessMeter::new(2, 44100);
+        meter.add_samples(&vec![0; 44100 * 2]);
+        assert_eq!(meter.integrated_loudness(), None);
+    }
+}
@@ -0,0 +1,44 @@
+/// Descriptive tags read from a file's container, such as its title and artist.
+///
+/// Tags that are not present in the file are `None`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metadata {
+    pub(crate) title: Option<String>,
+    pub(crate) artist: Option<String>,
+    pub(crate) album: Option<String>,
+    pub(crate) track_number: Option<u32>,
+    pub(crate) track_count: Option<u32>,
+    pub(crate) cover_art: Option<Vec<u8>>,
+}
+
+impl Metadata {
+    /// Returns the title of the track.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_ref().map(|s| &s[..])
+    }
+
+    /// Returns the artist of the track.
+    pub fn artist(&self) -> Option<&str> {
+        self.artist.as_ref().map(|s| &s[..])
+    }
+
+    /// Returns the album the track is from.
+    pub fn album(&self) -> Option<&str> {
+        self.album.as_ref().map(|s| &s[..])
+    }
+
+    /// Returns the position of the track on its album, starting from 1.
+    pub fn track_number(&self) -> Option<u32> {
+        self.track_number
+    }
+
+    /// Returns the number of tracks on the album.
+    pub fn track_count(&self) -> Option<u32> {
+        self.track_count
+    }
+
+    /// Returns the encoded cover art image, usually a JPEG or PNG file.
+    pub fn cover_art(&self) -> Option<&[u8]> {
+        self.cover_art.as_ref().map(|a| &a[..])
+    }
+}
@@ -0,0 +1,86 @@
+//! Reading files that are mapped into memory.
+//!
+//! Packets read from a mapped file are decoded directly from the mapping rather than being copied
+//! into a buffer first.
+
+extern crate memmap2;
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::Arc;
+
+use self::memmap2::Mmap;
+use {ReadError, Reader};
+
+/// A file mapped into memory, read by a `Reader` created with `Reader::from_mmap`.
+#[derive(Clone)]
+pub struct MappedFile {
+    map: Arc<Mmap>,
+    position: u64,
+}
+
+impl MappedFile {
+    /// Returns the contents of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl Read for MappedFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = cmp::min(self.position, self.map.len() as u64) as usize;
+        let len = (&self.map[start..]).read(buf)?;
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for MappedFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(position) => {
+                self.position = position;
+                return Ok(position);
+            }
+            SeekFrom::End(offset) => (self.map.len() as u64, offset),
+            SeekFrom::Current(offset) => (self.position, offset),
+        };
+        match base.checked_add_signed(offset) {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+impl Reader<MappedFile> {
+    /// Maps the file at `path` into memory and creates a `Reader` for it.
+    ///
+    /// Packets are passed to the decoder, and returned by `next_raw_packet`, as slices of the
+    /// mapping without being copied.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated, by this or any other process, while the
+    /// `Reader` or any `MappedFile` cloned from it exists. Doing so is undefined behaviour as the
+    /// mapped bytes can change while they are borrowed.
+    pub unsafe fn from_mmap<P: AsRef<Path>>(path: P) -> Result<Reader<MappedFile>, ReadError> {
+        let file = File::open(path)?;
+        // The caller guarantees the file isn't modified while it is mapped.
+        let map = Mmap::map(&file)?;
+        let file = MappedFile {
+            map: Arc::new(map),
+            position: 0,
+        };
+        let mut reader = Reader::new(file.clone())?;
+        reader.set_packet_map(file);
+        Ok(reader)
+    }
+}
@@ -1,27 +1,79 @@
-extern crate mp4parse;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::{cmp, mem};
 
-use self::mp4parse::{AudioCodecSpecific, AudioSampleEntry, CodecType, Error, SampleEntry};
-use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use index::PacketEntry;
+use {
+    invalid_data, read_be_u32, ChannelLayout, Format, GaplessInfo, Metadata, ReadError, StreamInfo,
+    Streaming, TrackInfo,
+};
 
-use {invalid_data, Format, ReadError};
+/// The longest tag value read, which is large enough for cover art.
+const MAX_TAG_LEN: u64 = 16 * 1024 * 1024;
+
+/// The number of entries read at once from sample tables that are read on demand.
+const TABLE_BLOCK_LEN: u32 = 1024;
 
 fn mp4_error(msg: &'static str) -> ReadError {
-    ReadError::Format(Format::Mp4, invalid_data(msg))
+    ReadError::Format(Format::Mp4, invalid_data(::ErrorKind::InvalidContainer, msg))
 }
 
-impl From<Error> for ReadError {
-    fn from(err: Error) -> ReadError {
-        match err {
-            Error::InvalidData(msg) => mp4_error(msg),
-            Error::Unsupported(msg) => mp4_error(msg),
-            Error::UnexpectedEOF => mp4_error("unexpected end of stream"),
-            Error::Io(ref err) if err.kind() == ErrorKind::UnexpectedEof => {
-                mp4_error("unexpected end of stream")
-            }
-            Error::Io(err) => ReadError::Io(err),
-            Error::NoMoov => mp4_error("missing moov atom"),
-            Error::OutOfMemory => mp4_error("out of memory"),
-        }
+/// The parsed movie (`moov`) box of an MP4 file.
+///
+/// Only the boxes needed to find and read ALAC tracks are parsed. Sample tables other than the
+/// time to sample table are read when a track is selected.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "container-context"), allow(dead_code))]
+pub struct Mp4Context {
+    major_brand: Option<[u8; 4]>,
+    timescale: Option<u32>,
+    tracks: Vec<Mp4Track>,
+}
+
+/// A track (`trak` box) of an MP4 file.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "container-context"), allow(dead_code))]
+pub struct Mp4Track {
+    track_id: Option<u32>,
+    timescale: Option<u32>,
+    // Runs of samples with the same duration, from the stts atom.
+    time_to_sample: Vec<(u32, u32)>,
+    stbl: Option<BoxRange>,
+}
+
+#[cfg(feature = "container-context")]
+impl Mp4Context {
+    /// Returns the major brand from the file type (`ftyp`) box, such as `M4A `.
+    pub fn major_brand(&self) -> Option<[u8; 4]> {
+        self.major_brand
+    }
+
+    /// Returns the timescale of the movie header (`mvhd`) box, which edit list durations use.
+    pub fn timescale(&self) -> Option<u32> {
+        self.timescale
+    }
+
+    /// Returns every track in the file, including tracks that are not ALAC.
+    pub fn tracks(&self) -> &[Mp4Track] {
+        &self.tracks
+    }
+}
+
+#[cfg(feature = "container-context")]
+impl Mp4Track {
+    /// Returns the ID from the track header (`tkhd`) box.
+    pub fn track_id(&self) -> Option<u32> {
+        self.track_id
+    }
+
+    /// Returns the timescale of the media header (`mdhd`) box.
+    pub fn timescale(&self) -> Option<u32> {
+        self.timescale
+    }
+
+    /// Returns the sample count and duration of each run of samples with the same duration, from
+    /// the time to sample (`stts`) box.
+    pub fn time_to_sample(&self) -> &[(u32, u32)] {
+        &self.time_to_sample
     }
 }
 
@@ -29,8 +81,96 @@ pub struct Mp4PacketReader<R> {
     reader: R,
     chunk_offsets: Vec<u64>,
     sample_sizes: Vec<u32>,
+    // Set when the respective table is read from the file as needed rather than held in
+    // `chunk_offsets` or `sample_sizes`, which are then empty.
+    lazy_chunk_offsets: Option<FileTable>,
+    lazy_sample_sizes: Option<FileTable>,
     sample_to_chunk: Vec<SampleToChunk>,
+    // Runs of samples (packets) with the same duration in frames, from the stts atom.
+    time_to_sample: Vec<(u32, u32)>,
+    gapless_info: Option<GaplessInfo>,
+    metadata: Metadata,
+    tracks: Vec<TrackInfo>,
+    track_id: Option<u32>,
     sample_idx: u32,
+    packet_offset: u64,
+    // The chunk containing the packet most recently read, so sequential reads don't search
+    // `sample_to_chunk` for every packet.
+    chunk_cursor: Option<ChunkCursor>,
+    // The position of `reader`, or `None` if it is unknown after a failed read.
+    position: Option<u64>,
+    // Packets longer than this are rejected rather than read into memory.
+    packet_len_limit: u64,
+    #[cfg(feature = "container-context")]
+    context: Mp4Context,
+}
+
+// Track run flags
+const TRUN_DATA_OFFSET: u32 = 0x1;
+const TRUN_FIRST_SAMPLE_FLAGS: u32 = 0x4;
+const TRUN_SAMPLE_DURATION: u32 = 0x100;
+const TRUN_SAMPLE_SIZE: u32 = 0x200;
+const TRUN_SAMPLE_FLAGS: u32 = 0x400;
+const TRUN_SAMPLE_COMPOSITION_TIME_OFFSET: u32 = 0x800;
+
+// Track fragment header flags
+const TFHD_BASE_DATA_OFFSET: u32 = 0x1;
+const TFHD_SAMPLE_DESCRIPTION_INDEX: u32 = 0x2;
+const TFHD_DEFAULT_SAMPLE_DURATION: u32 = 0x8;
+const TFHD_DEFAULT_SAMPLE_SIZE: u32 = 0x10;
+
+/// Sample values used when a track run doesn't give them.
+#[derive(Clone, Copy)]
+struct SampleDefaults {
+    duration: u32,
+    size: u32,
+}
+
+/// Reads the next 32 bit field from the remaining content of a track run.
+fn next_trun_field(fields: &mut &[u8]) -> Result<u32, ReadError> {
+    if fields.len() < 4 {
+        return Err(mp4_error("trun (track run) atom is too short"));
+    }
+    let value = read_be_u32(&fields[..4]);
+    *fields = &fields[4..];
+    Ok(value)
+}
+
+/// Returns the base data offset and sample defaults from a track fragment header.
+///
+/// Data offsets are relative to the start of the `moof` box unless a base data offset is given.
+fn parse_tfhd(
+    tfhd: &[u8],
+    moof_start: u64,
+    mut defaults: SampleDefaults,
+) -> Result<(u64, SampleDefaults), ReadError> {
+    let flags = read_be_u32(&tfhd[0..4]) & 0xff_ffff;
+    let mut fields = &tfhd[8..];
+    let mut next = |len: usize| -> Result<&[u8], ReadError> {
+        if fields.len() < len {
+            return Err(mp4_error("tfhd (track fragment header) atom is too short"));
+        }
+        let (field, rest) = fields.split_at(len);
+        fields = rest;
+        Ok(field)
+    };
+
+    let mut base_data_offset = moof_start;
+    if flags & TFHD_BASE_DATA_OFFSET != 0 {
+        let mut offset = [0; 8];
+        offset.copy_from_slice(next(8)?);
+        base_data_offset = u64::from_be_bytes(offset);
+    }
+    if flags & TFHD_SAMPLE_DESCRIPTION_INDEX != 0 {
+        next(4)?;
+    }
+    if flags & TFHD_DEFAULT_SAMPLE_DURATION != 0 {
+        defaults.duration = read_be_u32(next(4)?);
+    }
+    if flags & TFHD_DEFAULT_SAMPLE_SIZE != 0 {
+        defaults.size = read_be_u32(next(4)?);
+    }
+    Ok((base_data_offset, defaults))
 }
 
 #[derive(Clone, Copy)]
@@ -40,102 +180,1577 @@ struct SampleToChunk {
     samples_per_chunk: u32,
 }
 
+/// A chunk and the entry in `sample_to_chunk` describing it.
+#[derive(Clone, Copy)]
+struct ChunkCursor {
+    mapping_idx: usize,
+    chunk_idx: u32,
+    first_sample: u32,
+    samples_per_chunk: u32,
+}
+
+/// A table of big endian integers in the file, read a block at a time as entries are needed.
+struct FileTable {
+    // The file offset of the first entry.
+    start: u64,
+    len: u32,
+    // The length of each entry in bytes, either 4 or 8.
+    entry_len: u64,
+    // The entries most recently read, starting with entry `block_start`.
+    block: Vec<u64>,
+    block_start: u32,
+}
+
+impl FileTable {
+    fn new(start: u64, len: u32, entry_len: u64) -> FileTable {
+        FileTable {
+            start,
+            len,
+            entry_len,
+            block: Vec::new(),
+            block_start: 0,
+        }
+    }
+
+    /// Returns whether entry `idx` can be returned without reading from the file.
+    fn is_loaded(&self, idx: u32) -> bool {
+        idx >= self.block_start && ((idx - self.block_start) as usize) < self.block.len()
+    }
+
+    fn get<R: Read + Seek>(&mut self, reader: &mut R, idx: u32) -> Result<Option<u64>, ReadError> {
+        if idx >= self.len {
+            return Ok(None);
+        }
+        if !self.is_loaded(idx) {
+            let block_start = idx - idx % TABLE_BLOCK_LEN;
+            let block_len = cmp::min(TABLE_BLOCK_LEN, self.len - block_start);
+            self.block = self.read(reader, block_start, block_len)?;
+            self.block_start = block_start;
+        }
+        Ok(Some(self.block[(idx - self.block_start) as usize]))
+    }
+
+    /// Reads `len` entries starting with entry `start`.
+    fn read<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        start: u32,
+        len: u32,
+    ) -> Result<Vec<u64>, ReadError> {
+        reader.seek(SeekFrom::Start(self.start + start as u64 * self.entry_len))?;
+        let mut buf = vec![0; (len as u64 * self.entry_len) as usize];
+        reader.read_exact(&mut buf)?;
+        let entries = buf.chunks(self.entry_len as usize);
+        Ok(entries
+            .map(|entry| entry.iter().fold(0, |value, &b| value << 8 | b as u64))
+            .collect())
+    }
+}
+
+impl ChunkCursor {
+    fn contains(&self, sample_idx: u32) -> bool {
+        sample_idx >= self.first_sample && sample_idx - self.first_sample < self.samples_per_chunk
+    }
+
+    /// Returns whether the chunk ends immediately before sample `sample_idx`.
+    fn precedes(&self, sample_idx: u32) -> bool {
+        sample_idx as u64 == self.first_sample as u64 + self.samples_per_chunk as u64
+    }
+}
+
 impl<R: Read + Seek> Mp4PacketReader<R> {
-    pub fn new(mut reader: R) -> Result<(Mp4PacketReader<R>, Vec<u8>), ReadError> {
-        let mut context = mp4parse::MediaContext::new();
-        mp4parse::read_mp4(&mut reader, &mut context)?;
+    pub fn new(reader: R) -> Result<(Mp4PacketReader<R>, Vec<u8>), ReadError> {
+        Mp4PacketReader::new_with_track(reader, 0)
+    }
+
+    /// Creates a packet reader for the ALAC track with index `track_index` in `tracks`.
+    pub fn new_with_track(
+        mut reader: R,
+        track_index: usize,
+    ) -> Result<(Mp4PacketReader<R>, Vec<u8>), ReadError> {
+        let context = read_moov(&mut reader)?;
+        let sample_entries = find_sound_sample_entries(&mut reader)?;
+        let magic_cookies = find_magic_cookies(&mut reader, &sample_entries)?;
+        let channel_layouts = find_channel_layouts(&mut reader, &sample_entries)?;
+        let itunes_gapless_info = read_itunes_gapless_info(&mut reader)?;
+        let metadata = read_ilst_metadata(&mut reader)?;
 
-        let track = context
+        let languages = read_track_languages(&mut reader)?;
+
+        // Tracks with invalid magic cookies can't be decoded so are skipped.
+        let alac_tracks: Vec<_> = context
             .tracks
-            .into_iter()
-            .filter(|track| track.codec_type == CodecType::ALAC)
-            .next()
-            .ok_or(mp4_error("no alac tracks found"))?;
-
-        let magic_cookie = if let Some(SampleEntry::Audio(AudioSampleEntry {
-            codec_specific: AudioCodecSpecific::ALACSpecificBox(alac),
-            ..
-        })) = track.data
-        {
-            alac.data
-        } else {
-            return Err(mp4_error("missing sample entry atom"));
+            .iter()
+            .enumerate()
+            .filter_map(|(i, track)| {
+                // Older QuickTime files wrap the magic cookie in a wave atom, which takes
+                // precedence.
+                let magic_cookie = magic_cookies
+                    .iter()
+                    .filter(|c| c.track == i)
+                    .max_by_key(|c| c.in_wave)?
+                    .cookie
+                    .clone();
+                let magic_cookie = match channel_layouts.iter().find(|l| l.0 == i) {
+                    Some(&(_, tag)) => ::cookie_with_channel_layout(magic_cookie, tag),
+                    None => magic_cookie,
+                };
+                let stream_info = StreamInfo::from_cookie(&magic_cookie).ok()?;
+                let samples = track.time_to_sample.iter();
+                let frames = samples.map(|&(count, delta)| count as u64 * delta as u64);
+                let total_frames = Some(frames.sum()).filter(|_| !track.time_to_sample.is_empty());
+                Some((i, magic_cookie, stream_info, total_frames))
+            }).collect();
+        if alac_tracks.is_empty() {
+            return Err(mp4_error("no alac tracks found"));
+        }
+
+        let (track_idx, ref magic_cookie, _, _) = *alac_tracks
+            .get(track_index)
+            .ok_or(mp4_error("track index is out of range"))?;
+        let magic_cookie = magic_cookie.clone();
+        let sample_rate = alac_tracks[track_index].2.sample_rate() as u64;
+
+        // The track is borrowed rather than moved out of the context so the context can be kept
+        // intact for `Reader::container_context`.
+        let track = &context.tracks[track_idx];
+        let track_id = track.track_id;
+        let stbl = track
+            .stbl
+            .ok_or(mp4_error("missing stbl (sample table) atom"))?;
+
+        let chunk_offsets = read_chunk_offsets(&mut reader, stbl)?;
+        let sample_to_chunk = read_sample_to_chunk(&mut reader, stbl)?;
+        let sample_sizes = read_sample_sizes(&mut reader, stbl)?;
+
+        // Sample durations are assumed to be in frames as ALAC tracks use the sample rate as their
+        // timescale.
+        let time_to_sample = track.time_to_sample.clone();
+
+        // Edit lists are used by most decoders so take precedence over the iTunes tag.
+        let total_frames = alac_tracks[track_index].3;
+        let movie_timescale = context.timescale.unwrap_or(0) as u64;
+        let edit_list = read_edit_list(
+            &mut reader,
+            track_id,
+            movie_timescale,
+            sample_rate,
+            total_frames,
+        )?;
+        let gapless_info = edit_list.or(itunes_gapless_info);
+
+        let mut packet_reader = Mp4PacketReader {
+            reader,
+            chunk_offsets,
+            sample_sizes,
+            sample_to_chunk,
+            time_to_sample,
+            gapless_info,
+            metadata,
+            lazy_chunk_offsets: None,
+            lazy_sample_sizes: None,
+            tracks: Vec::new(),
+            track_id,
+            sample_idx: 0,
+            packet_offset: 0,
+            chunk_cursor: None,
+            position: None,
+            packet_len_limit: u64::MAX,
+            #[cfg(feature = "container-context")]
+            context,
         };
+        if let Some(track_id) = track_id {
+            packet_reader.read_fragments(track_id)?;
+        }
 
-        let chunk_offsets = if let Some(stco) = track.stco {
-            stco.offsets
-        } else {
-            return Err(mp4_error("missing stco (chunk offset) atom"));
+        // Only the selected track's fragments are read.
+        let tracks = alac_tracks.into_iter().enumerate().map(|(j, track)| {
+            let (i, _, stream_info, mut total_frames) = track;
+            if j == track_index {
+                total_frames = packet_reader.total_frames();
+            }
+            let language = languages.get(i).cloned().unwrap_or_default();
+            TrackInfo::new(stream_info, total_frames, language)
+        });
+        packet_reader.tracks = tracks.collect();
+
+        Ok((packet_reader, magic_cookie))
+    }
+
+    /// Replaces the stream packets are read from.
+    fn with_reader<S>(self, reader: S) -> Mp4PacketReader<S> {
+        Mp4PacketReader {
+            reader,
+            chunk_offsets: self.chunk_offsets,
+            sample_sizes: self.sample_sizes,
+            lazy_chunk_offsets: self.lazy_chunk_offsets,
+            lazy_sample_sizes: self.lazy_sample_sizes,
+            sample_to_chunk: self.sample_to_chunk,
+            time_to_sample: self.time_to_sample,
+            gapless_info: self.gapless_info,
+            metadata: self.metadata,
+            tracks: self.tracks,
+            track_id: self.track_id,
+            sample_idx: self.sample_idx,
+            packet_offset: self.packet_offset,
+            chunk_cursor: self.chunk_cursor,
+            position: None,
+            packet_len_limit: self.packet_len_limit,
+            #[cfg(feature = "container-context")]
+            context: self.context,
+        }
+    }
+
+    /// Appends the samples in any movie fragments (`moof` boxes) to the sample tables.
+    ///
+    /// Each track run (`trun`) is treated as a chunk of contiguous samples.
+    fn read_fragments(&mut self, track_id: u32) -> Result<(), ReadError> {
+        let file_end = self.reader.seek(SeekFrom::End(0))?;
+
+        // Only files with a movie extends box contain fragments.
+        let moov = match find_box(&mut self.reader, 0, file_end, b"moov")? {
+            Some(moov) => moov,
+            None => return Ok(()),
+        };
+        let mvex = match find_box(&mut self.reader, moov.content_start, moov.end, b"mvex")? {
+            Some(mvex) => mvex,
+            None => return Ok(()),
         };
 
-        let sample_sizes = if let Some(stsz) = track.stsz {
-            stsz.sample_sizes
-        } else {
-            return Err(mp4_error("missing stsz (sample size) atom"));
+        let mut defaults = SampleDefaults {
+            duration: 0,
+            size: 0,
         };
+        let mut offset = mvex.content_start;
+        while let Some(trex) = find_box(&mut self.reader, offset, mvex.end, b"trex")? {
+            offset = trex.end;
+            // version and flags, track_ID, default_sample_description_index,
+            // default_sample_duration, default_sample_size, default_sample_flags
+            let trex = read_full_box(&mut self.reader, trex, 24)?;
+            if read_be_u32(&trex[4..8]) == track_id {
+                defaults.duration = read_be_u32(&trex[12..16]);
+                defaults.size = read_be_u32(&trex[16..20]);
+            }
+        }
 
-        let sample_to_chunk = if let Some(stsc) = track.stsc {
-            stsc.samples
-        } else {
-            return Err(mp4_error("missing stsc (sample to chunk) atom"));
-        };
-
-        let sample_to_chunk = sample_to_chunk
-            .into_iter()
-            .scan((0, 0), |&mut (ref mut samples, ref mut prev_chunk), s| {
-                // s.first_chunk is 1 indexed
-                let first_chunk = s.first_chunk - 1;
-                *samples += (first_chunk - *prev_chunk) * s.samples_per_chunk;
-                *prev_chunk = first_chunk;
-                Some(SampleToChunk {
-                    first_sample: *samples,
-                    first_chunk,
-                    samples_per_chunk: s.samples_per_chunk,
-                })
-            }).collect();
+        let mut offset = 0;
+        while let Some(moof) = find_box(&mut self.reader, offset, file_end, b"moof")? {
+            offset = moof.end;
 
-        Ok((
-            Mp4PacketReader {
-                reader,
-                chunk_offsets,
-                sample_sizes,
-                sample_to_chunk,
-                sample_idx: 0,
-            },
-            magic_cookie,
-        ))
+            let mut traf_offset = moof.content_start;
+            while let Some(traf) = find_box(&mut self.reader, traf_offset, moof.end, b"traf")? {
+                traf_offset = traf.end;
+                let tfhd = find_box(&mut self.reader, traf.content_start, traf.end, b"tfhd")?
+                    .ok_or(mp4_error("missing tfhd (track fragment header) atom"))?;
+                let tfhd = read_full_box(&mut self.reader, tfhd, 8)?;
+                if read_be_u32(&tfhd[4..8]) != track_id {
+                    continue;
+                }
+
+                let (base_data_offset, traf_defaults) = parse_tfhd(&tfhd, moof.start, defaults)?;
+                let mut data_offset = base_data_offset;
+                let mut trun_offset = traf.content_start;
+                while let Some(trun) = find_box(&mut self.reader, trun_offset, traf.end, b"trun")? {
+                    trun_offset = trun.end;
+                    let trun = read_full_box(&mut self.reader, trun, 8)?;
+                    data_offset = self.append_trun(
+                        &trun,
+                        base_data_offset,
+                        data_offset,
+                        traf_defaults,
+                        file_end,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the samples in a track run to the sample tables, returning the end of its data.
+    fn append_trun(
+        &mut self,
+        trun: &[u8],
+        base_data_offset: u64,
+        data_offset: u64,
+        defaults: SampleDefaults,
+        file_end: u64,
+    ) -> Result<u64, ReadError> {
+        let flags = read_be_u32(&trun[0..4]) & 0xff_ffff;
+        let sample_count = read_be_u32(&trun[4..8]);
+        let mut fields = &trun[8..];
+
+        // Without an explicit offset a run follows on from the previous run.
+        let mut offset = data_offset;
+        if flags & TRUN_DATA_OFFSET != 0 {
+            let relative_offset = next_trun_field(&mut fields)? as i32 as i64;
+            offset = base_data_offset
+                .checked_add_signed(relative_offset)
+                .ok_or_else(|| mp4_error("trun (track run) data offset is out of range"))?;
+        }
+        if flags & TRUN_FIRST_SAMPLE_FLAGS != 0 {
+            next_trun_field(&mut fields)?;
+        }
+
+        let sample_fields = [
+            TRUN_SAMPLE_DURATION,
+            TRUN_SAMPLE_SIZE,
+            TRUN_SAMPLE_FLAGS,
+            TRUN_SAMPLE_COMPOSITION_TIME_OFFSET,
+        ];
+        let entry_len = sample_fields.iter().filter(|&&f| flags & f != 0).count() as u64 * 4;
+
+        // Bound the number of samples by the size of the run or, if every sample has the default
+        // size, the size of the file.
+        let max_samples = (fields.len() as u64)
+            .checked_div(entry_len)
+            .or_else(|| file_end.checked_div(defaults.size as u64))
+            .unwrap_or(0);
+        let total_samples = self.sample_sizes.len() as u64 + sample_count as u64;
+        if sample_count as u64 > max_samples || total_samples > u32::MAX as u64 {
+            return Err(mp4_error("trun (track run) atom contains too many samples"));
+        }
+        if sample_count == 0 {
+            return Ok(offset);
+        }
+
+        self.sample_to_chunk.push(SampleToChunk {
+            first_sample: self.sample_sizes.len() as u32,
+            first_chunk: self.chunk_offsets.len() as u32,
+            samples_per_chunk: sample_count,
+        });
+        self.chunk_offsets.push(offset);
+
+        for _ in 0..sample_count {
+            let mut duration = defaults.duration;
+            let mut size = defaults.size;
+            if flags & TRUN_SAMPLE_DURATION != 0 {
+                duration = next_trun_field(&mut fields)?;
+            }
+            if flags & TRUN_SAMPLE_SIZE != 0 {
+                size = next_trun_field(&mut fields)?;
+            }
+            if flags & TRUN_SAMPLE_FLAGS != 0 {
+                next_trun_field(&mut fields)?;
+            }
+            if flags & TRUN_SAMPLE_COMPOSITION_TIME_OFFSET != 0 {
+                next_trun_field(&mut fields)?;
+            }
+
+            self.sample_sizes.push(size);
+            match self.time_to_sample.last_mut() {
+                Some(&mut (ref mut count, delta)) if delta == duration => *count += 1,
+                _ => self.time_to_sample.push((1, duration)),
+            }
+            offset = offset
+                .checked_add(size as u64)
+                .ok_or_else(|| mp4_error("trun (track run) data offset is out of range"))?;
+        }
+        Ok(offset)
+    }
+
+    #[cfg(feature = "container-context")]
+    pub fn context(&self) -> &Mp4Context {
+        &self.context
+    }
+
+    pub fn packet_count(&self) -> u64 {
+        match self.lazy_sample_sizes {
+            Some(ref table) => table.len as u64,
+            None => self.sample_sizes.len() as u64,
+        }
+    }
+
+    pub fn packet_index(&self) -> u64 {
+        self.sample_idx as u64
+    }
+
+    /// Returns the size of the largest sample, or `None` if the sample sizes are read lazily.
+    pub fn max_packet_len(&self) -> Option<u64> {
+        match self.lazy_sample_sizes {
+            Some(_) => None,
+            None => self.sample_sizes.iter().max().map(|&size| size as u64),
+        }
+    }
+
+    /// Returns the total number of frames in all packets, or `None` if the file has no time to
+    /// sample table.
+    pub fn total_frames(&self) -> Option<u64> {
+        if self.time_to_sample.is_empty() {
+            return None;
+        }
+        let frames = self.time_to_sample.iter();
+        Some(frames.map(|&(count, delta)| count as u64 * delta as u64).sum())
+    }
+
+    /// Returns the ALAC tracks in the file.
+    pub fn tracks(&self) -> &[TrackInfo] {
+        &self.tracks
+    }
+
+    /// Returns the encoder delay and stream length from the track's edit list or the iTunes
+    /// `iTunSMPB` tag, if present.
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.gapless_info
+    }
+
+    /// Returns the tags read from the file, leaving empty metadata in their place.
+    pub fn take_metadata(&mut self) -> Metadata {
+        mem::take(&mut self.metadata)
+    }
+
+    /// Returns the index of the packet containing `frame` and the frame that packet starts at, or
+    /// `None` if the file has no time to sample table.
+    pub fn packet_for_frame(&self, frame: u64) -> Option<(u64, u64)> {
+        if self.time_to_sample.is_empty() {
+            return None;
+        }
+
+        let mut packet = 0;
+        let mut packet_start = 0;
+        for &(count, delta) in &self.time_to_sample {
+            let run_frames = count as u64 * delta as u64;
+            if frame < packet_start + run_frames {
+                let packets_into_run = (frame - packet_start) / delta as u64;
+                return Some((
+                    packet + packets_into_run,
+                    packet_start + packets_into_run * delta as u64,
+                ));
+            }
+            packet += count as u64;
+            packet_start += run_frames;
+        }
+        Some((packet, packet_start))
+    }
+
+    /// Positions the reader so the next packet read is `packet`.
+    pub fn seek_to_packet(&mut self, packet: u64) -> Result<(), ReadError> {
+        let sample_idx = cmp::min(packet, self.packet_count()) as u32;
+
+        // Packets that don't start a chunk are read from the end of the previous packet, so find
+        // the start of the chunk containing the packet.
+        let mut packet_offset = 0;
+        if (sample_idx as u64) < self.packet_count() {
+            let cursor = self.find_chunk(sample_idx)?;
+            packet_offset = self
+                .cursor_offset(cursor, cursor.first_sample)?
+                .ok_or(mp4_error("missing chunk offset"))?;
+            for sample_idx in cursor.first_sample..sample_idx {
+                packet_offset = packet_offset.saturating_add(self.sample_size(sample_idx)? as u64);
+            }
+        }
+
+        self.sample_idx = sample_idx;
+        self.packet_offset = packet_offset;
+        Ok(())
+    }
+
+    /// Returns the stream position following the packet most recently read, if it is known.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    /// Sets the length of the longest packet that will be read.
+    pub fn set_packet_len_limit(&mut self, limit: u64) {
+        self.packet_len_limit = limit;
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
     }
 
     pub fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        let entry = match self.next_packet_entry()? {
+            Some(entry) => entry,
+            None => {
+                buf.clear();
+                return Ok(());
+            }
+        };
+
+        if entry.len > self.packet_len_limit {
+            return Err(mp4_error("packet is longer than the stream allows"));
+        }
+
+        // The sample index and offset are only advanced once the whole packet has been read so a
+        // failed read can be retried. The stream position is unknown until the read succeeds.
+        if self.position.take() != Some(entry.offset) {
+            self.reader.seek(SeekFrom::Start(entry.offset))?;
+        }
+        buf.resize(entry.len as usize, 0);
+        self.reader.read_exact(&mut buf[..])?;
+
+        self.advance(entry);
+        self.position = Some(self.packet_offset);
+        Ok(())
+    }
+
+    /// Moves past the next packet without reading it, returning its location in the file or
+    /// `None` if there are no more packets.
+    #[cfg_attr(not(feature = "mmap"), allow(dead_code))]
+    pub fn skip_packet(&mut self) -> Result<Option<PacketEntry>, ReadError> {
+        let entry = self.next_packet_entry()?;
+        if let Some(entry) = entry {
+            self.advance(entry);
+        }
+        Ok(entry)
+    }
+
+    fn next_packet_entry(&mut self) -> Result<Option<PacketEntry>, ReadError> {
         let sample_idx = self.sample_idx;
-        if sample_idx as usize == self.sample_sizes.len() {
-            buf.clear();
+        if sample_idx as u64 == self.packet_count() {
+            return Ok(None);
+        }
+
+        // Packets at the start of a chunk begin at the chunk offset, all others follow on from the
+        // previous packet.
+        let offset = match self.next_chunk_offset(sample_idx) {
+            Ok(Some(chunk_offset)) => chunk_offset,
+            Ok(None) => self.packet_offset,
+            Err(ReadError::Io(err)) => return Err(ReadError::Io(err)),
+            Err(err) => {
+                // Not a transient error so end the stream rather than failing again on retry.
+                self.sample_idx = self.packet_count() as u32;
+                return Err(err);
+            }
+        };
+        let len = self.sample_size(sample_idx)? as u64;
+        Ok(Some(PacketEntry { offset, len }))
+    }
+
+    fn advance(&mut self, entry: PacketEntry) {
+        self.sample_idx += 1;
+        self.packet_offset = entry.offset.saturating_add(entry.len);
+    }
+
+    /// Returns the offset and length of every packet.
+    ///
+    /// This is not available while sample tables are read lazily.
+    pub fn packet_entries(&self) -> Result<Vec<PacketEntry>, ReadError> {
+        if self.lazy_chunk_offsets.is_some() || self.lazy_sample_sizes.is_some() {
+            return Err(ReadError::Io(io::Error::new(
+                ErrorKind::Unsupported,
+                "packets cannot be indexed while sample tables are read lazily",
+            )));
+        }
+
+        let mut entries = Vec::with_capacity(self.sample_sizes.len());
+        let mut next_offset = 0;
+        for (sample_idx, &len) in self.sample_sizes.iter().enumerate() {
+            let cursor = self.find_chunk(sample_idx as u32)?;
+            let offset = if cursor.first_sample == sample_idx as u32 {
+                *self
+                    .chunk_offsets
+                    .get(cursor.chunk_idx as usize)
+                    .ok_or(mp4_error("missing chunk offset"))?
+            } else {
+                next_offset
+            };
+            entries.push(PacketEntry {
+                offset,
+                len: len as u64,
+            });
+            next_offset = offset.saturating_add(len as u64);
+        }
+        Ok(entries)
+    }
+
+    /// Switches between holding the sample size and chunk offset tables in memory and reading
+    /// them from the file as they are needed.
+    ///
+    /// Tables that don't match the file, such as those built from movie fragments, and compact
+    /// or constant sample sizes are always held in memory.
+    pub fn set_lazy_sample_tables(&mut self, enabled: bool) -> Result<(), ReadError> {
+        // The reader is moved when tables are read.
+        self.position = None;
+        if !enabled {
+            if let Some(table) = self.lazy_chunk_offsets.take() {
+                self.chunk_offsets = table.read(&mut self.reader, 0, table.len)?;
+            }
+            if let Some(table) = self.lazy_sample_sizes.take() {
+                let sizes = table.read(&mut self.reader, 0, table.len)?;
+                self.sample_sizes = sizes.into_iter().map(|size| size as u32).collect();
+            }
             return Ok(());
         }
 
+        let stbl = match find_stbl(&mut self.reader, self.track_id)? {
+            Some(stbl) => stbl,
+            None => return Ok(()),
+        };
+
+        if self.lazy_chunk_offsets.is_none() {
+            let stco = find_box(&mut self.reader, stbl.content_start, stbl.end, b"stco")?;
+            let co64 = find_box(&mut self.reader, stbl.content_start, stbl.end, b"co64")?;
+            let table = match (stco, co64) {
+                (Some(stco), _) => file_table(&mut self.reader, stco, 8, 4)?,
+                (None, Some(co64)) => file_table(&mut self.reader, co64, 8, 8)?,
+                (None, None) => None,
+            };
+            if let Some(table) = table.filter(|t| t.len as usize == self.chunk_offsets.len()) {
+                self.chunk_offsets = Vec::new();
+                self.lazy_chunk_offsets = Some(table);
+            }
+        }
+
+        if self.lazy_sample_sizes.is_none() {
+            let mut table = None;
+            if let Some(stsz) = find_box(&mut self.reader, stbl.content_start, stbl.end, b"stsz")? {
+                // Only tables of individual sample sizes are read from the file.
+                if read_box_u32(&mut self.reader, stsz, 4)? == 0 {
+                    table = file_table(&mut self.reader, stsz, 12, 4)?;
+                }
+            }
+            if let Some(table) = table.filter(|t| t.len as usize == self.sample_sizes.len()) {
+                self.sample_sizes = Vec::new();
+                self.lazy_sample_sizes = Some(table);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the size of sample `sample_idx`.
+    fn sample_size(&mut self, sample_idx: u32) -> Result<u32, ReadError> {
+        let size = match self.lazy_sample_sizes {
+            Some(ref mut table) => {
+                if !table.is_loaded(sample_idx) {
+                    self.position = None;
+                }
+                table
+                    .get(&mut self.reader, sample_idx)?
+                    .map(|size| size as u32)
+            }
+            None => self.sample_sizes.get(sample_idx as usize).cloned(),
+        };
+        size.ok_or(mp4_error("missing sample size"))
+    }
+
+    /// Like `chunk_offset` but for the packet following the one most recently read, which is
+    /// either in the same chunk or the next.
+    fn next_chunk_offset(&mut self, sample_idx: u32) -> Result<Option<u64>, ReadError> {
+        let cursor = match self.chunk_cursor {
+            Some(cursor) if cursor.contains(sample_idx) => cursor,
+            Some(cursor) if cursor.precedes(sample_idx) => {
+                self.following_chunk(cursor, sample_idx)?
+            }
+            _ => self.find_chunk(sample_idx)?,
+        };
+        self.chunk_cursor = Some(cursor);
+        self.cursor_offset(cursor, sample_idx)
+    }
+
+    fn cursor_offset(
+        &mut self,
+        cursor: ChunkCursor,
+        sample_idx: u32,
+    ) -> Result<Option<u64>, ReadError> {
+        if sample_idx != cursor.first_sample {
+            return Ok(None);
+        }
+        let chunk_offset = match self.lazy_chunk_offsets {
+            Some(ref mut table) => {
+                if !table.is_loaded(cursor.chunk_idx) {
+                    self.position = None;
+                }
+                table.get(&mut self.reader, cursor.chunk_idx)?
+            }
+            None => self.chunk_offsets.get(cursor.chunk_idx as usize).cloned(),
+        };
+        match chunk_offset {
+            Some(chunk_offset) => Ok(Some(chunk_offset)),
+            None => Err(mp4_error("missing chunk offset")),
+        }
+    }
+
+    /// Returns the chunk starting with `sample_idx` which immediately follows `cursor`.
+    fn following_chunk(
+        &self,
+        cursor: ChunkCursor,
+        sample_idx: u32,
+    ) -> Result<ChunkCursor, ReadError> {
+        // Moving to the next mapping is rare so it is found by searching.
+        match self.sample_to_chunk.get(cursor.mapping_idx + 1) {
+            Some(next) if next.first_sample <= sample_idx => self.find_chunk(sample_idx),
+            _ => Ok(ChunkCursor {
+                chunk_idx: cursor.chunk_idx + 1,
+                first_sample: sample_idx,
+                ..cursor
+            }),
+        }
+    }
+
+    /// Returns the chunk containing `sample_idx` by searching the sample to chunk mapping.
+    fn find_chunk(&self, sample_idx: u32) -> Result<ChunkCursor, ReadError> {
         // Find the current sample to chunk mapping
-        let sample_to_chunk_idx = self.sample_to_chunk
-            .binary_search_by_key(&self.sample_idx, |s| s.first_sample)
+        let mapping_idx = match self
+            .sample_to_chunk
+            .binary_search_by_key(&sample_idx, |s| s.first_sample)
+        {
+            Ok(i) => i,
             // If we are past s.first_sample we want the index of s
-            .unwrap_or_else(|i| i - 1);
-        let sample_to_chunk = self.sample_to_chunk[sample_to_chunk_idx];
+            Err(0) => return Err(mp4_error("missing sample to chunk mapping")),
+            Err(i) => i - 1,
+        };
+        let sample_to_chunk = self.sample_to_chunk[mapping_idx];
         let samples_per_chunk = sample_to_chunk.samples_per_chunk;
+        if samples_per_chunk == 0 {
+            return Err(mp4_error("chunks must contain one or more samples"));
+        }
+
+        let samples_past_first_chunk = sample_idx - sample_to_chunk.first_sample;
+        let chunks_past_first_chunk = samples_past_first_chunk / samples_per_chunk;
+        Ok(ChunkCursor {
+            mapping_idx,
+            chunk_idx: sample_to_chunk.first_chunk + chunks_past_first_chunk,
+            first_sample: sample_idx - samples_past_first_chunk % samples_per_chunk,
+            samples_per_chunk,
+        })
+    }
+}
+
+impl<R: Read> Mp4PacketReader<Streaming<R>> {
+    /// Creates a packet reader for a stream that cannot seek backwards.
+    ///
+    /// Every top-level box up to and including `moov` is buffered in memory and parsed as if it
+    /// were a complete file. Packets are then read from the stream as it is consumed.
+    pub fn new_streaming(
+        mut reader: Streaming<R>,
+    ) -> Result<(Mp4PacketReader<Streaming<R>>, Vec<u8>), ReadError> {
+        let mut header = Vec::new();
+        loop {
+            let box_start = header.len();
+            header.resize(box_start + 8, 0);
+            if let Err(err) = reader.read_exact(&mut header[box_start..]) {
+                return Err(match err.kind() {
+                    ErrorKind::UnexpectedEof => mp4_error("missing moov atom"),
+                    _ => err.into(),
+                });
+            }
 
-        let chunks_past_first_chunk =
-            (sample_idx - sample_to_chunk.first_sample) / samples_per_chunk;
-        let samples_into_chunk = sample_idx - chunks_past_first_chunk * samples_per_chunk;
+            let box_type = [
+                header[box_start + 4],
+                header[box_start + 5],
+                header[box_start + 6],
+                header[box_start + 7],
+            ];
+            if &box_type == b"mdat" {
+                return Err(mp4_error("mdat atom precedes moov atom in a streaming reader"));
+            }
+            let size = match read_be_u32(&header[box_start..box_start + 4]) {
+                0 => return Err(mp4_error("missing moov atom")),
+                1 => {
+                    let mut size = [0; 8];
+                    reader.read_exact(&mut size)?;
+                    header.extend_from_slice(&size);
+                    u64::from_be_bytes(size)
+                }
+                size => size as u64,
+            };
+            let content_len = size
+                .checked_sub((header.len() - box_start) as u64)
+                .ok_or(mp4_error("atom is too short"))?;
 
-        // Seek to next chunk offset if starting a new chunk
-        if samples_into_chunk == 0 {
-            let chunk_idx = sample_to_chunk.first_chunk + chunks_past_first_chunk;
-            let chunk_offset = self.chunk_offsets[chunk_idx as usize];
-            self.reader.seek(SeekFrom::Start(chunk_offset))?;
+            // The content is read incrementally so a corrupt size can't cause a huge allocation.
+            let read = (&mut reader)
+                .take(content_len)
+                .read_to_end(&mut header)?;
+            if read as u64 != content_len {
+                return Err(mp4_error("unexpected end of stream"));
+            }
+            if &box_type == b"moov" {
+                break;
+            }
         }
 
-        let packet_len = self.sample_sizes[sample_idx as usize] as usize;
-        buf.resize(packet_len, 0);
-        self.reader.read_exact(&mut buf[..])?;
-        self.sample_idx += 1;
-        Ok(())
+        let (packet_reader, magic_cookie) = Mp4PacketReader::new(Cursor::new(header))?;
+        Ok((packet_reader.with_reader(reader), magic_cookie))
+    }
+}
+
+/// Returns the language of each track, in the order of the trak atoms, from its media header.
+fn read_track_languages<R: Read + Seek>(reader: &mut R) -> Result<Vec<Option<String>>, ReadError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let moov = match find_box(reader, 0, file_end, b"moov")? {
+        Some(moov) => moov,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut languages = Vec::new();
+    let mut offset = moov.content_start;
+    while let Some(trak) = find_box(reader, offset, moov.end, b"trak")? {
+        offset = trak.end;
+        let mdhd = match find_box(reader, trak.content_start, trak.end, b"mdia")? {
+            Some(mdia) => find_box(reader, mdia.content_start, mdia.end, b"mdhd")?,
+            None => None,
+        };
+        let mdhd = match mdhd {
+            Some(mdhd) => read_box(reader, mdhd, 64)?,
+            None => None,
+        };
+        // The language follows the times, timescale and duration, which are 64 bit in version 1.
+        let language = mdhd.and_then(|mdhd| {
+            let offset = if mdhd.first() == Some(&1) { 32 } else { 20 };
+            let code = mdhd.get(offset..offset + 2)?;
+            let code = u16::from_be_bytes([code[0], code[1]]);
+            // Smaller values are QuickTime's Macintosh language codes, including 0 when the
+            // language is not given.
+            if code < 0x400 {
+                None
+            } else {
+                Some(parse_language(code))
+            }
+        });
+        languages.push(language);
+    }
+    Ok(languages)
+}
+
+/// Decodes an ISO 639-2/T language code packed as three 5 bit values offset from 0x60.
+fn parse_language(code: u16) -> String {
+    (0..3)
+        .rev()
+        .map(|i| (((code >> (i * 5)) & 0x1f) as u8 + 0x60) as char)
+        .collect()
+}
+
+/// A magic cookie found in a sample entry.
+struct MagicCookie {
+    // The index of the track in the order of the trak atoms.
+    track: usize,
+    // Whether the cookie is wrapped in a QuickTime `wave` atom.
+    in_wave: bool,
+    cookie: Vec<u8>,
+}
+
+/// An `alac` or `mp4a` sample entry.
+struct SoundSampleEntry {
+    // The index of the track in the order of the trak atoms.
+    track: usize,
+    // The start of the atoms following the sound sample description fields.
+    children_start: u64,
+    end: u64,
+}
+
+/// Finds the `alac` and `mp4a` sample entries in each track.
+fn find_sound_sample_entries<R: Read + Seek>(
+    reader: &mut R,
+) -> Result<Vec<SoundSampleEntry>, ReadError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let moov = match find_box(reader, 0, file_end, b"moov")? {
+        Some(moov) => moov,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut entries = Vec::new();
+    let mut offset = moov.content_start;
+    let mut tracks = 0;
+    while let Some(trak) = find_box(reader, offset, moov.end, b"trak")? {
+        offset = trak.end;
+        let track = tracks;
+        tracks += 1;
+
+        let mut range = (trak.content_start, trak.end);
+        for box_type in [b"mdia", b"minf", b"stbl", b"stsd"].iter() {
+            range = match find_box(reader, range.0, range.1, box_type)? {
+                Some(found) => (found.content_start, found.end),
+                None => (range.1, range.1),
+            };
+        }
+        // The sample description atom is a full box followed by an entry count.
+        let entries_start = cmp::min(range.0 + 8, range.1);
+
+        for entry_type in [b"alac", b"mp4a"].iter() {
+            let entry = match find_box(reader, entries_start, range.1, entry_type)? {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // Version 1 and 2 sound sample descriptions have extra fields.
+            let mut version = [0; 2];
+            reader.seek(SeekFrom::Start(entry.content_start + 8))?;
+            reader.read_exact(&mut version)?;
+            let fields_len = match u16::from_be_bytes(version) {
+                0 => 28,
+                1 => 44,
+                _ => 64,
+            };
+            entries.push(SoundSampleEntry {
+                track,
+                children_start: cmp::min(entry.content_start + fields_len, entry.end),
+                end: entry.end,
+            });
+        }
+    }
+    Ok(entries)
+}
+
+/// Finds the magic cookies of ALAC sample entries.
+///
+/// The cookie is usually stored in an `alac` atom within an `alac` sample entry, but older
+/// QuickTime files wrap it in a `wave` atom within an `alac` or `mp4a` sample entry.
+fn find_magic_cookies<R: Read + Seek>(
+    reader: &mut R,
+    entries: &[SoundSampleEntry],
+) -> Result<Vec<MagicCookie>, ReadError> {
+    let mut cookies = Vec::new();
+    for entry in entries {
+        // The alac atom is a full box.
+        let alac = match find_box(reader, entry.children_start, entry.end, b"alac")? {
+            Some(alac) => read_box(reader, alac, 1024)?,
+            None => None,
+        };
+        if let Some(cookie) = alac.as_ref().and_then(|alac| alac.get(4..)) {
+            cookies.push(MagicCookie {
+                track: entry.track,
+                in_wave: false,
+                cookie: cookie.to_vec(),
+            });
+        }
+
+        let wave = match find_box(reader, entry.children_start, entry.end, b"wave")? {
+            Some(wave) => wave,
+            None => continue,
+        };
+        if find_box(reader, wave.content_start, wave.end, b"alac")?.is_none() {
+            continue;
+        }
+        // `StreamInfo::from_cookie` accepts the content of the wave atom as a magic cookie.
+        if let Some(cookie) = read_box(reader, wave, 1024)? {
+            cookies.push(MagicCookie {
+                track: entry.track,
+                in_wave: true,
+                cookie,
+            });
+        }
+    }
+    Ok(cookies)
+}
+
+/// Returns the track index and Core Audio channel layout tag of each sample entry with a
+/// QuickTime (`chan`) or ISO (`chnl`) channel layout atom.
+fn find_channel_layouts<R: Read + Seek>(
+    reader: &mut R,
+    entries: &[SoundSampleEntry],
+) -> Result<Vec<(usize, u32)>, ReadError> {
+    let mut layouts = Vec::new();
+    for entry in entries {
+        // Both atoms are full boxes.
+        if let Some(chan) = find_box(reader, entry.children_start, entry.end, b"chan")? {
+            let tag =
+                read_box(reader, chan, 1024)?.and_then(|chan| chan.get(4..8).map(read_be_u32));
+            match tag {
+                // Layouts given by a channel bitmap or channel descriptions are not supported.
+                Some(0) | Some(0x10000) | None => (),
+                Some(tag) => layouts.push((entry.track, tag)),
+            }
+        } else if let Some(chnl) = find_box(reader, entry.children_start, entry.end, b"chnl")? {
+            let chnl = read_box(reader, chnl, 1024)?.unwrap_or_default();
+            if let Some(layout) = parse_chnl(&chnl) {
+                layouts.push((entry.track, layout.tag()));
+            }
+        }
+    }
+    Ok(layouts)
+}
+
+/// Returns the layout of a version 0 ISO channel layout atom that uses a predefined layout with
+/// no omitted channels.
+fn parse_chnl(chnl: &[u8]) -> Option<ChannelLayout> {
+    const CHANNEL_STRUCTURED: u8 = 1;
+    let (&version, &stream_structure, &defined_layout) =
+        (chnl.first()?, chnl.get(4)?, chnl.get(5)?);
+    if version != 0 || stream_structure & CHANNEL_STRUCTURED == 0 {
+        return None;
+    }
+    if chnl.get(6..14)? != [0; 8] {
+        return None;
+    }
+    // Predefined layouts are the channel configurations of ISO/IEC 23091-3.
+    match defined_layout {
+        1 => Some(ChannelLayout::Mono),
+        2 => Some(ChannelLayout::Stereo),
+        3 => Some(ChannelLayout::Mpeg3_0B),
+        4 => Some(ChannelLayout::Mpeg4_0B),
+        5 => Some(ChannelLayout::Mpeg5_0D),
+        6 => Some(ChannelLayout::Mpeg5_1D),
+        7 => Some(ChannelLayout::Mpeg7_1B),
+        11 => Some(ChannelLayout::Aac6_1),
+        _ => None,
+    }
+}
+
+/// The location of a box within the file.
+#[derive(Clone, Copy, Debug)]
+struct BoxRange {
+    start: u64,
+    content_start: u64,
+    end: u64,
+}
+
+/// Returns the location and type of the box starting at `offset`, which must end before `end`.
+///
+/// Malformed boxes return `None` as the boxes read this way are not required to decode the stream.
+fn next_box<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    end: u64,
+) -> Result<Option<(BoxRange, [u8; 4])>, ReadError> {
+    if offset + 8 > end {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+
+    let mut header_len = 8;
+    let size = match read_be_u32(&header[0..4]) {
+        // The box extends to the end of its parent.
+        0 => end - offset,
+        1 => {
+            let mut size = [0; 8];
+            reader.read_exact(&mut size)?;
+            header_len = 16;
+            u64::from_be_bytes(size)
+        }
+        size => size as u64,
+    };
+    if size < header_len || size > end - offset {
+        return Ok(None);
+    }
+
+    let range = BoxRange {
+        start: offset,
+        content_start: offset + header_len,
+        end: offset + size,
+    };
+    Ok(Some((range, [header[4], header[5], header[6], header[7]])))
+}
+
+/// Returns the location of the first box of type `box_type` between `start` and `end`.
+///
+/// Malformed boxes end the search as these boxes are not required to decode the stream.
+fn find_box<R: Read + Seek>(
+    reader: &mut R,
+    start: u64,
+    end: u64,
+    box_type: &[u8; 4],
+) -> Result<Option<BoxRange>, ReadError> {
+    let mut offset = start;
+    while let Some((range, found_type)) = next_box(reader, offset, end)? {
+        if &found_type == box_type {
+            return Ok(Some(range));
+        }
+        offset = range.end;
+    }
+    Ok(None)
+}
+
+/// Reads the movie (`moov`) box and the file type (`ftyp`) box preceding it.
+fn read_moov<R: Read + Seek>(reader: &mut R) -> Result<Mp4Context, ReadError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let moov = find_box(reader, 0, file_end, b"moov")?.ok_or(mp4_error("missing moov atom"))?;
+
+    let major_brand = match find_box(reader, 0, moov.start, b"ftyp")? {
+        Some(ftyp) => read_box(reader, ftyp, 1024)?.and_then(|ftyp| {
+            let brand = ftyp.get(0..4)?;
+            Some([brand[0], brand[1], brand[2], brand[3]])
+        }),
+        None => None,
+    };
+    let timescale = match find_box(reader, moov.content_start, moov.end, b"mvhd")? {
+        Some(mvhd) => Some(read_timescale(reader, mvhd)?),
+        None => None,
+    };
+
+    let mut tracks = Vec::new();
+    let mut offset = moov.content_start;
+    while let Some(trak) = find_box(reader, offset, moov.end, b"trak")? {
+        offset = trak.end;
+        let track_id = read_track_id(reader, trak)?;
+
+        let mut timescale = None;
+        let mut stbl = None;
+        if let Some(mdia) = find_box(reader, trak.content_start, trak.end, b"mdia")? {
+            if let Some(mdhd) = find_box(reader, mdia.content_start, mdia.end, b"mdhd")? {
+                timescale = Some(read_timescale(reader, mdhd)?);
+            }
+            if let Some(minf) = find_box(reader, mdia.content_start, mdia.end, b"minf")? {
+                stbl = find_box(reader, minf.content_start, minf.end, b"stbl")?;
+            }
+        }
+
+        let stts = match stbl {
+            Some(stbl) => find_box(reader, stbl.content_start, stbl.end, b"stts")?,
+            None => None,
+        };
+        let time_to_sample = match stts {
+            Some(stts) => read_table(reader, stts, 8, 8, |entry| {
+                (read_be_u32(&entry[0..4]), read_be_u32(&entry[4..8]))
+            })?,
+            None => Vec::new(),
+        };
+
+        tracks.push(Mp4Track {
+            track_id,
+            timescale,
+            time_to_sample,
+            stbl,
+        });
+    }
+
+    Ok(Mp4Context {
+        major_brand,
+        timescale,
+        tracks,
+    })
+}
+
+/// Reads the timescale of a movie (`mvhd`) or media (`mdhd`) header found with `find_box`.
+fn read_timescale<R: Read + Seek>(reader: &mut R, header: BoxRange) -> Result<u32, ReadError> {
+    // The timescale follows the creation and modification times, which are 64 bit in version 1.
+    let version = read_box_u32(reader, header, 0)? >> 24;
+    read_box_u32(reader, header, if version == 1 { 20 } else { 12 })
+}
+
+/// Reads the track ID from the track header (`tkhd`) of a trak atom found with `find_box`.
+fn read_track_id<R: Read + Seek>(reader: &mut R, trak: BoxRange) -> Result<Option<u32>, ReadError> {
+    let tkhd = match find_box(reader, trak.content_start, trak.end, b"tkhd")? {
+        Some(tkhd) => read_full_box(reader, tkhd, 24)?,
+        None => return Ok(None),
+    };
+    // The track ID follows the creation and modification times, which are 64 bit in version 1.
+    let id_offset = if tkhd[0] == 1 { 20 } else { 12 };
+    Ok(Some(read_be_u32(&tkhd[id_offset..id_offset + 4])))
+}
+
+/// Reads the entries of a sample table atom found with `find_box`, a full box whose entry count
+/// ends `header_len` bytes into its content and is followed by `entry_len` byte entries.
+fn read_table<R, T, F>(
+    reader: &mut R,
+    range: BoxRange,
+    header_len: usize,
+    entry_len: usize,
+    parse_entry: F,
+) -> Result<Vec<T>, ReadError>
+where
+    R: Read + Seek,
+    F: FnMut(&[u8]) -> T,
+{
+    let table = read_full_box(reader, range, header_len as u64)?;
+    let len = read_be_u32(&table[header_len - 4..header_len]) as u64;
+    let entries = &table[header_len..];
+    if len * entry_len as u64 > entries.len() as u64 {
+        return Err(mp4_error("sample table atom is too short"));
+    }
+    Ok(entries
+        .chunks_exact(entry_len)
+        .take(len as usize)
+        .map(parse_entry)
+        .collect())
+}
+
+/// Reads the 32 bit (stco) or 64 bit (co64) chunk offsets of a sample table.
+fn read_chunk_offsets<R: Read + Seek>(
+    reader: &mut R,
+    stbl: BoxRange,
+) -> Result<Vec<u64>, ReadError> {
+    if let Some(stco) = find_box(reader, stbl.content_start, stbl.end, b"stco")? {
+        read_table(reader, stco, 8, 4, |entry| read_be_u32(entry) as u64)
+    } else if let Some(co64) = find_box(reader, stbl.content_start, stbl.end, b"co64")? {
+        read_table(reader, co64, 8, 8, |entry| {
+            let mut offset = [0; 8];
+            offset.copy_from_slice(entry);
+            u64::from_be_bytes(offset)
+        })
+    } else {
+        Err(mp4_error("missing stco or co64 (chunk offset) atom"))
+    }
+}
+
+/// Reads the sample to chunk (stsc) table of a sample table, numbering its samples.
+fn read_sample_to_chunk<R: Read + Seek>(
+    reader: &mut R,
+    stbl: BoxRange,
+) -> Result<Vec<SampleToChunk>, ReadError> {
+    let stsc = find_box(reader, stbl.content_start, stbl.end, b"stsc")?
+        .ok_or(mp4_error("missing stsc (sample to chunk) atom"))?;
+    // Each entry is a first chunk, samples per chunk and sample description index.
+    let entries = read_table(reader, stsc, 8, 12, |entry| {
+        (read_be_u32(&entry[0..4]), read_be_u32(&entry[4..8]))
+    })?;
+
+    let mut sample_to_chunk = Vec::with_capacity(entries.len());
+    let (mut samples, mut prev_chunk, mut prev_samples_per_chunk) = (0u32, 0, 0);
+    for (first_chunk, samples_per_chunk) in entries {
+        // first_chunk is 1 indexed and entries must be in increasing chunk order.
+        let first_chunk = match first_chunk.checked_sub(1) {
+            Some(first_chunk) if first_chunk >= prev_chunk => first_chunk,
+            _ => return Err(mp4_error("invalid stsc (sample to chunk) atom")),
+        };
+        // The chunks since the previous entry use the previous entry's samples per chunk.
+        samples = (first_chunk - prev_chunk)
+            .checked_mul(prev_samples_per_chunk)
+            .and_then(|s| s.checked_add(samples))
+            .ok_or(mp4_error("stsc (sample to chunk) atom contains too many samples"))?;
+        prev_chunk = first_chunk;
+        prev_samples_per_chunk = samples_per_chunk;
+        sample_to_chunk.push(SampleToChunk {
+            first_sample: samples,
+            first_chunk,
+            samples_per_chunk,
+        });
+    }
+    Ok(sample_to_chunk)
+}
+
+/// Reads the sample sizes from the sample size (stsz) or compact sample size (stz2) atom of a
+/// sample table.
+fn read_sample_sizes<R: Read + Seek>(
+    reader: &mut R,
+    stbl: BoxRange,
+) -> Result<Vec<u32>, ReadError> {
+    if let Some(stsz) = find_box(reader, stbl.content_start, stbl.end, b"stsz")? {
+        // The sizes are only listed if the sample size field is zero.
+        let sample_size = read_box_u32(reader, stsz, 4)?;
+        if sample_size == 0 {
+            return read_table(reader, stsz, 12, 4, read_be_u32);
+        }
+        let sample_count = read_box_u32(reader, stsz, 8)?;
+        let file_end = reader.seek(SeekFrom::End(0))?;
+        if sample_count as u64 * sample_size as u64 > file_end {
+            return Err(mp4_error("stsz (sample size) atom contains too many samples"));
+        }
+        Ok(vec![sample_size; sample_count as usize])
+    } else if let Some(stz2) = find_box(reader, stbl.content_start, stbl.end, b"stz2")? {
+        parse_stz2(&read_full_box(reader, stz2, 12)?)
+    } else {
+        Err(mp4_error("missing stsz or stz2 (sample size) atom"))
+    }
+}
+
+/// Returns the trak atom of the track with ID `track_id`, or the first track if the ID is unknown.
+fn find_trak<R: Read + Seek>(
+    reader: &mut R,
+    track_id: Option<u32>,
+) -> Result<Option<BoxRange>, ReadError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let moov = match find_box(reader, 0, file_end, b"moov")? {
+        Some(moov) => moov,
+        None => return Ok(None),
+    };
+
+    let mut offset = moov.content_start;
+    while let Some(trak) = find_box(reader, offset, moov.end, b"trak")? {
+        offset = trak.end;
+        if track_id.is_some() && read_track_id(reader, trak)? != track_id {
+            continue;
+        }
+        return Ok(Some(trak));
+    }
+    Ok(None)
+}
+
+/// Returns the sample table (stbl) atom of the track with ID `track_id`, or of the first track if
+/// the ID is unknown.
+fn find_stbl<R: Read + Seek>(
+    reader: &mut R,
+    track_id: Option<u32>,
+) -> Result<Option<BoxRange>, ReadError> {
+    let mut found = match find_trak(reader, track_id)? {
+        Some(trak) => trak,
+        None => return Ok(None),
+    };
+    for box_type in [b"mdia", b"minf", b"stbl"].iter() {
+        found = match find_box(reader, found.content_start, found.end, box_type)? {
+            Some(found) => found,
+            None => return Ok(None),
+        };
+    }
+    Ok(Some(found))
+}
+
+/// Returns the table of `entry_len` byte entries in a full box found with `find_box`, which
+/// starts `header_len` bytes into its content after the entry count.
+fn file_table<R: Read + Seek>(
+    reader: &mut R,
+    range: BoxRange,
+    header_len: u64,
+    entry_len: u64,
+) -> Result<Option<FileTable>, ReadError> {
+    if range.end - range.content_start < header_len {
+        return Ok(None);
+    }
+    let len = read_box_u32(reader, range, header_len - 4)?;
+    let start = range.content_start + header_len;
+    if len as u64 * entry_len > range.end - start {
+        return Err(mp4_error("sample table atom is too short"));
+    }
+    Ok(Some(FileTable::new(start, len, entry_len)))
+}
+
+/// Reads the 32 bit field `offset` bytes into the content of a box found with `find_box`.
+fn read_box_u32<R: Read + Seek>(
+    reader: &mut R,
+    range: BoxRange,
+    offset: u64,
+) -> Result<u32, ReadError> {
+    if range.end - range.content_start < offset + 4 {
+        return Err(mp4_error("atom is too short"));
+    }
+    reader.seek(SeekFrom::Start(range.content_start + offset))?;
+    let mut field = [0; 4];
+    reader.read_exact(&mut field)?;
+    Ok(read_be_u32(&field))
+}
+
+/// Returns the start and end of the items in the `moov/udta/meta/ilst` box holding iTunes tags.
+fn find_ilst<R: Read + Seek>(reader: &mut R) -> Result<Option<(u64, u64)>, ReadError> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+
+    let mut range = (0, file_end);
+    for (i, box_type) in [b"moov", b"udta", b"meta", b"ilst"].iter().enumerate() {
+        range = match find_box(reader, range.0, range.1, box_type)? {
+            Some(found) => (found.content_start, found.end),
+            None => return Ok(None),
+        };
+        // The meta box is a full box with a version and flags before its children.
+        if i == 2 {
+            range.0 = cmp::min(range.0 + 4, range.1);
+        }
+    }
+    Ok(Some(range))
+}
+
+/// Reads the encoder delay and stream length from the edit list (`edts/elst`) of the track with
+/// ID `track_id`.
+///
+/// Only the first edit of the media is used. Empty edits, which delay the start of the track,
+/// are ignored. The media timescale is assumed to be the sample rate, and `movie_timescale` is
+/// the timescale of edit durations.
+fn read_edit_list<R: Read + Seek>(
+    reader: &mut R,
+    track_id: Option<u32>,
+    movie_timescale: u64,
+    sample_rate: u64,
+    total_frames: Option<u64>,
+) -> Result<Option<GaplessInfo>, ReadError> {
+    let trak = match find_trak(reader, track_id)? {
+        Some(trak) => trak,
+        None => return Ok(None),
+    };
+    let elst = match find_box(reader, trak.content_start, trak.end, b"edts")? {
+        Some(edts) => find_box(reader, edts.content_start, edts.end, b"elst")?,
+        None => None,
+    };
+    let elst = match elst {
+        Some(elst) => read_full_box(reader, elst, 8)?,
+        None => return Ok(None),
+    };
+
+    // Each edit is a segment duration, media time and rate, with 64 bit durations and times in
+    // version 1.
+    let version = elst[0];
+    let entry_count = read_be_u32(&elst[4..8]) as usize;
+    let entry_len = if version == 1 { 20 } else { 12 };
+    let edits = elst[8..].chunks(entry_len).take(entry_count);
+    for edit in edits.filter(|edit| edit.len() == entry_len) {
+        let (segment_duration, media_time) = if version == 1 {
+            let mut segment_duration = [0; 8];
+            segment_duration.copy_from_slice(&edit[0..8]);
+            let mut media_time = [0; 8];
+            media_time.copy_from_slice(&edit[8..16]);
+            (u64::from_be_bytes(segment_duration), i64::from_be_bytes(media_time))
+        } else {
+            (read_be_u32(&edit[0..4]) as u64, read_be_u32(&edit[4..8]) as i32 as i64)
+        };
+        if media_time < 0 {
+            continue;
+        }
+
+        let priming_frames = media_time as u64;
+        // A zero duration edit extends to the end of the media.
+        let valid_frames = if segment_duration == 0 {
+            match total_frames {
+                Some(total_frames) => total_frames.saturating_sub(priming_frames),
+                None => return Ok(None),
+            }
+        } else if movie_timescale == 0 {
+            return Ok(None);
+        } else {
+            (segment_duration as u128 * sample_rate as u128 / movie_timescale as u128) as u64
+        };
+        return Ok(Some(GaplessInfo::new(priming_frames, valid_frames)));
+    }
+    Ok(None)
+}
+
+/// Parses the content of a compact sample size (stz2) atom, which stores sizes as 4, 8 or 16 bit
+/// fields.
+fn parse_stz2(stz2: &[u8]) -> Result<Vec<u32>, ReadError> {
+    let field_size = stz2[7];
+    let sample_count = read_be_u32(&stz2[8..12]) as usize;
+    let fields = &stz2[12..];
+    if !matches!(field_size, 4 | 8 | 16) {
+        return Err(mp4_error("invalid stz2 (compact sample size) field size"));
+    }
+    if sample_count as u64 * field_size as u64 > fields.len() as u64 * 8 {
+        return Err(mp4_error("stz2 (compact sample size) atom is too short"));
+    }
+
+    let sample_sizes = (0..sample_count).map(|i| match field_size {
+        // The first of each pair of 4 bit fields is in the high bits.
+        4 if i % 2 == 0 => (fields[i / 2] >> 4) as u32,
+        4 => (fields[i / 2] & 0xf) as u32,
+        8 => fields[i] as u32,
+        _ => u16::from_be_bytes([fields[2 * i], fields[2 * i + 1]]) as u32,
+    });
+    Ok(sample_sizes.collect())
+}
+
+/// Reads the content of a full box found with `find_box`, which must be at least `min_len` bytes
+/// long including its version and flags.
+fn read_full_box<R: Read + Seek>(
+    reader: &mut R,
+    range: BoxRange,
+    min_len: u64,
+) -> Result<Vec<u8>, ReadError> {
+    if range.end - range.content_start < min_len {
+        return Err(mp4_error("atom is too short"));
+    }
+    reader.seek(SeekFrom::Start(range.content_start))?;
+    let mut buf = vec![0; (range.end - range.content_start) as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Reads the content of a box found with `find_box` if it is no longer than `max_len`.
+fn read_box<R: Read + Seek>(
+    reader: &mut R,
+    range: BoxRange,
+    max_len: u64,
+) -> Result<Option<Vec<u8>>, ReadError> {
+    if range.end - range.content_start > max_len {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(range.content_start))?;
+    let mut buf = vec![0; (range.end - range.content_start) as usize];
+    reader.read_exact(&mut buf)?;
+    Ok(Some(buf))
+}
+
+/// Reads the encoder delay and stream length from the `iTunSMPB` tag written by iTunes and
+/// afconvert.
+///
+/// The tag is stored in `moov/udta/meta/ilst` as a freeform (`----`) item. Its value is a string
+/// of hexadecimal fields, the second to fourth of which are the priming frames, padding frames and
+/// number of valid frames.
+fn read_itunes_gapless_info<R: Read + Seek>(
+    reader: &mut R,
+) -> Result<Option<GaplessInfo>, ReadError> {
+    let (mut offset, ilst_end) = match find_ilst(reader)? {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    while let Some(item) = find_box(reader, offset, ilst_end, b"----")? {
+        offset = item.end;
+
+        // Both boxes are full boxes, and data boxes also contain a type indicator and locale.
+        let name = match find_box(reader, item.content_start, item.end, b"name")? {
+            Some(name) => read_box(reader, name, 64)?,
+            None => None,
+        };
+        if name.as_ref().map(|n| n.get(4..)) != Some(Some(&b"iTunSMPB"[..])) {
+            continue;
+        }
+        let data = match find_box(reader, item.content_start, item.end, b"data")? {
+            Some(data) => read_box(reader, data, 256)?,
+            None => None,
+        };
+
+        let value = match data.as_ref().and_then(|d| d.get(8..)) {
+            Some(value) => String::from_utf8_lossy(value).into_owned(),
+            None => return Ok(None),
+        };
+        let fields: Vec<_> = value
+            .split_whitespace()
+            .map(|field| u64::from_str_radix(field, 16).ok())
+            .collect();
+        return Ok(match (fields.get(1), fields.get(3)) {
+            (Some(&Some(priming_frames)), Some(&Some(valid_frames))) => {
+                Some(GaplessInfo::new(priming_frames, valid_frames))
+            }
+            _ => None,
+        });
+    }
+    Ok(None)
+}
+
+/// Reads the title, artist, album, track number and cover art tags from `moov/udta/meta/ilst`.
+///
+/// Each item's value is stored in a data box after a type indicator and locale.
+fn read_ilst_metadata<R: Read + Seek>(reader: &mut R) -> Result<Metadata, ReadError> {
+    let mut metadata = Metadata::default();
+    let (mut offset, ilst_end) = match find_ilst(reader)? {
+        Some(range) => range,
+        None => return Ok(metadata),
+    };
+    while let Some((item, item_type)) = next_box(reader, offset, ilst_end)? {
+        offset = item.end;
+
+        let data = match find_box(reader, item.content_start, item.end, b"data")? {
+            Some(data) => read_box(reader, data, MAX_TAG_LEN)?,
+            None => None,
+        };
+        let value = match data.as_ref().and_then(|d| d.get(8..)) {
+            Some(value) => value,
+            None => continue,
+        };
+        let text = || Some(String::from_utf8_lossy(value).into_owned());
+
+        match &item_type {
+            b"\xa9nam" => metadata.title = text(),
+            b"\xa9ART" => metadata.artist = text(),
+            b"\xa9alb" => metadata.album = text(),
+            b"trkn" if value.len() >= 6 => {
+                // The number and count follow two reserved bytes. Zero means unknown.
+                let number = u16::from_be_bytes([value[2], value[3]]);
+                let count = u16::from_be_bytes([value[4], value[5]]);
+                metadata.track_number = Some(number as u32).filter(|&n| n != 0);
+                metadata.track_count = Some(count as u32).filter(|&n| n != 0);
+            }
+            b"covr" => metadata.cover_art = Some(value.to_vec()),
+            _ => (),
+        }
+    }
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_stz2;
+
+    #[test]
+    fn parse_stz2_field_sizes() {
+        let header = |field_size: u8, sample_count: u8| {
+            vec![0, 0, 0, 0, 0, 0, 0, field_size, 0, 0, 0, sample_count]
+        };
+
+        let mut stz2 = header(4, 3);
+        stz2.extend_from_slice(&[0x12, 0x30]);
+        assert_eq!(parse_stz2(&stz2).unwrap(), [1, 2, 3]);
+
+        let mut stz2 = header(8, 2);
+        stz2.extend_from_slice(&[200, 7]);
+        assert_eq!(parse_stz2(&stz2).unwrap(), [200, 7]);
+
+        let mut stz2 = header(16, 2);
+        stz2.extend_from_slice(&[0x12, 0x34, 0, 1]);
+        assert_eq!(parse_stz2(&stz2).unwrap(), [0x1234, 1]);
+
+        // Too few fields for the sample count.
+        assert!(parse_stz2(&stz2[..15]).is_err());
+        assert!(parse_stz2(&header(12, 0)).is_err());
     }
 }
@@ -0,0 +1,136 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use {invalid_data, ErrorKind, Format, ReadError};
+
+/// Writes `packet` to `writer` in the raw packet stream format read by `Reader::new_raw`.
+///
+/// Each packet is preceded by its length in bytes as a big endian `u32`. The stream has no header
+/// so the magic cookie must be passed to the reader separately.
+pub fn write_raw_packet<W: Write>(writer: &mut W, packet: &[u8]) -> io::Result<()> {
+    if packet.len() > u32::MAX as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "packet is too long for a raw packet stream",
+        ));
+    }
+    writer.write_all(&(packet.len() as u32).to_be_bytes())?;
+    writer.write_all(packet)
+}
+
+pub struct RawPacketReader<R> {
+    reader: R,
+    max_packet_len: usize,
+    // The stream position of the first packet.
+    start: u64,
+    packet_idx: u64,
+    // The position of `reader`, or `None` if it is unknown after a failed read.
+    position: Option<u64>,
+}
+
+impl<R: Read + Seek> RawPacketReader<R> {
+    pub fn new(mut reader: R, max_packet_len: usize) -> Result<RawPacketReader<R>, ReadError> {
+        let start = reader.stream_position()?;
+        Ok(RawPacketReader {
+            reader,
+            max_packet_len,
+            start,
+            packet_idx: 0,
+            position: Some(start),
+        })
+    }
+
+    pub fn packet_index(&self) -> u64 {
+        self.packet_idx
+    }
+
+    /// Returns the stream position following the packet most recently read, if it is known.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        buf.clear();
+        // After a failed read the stream is rescanned so the read can be retried.
+        if self.position.is_none() {
+            let packet = self.packet_idx;
+            self.seek_to_packet(packet)?;
+        }
+        let len = match self.read_len()? {
+            Some(len) => len,
+            None => return Ok(()),
+        };
+        buf.resize(len, 0);
+        if let Err(err) = self.reader.read_exact(&mut buf[..]) {
+            self.position = None;
+            return Err(err.into());
+        }
+
+        self.packet_idx += 1;
+        self.position = self.position.map(|position| position + 4 + len as u64);
+        Ok(())
+    }
+
+    /// Moves to packet `packet` by skipping over earlier packets, starting again from the first
+    /// packet when moving backwards. Seeking past the last packet leaves the reader at the end of
+    /// the stream.
+    pub fn seek_to_packet(&mut self, packet: u64) -> Result<(), ReadError> {
+        if packet < self.packet_idx || self.position.is_none() {
+            self.reader.seek(SeekFrom::Start(self.start))?;
+            self.position = Some(self.start);
+            self.packet_idx = 0;
+        }
+
+        while self.packet_idx < packet {
+            let len = match self.read_len()? {
+                Some(len) => len as i64,
+                None => break,
+            };
+            self.reader.seek(SeekFrom::Current(len))?;
+            self.packet_idx += 1;
+            self.position = self.position.map(|position| position + 4 + len as u64);
+        }
+        Ok(())
+    }
+
+    /// Reads the length of the next packet, or returns `None` at the end of the stream.
+    fn read_len(&mut self) -> Result<Option<usize>, ReadError> {
+        let mut len = [0; 4];
+        let mut filled = 0;
+        while filled < len.len() {
+            match self.reader.read(&mut len[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => {
+                    self.position = None;
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                Ok(n) => filled += n,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => {
+                    self.position = None;
+                    return Err(err.into());
+                }
+            }
+        }
+
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > self.max_packet_len {
+            self.position = None;
+            return Err(ReadError::Format(
+                Format::Raw,
+                invalid_data(
+                    ErrorKind::InvalidContainer,
+                    "raw packet length is out of range",
+                ),
+            ));
+        }
+        Ok(Some(len))
+    }
+}
@@ -1,49 +1,248 @@
+use std::cmp;
 use std::error;
 use std::fmt;
 use std::io::{self, Read, Seek, SeekFrom};
-use std::marker::PhantomData;
+#[cfg(feature = "mmap")]
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
-use {Decoder, InvalidData, Sample, StreamInfo};
+#[cfg(feature = "rayon")]
+extern crate rayon;
+
+#[cfg(feature = "rayon")]
+use self::rayon::prelude::*;
+use dec::{self, DecodedPacket};
+use index::{IndexedPacketReader, PacketEntry, PacketIndex};
+#[cfg(feature = "mmap")]
+use mmap::MappedFile;
+use raw::RawPacketReader;
+use source::{PacketSource, SourcePacketReader};
+#[cfg(feature = "threaded")]
+use threaded::{ThreadedPackets, ThreadedSamples};
+use {
+    invalid_data, Decoder, ErrorKind, InvalidData, Metadata, Requantize, Sample, StreamInfo,
+    Streaming,
+};
 
 /// The format of an ALAC file.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum Format {
     #[cfg(feature = "caf")]
     Caf,
     #[cfg(feature = "mp4")]
     Mp4,
-    #[doc(hidden)]
-    __Nonexhaustive,
+    /// A stream of length-prefixed packets read with `Reader::new_raw`.
+    Raw,
+    /// A container read by a user-provided `PacketSource`.
+    Custom,
+}
+
+/// The parsed container of an ALAC file.
+#[cfg(feature = "container-context")]
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum ContainerContext<'a> {
+    #[cfg(feature = "caf")]
+    Caf(&'a CafContext),
+    #[cfg(feature = "mp4")]
+    Mp4(&'a Mp4Context),
+}
+
+/// Encoder delay and stream length used for gapless playback.
+///
+/// Encoders add priming frames to the start of a stream and may pad the final packet. Trimming
+/// both gives exactly the original audio so that consecutive tracks play without a gap.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GaplessInfo {
+    priming_frames: u64,
+    valid_frames: u64,
+}
+
+impl GaplessInfo {
+    pub(crate) fn new(priming_frames: u64, valid_frames: u64) -> GaplessInfo {
+        GaplessInfo {
+            priming_frames,
+            valid_frames,
+        }
+    }
+
+    /// Returns the number of frames added by the encoder at the start of the stream.
+    pub fn priming_frames(&self) -> u64 {
+        self.priming_frames
+    }
+
+    /// Returns the number of frames of audio following the priming frames, excluding padding.
+    pub fn valid_frames(&self) -> u64 {
+        self.valid_frames
+    }
+}
+
+/// Information about an ALAC track in a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackInfo {
+    stream_info: StreamInfo,
+    total_frames: Option<u64>,
+    language: Option<String>,
+}
+
+impl TrackInfo {
+    pub(crate) fn new(
+        stream_info: StreamInfo,
+        total_frames: Option<u64>,
+        language: Option<String>,
+    ) -> TrackInfo {
+        TrackInfo {
+            stream_info,
+            total_frames,
+            language,
+        }
+    }
+
+    /// Returns the codec parameters of the track.
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    /// Returns the number of samples per channel in the track, including any priming and padding
+    /// frames, if the container gives it.
+    pub fn total_samples(&self) -> Option<u64> {
+        self.total_frames
+    }
+
+    /// Returns the duration of the track, including any priming and padding frames, if the
+    /// container gives it.
+    pub fn duration(&self) -> Option<Duration> {
+        let total_samples = self.total_frames?;
+        let sample_rate = self.stream_info.sample_rate() as u64;
+        let nanos = (total_samples % sample_rate) * 1_000_000_000 / sample_rate;
+        Some(Duration::new(total_samples / sample_rate, nanos as u32))
+    }
+
+    /// Returns the ISO 639-2/T language code of the track, such as `"eng"`, if the container
+    /// gives it.
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_ref().map(|l| &l[..])
+    }
 }
 
 /// An error when reading an ALAC file using a `Reader`.
 ///
-/// A `ReadError::Decoder` will occur if the current packet is invalid. If more samples are read
+/// A `ReadError::Packet` will occur if the current packet is invalid. If more samples are read
 /// the reader will skip to the next packet.
+///
+/// A `ReadError::Io` that occurs while reading a packet, for example `ErrorKind::WouldBlock` or
+/// `ErrorKind::Interrupted`, leaves the reader positioned at the start of that packet. If more
+/// samples are read the packet will be read again.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum ReadError {
+    /// The underlying reader returned an error.
     Io(io::Error),
+    /// The file is not in a supported container format.
     UnsupportedFormat,
+    /// The container is invalid or does not contain an ALAC stream.
     Format(Format, InvalidData),
+    /// The stream's magic cookie is invalid.
     Decoder(InvalidData),
+    /// The packet at the given location could not be decoded.
+    Packet(PacketLocation, InvalidData),
 }
 
-impl error::Error for ReadError {
-    fn description(&self) -> &str {
-        match *self {
-            ReadError::Io(_) => "IO error",
-            ReadError::UnsupportedFormat => "unsupported format",
-            ReadError::Format(_, _) => "format error",
-            ReadError::Decoder(_) => "decoder error",
+/// How a `Samples` iterator handles packets that fail to decode.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ErrorPolicy {
+    /// A `ReadError::Packet` is yielded and iteration continues from the next packet.
+    #[default]
+    Report,
+    /// The packet is dropped without an error.
+    Skip,
+    /// The packet is replaced with a packet of silence.
+    SilenceFill,
+    /// A `ReadError::Packet` is yielded and iteration ends.
+    Stop,
+}
+
+/// The location of a packet in a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PacketLocation {
+    pub(crate) packet_index: u64,
+    pub(crate) byte_offset: Option<u64>,
+    pub(crate) sample_position: u64,
+}
+
+impl PacketLocation {
+    /// Returns the index of the packet in the container's packet table.
+    pub fn packet_index(&self) -> u64 {
+        self.packet_index
+    }
+
+    /// Returns the offset of the start of the packet from the start of the file, if known.
+    pub fn byte_offset(&self) -> Option<u64> {
+        self.byte_offset
+    }
+
+    /// Returns the position in samples per channel, as used by `seek_to_sample`, that the packet's
+    /// first frame would have been returned at.
+    ///
+    /// Frames from earlier packets that failed to decode are not counted.
+    pub fn sample_position(&self) -> u64 {
+        self.sample_position
+    }
+}
+
+impl fmt::Display for PacketLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "packet {}", self.packet_index)?;
+        if let Some(byte_offset) = self.byte_offset {
+            write!(f, " at byte {}", byte_offset)?;
         }
+        write!(f, " (sample {})", self.sample_position)
+    }
+}
+
+/// A compressed packet read from a file without being decoded.
+#[derive(Clone, Copy, Debug)]
+pub struct RawPacket<'a> {
+    data: &'a [u8],
+    frames: u32,
+    sample_rate: u32,
+    location: PacketLocation,
+}
+
+impl<'a> RawPacket<'a> {
+    /// Returns the compressed packet.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Returns the number of frames the packet decodes to, as given by its first element's header.
+    ///
+    /// This includes any frames that would be trimmed by gapless playback.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Returns the duration of the packet.
+    pub fn duration(&self) -> Duration {
+        let nanos = self.frames as u64 * 1_000_000_000 / self.sample_rate as u64;
+        Duration::from_nanos(nanos)
+    }
+
+    /// Returns the location of the packet in the file.
+    pub fn location(&self) -> PacketLocation {
+        self.location
     }
+}
 
-    fn cause(&self) -> Option<&error::Error> {
+impl error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
             ReadError::Io(ref err) => Some(err),
-            ReadError::UnsupportedFormat => return None,
+            ReadError::UnsupportedFormat => None,
             ReadError::Format(_, ref err) => Some(err),
             ReadError::Decoder(ref err) => Some(err),
+            ReadError::Packet(_, ref err) => Some(err),
         }
     }
 }
@@ -55,6 +254,9 @@ impl fmt::Display for ReadError {
             ReadError::UnsupportedFormat => write!(f, "unsupported format"),
             ReadError::Format(format, ref err) => write!(f, "{:?} error: {}", format, err),
             ReadError::Decoder(ref err) => write!(f, "decoder error: {}", err),
+            ReadError::Packet(location, ref err) => {
+                write!(f, "decoder error in {}: {}", location, err)
+            }
         }
     }
 }
@@ -68,201 +270,1724 @@ impl From<io::Error> for ReadError {
 /// An ALAC reader and decoder supporting `mp4` and `caf` files (if the respective Cargo features
 /// are enabled).
 pub struct Reader<R: Read + Seek> {
-    packet_buf: Vec<u8>,
+    packet_buf: PacketBuf,
     packet_reader: PacketReader<R>,
+    magic_cookie: Vec<u8>,
+    tracks: Vec<TrackInfo>,
+    metadata: Metadata,
     decoder: Decoder,
+    resync: bool,
+    // `Some` once packet boundaries have been lost and packets are being located by scanning.
+    resync_buf: Option<Vec<u8>>,
+    error_policy: ErrorPolicy,
+    // Set once a packet has failed to decode with `ErrorPolicy::Stop`.
+    stopped: bool,
+    gapless: bool,
+    // Frames to discard from the start of the next decoded packets after a seek.
+    skip_frames: u64,
+    // The position in frames of the next frame to be returned.
+    frame_position: u64,
+    throughput: Throughput,
+    last_packet_stats: Option<PacketStats>,
+}
+
+/// Statistics for a single decoded packet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PacketStats {
+    bytes: u64,
+    frames: u32,
+    channels: u8,
+    uncompressed: bool,
+    sample_rate: u32,
+}
+
+impl PacketStats {
+    /// Returns the length of the compressed packet in bytes.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the number of samples decoded, before any priming or padding frames are removed.
+    pub fn samples(&self) -> u64 {
+        self.frames as u64 * self.channels as u64
+    }
+
+    /// Returns the number of frames decoded, before any priming or padding frames are removed.
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    /// Returns `true` if any of the packet's channels were stored uncompressed because they
+    /// could not be compressed.
+    pub fn uncompressed(&self) -> bool {
+        self.uncompressed
+    }
+
+    /// Returns the bit rate of the packet in bits per second, or `None` if the packet decoded
+    /// to no frames.
+    pub fn bit_rate(&self) -> Option<f64> {
+        if self.frames == 0 {
+            return None;
+        }
+        let secs = self.frames as f64 / self.sample_rate as f64;
+        Some((self.bytes * 8) as f64 / secs)
+    }
+}
+
+/// Decode throughput statistics for a `Reader`.
+///
+/// Time is measured from the start to the end of each packet read and decode, so time spent by
+/// the caller between packets is not included. On `wasm32-unknown-unknown`, which has no clock,
+/// the elapsed time is always zero.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Throughput {
+    packets: u64,
+    bytes_in: u64,
+    frames_out: u64,
+    elapsed: Duration,
+    sample_rate: u32,
+}
+
+impl Throughput {
+    /// Returns the number of packets read from the container.
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Returns the number of compressed bytes read from the container.
+    pub fn bytes_in(&self) -> u64 {
+        self.bytes_in
+    }
+
+    /// Returns the number of frames decoded.
+    pub fn frames_out(&self) -> u64 {
+        self.frames_out
+    }
+
+    /// Returns the total time spent reading and decoding packets.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Returns the average number of packets read and decoded per second, or `None` if no time
+    /// has elapsed.
+    pub fn packets_per_second(&self) -> Option<f64> {
+        let secs = self.elapsed_secs()?;
+        Some(self.packets as f64 / secs)
+    }
+
+    /// Returns the duration of audio decoded divided by the time taken to decode it, or `None`
+    /// if no time has elapsed.
+    ///
+    /// A value greater than `1.0` means decoding is faster than realtime playback.
+    pub fn realtime_factor(&self) -> Option<f64> {
+        let secs = self.elapsed_secs()?;
+        let audio_secs = self.frames_out as f64 / self.sample_rate as f64;
+        Some(audio_secs / secs)
+    }
+
+    fn elapsed_secs(&self) -> Option<f64> {
+        if self.elapsed == Duration::from_secs(0) {
+            None
+        } else {
+            Some(self.elapsed.as_secs_f64())
+        }
+    }
 }
 
 impl<R: Read + Seek> Reader<R> {
     /// Attempts to create a `Reader` from a seekable byte stream.
     pub fn new(reader: R) -> Result<Reader<R>, ReadError> {
-        let (packet_reader, magic_cookie) = PacketReader::new(reader)?;
+        Reader::new_with_track(reader, 0)
+    }
+
+    /// Attempts to create a `Reader` for the ALAC track with index `track_index` in `tracks`.
+    ///
+    /// CAF files only ever contain a single track.
+    pub fn new_with_track(reader: R, track_index: usize) -> Result<Reader<R>, ReadError> {
+        let (packet_reader, magic_cookie) = PacketReader::new(reader, track_index)?;
+        Reader::from_packet_reader(packet_reader, magic_cookie)
+    }
+
+    /// Creates a `Reader` for a file previously indexed with `packet_index` without parsing the
+    /// file's container.
+    ///
+    /// The index is not checked against the file so it must have been created from the same file.
+    pub fn from_packet_index(reader: R, index: PacketIndex) -> Result<Reader<R>, ReadError> {
+        let magic_cookie = index.magic_cookie().to_vec();
+        let packet_reader = PacketReader::Indexed(IndexedPacketReader::new(reader, index));
+        Reader::from_packet_reader(packet_reader, magic_cookie)
+    }
+
+    /// Creates a `Reader` for a raw stream of ALAC packets, as written by `write_raw_packet`,
+    /// starting at the current position of `reader`.
+    ///
+    /// Raw streams have no container so the stream is described by `stream_info` instead of a
+    /// magic cookie in the file. Their length is unknown, so `total_samples` and `packet_count`
+    /// return zero, and seeking skips over every earlier packet.
+    pub fn new_raw(reader: R, stream_info: StreamInfo) -> Result<Reader<R>, ReadError> {
+        let packet_reader = RawPacketReader::new(reader, max_packet_len(&stream_info))?;
+        Reader::from_packet_reader(PacketReader::Raw(packet_reader), stream_info.to_cookie())
+    }
+
+    /// Creates a `Reader` for packets read from `reader` by a custom `PacketSource`.
+    ///
+    /// `format` returns `Format::Custom` and the reader has no tags or container context. A
+    /// `PacketIndex` cannot be created for it.
+    pub fn from_packet_source<P>(reader: R, source: P) -> Result<Reader<R>, ReadError>
+    where
+        P: PacketSource + Send + 'static,
+    {
+        let packet_reader = SourcePacketReader::new(reader, Box::new(source));
+        let magic_cookie = packet_reader.magic_cookie().to_vec();
+        Reader::from_packet_reader(PacketReader::Source(packet_reader), magic_cookie)
+    }
+
+    fn from_packet_reader(
+        mut packet_reader: PacketReader<R>,
+        magic_cookie: Vec<u8>,
+    ) -> Result<Reader<R>, ReadError> {
         let stream_info = StreamInfo::from_cookie(&magic_cookie).map_err(ReadError::Decoder)?;
+        // Priming frames are always at the start of the first packet.
+        let priming_frames = packet_reader
+            .gapless_info()
+            .map_or(0, |info| info.priming_frames());
+        let throughput = Throughput {
+            packets: 0,
+            bytes_in: 0,
+            frames_out: 0,
+            elapsed: Duration::from_secs(0),
+            sample_rate: stream_info.sample_rate(),
+        };
+
+        let tracks = packet_reader.tracks().unwrap_or_else(|| {
+            vec![TrackInfo::new(
+                stream_info.clone(),
+                packet_reader.total_frames(),
+                None,
+            )]
+        });
+        let metadata = packet_reader.take_metadata();
+
+        // Reserve the packet buffer once rather than growing it as longer packets are read. The
+        // reservation is bounded so a corrupt packet table can't cause a huge allocation.
+        let max_packet_size = expected_max_packet_len(&stream_info, packet_reader.max_packet_len());
+        let uncompressed_len = uncompressed_packet_len(&stream_info);
+        let packet_buf = PacketBuf::with_capacity(cmp::min(max_packet_size, uncompressed_len));
+        // Longer packets can't be valid so they are rejected before being read into memory.
+        packet_reader.set_packet_len_limit(max_packet_len(&stream_info) as u64);
 
         Ok(Reader {
-            packet_buf: Vec::new(),
+            packet_buf,
             packet_reader,
+            magic_cookie,
+            tracks,
+            metadata,
             decoder: Decoder::new(stream_info),
+            resync: false,
+            resync_buf: None,
+            error_policy: ErrorPolicy::Report,
+            stopped: false,
+            gapless: true,
+            skip_frames: priming_frames,
+            frame_position: priming_frames,
+            throughput,
+            last_packet_stats: None,
         })
     }
 
-    /// Returns the format of this ALAC file.
-    pub fn format(&self) -> Format {
-        self.packet_reader.format()
+    /// Borrows packets from `map`, which must be the file being read, instead of reading them.
+    #[cfg(feature = "mmap")]
+    pub(crate) fn set_packet_map(&mut self, map: MappedFile) {
+        self.packet_buf.map = Some(map);
     }
 
-    /// Returns a `StreamInfo` describing the ALAC stream in this file.
-    pub fn stream_info(&self) -> &StreamInfo {
-        self.decoder.stream_info()
+    /// Returns the ALAC tracks in the file.
+    ///
+    /// The track being read is chosen with `new_with_track`.
+    pub fn tracks(&self) -> &[TrackInfo] {
+        &self.tracks
     }
 
-    /// Returns an iterator over the samples in the ALAC stream.
+    /// Returns the title, artist and other tags stored in the file.
     ///
-    /// Channels are interleaved, e.g. for a stereo stream they would be yielded in the order
-    /// `[left, right, left, right, ..]`.
-    pub fn into_samples<S: Sample>(self) -> Samples<R, S> {
-        Samples {
-            reader: self,
-            samples: Vec::new(),
-            sample_len: 0,
-            sample_pos: 0,
-        }
+    /// Tags are not available for CAF files read with `new_streaming`.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
     }
 
-    /// Returns an iterator-like type that decodes packets into a user-provided buffer.
-    pub fn into_packets<S: Sample>(self) -> Packets<R, S> {
-        Packets {
-            reader: self,
-            phantom: PhantomData,
+    /// Returns the location of every packet in the file, which can be saved and later passed to
+    /// `from_packet_index` to reopen the file without parsing its container.
+    pub fn packet_index(&self) -> Result<PacketIndex, ReadError> {
+        Ok(PacketIndex::new(
+            self.format(),
+            self.magic_cookie.clone(),
+            self.packet_reader.total_frames(),
+            self.packet_reader.gapless_info(),
+            self.packet_reader.packet_entries()?,
+        ))
+    }
+
+    /// Enables or disables recovery from packets that fail to decode.
+    ///
+    /// By default a packet that fails to decode results in a `ReadError::Packet` and the reader
+    /// moves on to the next packet in the container's packet table. This is the right behaviour
+    /// for isolated corrupt packets but not if the packet table and packet data disagree, in
+    /// which case every following packet fails too.
+    ///
+    /// With recovery enabled the first packet that fails to decode causes the reader to ignore
+    /// packet boundaries from then on. Packet data is instead treated as a contiguous byte stream
+    /// which is scanned for decodable packets, salvaging as much audio as possible. No error is
+    /// returned for data skipped while scanning.
+    pub fn set_resync(&mut self, enabled: bool) {
+        self.resync = enabled;
+        if !enabled {
+            self.resync_buf = None;
         }
     }
 
-    fn decode_next_packet_into<'a, S: Sample>(
-        &mut self,
-        out: &'a mut [S],
-    ) -> Result<Option<&'a [S]>, ReadError> {
-        // Read the next packet
-        self.packet_reader.next_packet_into(&mut self.packet_buf)?;
-        if self.packet_buf.is_empty() {
-            return Ok(None);
+    /// Enables or disables reading MP4 sample tables from the file as they are needed rather than
+    /// holding them in memory.
+    ///
+    /// The packet size and chunk offset tables of very long files can take tens of megabytes.
+    /// With lazy tables only a block of each is held in memory, at the cost of extra reads when
+    /// seeking or moving on to the next block. `packet_index` is not available while enabled.
+    /// Files with movie fragments, and tables held by `container_context`, stay in memory. For
+    /// other formats this does nothing.
+    pub fn set_lazy_sample_tables(&mut self, enabled: bool) -> Result<(), ReadError> {
+        self.packet_reader.set_lazy_sample_tables(enabled)
+    }
+
+    /// Enables or disables gapless playback, which is enabled by default.
+    ///
+    /// When enabled, and the file contains gapless playback information, the encoder's priming
+    /// frames at the start of the stream and padding at the end are not returned. Sample
+    /// positions, `total_samples` and `duration` then exclude them.
+    ///
+    /// Changing this setting moves the reader back to the start of the stream.
+    pub fn set_gapless(&mut self, enabled: bool) -> Result<(), ReadError> {
+        self.gapless = enabled;
+        if self.packet_reader.packet_index() > 0 {
+            return self.seek_to_sample(0);
         }
 
-        // Decode the next packet
-        let samples = self
-            .decoder
-            .decode_packet(&self.packet_buf, out)
-            .map_err(|err| ReadError::Decoder(err))?;
+        // No packets have been read so there is no need to seek.
+        let start = self.frame_range().0;
+        self.skip_frames = start;
+        self.frame_position = start;
+        Ok(())
+    }
 
-        if samples.len() == 0 {
-            Ok(None)
+    /// Returns the encoder delay and stream length given by the container, if any.
+    ///
+    /// This is returned whether or not gapless playback is enabled.
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.packet_reader.gapless_info()
+    }
+
+    /// Returns the gapless playback information in use, or `None` if samples are not trimmed.
+    fn trim(&self) -> Option<GaplessInfo> {
+        if self.gapless {
+            self.gapless_info()
         } else {
-            Ok(Some(samples))
+            None
         }
     }
-}
 
-/// An iterator that yields samples of type `S` read from a `Reader`.
-pub struct Samples<R: Read + Seek, S> {
-    reader: Reader<R>,
-    samples: Vec<S>,
-    sample_len: usize,
-    sample_pos: usize,
-}
+    /// Overrides the sample rate given in the file's magic cookie.
+    ///
+    /// This is useful for broken files whose stated sample rate is wrong. The new sample rate is
+    /// returned by `stream_info` and used for all time calculations, such as `duration`.
+    ///
+    /// Frame counts, including `gapless_info`, are unchanged. `tracks` describes the tracks as
+    /// stored in the file so its durations keep the file's sample rate.
+    pub fn set_sample_rate(&mut self, sample_rate: u32) -> Result<(), InvalidData> {
+        let stream_info = self.stream_info().clone().with_sample_rate(sample_rate)?;
+        self.decoder.set_stream_info(stream_info);
+        self.throughput.sample_rate = sample_rate;
+        Ok(())
+    }
+
+    /// Sets a linear gain to apply to each channel as samples are decoded.
+    ///
+    /// See `Decoder::set_channel_gains` for details.
+    pub fn set_channel_gains(&mut self, gains: &[f64]) {
+        self.decoder.set_channel_gains(gains);
+    }
 
-impl<R: Read + Seek, S: Sample> Samples<R, S> {
     /// Returns the format of this ALAC file.
     pub fn format(&self) -> Format {
-        self.reader.format()
+        self.packet_reader.format()
     }
 
     /// Returns a `StreamInfo` describing the ALAC stream in this file.
     pub fn stream_info(&self) -> &StreamInfo {
-        self.reader.stream_info()
+        self.decoder.stream_info()
     }
-}
 
-impl<R: Read + Seek, S: Sample> Iterator for Samples<R, S> {
-    type Item = Result<S, ReadError>;
+    /// Returns the parsed container for details not otherwise exposed by `Reader`, or `None` if
+    /// the reader was created from a `PacketIndex` or `PacketSource`.
+    ///
+    /// See the `container` module for the types involved.
+    #[cfg(feature = "container-context")]
+    pub fn container_context(&self) -> Option<ContainerContext<'_>> {
+        self.packet_reader.context()
+    }
 
-    fn next(&mut self) -> Option<Result<S, ReadError>> {
-        // Allocate sample buffer if required
-        if self.samples.is_empty() {
-            let max_samples = self.stream_info().max_samples_per_packet() as usize;
-            self.samples = vec![S::from_decoder(0, 16); max_samples];
+    /// Consumes the reader returning a `Reader` for the next file in a stream of concatenated
+    /// files, or `None` if there is no next file.
+    ///
+    /// Some capture pipelines concatenate CAF files into a single stream. Only CAF files with a
+    /// known data chunk size can be concatenated. For other formats this always returns `None`.
+    pub fn next_stream(self) -> Result<Option<Reader<R>>, ReadError> {
+        #[cfg(feature = "mmap")]
+        let map = self.packet_buf.map.clone();
+        #[cfg_attr(not(feature = "mmap"), allow(unused_mut))]
+        let mut next = match self.packet_reader.into_next_file()? {
+            Some(reader) => Reader::new(reader)?,
+            None => return Ok(None),
+        };
+        // Packets of a following stream in a mapped file are borrowed from the same mapping.
+        #[cfg(feature = "mmap")]
+        {
+            next.packet_buf.map = map;
         }
+        Ok(Some(next))
+    }
 
-        // Decode the next packet if we're at the end of the current one.
-        if self.sample_pos == self.sample_len {
-            self.sample_len = match self.reader.decode_next_packet_into(&mut self.samples) {
-                Ok(Some(s)) => s.len(),
-                Ok(None) => return None,
-                Err(e) => return Some(Err(e)),
-            };
-            self.sample_pos = 0;
-        }
+    /// Returns decode throughput statistics for the packets decoded so far.
+    pub fn throughput(&self) -> &Throughput {
+        &self.throughput
+    }
 
-        let sample_pos = self.sample_pos;
-        self.sample_pos += 1;
-        Some(Ok(self.samples[sample_pos]))
+    /// Returns the number of packets in the container's packet table.
+    pub fn packet_count(&self) -> u64 {
+        self.packet_reader.packet_count()
     }
-}
 
-/// An iterator-like type that decodes packets into a user-provided buffer.
-pub struct Packets<R: Read + Seek, S> {
-    reader: Reader<R>,
-    phantom: PhantomData<[S]>,
-}
+    /// Returns the length in bytes of the longest packet in the stream.
+    ///
+    /// This is taken from the container's packet table if it has one, otherwise from
+    /// `StreamInfo::max_frame_bytes`, falling back to an upper bound on the length of a packet if
+    /// that is zero. It can be used to size buffers for packets returned by `next_raw_packet`.
+    pub fn max_packet_size(&self) -> usize {
+        expected_max_packet_len(self.stream_info(), self.packet_reader.max_packet_len())
+    }
 
-impl<R: Read + Seek, S: Sample> Packets<R, S> {
-    /// Returns the format of this ALAC file.
-    pub fn format(&self) -> Format {
-        self.reader.format()
+    /// Returns the total number of samples per channel in the stream.
+    ///
+    /// This is read from the container's sample tables. If they do not give the stream length
+    /// every packet is assumed to contain `StreamInfo::max_frames_per_packet` frames.
+    pub fn total_samples(&self) -> u64 {
+        let (start, end) = self.frame_range();
+        end.saturating_sub(start)
     }
 
-    /// Returns a `StreamInfo` describing the ALAC stream in this file.
-    pub fn stream_info(&self) -> &StreamInfo {
-        self.reader.stream_info()
+    /// Returns the untrimmed number of frames in the stream given by the container, if any.
+    pub(crate) fn container_total_frames(&self) -> Option<u64> {
+        self.packet_reader.total_frames()
     }
 
-    /// Reads the next packet and decodes it into `out`.
-    ///
-    /// Channels are interleaved, e.g. for a stereo packet `out` would contains samples in the
-    /// order `[left, right, left, right, ..]`.
-    ///
-    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
-    pub fn next_into<'a>(&mut self, out: &'a mut [S]) -> Result<Option<&'a [S]>, ReadError> {
-        self.reader.decode_next_packet_into(out)
+    /// Returns `true` if the length of the stream is given by the container.
+    fn is_length_known(&self) -> bool {
+        self.trim().is_some()
+            || self.packet_reader.total_frames().is_some()
+            || self.packet_reader.has_packet_count()
     }
-}
 
-#[cfg(feature = "caf")]
-use caf::CafPacketReader;
-#[cfg(feature = "mp4")]
-use mp4::Mp4PacketReader;
+    /// Returns the untrimmed frame positions the stream starts and ends at.
+    fn frame_range(&self) -> (u64, u64) {
+        if let Some(trim) = self.trim() {
+            let start = trim.priming_frames();
+            return (start, start + trim.valid_frames());
+        }
 
-enum PacketReader<R: Read + Seek> {
-    #[cfg(feature = "caf")]
-    Caf(CafPacketReader<R>),
-    #[cfg(feature = "mp4")]
-    Mp4(Mp4PacketReader<R>),
-}
+        let end = self.packet_reader.total_frames().unwrap_or_else(|| {
+            self.packet_count() * self.stream_info().max_frames_per_packet() as u64
+        });
+        (0, end)
+    }
 
-impl<R: Read + Seek> PacketReader<R> {
-    fn new(mut reader: R) -> Result<(PacketReader<R>, Vec<u8>), ReadError> {
-        let mut magic = [0; 8];
-        reader.read_exact(&mut magic)?;
-        reader.seek(SeekFrom::Current(-(magic.len() as i64)))?;
+    /// Returns the duration of the stream.
+    pub fn duration(&self) -> Duration {
+        let total_samples = self.total_samples();
+        let sample_rate = self.stream_info().sample_rate() as u64;
+        let nanos = (total_samples % sample_rate) * 1_000_000_000 / sample_rate;
+        Duration::new(total_samples / sample_rate, nanos as u32)
+    }
 
-        match (&magic[0..4], &magic[4..8]) {
-            #[cfg(feature = "caf")]
-            (b"caff", _) => {
-                let (reader, magic_cookie) = CafPacketReader::new(reader)?;
-                Ok((PacketReader::Caf(reader), magic_cookie))
-            }
-            #[cfg(feature = "mp4")]
-            (_, b"ftyp") => {
-                let (reader, magic_cookie) = Mp4PacketReader::new(reader)?;
-                Ok((PacketReader::Mp4(reader), magic_cookie))
-            }
-            _ => Err(ReadError::UnsupportedFormat),
-        }
+    /// Returns the number of packets in the container's packet table that have not yet been read.
+    pub fn packets_remaining(&self) -> u64 {
+        self.packet_count()
+            .saturating_sub(self.packet_reader.packet_index())
     }
 
-    fn format(&self) -> Format {
-        match *self {
-            #[cfg(feature = "caf")]
-            PacketReader::Caf(_) => Format::Caf,
-            #[cfg(feature = "mp4")]
-            PacketReader::Mp4(_) => Format::Mp4,
-        }
+    /// Positions the reader so the next sample decoded is the first channel of frame `sample`.
+    ///
+    /// `sample` counts samples per channel from the start of the stream. The reader moves to the
+    /// packet containing it using the container's sample tables and discards any earlier samples
+    /// in that packet once it is decoded. Seeking past the end of the stream is not an error, no
+    /// more samples will be decoded.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<(), ReadError> {
+        let sample = self.frame_range().0.saturating_add(sample);
+        let frames_per_packet = self.stream_info().max_frames_per_packet() as u64;
+        let (packet, packet_start) = self
+            .packet_reader
+            .packet_for_frame(sample, frames_per_packet);
+
+        self.packet_reader.seek_to_packet(packet)?;
+        self.skip_frames = sample - packet_start;
+        self.frame_position = sample;
+        // Packet boundaries are known again after a seek.
+        self.resync_buf = None;
+        self.stopped = false;
+        Ok(())
     }
 
-    fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
-        match *self {
-            #[cfg(feature = "caf")]
-            PacketReader::Caf(ref mut r) => r.next_packet_into(buf),
+    /// Returns the position in samples per channel, as used by `seek_to_sample`, of the next frame
+    /// to be decoded.
+    pub fn sample_position(&self) -> u64 {
+        self.frame_position.saturating_sub(self.frame_range().0)
+    }
+
+    /// Returns a reference to the underlying reader.
+    ///
+    /// Its position is not necessarily that of the next packet to be read.
+    pub fn get_ref(&self) -> &R {
+        self.packet_reader.get_ref()
+    }
+
+    /// Consumes the `Reader` returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.packet_reader.into_inner()
+    }
+
+    /// Returns an iterator over the samples in the ALAC stream.
+    ///
+    /// Channels are interleaved, e.g. for a stereo stream they would be yielded in the order
+    /// `[left, right, left, right, ..]`.
+    ///
+    /// Samples of a type with fewer bits than the stream's bit depth, such as `i16` for a 24 bit
+    /// stream, are truncated unless dither is enabled using `Samples::with_dither`.
+    pub fn into_samples<S: Sample>(mut self) -> Samples<R, S> {
+        self.allow_requantize();
+        Samples {
+            reader: self,
+            samples: Vec::new(),
+            sample_len: 0,
+            sample_pos: 0,
+        }
+    }
+
+    /// Returns an iterator-like type that yields one frame, a sample for each channel, at a time.
+    ///
+    /// Narrow sample types are handled in the same way as by `into_samples`.
+    pub fn into_frames<S: Sample>(mut self) -> Frames<R, S> {
+        self.allow_requantize();
+        Frames {
+            reader: self,
+            samples: Vec::new(),
+            sample_len: 0,
+            sample_pos: 0,
+        }
+    }
+
+    /// Returns an iterator-like type that decodes packets into a user-provided buffer.
+    ///
+    /// Narrow sample types are handled in the same way as by `into_samples`.
+    pub fn into_packets<S: Sample>(mut self) -> Packets<R, S> {
+        self.allow_requantize();
+        Packets {
+            reader: self,
+            pool: Vec::new(),
+        }
+    }
+
+    /// Decodes all remaining packets in parallel, returning the samples that `into_samples` would
+    /// yield.
+    ///
+    /// Every packet is read into memory before being decoded on rayon's thread pool, each thread
+    /// with its own copy of the decoder. Errors are handled as by `ErrorPolicy::Report`, the
+    /// default for `into_samples`, except that the error for the first packet that fails to
+    /// decode is returned in place of any samples.
+    ///
+    /// With resync enabled packet boundaries are only found by scanning the stream in order, so
+    /// packets are decoded on the calling thread instead.
+    #[cfg(feature = "rayon")]
+    pub fn decode_all_parallel<S: Sample + Send>(mut self) -> Result<Vec<S>, ReadError> {
+        if self.resync {
+            return self.into_samples().collect();
+        }
+
+        self.allow_requantize();
+        let channels = self.stream_info().channels() as usize;
+        let bit_depth = self.stream_info().bit_depth();
+        let max_samples = self.stream_info().max_samples_per_packet() as usize;
+        let end = self.trim().map_or(u64::MAX, |_| self.frame_range().1);
+        let skip_frames = self.skip_frames;
+        let frame_position = self.frame_position;
+
+        let mut data = Vec::new();
+        let mut packets = Vec::new();
+        while let Some(packet) = self.next_raw_packet()? {
+            let start = data.len();
+            data.extend_from_slice(packet.data());
+            packets.push((start..data.len(), packet.location()));
+        }
+
+        let decoder = &self.decoder;
+        // Every sample is overwritten before being read so buffers start as silence.
+        let new_buf = || vec![S::from_decoder(0, bit_depth); max_samples];
+        let decoded: Vec<Result<Vec<S>, ReadError>> = packets
+            .par_iter()
+            .map_init(
+                || (decoder.clone(), new_buf()),
+                |&mut (ref mut decoder, ref mut out), &(ref range, location)| {
+                    let samples = decoder.decode_packet(&data[range.clone()], out);
+                    samples
+                        .map(|samples| samples.to_vec())
+                        .map_err(|err| ReadError::Packet(location, err))
+                },
+            )
+            .collect();
+
+        let mut samples = Vec::new();
+        for packet in decoded {
+            samples.extend_from_slice(&packet?);
+        }
+
+        // Trim frames skipped after a seek and, if gapless playback is enabled, padding.
+        let start = cmp::min(skip_frames as usize * channels, samples.len());
+        let frames = ((samples.len() - start) / channels) as u64;
+        let frames = cmp::min(frames, end.saturating_sub(frame_position)) as usize;
+        samples.truncate(start + frames * channels);
+        samples.drain(..start);
+        Ok(samples)
+    }
+
+    /// Allows samples to be truncated when decoded into a narrow sample type, leaving dither
+    /// enabled if it already is.
+    fn allow_requantize(&mut self) {
+        if self.decoder.options().requantization().is_none() {
+            self.decoder.set_requantize(Requantize::Truncate);
+        }
+    }
+
+    fn set_dither(&mut self, dither: bool) {
+        let requantize = if dither {
+            Requantize::Dither
+        } else {
+            Requantize::Truncate
+        };
+        self.decoder.set_requantize(requantize);
+    }
+
+    fn is_dithered(&self) -> bool {
+        self.decoder.options().requantization() == Some(Requantize::Dither)
+    }
+
+    fn decode_next_packet_into<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<Option<&'a [S]>, ReadError> {
+        let start = now();
+        self.last_packet_stats = None;
+        let result = self.decode_next_packet_skipping(out);
+        if let Some(start) = start {
+            self.throughput.elapsed += start.elapsed();
+        }
+        if let Ok(Some(samples)) = result {
+            let channels = self.stream_info().channels() as u64;
+            let frames = samples.len() as u64 / channels;
+            self.throughput.frames_out += frames;
+            self.frame_position += frames;
+        }
+        result
+    }
+
+    fn decode_next_packet_skipping<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<Option<&'a [S]>, ReadError> {
+        let channels = self.stream_info().channels() as usize;
+        // Padding is only trimmed when the end of the stream is known.
+        let end = self.trim().map_or(u64::MAX, |_| self.frame_range().1);
+        loop {
+            if self.frame_position >= end {
+                return Ok(None);
+            }
+
+            let num_samples = match self.decode_next_packet_into_inner(out)? {
+                Some(samples) => samples.len(),
+                None => return Ok(None),
+            };
+
+            let skip = cmp::min(self.skip_frames, (num_samples / channels) as u64);
+            self.skip_frames -= skip;
+            let skip = skip as usize * channels;
+            if skip < num_samples {
+                let frames = cmp::min(
+                    ((num_samples - skip) / channels) as u64,
+                    end - self.frame_position,
+                );
+                let len = frames as usize * channels;
+                // Decoded samples always start at the beginning of `out`.
+                out.copy_within(skip..skip + len, 0);
+                return Ok(Some(&out[..len]));
+            }
+        }
+    }
+
+    fn decode_next_packet_into_inner<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<Option<&'a [S]>, ReadError> {
+        if self.resync_buf.is_some() {
+            return self.decode_next_packet_resync(out);
+        }
+
+        let num_samples = loop {
+            if self.stopped || !self.read_next_packet()? {
+                return Ok(None);
+            }
+
+            match self.decode_current_packet(out) {
+                Ok(samples) => break samples.len(),
+                Err(ReadError::Packet(..)) if self.resync => {
+                    // Skip the first byte so the failed packet is not found again.
+                    self.resync_buf = Some(self.packet_buf.data()[1..].to_vec());
+                    return self.decode_next_packet_resync(out);
+                }
+                Err(ReadError::Packet(..)) if self.error_policy == ErrorPolicy::Skip => continue,
+                Err(ReadError::Packet(..)) if self.error_policy == ErrorPolicy::SilenceFill => {
+                    // The decoder's concealment mode is never changed from silence.
+                    break self.decoder.conceal_lost_packet(out).len();
+                }
+                Err(err @ ReadError::Packet(..)) => {
+                    self.stopped = self.error_policy == ErrorPolicy::Stop;
+                    return Err(err);
+                }
+                Err(err) => return Err(err),
+            }
+        };
+
+        if num_samples == 0 {
+            Ok(None)
+        } else {
+            Ok(Some(&out[..num_samples]))
+        }
+    }
+
+    fn decode_next_packet_resync<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<Option<&'a [S]>, ReadError> {
+        let max_packet_len = self.max_packet_len();
+        let mut resync_buf = self.resync_buf.take().unwrap_or_default();
+        let mut end_of_stream = false;
+
+        loop {
+            // Buffer enough data that a whole packet is available at every scanned offset.
+            while !end_of_stream && resync_buf.len() < 2 * max_packet_len {
+                match self.read_next_packet() {
+                    Ok(true) => resync_buf.extend_from_slice(self.packet_buf.data()),
+                    Ok(false) => end_of_stream = true,
+                    Err(err) => {
+                        self.resync_buf = Some(resync_buf);
+                        return Err(err);
+                    }
+                }
+            }
+
+            let max_offset = if end_of_stream {
+                resync_buf.len()
+            } else {
+                resync_buf.len() - max_packet_len
+            };
+
+            if let Some((offset, decoded)) = self.decoder.find_packet(&resync_buf, max_offset, out) {
+                resync_buf.drain(..offset + decoded.consumed);
+                self.resync_buf = Some(resync_buf);
+                self.record_packet_stats(decoded.consumed, decoded);
+                return Ok(Some(&out[..decoded.num_samples]));
+            }
+
+            resync_buf.drain(..max_offset);
+            if end_of_stream {
+                self.resync_buf = Some(resync_buf);
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns an upper bound on the length of a packet in this stream.
+    fn max_packet_len(&self) -> usize {
+        max_packet_len(self.stream_info())
+    }
+
+    /// Reads the next compressed packet without decoding it, or returns `None` if there are no
+    /// more packets.
+    ///
+    /// This allows packets to be passed through to another container or a network stream. Packets
+    /// are returned whole as they are stored in the file, including those that would be trimmed
+    /// by gapless playback or only partially returned after a seek. A packet that does not start
+    /// with an audio element results in a `ReadError::Packet` error.
+    pub fn next_raw_packet(&mut self) -> Result<Option<RawPacket<'_>>, ReadError> {
+        if !self.read_next_packet()? {
+            return Ok(None);
+        }
+
+        let location = self.current_packet_location();
+        let frame_length = self.stream_info().max_frames_per_packet();
+        let frames = match dec::packet_frames(self.packet_buf.data(), frame_length) {
+            Some(frames) => frames,
+            None => {
+                let err = invalid_data(
+                    ErrorKind::UnsupportedElement,
+                    "packet does not start with an audio element",
+                );
+                return Err(ReadError::Packet(location, err));
+            }
+        };
+
+        // The following packet starts after this one even if frames were to be skipped.
+        let packet_start = self.frame_position.saturating_sub(self.skip_frames);
+        self.frame_position = packet_start + frames as u64;
+        self.skip_frames = 0;
+
+        Ok(Some(RawPacket {
+            data: self.packet_buf.data(),
+            frames,
+            sample_rate: self.stream_info().sample_rate(),
+            location,
+        }))
+    }
+
+    /// Reads the next packet into `packet_buf` returning `false` if there are no more packets.
+    pub(crate) fn read_next_packet(&mut self) -> Result<bool, ReadError> {
+        if !self.packet_buf.read_from(&mut self.packet_reader)? {
+            return Ok(false);
+        }
+        self.throughput.packets += 1;
+        self.throughput.bytes_in += self.packet_buf.data().len() as u64;
+        Ok(true)
+    }
+
+    /// Returns the compressed packet most recently read by `read_next_packet`.
+    pub(crate) fn current_packet(&self) -> &[u8] {
+        self.packet_buf.data()
+    }
+
+    /// Decodes the packet most recently read by `read_next_packet`.
+    pub(crate) fn decode_current_packet<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<&'a [S], ReadError> {
+        let packet = self.packet_buf.data();
+        match self.decoder.decode_packet_detailed(packet, out) {
+            Ok(decoded) => {
+                self.record_packet_stats(packet.len(), decoded);
+                Ok(&out[..decoded.num_samples])
+            }
+            Err(err) => {
+                self.last_packet_stats = None;
+                Err(ReadError::Packet(self.current_packet_location(), err))
+            }
+        }
+    }
+
+    fn record_packet_stats(&mut self, bytes: usize, decoded: DecodedPacket) {
+        let info = self.stream_info();
+        let channels = info.channels();
+        self.last_packet_stats = Some(PacketStats {
+            bytes: bytes as u64,
+            frames: (decoded.num_samples / channels as usize) as u32,
+            channels,
+            uncompressed: decoded.uncompressed,
+            sample_rate: info.sample_rate(),
+        });
+    }
+
+    /// Returns the location of the packet most recently read by `read_next_packet`.
+    fn current_packet_location(&self) -> PacketLocation {
+        // Frames still to be skipped after a seek belong to this or following packets.
+        let packet_start = self.frame_position.saturating_sub(self.skip_frames);
+        PacketLocation {
+            packet_index: self.packet_reader.packet_index().saturating_sub(1),
+            byte_offset: self.packet_buf.offset,
+            sample_position: packet_start.saturating_sub(self.frame_range().0),
+        }
+    }
+}
+
+impl<R: Read> Reader<Streaming<R>> {
+    /// Attempts to create a `Reader` from a non-seekable byte stream such as a pipe or network
+    /// socket.
+    ///
+    /// CAF files must have their packet table before their audio data, and MP4 files must have
+    /// their `moov` atom before their `mdat` atom. Fragmented MP4 files are not supported.
+    ///
+    /// Reads following a backward `seek_to_sample` fail as the stream cannot be rewound.
+    pub fn new_streaming(reader: R) -> Result<Reader<Streaming<R>>, ReadError> {
+        let (packet_reader, magic_cookie) = PacketReader::new_streaming(Streaming::new(reader))?;
+        Reader::from_packet_reader(packet_reader, magic_cookie)
+    }
+}
+
+/// An iterator that yields samples of type `S` read from a `Reader`.
+pub struct Samples<R: Read + Seek, S> {
+    reader: Reader<R>,
+    samples: Vec<S>,
+    sample_len: usize,
+    sample_pos: usize,
+}
+
+impl<R: Read + Seek, S: Sample> Samples<R, S> {
+    /// Returns the format of this ALAC file.
+    pub fn format(&self) -> Format {
+        self.reader.format()
+    }
+
+    /// Returns a `StreamInfo` describing the ALAC stream in this file.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.reader.stream_info()
+    }
+
+    /// Returns decode throughput statistics for the packets decoded so far.
+    pub fn throughput(&self) -> &Throughput {
+        self.reader.throughput()
+    }
+
+    /// Consumes the iterator returning one for the next file in a stream of concatenated files, or
+    /// `None` if there is no next file.
+    ///
+    /// Any samples not yet yielded from the current file are discarded. See `Reader::next_stream`
+    /// for details.
+    pub fn next_stream(self) -> Result<Option<Samples<R, S>>, ReadError> {
+        let dither = self.reader.is_dithered();
+        let next = self.reader.next_stream()?;
+        Ok(next.map(|reader| reader.into_samples().with_dither(dither)))
+    }
+
+    /// Sets whether TPDF dither is added to samples before they are requantized for a sample type
+    /// with fewer bits than the stream's bit depth, such as `i16` for a 24 bit stream. Samples
+    /// are truncated by default. Has no effect on sample types at least as wide as the stream.
+    pub fn with_dither(mut self, dither: bool) -> Samples<R, S> {
+        self.reader.set_dither(dither);
+        self
+    }
+
+    /// Sets how packets that fail to decode are handled, which is `ErrorPolicy::Report` by
+    /// default.
+    ///
+    /// The policy has no effect on packets that fail while resync is enabled.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Samples<R, S> {
+        self.reader.error_policy = policy;
+        self
+    }
+
+    /// Positions the iterator so the next sample yielded is the first channel of frame `sample`.
+    ///
+    /// Seeking resumes iteration stopped by `ErrorPolicy::Stop`. See `Reader::seek_to_sample` for
+    /// details.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<(), ReadError> {
+        self.reader.seek_to_sample(sample)?;
+        self.sample_len = 0;
+        self.sample_pos = 0;
+        Ok(())
+    }
+
+    /// Returns the position in samples per channel, as used by `seek_to_sample`, of the frame
+    /// containing the next sample to be yielded.
+    pub fn sample_position(&self) -> u64 {
+        let channels = self.stream_info().channels() as usize;
+        let buffered = (self.sample_len / channels - self.sample_pos / channels) as u64;
+        self.reader.sample_position() - buffered
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Consumes the iterator returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+#[cfg(feature = "threaded")]
+impl<R: Read + Seek + Send + 'static, S: Sample + Send + 'static> Samples<R, S> {
+    /// Consumes the iterator returning one that reads and decodes each packet on a background
+    /// thread while the samples of the previous packet are consumed.
+    pub fn threaded(self) -> ThreadedSamples<S> {
+        let samples = self.samples[self.sample_pos..self.sample_len].to_vec();
+        let packets = Packets {
+            reader: self.reader,
+            pool: vec![self.samples],
+        };
+        ThreadedSamples::new(packets, samples)
+    }
+}
+
+impl<R: Read + Seek, S: Sample> Iterator for Samples<R, S> {
+    type Item = Result<S, ReadError>;
+
+    fn next(&mut self) -> Option<Result<S, ReadError>> {
+        // Allocate sample buffer if required
+        if self.samples.is_empty() {
+            let max_samples = self.stream_info().max_samples_per_packet() as usize;
+            self.samples = vec![S::from_decoder(0, 16); max_samples];
+        }
+
+        // Decode the next packet if we're at the end of the current one.
+        if self.sample_pos == self.sample_len {
+            self.sample_len = match self.reader.decode_next_packet_into(&mut self.samples) {
+                Ok(Some(s)) => s.len(),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            self.sample_pos = 0;
+        }
+
+        let sample_pos = self.sample_pos;
+        self.sample_pos += 1;
+        Some(Ok(self.samples[sample_pos]))
+    }
+
+    /// The upper bound is taken from the container's sample tables and will be wrong if they do
+    /// not match the packets, or if packets fail to decode. It is `None` if the stream's length
+    /// is unknown, for example for raw streams.
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let reader = &self.reader;
+        let buffered = self.sample_len - self.sample_pos;
+        if !reader.is_length_known() {
+            return (buffered, None);
+        }
+        let frames_remaining = reader.frame_range().1.saturating_sub(reader.frame_position);
+        let channels = reader.stream_info().channels() as u64;
+        let remaining = (frames_remaining * channels + buffered as u64) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+/// An iterator-like type that yields frames of samples of type `S` read from a `Reader`.
+///
+/// Frames are borrowed from an internal buffer so are returned by `next_frame` rather than an
+/// `Iterator` implementation.
+pub struct Frames<R: Read + Seek, S> {
+    reader: Reader<R>,
+    samples: Vec<S>,
+    sample_len: usize,
+    sample_pos: usize,
+}
+
+impl<R: Read + Seek, S: Sample> Frames<R, S> {
+    /// Returns the format of this ALAC file.
+    pub fn format(&self) -> Format {
+        self.reader.format()
+    }
+
+    /// Returns a `StreamInfo` describing the ALAC stream in this file.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.reader.stream_info()
+    }
+
+    /// Returns decode throughput statistics for the packets decoded so far.
+    pub fn throughput(&self) -> &Throughput {
+        self.reader.throughput()
+    }
+
+    /// Consumes the iterator returning one for the next file in a stream of concatenated files, or
+    /// `None` if there is no next file.
+    ///
+    /// Any frames not yet yielded from the current file are discarded. See `Reader::next_stream`
+    /// for details.
+    pub fn next_stream(self) -> Result<Option<Frames<R, S>>, ReadError> {
+        let dither = self.reader.is_dithered();
+        let next = self.reader.next_stream()?;
+        Ok(next.map(|reader| reader.into_frames().with_dither(dither)))
+    }
+
+    /// Sets whether TPDF dither is added to samples before they are requantized for a narrow
+    /// sample type. See `Samples::with_dither` for details.
+    pub fn with_dither(mut self, dither: bool) -> Frames<R, S> {
+        self.reader.set_dither(dither);
+        self
+    }
+
+    /// Sets how packets that fail to decode are handled. See `Samples::with_error_policy` for
+    /// details.
+    pub fn with_error_policy(mut self, policy: ErrorPolicy) -> Frames<R, S> {
+        self.reader.error_policy = policy;
+        self
+    }
+
+    /// Positions the iterator so the next frame yielded is frame `sample`.
+    ///
+    /// See `Samples::seek_to_sample` for details.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<(), ReadError> {
+        self.reader.seek_to_sample(sample)?;
+        self.sample_len = 0;
+        self.sample_pos = 0;
+        Ok(())
+    }
+
+    /// Returns the position in samples per channel, as used by `seek_to_sample`, of the next frame
+    /// to be yielded.
+    pub fn sample_position(&self) -> u64 {
+        let channels = self.stream_info().channels() as usize;
+        let buffered = ((self.sample_len - self.sample_pos) / channels) as u64;
+        self.reader.sample_position() - buffered
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Consumes the iterator returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Returns the next frame, holding one sample for each channel in the order given by
+    /// `StreamInfo::channel_layout`, or `None` at the end of the stream.
+    ///
+    /// Errors are returned in the same way as by `Samples`.
+    pub fn next_frame(&mut self) -> Option<Result<&[S], ReadError>> {
+        // Allocate sample buffer if required
+        if self.samples.is_empty() {
+            let max_samples = self.stream_info().max_samples_per_packet() as usize;
+            self.samples = vec![S::from_decoder(0, 16); max_samples];
+        }
+
+        // Decode the next packet if we're at the end of the current one.
+        if self.sample_pos == self.sample_len {
+            self.sample_len = match self.reader.decode_next_packet_into(&mut self.samples) {
+                Ok(Some(s)) => s.len(),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            self.sample_pos = 0;
+        }
+
+        let channels = self.stream_info().channels() as usize;
+        let frame_pos = self.sample_pos;
+        self.sample_pos += channels;
+        Some(Ok(&self.samples[frame_pos..frame_pos + channels]))
+    }
+}
+
+/// A type that decodes packets into a user-provided buffer using `next_into`, or yields each
+/// packet in its own buffer as an `Iterator`.
+pub struct Packets<R: Read + Seek, S> {
+    reader: Reader<R>,
+    // Buffers returned by `recycle` for reuse by `next`.
+    pool: Vec<Vec<S>>,
+}
+
+impl<R: Read + Seek, S: Sample> Packets<R, S> {
+    /// Returns the format of this ALAC file.
+    pub fn format(&self) -> Format {
+        self.reader.format()
+    }
+
+    /// Returns a `StreamInfo` describing the ALAC stream in this file.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.reader.stream_info()
+    }
+
+    /// Returns decode throughput statistics for the packets decoded so far.
+    pub fn throughput(&self) -> &Throughput {
+        self.reader.throughput()
+    }
+
+    /// Returns the number of packets in the container's packet table.
+    pub fn packet_count(&self) -> u64 {
+        self.reader.packet_count()
+    }
+
+    /// Returns the length in bytes of the longest packet in the stream.
+    ///
+    /// See `Reader::max_packet_size` for details.
+    pub fn max_packet_size(&self) -> usize {
+        self.reader.max_packet_size()
+    }
+
+    /// Returns the number of packets in the container's packet table that have not yet been read.
+    pub fn packets_remaining(&self) -> u64 {
+        self.reader.packets_remaining()
+    }
+
+    /// Consumes the iterator returning one for the next file in a stream of concatenated files, or
+    /// `None` if there is no next file.
+    ///
+    /// See `Reader::next_stream` for details.
+    pub fn next_stream(self) -> Result<Option<Packets<R, S>>, ReadError> {
+        let dither = self.reader.is_dithered();
+        let next = self.reader.next_stream()?;
+        Ok(next.map(|reader| reader.into_packets().with_dither(dither)))
+    }
+
+    /// Sets whether TPDF dither is added to samples before they are requantized for a narrow
+    /// sample type. See `Samples::with_dither` for details.
+    pub fn with_dither(mut self, dither: bool) -> Packets<R, S> {
+        self.reader.set_dither(dither);
+        self
+    }
+
+    /// Positions the reader so the next packet decoded starts with the first channel of frame
+    /// `sample`.
+    ///
+    /// See `Reader::seek_to_sample` for details.
+    pub fn seek_to_sample(&mut self, sample: u64) -> Result<(), ReadError> {
+        self.reader.seek_to_sample(sample)
+    }
+
+    /// Returns the position in samples per channel, as used by `seek_to_sample`, of the first
+    /// frame of the next packet to be decoded.
+    pub fn sample_position(&self) -> u64 {
+        self.reader.sample_position()
+    }
+
+    /// Returns a reference to the underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.reader.get_ref()
+    }
+
+    /// Consumes the type returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+
+    /// Reads the next packet and decodes it into `out`.
+    ///
+    /// Channels are interleaved, e.g. for a stereo packet `out` would contains samples in the
+    /// order `[left, right, left, right, ..]`.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    pub fn next_into<'a>(&mut self, out: &'a mut [S]) -> Result<Option<&'a [S]>, ReadError> {
+        self.reader.decode_next_packet_into(out)
+    }
+
+    /// Returns statistics for the packet decoded by the last call to `next_into`, or `None` if it
+    /// did not decode a packet.
+    pub fn last_packet_stats(&self) -> Option<PacketStats> {
+        self.reader.last_packet_stats
+    }
+
+    /// Returns a buffer yielded by the iterator so it can be reused for a later packet, avoiding
+    /// an allocation.
+    pub fn recycle(&mut self, buf: Vec<S>) {
+        self.pool.push(buf);
+    }
+}
+
+#[cfg(feature = "threaded")]
+impl<R: Read + Seek + Send + 'static, S: Sample + Send + 'static> Packets<R, S> {
+    /// Consumes the iterator returning one that reads and decodes packets on a background thread,
+    /// up to two packets ahead of the consumer.
+    ///
+    /// This keeps slow reads, such as from network shares or SD cards, from delaying packets
+    /// during real-time playback.
+    pub fn threaded(self) -> ThreadedPackets<S> {
+        ThreadedPackets::new(self)
+    }
+}
+
+/// Yields the samples of each packet in a newly allocated buffer, or one returned by `recycle`.
+impl<R: Read + Seek, S: Sample> Iterator for Packets<R, S> {
+    type Item = Result<Vec<S>, ReadError>;
+
+    fn next(&mut self) -> Option<Result<Vec<S>, ReadError>> {
+        let max_samples = self.stream_info().max_samples_per_packet() as usize;
+        let mut buf = self.pool.pop().unwrap_or_default();
+        buf.resize(max_samples, S::from_decoder(0, 16));
+
+        match self.reader.decode_next_packet_into(&mut buf) {
+            Ok(Some(s)) => {
+                let len = s.len();
+                buf.truncate(len);
+                Some(Ok(buf))
+            }
+            Ok(None) => {
+                self.pool.push(buf);
+                None
+            }
+            Err(e) => {
+                self.pool.push(buf);
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "container-context", feature = "caf"))]
+use caf::CafContext;
+#[cfg(feature = "caf")]
+use caf::CafPacketReader;
+#[cfg(all(feature = "container-context", feature = "mp4"))]
+use mp4::Mp4Context;
+#[cfg(feature = "mp4")]
+use mp4::Mp4PacketReader;
+
+/// The packet most recently read by a `Reader`.
+struct PacketBuf {
+    buf: Vec<u8>,
+    // The file offset of the packet, if known.
+    offset: Option<u64>,
+    // The file packets are borrowed from rather than being copied into `buf`.
+    #[cfg(feature = "mmap")]
+    map: Option<MappedFile>,
+    // The location of the packet in `map`, if it was borrowed.
+    #[cfg(feature = "mmap")]
+    mapped: Option<Range<usize>>,
+}
+
+impl PacketBuf {
+    fn with_capacity(capacity: usize) -> PacketBuf {
+        PacketBuf {
+            buf: Vec::with_capacity(capacity),
+            offset: None,
+            #[cfg(feature = "mmap")]
+            map: None,
+            #[cfg(feature = "mmap")]
+            mapped: None,
+        }
+    }
+
+    fn data(&self) -> &[u8] {
+        #[cfg(feature = "mmap")]
+        {
+            if let (Some(map), Some(range)) = (&self.map, &self.mapped) {
+                return &map.as_bytes()[range.clone()];
+            }
+        }
+        &self.buf
+    }
+
+    /// Reads the next packet from `packet_reader` returning `false` if there are no more packets.
+    fn read_from<R: Read + Seek>(
+        &mut self,
+        packet_reader: &mut PacketReader<R>,
+    ) -> Result<bool, ReadError> {
+        #[cfg(feature = "mmap")]
+        {
+            self.mapped = None;
+            if let Some(ref map) = self.map {
+                let entry = match packet_reader.skip_packet()? {
+                    Some(entry) => entry,
+                    None => return Ok(false),
+                };
+                let len = map.as_bytes().len() as u64;
+                if entry.offset > len || entry.len > len - entry.offset {
+                    return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+                }
+                self.offset = Some(entry.offset);
+                self.mapped = Some(entry.offset as usize..(entry.offset + entry.len) as usize);
+                return Ok(true);
+            }
+        }
+
+        packet_reader.next_packet_into(&mut self.buf)?;
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+        let len = self.buf.len() as u64;
+        self.offset = packet_reader.position().map(|end| end - len);
+        Ok(true)
+    }
+}
+
+// Each `Reader` holds a single packet reader so the size of the largest doesn't matter.
+#[allow(clippy::large_enum_variant)]
+enum PacketReader<R: Read + Seek> {
+    #[cfg(feature = "caf")]
+    Caf(CafPacketReader<R>),
+    #[cfg(feature = "mp4")]
+    Mp4(Mp4PacketReader<R>),
+    Indexed(IndexedPacketReader<R>),
+    Raw(RawPacketReader<R>),
+    Source(SourcePacketReader<R>),
+}
+
+/// Returns an upper bound on the length of a packet in a stream described by `info`.
+pub(crate) fn max_packet_len(info: &StreamInfo) -> usize {
+    let uncompressed_len = uncompressed_packet_len(info);
+    cmp::max(uncompressed_len, info.max_frame_bytes() as usize)
+}
+
+/// Returns the length of an uncompressed packet in a stream described by `info`.
+fn uncompressed_packet_len(info: &StreamInfo) -> usize {
+    // An uncompressed packet plus the headers of up to one element per channel.
+    let uncompressed_bits = info.max_samples_per_packet() as usize * info.bit_depth() as usize;
+    uncompressed_bits / 8 + info.channels() as usize * 8 + 2
+}
+
+/// Returns the length of the longest packet expected in a stream, preferring the container's
+/// packet table to the magic cookie's `max_frame_bytes`.
+fn expected_max_packet_len(info: &StreamInfo, container_max: Option<u64>) -> usize {
+    match container_max {
+        Some(len) => cmp::min(len, usize::MAX as u64) as usize,
+        None if info.max_frame_bytes() > 0 => info.max_frame_bytes() as usize,
+        None => max_packet_len(info),
+    }
+}
+
+/// Returns the current time, or `None` on `wasm32-unknown-unknown` where `Instant::now` panics.
+fn now() -> Option<Instant> {
+    if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+        None
+    } else {
+        Some(Instant::now())
+    }
+}
+
+/// Returns the container format of the file read by `reader` by checking its first bytes, or
+/// `None` if it is not in a supported format.
+///
+/// This is much cheaper than `Reader::new` but a file identified as a supported format may still
+/// fail to open, e.g. if it contains no ALAC stream. The position of `reader` is left unchanged.
+pub fn probe<R: Read + Seek>(reader: &mut R) -> Result<Option<Format>, io::Error> {
+    let start = reader.stream_position()?;
+    let mut magic = [0; 8];
+    let result = reader.read_exact(&mut magic);
+    reader.seek(SeekFrom::Start(start))?;
+    match result {
+        Ok(()) => Ok(format_from_magic(&magic)),
+        Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the container format identified by the first eight bytes of a file without consuming
+/// them.
+fn peek_format<R: Read + Seek>(reader: &mut R) -> Result<Option<Format>, ReadError> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    reader.seek(SeekFrom::Current(-(magic.len() as i64)))?;
+    Ok(format_from_magic(&magic))
+}
+
+fn format_from_magic(magic: &[u8; 8]) -> Option<Format> {
+    match (&magic[0..4], &magic[4..8]) {
+        #[cfg(feature = "caf")]
+        (b"caff", _) => Some(Format::Caf),
+        #[cfg(feature = "mp4")]
+        (_, b"ftyp") => Some(Format::Mp4),
+        _ => None,
+    }
+}
+
+impl<R: Read> PacketReader<Streaming<R>> {
+    fn new_streaming(
+        mut reader: Streaming<R>,
+    ) -> Result<(PacketReader<Streaming<R>>, Vec<u8>), ReadError> {
+        match peek_format(&mut reader)? {
+            // The CAF parser only reads forwards when the packet table precedes the audio data.
+            #[cfg(feature = "caf")]
+            Some(Format::Caf) => {
+                let (reader, magic_cookie) = CafPacketReader::new(reader)?;
+                Ok((PacketReader::Caf(reader), magic_cookie))
+            }
+            #[cfg(feature = "mp4")]
+            Some(Format::Mp4) => {
+                let (reader, magic_cookie) = Mp4PacketReader::new_streaming(reader)?;
+                Ok((PacketReader::Mp4(reader), magic_cookie))
+            }
+            _ => Err(ReadError::UnsupportedFormat),
+        }
+    }
+}
+
+impl<R: Read + Seek> PacketReader<R> {
+    fn new(mut reader: R, track_index: usize) -> Result<(PacketReader<R>, Vec<u8>), ReadError> {
+        match peek_format(&mut reader)? {
+            #[cfg(feature = "caf")]
+            Some(Format::Caf) => {
+                if track_index != 0 {
+                    return Err(ReadError::Format(
+                        Format::Caf,
+                        ::invalid_data(
+                            ::ErrorKind::InvalidContainer,
+                            "track index is out of range",
+                        ),
+                    ));
+                }
+                let (reader, magic_cookie) = CafPacketReader::new(reader)?;
+                Ok((PacketReader::Caf(reader), magic_cookie))
+            }
+            #[cfg(feature = "mp4")]
+            Some(Format::Mp4) => {
+                let (reader, magic_cookie) = Mp4PacketReader::new_with_track(reader, track_index)?;
+                Ok((PacketReader::Mp4(reader), magic_cookie))
+            }
+            _ => Err(ReadError::UnsupportedFormat),
+        }
+    }
+
+    fn format(&self) -> Format {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(_) => Format::Caf,
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(_) => Format::Mp4,
+            PacketReader::Indexed(ref r) => r.format(),
+            PacketReader::Raw(_) => Format::Raw,
+            PacketReader::Source(_) => Format::Custom,
+        }
+    }
+
+    #[cfg(feature = "container-context")]
+    fn context(&self) -> Option<ContainerContext<'_>> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => Some(ContainerContext::Caf(r.context())),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => Some(ContainerContext::Mp4(r.context())),
+            PacketReader::Indexed(_) | PacketReader::Raw(_) | PacketReader::Source(_) => None,
+        }
+    }
+
+    /// Returns the ALAC tracks in the file if the container can contain more than one.
+    fn tracks(&self) -> Option<Vec<TrackInfo>> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(_) => None,
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => Some(r.tracks().to_vec()),
+            PacketReader::Indexed(_) | PacketReader::Raw(_) | PacketReader::Source(_) => None,
+        }
+    }
+
+    /// Returns the tags read from the container, leaving empty metadata in their place.
+    fn take_metadata(&mut self) -> Metadata {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref mut r) => r.take_metadata(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref mut r) => r.take_metadata(),
+            PacketReader::Indexed(_) | PacketReader::Raw(_) | PacketReader::Source(_) => {
+                Metadata::default()
+            }
+        }
+    }
+
+    fn into_next_file(self) -> Result<Option<R>, ReadError> {
+        match self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(r) => r.into_next_file(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(_) => Ok(None),
+            PacketReader::Indexed(_) | PacketReader::Raw(_) | PacketReader::Source(_) => Ok(None),
+        }
+    }
+
+    fn packet_count(&self) -> u64 {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.packet_count(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.packet_count(),
+            PacketReader::Indexed(ref r) => r.packet_count(),
+            PacketReader::Raw(_) => 0,
+            PacketReader::Source(ref r) => r.packet_count(),
+        }
+    }
+
+    /// Returns `false` if the number of packets is unknown, in which case `packet_count` is zero.
+    fn has_packet_count(&self) -> bool {
+        match *self {
+            PacketReader::Raw(_) => false,
+            PacketReader::Source(ref r) => r.has_packet_count(),
+            _ => true,
+        }
+    }
+
+    fn packet_index(&self) -> u64 {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.packet_index(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.packet_index(),
+            PacketReader::Indexed(ref r) => r.packet_index(),
+            PacketReader::Raw(ref r) => r.packet_index(),
+            PacketReader::Source(ref r) => r.packet_index(),
+        }
+    }
+
+    fn position(&self) -> Option<u64> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.position(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.position(),
+            PacketReader::Indexed(ref r) => r.position(),
+            PacketReader::Raw(ref r) => r.position(),
+            PacketReader::Source(_) => None,
+        }
+    }
+
+    fn get_ref(&self) -> &R {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.get_ref(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.get_ref(),
+            PacketReader::Indexed(ref r) => r.get_ref(),
+            PacketReader::Raw(ref r) => r.get_ref(),
+            PacketReader::Source(ref r) => r.get_ref(),
+        }
+    }
+
+    fn into_inner(self) -> R {
+        match self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(r) => r.into_inner(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(r) => r.into_inner(),
+            PacketReader::Indexed(r) => r.into_inner(),
+            PacketReader::Raw(r) => r.into_inner(),
+            PacketReader::Source(r) => r.into_inner(),
+        }
+    }
+
+    /// Sets the length of the longest packet read from containers with a table of packet lengths.
+    fn set_packet_len_limit(&mut self, limit: u64) {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref mut r) => r.set_packet_len_limit(limit),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref mut r) => r.set_packet_len_limit(limit),
+            PacketReader::Indexed(ref mut r) => r.set_packet_len_limit(limit),
+            // Raw streams are given their limit when created and packet sources read packets
+            // themselves.
+            PacketReader::Raw(_) | PacketReader::Source(_) => (),
+        }
+    }
+
+    fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref mut r) => r.next_packet_into(buf),
             #[cfg(feature = "mp4")]
             PacketReader::Mp4(ref mut r) => r.next_packet_into(buf),
+            PacketReader::Indexed(ref mut r) => r.next_packet_into(buf),
+            PacketReader::Raw(ref mut r) => r.next_packet_into(buf),
+            PacketReader::Source(ref mut r) => r.next_packet_into(buf),
+        }
+    }
+
+    /// Moves past the next packet without reading it, returning its location in the file or
+    /// `None` if there are no more packets.
+    ///
+    /// Only containers with a table of packet locations support this.
+    #[cfg(feature = "mmap")]
+    fn skip_packet(&mut self) -> Result<Option<PacketEntry>, ReadError> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref mut r) => Ok(r.skip_packet()),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref mut r) => r.skip_packet(),
+            PacketReader::Indexed(ref mut r) => Ok(r.skip_packet()),
+            PacketReader::Raw(_) | PacketReader::Source(_) => {
+                unreachable!("packets can only be skipped in containers with packet tables")
+            }
+        }
+    }
+
+    /// Returns the index of the packet containing `frame` and the frame that packet starts at.
+    fn packet_for_frame(&self, frame: u64, frames_per_packet: u64) -> (u64, u64) {
+        // Without a table of packet durations every packet is assumed to be full length.
+        let packet = frame / frames_per_packet;
+        let full_packets = (packet, packet * frames_per_packet);
+
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(_) => full_packets,
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.packet_for_frame(frame).unwrap_or(full_packets),
+            PacketReader::Indexed(_) | PacketReader::Raw(_) | PacketReader::Source(_) => {
+                full_packets
+            }
+        }
+    }
+
+    fn seek_to_packet(&mut self, packet: u64) -> Result<(), ReadError> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref mut r) => {
+                r.seek_to_packet(packet);
+                Ok(())
+            }
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref mut r) => r.seek_to_packet(packet),
+            PacketReader::Indexed(ref mut r) => {
+                r.seek_to_packet(packet);
+                Ok(())
+            }
+            PacketReader::Raw(ref mut r) => r.seek_to_packet(packet),
+            PacketReader::Source(ref mut r) => r.seek_to_packet(packet),
+        }
+    }
+
+    fn total_frames(&self) -> Option<u64> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.total_frames(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.total_frames(),
+            PacketReader::Indexed(ref r) => r.total_frames(),
+            PacketReader::Raw(_) => None,
+            PacketReader::Source(ref r) => r.total_frames(),
+        }
+    }
+
+    /// Returns the length of the longest packet in the container's packet table, if known.
+    fn max_packet_len(&self) -> Option<u64> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.max_packet_len(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.max_packet_len(),
+            PacketReader::Indexed(ref r) => r.max_packet_len(),
+            PacketReader::Raw(_) | PacketReader::Source(_) => None,
+        }
+    }
+
+    fn gapless_info(&self) -> Option<GaplessInfo> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => r.gapless_info(),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.gapless_info(),
+            PacketReader::Indexed(ref r) => r.gapless_info(),
+            PacketReader::Raw(_) => None,
+            PacketReader::Source(ref r) => r.gapless_info(),
+        }
+    }
+
+    #[cfg_attr(not(feature = "mp4"), allow(unused_variables))]
+    fn set_lazy_sample_tables(&mut self, enabled: bool) -> Result<(), ReadError> {
+        match *self {
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref mut r) => r.set_lazy_sample_tables(enabled),
+            _ => Ok(()),
+        }
+    }
+
+    fn packet_entries(&self) -> Result<Vec<PacketEntry>, ReadError> {
+        match *self {
+            #[cfg(feature = "caf")]
+            PacketReader::Caf(ref r) => Ok(r.packet_entries()),
+            #[cfg(feature = "mp4")]
+            PacketReader::Mp4(ref r) => r.packet_entries(),
+            PacketReader::Indexed(ref r) => Ok(r.packet_entries()),
+            PacketReader::Raw(_) | PacketReader::Source(_) => Err(ReadError::Io(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "raw streams and packet sources cannot be indexed",
+            ))),
         }
     }
 }
@@ -0,0 +1,376 @@
+//! Helpers for ALAC streams carried over RTP, as used by AirPlay.
+//!
+//! RTP timestamps count frames at the stream's sample rate and wrap at `2^32`, and sequence
+//! numbers count packets and wrap at `2^16`. `RtpClock` maps both onto unwrapped positions
+//! relative to the first packet of a stream.
+//!
+//! `Depacketizer` strips RTP headers, puts packets back in order and decodes them, concealing
+//! any that are lost.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use {invalid_data, read_be_u16, read_be_u32, Decoder, ErrorKind, InvalidData, Sample, StreamInfo};
+
+/// Maps RTP timestamps and sequence numbers to absolute frame positions and packet indices.
+///
+/// Positions are relative to the timestamp and sequence number the clock was created with and
+/// may be negative for packets sent before them. Packets may arrive out of order. Wraparound is
+/// resolved relative to the latest packet seen, so packets must not be more than `2^31` frames or
+/// `2^15` packets away from it.
+#[derive(Clone, Debug)]
+pub struct RtpClock {
+    frames_per_packet: u32,
+    base_timestamp: u32,
+    base_sequence: u16,
+    latest_position: i64,
+    latest_index: i64,
+}
+
+impl RtpClock {
+    /// Creates an `RtpClock` for a stream whose first packet has the given timestamp and sequence
+    /// number.
+    pub fn new(stream_info: &StreamInfo, timestamp: u32, sequence: u16) -> RtpClock {
+        RtpClock {
+            frames_per_packet: stream_info.max_frames_per_packet(),
+            base_timestamp: timestamp,
+            base_sequence: sequence,
+            latest_position: 0,
+            latest_index: 0,
+        }
+    }
+
+    /// Returns the frame position of the first frame of a packet with the given RTP timestamp.
+    pub fn frame_position(&mut self, timestamp: u32) -> i64 {
+        let latest = self.timestamp(self.latest_position);
+        let position = self.latest_position + timestamp.wrapping_sub(latest) as i32 as i64;
+        self.latest_position = self.latest_position.max(position);
+        position
+    }
+
+    /// Returns the index of the packet with the given sequence number.
+    pub fn packet_index(&mut self, sequence: u16) -> i64 {
+        let latest = self.sequence(self.latest_index);
+        let index = self.latest_index + sequence.wrapping_sub(latest) as i16 as i64;
+        self.latest_index = self.latest_index.max(index);
+        index
+    }
+
+    /// Returns the RTP timestamp of a frame position.
+    pub fn timestamp(&self, frame_position: i64) -> u32 {
+        self.base_timestamp.wrapping_add(frame_position as u32)
+    }
+
+    /// Returns the sequence number of a packet index.
+    pub fn sequence(&self, packet_index: i64) -> u16 {
+        self.base_sequence.wrapping_add(packet_index as u16)
+    }
+
+    /// Returns the frame position a packet is expected to start at if every packet before it
+    /// contains a whole frame length of frames.
+    pub fn expected_frame_position(&self, packet_index: i64) -> i64 {
+        packet_index * self.frames_per_packet as i64
+    }
+}
+
+/// The fixed fields of an RTP packet header.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RtpHeader {
+    marker: bool,
+    payload_type: u8,
+    sequence: u16,
+    timestamp: u32,
+    ssrc: u32,
+}
+
+impl RtpHeader {
+    /// Returns the marker bit, which AirPlay sets on the first packet after a flush.
+    pub fn marker(&self) -> bool {
+        self.marker
+    }
+
+    /// Returns the payload type.
+    pub fn payload_type(&self) -> u8 {
+        self.payload_type
+    }
+
+    /// Returns the sequence number.
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Returns the timestamp of the first frame in the packet.
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// Returns the synchronization source identifier.
+    pub fn ssrc(&self) -> u32 {
+        self.ssrc
+    }
+}
+
+/// Splits an RTP packet into its header and payload.
+///
+/// Contributing sources, header extensions and padding are skipped.
+pub fn parse_rtp_packet(packet: &[u8]) -> Result<(RtpHeader, &[u8]), InvalidData> {
+    if packet.len() < 12 {
+        return Err(invalid_data(
+            ErrorKind::InvalidContainer,
+            "rtp packet is too short",
+        ));
+    }
+    if packet[0] >> 6 != 2 {
+        return Err(invalid_data(
+            ErrorKind::InvalidContainer,
+            "rtp packet version must be 2",
+        ));
+    }
+
+    let header = RtpHeader {
+        marker: packet[1] & 0x80 != 0,
+        payload_type: packet[1] & 0x7f,
+        sequence: read_be_u16(&packet[2..4]),
+        timestamp: read_be_u32(&packet[4..8]),
+        ssrc: read_be_u32(&packet[8..12]),
+    };
+
+    let csrc_count = (packet[0] & 0x0f) as usize;
+    let mut payload = packet.get(12 + csrc_count * 4..).ok_or(invalid_data(
+        ErrorKind::InvalidContainer,
+        "rtp packet is too short",
+    ))?;
+
+    if packet[0] & 0x10 != 0 {
+        if payload.len() < 4 {
+            return Err(invalid_data(
+                ErrorKind::InvalidContainer,
+                "rtp header extension is too short",
+            ));
+        }
+        let extension_len = 4 + read_be_u16(&payload[2..4]) as usize * 4;
+        payload = payload.get(extension_len..).ok_or(invalid_data(
+            ErrorKind::InvalidContainer,
+            "rtp header extension is too short",
+        ))?;
+    }
+
+    if packet[0] & 0x20 != 0 {
+        let padding = *payload.last().ok_or(invalid_data(
+            ErrorKind::InvalidContainer,
+            "rtp padding is invalid",
+        ))? as usize;
+        if padding == 0 || padding > payload.len() {
+            return Err(invalid_data(
+                ErrorKind::InvalidContainer,
+                "rtp padding is invalid",
+            ));
+        }
+        payload = &payload[..payload.len() - padding];
+    }
+
+    Ok((header, payload))
+}
+
+/// Decodes ALAC packets received over RTP.
+///
+/// Packets are pushed as they arrive and decoded in sequence number order. A missing packet is
+/// waited for until a packet more than `jitter_packets` later arrives, after which it is treated
+/// as lost and concealed with `Decoder::conceal_lost_packet` so the output keeps its timing.
+pub struct Depacketizer {
+    decoder: Decoder,
+    clock: Option<RtpClock>,
+    jitter_packets: u32,
+    // Payloads that have arrived but not been decoded, by packet index.
+    pending: BTreeMap<i64, Vec<u8>>,
+    next_index: i64,
+    packets_lost: u64,
+}
+
+impl Depacketizer {
+    /// Creates a `Depacketizer` for a stream described by the `StreamInfo`.
+    pub fn new(stream_info: StreamInfo, jitter_packets: u32) -> Depacketizer {
+        Depacketizer {
+            decoder: Decoder::new(stream_info),
+            clock: None,
+            jitter_packets,
+            pending: BTreeMap::new(),
+            next_index: 0,
+            packets_lost: 0,
+        }
+    }
+
+    /// Returns the decoder, for example to set the concealment mode.
+    pub fn decoder(&mut self) -> &mut Decoder {
+        &mut self.decoder
+    }
+
+    /// Returns the number of packets that have been concealed.
+    pub fn packets_lost(&self) -> u64 {
+        self.packets_lost
+    }
+
+    /// Returns the index of the next packet to be decoded, counted from the first packet pushed.
+    pub fn next_packet_index(&self) -> i64 {
+        self.next_index
+    }
+
+    /// Adds a received RTP packet.
+    ///
+    /// Packets that arrive after they were due to be decoded, or that duplicate a packet already
+    /// received, are dropped.
+    pub fn push(&mut self, packet: &[u8]) -> Result<(), InvalidData> {
+        let (header, payload) = parse_rtp_packet(packet)?;
+        let stream_info = self.decoder.stream_info();
+        let clock = self
+            .clock
+            .get_or_insert_with(|| RtpClock::new(stream_info, header.timestamp, header.sequence));
+
+        let index = clock.packet_index(header.sequence);
+        if index >= self.next_index {
+            self.pending
+                .entry(index)
+                .or_insert_with(|| payload.to_vec());
+        }
+        Ok(())
+    }
+
+    /// Decodes the next packet into `out`, returning `None` if it has not arrived and is not yet
+    /// considered lost.
+    ///
+    /// Channels are interleaved, e.g. for a stereo packet `out` would contains samples in the
+    /// order `[left, right, left, right, ..]`.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    pub fn next_into<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<Option<&'a [S]>, InvalidData> {
+        let latest = match self.pending.keys().next_back() {
+            Some(&latest) => latest,
+            None => return Ok(None),
+        };
+
+        let index = self.next_index;
+        match self.pending.remove(&index) {
+            Some(payload) => {
+                self.next_index += 1;
+                self.decoder.decode_packet(&payload, out).map(Some)
+            }
+            None if latest - index > self.jitter_packets as i64 => {
+                self.next_index += 1;
+                self.packets_lost += 1;
+                Ok(Some(self.decoder.conceal_lost_packet(out)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rtp_packet, Depacketizer, RtpClock};
+    use alloc::vec::Vec;
+    use StreamInfo;
+
+    fn clock(timestamp: u32, sequence: u16) -> RtpClock {
+        let params = "352 0 16 40 10 14 2 255 0 0 44100";
+        let stream_info = StreamInfo::from_sdp_format_parameters(params).unwrap();
+        RtpClock::new(&stream_info, timestamp, sequence)
+    }
+
+    #[test]
+    fn wraparound() {
+        let mut clock = clock(u32::MAX - 351, u16::MAX);
+
+        assert_eq!(clock.packet_index(u16::MAX), 0);
+        assert_eq!(clock.frame_position(u32::MAX - 351), 0);
+
+        assert_eq!(clock.packet_index(0), 1);
+        assert_eq!(clock.frame_position(0), 352);
+        assert_eq!(clock.expected_frame_position(1), 352);
+
+        assert_eq!(clock.timestamp(704), 352);
+        assert_eq!(clock.sequence(2), 1);
+    }
+
+    #[test]
+    fn reordered_packets() {
+        let mut clock = clock(1000, 10);
+
+        assert_eq!(clock.packet_index(12), 2);
+        assert_eq!(clock.frame_position(1704), 704);
+
+        // A late packet does not move the clock backwards.
+        assert_eq!(clock.packet_index(11), 1);
+        assert_eq!(clock.frame_position(1352), 352);
+        assert_eq!(clock.packet_index(9), -1);
+        assert_eq!(clock.packet_index(13), 3);
+    }
+
+    fn rtp_packet(sequence: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x80, 96];
+        packet.extend_from_slice(&sequence.to_be_bytes());
+        packet.extend_from_slice(&(sequence as u32 * 4096).to_be_bytes());
+        packet.extend_from_slice(&0x1234u32.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn parse_header() {
+        // One contributing source, a one word extension and two bytes of padding.
+        let mut packet = vec![0xb1, 0xe0, 0, 7, 0, 0, 1, 0, 0, 0, 0, 9];
+        packet.extend_from_slice(&[0xaa; 4]);
+        packet.extend_from_slice(&[0xbe, 0xde, 0, 1, 0, 0, 0, 0]);
+        packet.extend_from_slice(&[1, 2, 3, 0, 2]);
+
+        let (header, payload) = parse_rtp_packet(&packet).unwrap();
+        assert!(header.marker());
+        assert_eq!(header.payload_type(), 96);
+        assert_eq!(header.sequence(), 7);
+        assert_eq!(header.timestamp(), 256);
+        assert_eq!(header.ssrc(), 9);
+        assert_eq!(payload, &[1, 2, 3]);
+
+        assert!(parse_rtp_packet(&packet[..20]).is_err());
+        packet[0] = 0x40;
+        assert!(parse_rtp_packet(&packet).is_err());
+    }
+
+    #[test]
+    fn depacketizer_reorders_and_conceals() {
+        let cookie = include_bytes!("../tests/data/magic_cookie.bin");
+        let stream_info = StreamInfo::from_cookie(cookie).unwrap();
+        let payload = &include_bytes!("../tests/data/packet_16_bit.bin")[..8581];
+        let mut depacketizer = Depacketizer::new(stream_info, 1);
+        let mut out = vec![0i16; 8192];
+
+        depacketizer.push(&rtp_packet(u16::MAX, payload)).unwrap();
+        depacketizer.push(&rtp_packet(1, payload)).unwrap();
+        depacketizer.push(&rtp_packet(0, payload)).unwrap();
+        for index in 0..3 {
+            assert_eq!(depacketizer.next_packet_index(), index);
+            assert_eq!(
+                depacketizer.next_into(&mut out).unwrap().unwrap().len(),
+                8192
+            );
+        }
+        assert!(depacketizer.next_into(&mut out).unwrap().is_none());
+
+        // Sequence number 2 is waited for until a packet more than one later arrives.
+        depacketizer.push(&rtp_packet(3, payload)).unwrap();
+        assert!(depacketizer.next_into(&mut out).unwrap().is_none());
+        depacketizer.push(&rtp_packet(4, payload)).unwrap();
+        let silence = depacketizer.next_into(&mut out).unwrap().unwrap();
+        assert!(silence.iter().all(|&s| s == 0));
+        assert_eq!(depacketizer.packets_lost(), 1);
+
+        // The lost packet arriving late is dropped.
+        depacketizer.push(&rtp_packet(2, payload)).unwrap();
+        assert_eq!(depacketizer.next_packet_index(), 4);
+        assert!(depacketizer.next_into(&mut out).unwrap().is_some());
+        assert!(depacketizer.next_into(&mut out).unwrap().is_some());
+        assert!(depacketizer.next_into(&mut out).unwrap().is_none());
+    }
+}
@@ -0,0 +1,241 @@
+//! Detection and trimming of leading and trailing silence.
+//!
+//! A frame is considered silent if every channel in it is at or below a threshold given in dBFS.
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use std::collections::VecDeque;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use std::io::{Read, Seek};
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use {ReadError, Reader, Samples};
+
+/// The amount of leading and trailing silence in a stream, measured in frames.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SilenceReport {
+    leading_frames: u64,
+    trailing_frames: u64,
+    total_frames: u64,
+}
+
+impl SilenceReport {
+    /// Returns the number of silent frames before the first non-silent frame.
+    pub fn leading_frames(&self) -> u64 {
+        self.leading_frames
+    }
+
+    /// Returns the number of silent frames after the last non-silent frame.
+    ///
+    /// If the stream is entirely silent this is zero and all frames are counted as leading.
+    pub fn trailing_frames(&self) -> u64 {
+        self.trailing_frames
+    }
+
+    /// Returns the total number of frames in the stream.
+    pub fn total_frames(&self) -> u64 {
+        self.total_frames
+    }
+
+    /// Returns `true` if no frame in the stream was above the threshold.
+    pub fn is_silent(&self) -> bool {
+        self.leading_frames == self.total_frames
+    }
+}
+
+/// Accumulates leading and trailing silence over a stream of samples.
+pub struct SilenceDetector {
+    channels: usize,
+    threshold: u32,
+    leading_frames: u64,
+    trailing_frames: u64,
+    total_frames: u64,
+    seen_sound: bool,
+}
+
+impl SilenceDetector {
+    /// Creates a `SilenceDetector` for a stream with `channels` interleaved channels.
+    ///
+    /// Samples at or below `threshold_db` dBFS are treated as silence.
+    pub fn new(channels: u8, threshold_db: f64) -> SilenceDetector {
+        SilenceDetector {
+            channels: channels as usize,
+            threshold: threshold(threshold_db),
+            leading_frames: 0,
+            trailing_frames: 0,
+            total_frames: 0,
+            seen_sound: false,
+        }
+    }
+
+    /// Adds interleaved samples to the measurement.
+    ///
+    /// Samples must be left-aligned as produced by `Sample for i32`.
+    pub fn add_samples(&mut self, samples: &[i32]) {
+        if self.channels == 0 {
+            return;
+        }
+
+        for frame in samples.chunks(self.channels) {
+            self.total_frames += 1;
+            if is_silent(frame, self.threshold) {
+                if self.seen_sound {
+                    self.trailing_frames += 1;
+                } else {
+                    self.leading_frames += 1;
+                }
+            } else {
+                self.seen_sound = true;
+                self.trailing_frames = 0;
+            }
+        }
+    }
+
+    /// Returns the silence measured over the samples added so far.
+    pub fn report(&self) -> SilenceReport {
+        SilenceReport {
+            leading_frames: self.leading_frames,
+            trailing_frames: self.trailing_frames,
+            total_frames: self.total_frames,
+        }
+    }
+}
+
+/// Measures the leading and trailing silence of the ALAC stream in `reader`.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub fn scan<R: Read + Seek>(
+    reader: Reader<R>,
+    threshold_db: f64,
+) -> Result<SilenceReport, ReadError> {
+    let stream_info = reader.stream_info().clone();
+    let mut detector = SilenceDetector::new(stream_info.channels(), threshold_db);
+
+    let mut packets = reader.into_packets::<i32>();
+    let mut buf = vec![0; stream_info.max_samples_per_packet() as usize];
+    while let Some(samples) = packets.next_into(&mut buf)? {
+        detector.add_samples(samples);
+    }
+
+    Ok(detector.report())
+}
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+impl<R: Read + Seek> Samples<R, i32> {
+    /// Returns an iterator that skips leading and trailing silence at or below `threshold_db`
+    /// dBFS.
+    ///
+    /// Silence in the middle of the stream is preserved. As it cannot be known whether silence is
+    /// trailing until the end of the stream, silent frames are buffered until the next non-silent
+    /// frame is decoded.
+    pub fn trim_silence(self, threshold_db: f64) -> TrimSilence<R> {
+        let channels = self.stream_info().channels() as usize;
+        TrimSilence {
+            samples: self,
+            threshold: threshold(threshold_db),
+            channels,
+            frame: Vec::with_capacity(channels),
+            ready: VecDeque::new(),
+            silent: Vec::new(),
+            seen_sound: false,
+        }
+    }
+}
+
+/// An iterator that yields samples read from a `Reader` with leading and trailing silence removed.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+pub struct TrimSilence<R: Read + Seek> {
+    samples: Samples<R, i32>,
+    threshold: u32,
+    channels: usize,
+    frame: Vec<i32>,
+    ready: VecDeque<i32>,
+    silent: Vec<i32>,
+    seen_sound: bool,
+}
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+impl<R: Read + Seek> Iterator for TrimSilence<R> {
+    type Item = Result<i32, ReadError>;
+
+    fn next(&mut self) -> Option<Result<i32, ReadError>> {
+        loop {
+            if let Some(sample) = self.ready.pop_front() {
+                return Some(Ok(sample));
+            }
+
+            // Frames never span packets so a partial frame only occurs at the end of the stream.
+            self.frame.clear();
+            while self.frame.len() < self.channels {
+                match self.samples.next()? {
+                    Ok(sample) => self.frame.push(sample),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            if is_silent(&self.frame, self.threshold) {
+                if self.seen_sound {
+                    self.silent.extend_from_slice(&self.frame);
+                }
+            } else {
+                self.seen_sound = true;
+                self.ready.extend(self.silent.drain(..));
+                self.ready.extend(self.frame.iter().cloned());
+            }
+        }
+    }
+}
+
+fn threshold(threshold_db: f64) -> u32 {
+    let threshold = 10f64.powf(threshold_db / 20.0) * 2_147_483_648.0;
+    if threshold >= u32::MAX as f64 {
+        u32::MAX
+    } else if threshold > 0.0 {
+        threshold as u32
+    } else {
+        0
+    }
+}
+
+fn is_silent(frame: &[i32], threshold: u32) -> bool {
+    frame.iter().all(|s| s.unsigned_abs() <= threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SilenceDetector;
+
+    #[test]
+    fn leading_and_trailing_silence() {
+        let mut detector = SilenceDetector::new(2, -60.0);
+        detector.add_samples(&[
+            0,
+            0,
+            1 << 10,
+            -(1 << 10),
+            0,
+            1 << 24,
+            1 << 20,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ]);
+
+        let report = detector.report();
+        assert_eq!(report.leading_frames(), 2);
+        assert_eq!(report.trailing_frames(), 3);
+        assert_eq!(report.total_frames(), 6);
+        assert!(!report.is_silent());
+    }
+
+    #[test]
+    fn all_silent() {
+        let mut detector = SilenceDetector::new(1, -60.0);
+        detector.add_samples(&[0; 16]);
+
+        let report = detector.report();
+        assert_eq!(report.leading_frames(), 16);
+        assert_eq!(report.trailing_frames(), 0);
+        assert!(report.is_silent());
+    }
+}
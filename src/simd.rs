@@ -0,0 +1,278 @@
+//! SIMD versions of the stereo unmixing and extra bits loops of the decoder.
+//!
+//! Each function processes as many whole vectors of samples as it can and returns the number of
+//! samples processed, leaving the rest to the scalar loops in `dec`. On x86 AVX2 is used if it is
+//! detected at runtime and SSE2 otherwise. NEON is always available on aarch64.
+
+#[cfg(target_arch = "aarch64")]
+use std::arch::aarch64::*;
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// Unmixes the stereo channels `u` and `v` in place, returning the number of samples unmixed.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn unmix_stereo(u: &mut [i32], v: &mut [i32], mix_bits: u8, mix_res: i8) -> usize {
+    if is_x86_feature_detected!("avx2") {
+        unsafe { unmix_stereo_avx2(u, v, mix_bits, mix_res) }
+    } else if is_x86_feature_detected!("sse2") {
+        unsafe { unmix_stereo_sse2(u, v, mix_bits, mix_res) }
+    } else {
+        0
+    }
+}
+
+/// Unmixes the stereo channels `u` and `v` in place, returning the number of samples unmixed.
+#[cfg(target_arch = "aarch64")]
+pub fn unmix_stereo(u: &mut [i32], v: &mut [i32], mix_bits: u8, mix_res: i8) -> usize {
+    unsafe { unmix_stereo_neon(u, v, mix_bits, mix_res) }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn unmix_stereo(_: &mut [i32], _: &mut [i32], _: u8, _: i8) -> usize {
+    0
+}
+
+/// Shifts each sample in `buf` left by `sample_shift` bits and appends its extra bits, returning
+/// the number of samples updated.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub fn append_extra_bits(buf: &mut [i32], extra_bits: &[u16], sample_shift: u8) -> usize {
+    if is_x86_feature_detected!("avx2") {
+        unsafe { append_extra_bits_avx2(buf, extra_bits, sample_shift) }
+    } else if is_x86_feature_detected!("sse2") {
+        unsafe { append_extra_bits_sse2(buf, extra_bits, sample_shift) }
+    } else {
+        0
+    }
+}
+
+/// Shifts each sample in `buf` left by `sample_shift` bits and appends its extra bits, returning
+/// the number of samples updated.
+#[cfg(target_arch = "aarch64")]
+pub fn append_extra_bits(buf: &mut [i32], extra_bits: &[u16], sample_shift: u8) -> usize {
+    unsafe { append_extra_bits_neon(buf, extra_bits, sample_shift) }
+}
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+pub fn append_extra_bits(_: &mut [i32], _: &[u16], _: u8) -> usize {
+    0
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn unmix_stereo_avx2(u: &mut [i32], v: &mut [i32], mix_bits: u8, mix_res: i8) -> usize {
+    // The shift is masked to match `i32::wrapping_shr` in the scalar loop.
+    let shift = _mm_cvtsi32_si128(mix_bits as i32 & 31);
+    let res = _mm256_set1_epi32(mix_res as i32);
+
+    let mut len = 0;
+    for (u, v) in u.chunks_exact_mut(8).zip(v.chunks_exact_mut(8)) {
+        let u = u.as_mut_ptr() as *mut __m256i;
+        let v = v.as_mut_ptr() as *mut __m256i;
+        let x = _mm256_loadu_si256(u);
+        let y = _mm256_loadu_si256(v);
+
+        let r = _mm256_sub_epi32(x, _mm256_sra_epi32(_mm256_mullo_epi32(y, res), shift));
+        let l = _mm256_add_epi32(r, y);
+
+        _mm256_storeu_si256(u, l);
+        _mm256_storeu_si256(v, r);
+        len += 8;
+    }
+    len
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn unmix_stereo_sse2(u: &mut [i32], v: &mut [i32], mix_bits: u8, mix_res: i8) -> usize {
+    let shift = _mm_cvtsi32_si128(mix_bits as i32 & 31);
+    let res = _mm_set1_epi32(mix_res as i32);
+
+    let mut len = 0;
+    for (u, v) in u.chunks_exact_mut(4).zip(v.chunks_exact_mut(4)) {
+        let u = u.as_mut_ptr() as *mut __m128i;
+        let v = v.as_mut_ptr() as *mut __m128i;
+        let x = _mm_loadu_si128(u);
+        let y = _mm_loadu_si128(v);
+
+        let r = _mm_sub_epi32(x, _mm_sra_epi32(mullo_epi32_sse2(y, res), shift));
+        let l = _mm_add_epi32(r, y);
+
+        _mm_storeu_si128(u, l);
+        _mm_storeu_si128(v, r);
+        len += 4;
+    }
+    len
+}
+
+/// Multiplies packed 32 bit integers keeping the low 32 bits of each product, as
+/// `_mm_mullo_epi32` does in SSE4.1.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn mullo_epi32_sse2(a: __m128i, b: __m128i) -> __m128i {
+    // The low 32 bits of a product are the same whether its operands are signed or unsigned.
+    let even = _mm_mul_epu32(a, b);
+    let odd = _mm_mul_epu32(_mm_srli_epi64(a, 32), _mm_srli_epi64(b, 32));
+    _mm_unpacklo_epi32(
+        _mm_shuffle_epi32(even, 0b00_00_10_00),
+        _mm_shuffle_epi32(odd, 0b00_00_10_00),
+    )
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn append_extra_bits_avx2(buf: &mut [i32], extra_bits: &[u16], sample_shift: u8) -> usize {
+    let shift = _mm_cvtsi32_si128(sample_shift as i32);
+
+    let mut len = 0;
+    for (buf, extra_bits) in buf.chunks_exact_mut(8).zip(extra_bits.chunks_exact(8)) {
+        let buf = buf.as_mut_ptr() as *mut __m256i;
+        let x = _mm256_loadu_si256(buf);
+        let bits = _mm256_cvtepu16_epi32(_mm_loadu_si128(extra_bits.as_ptr() as *const __m128i));
+        _mm256_storeu_si256(buf, _mm256_or_si256(_mm256_sll_epi32(x, shift), bits));
+        len += 8;
+    }
+    len
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn append_extra_bits_sse2(buf: &mut [i32], extra_bits: &[u16], sample_shift: u8) -> usize {
+    let shift = _mm_cvtsi32_si128(sample_shift as i32);
+    let zero = _mm_setzero_si128();
+
+    let mut len = 0;
+    for (buf, extra_bits) in buf.chunks_exact_mut(4).zip(extra_bits.chunks_exact(4)) {
+        let buf = buf.as_mut_ptr() as *mut __m128i;
+        let x = _mm_loadu_si128(buf);
+        let bits = _mm_loadl_epi64(extra_bits.as_ptr() as *const __m128i);
+        let bits = _mm_unpacklo_epi16(bits, zero);
+        _mm_storeu_si128(buf, _mm_or_si128(_mm_sll_epi32(x, shift), bits));
+        len += 4;
+    }
+    len
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn unmix_stereo_neon(u: &mut [i32], v: &mut [i32], mix_bits: u8, mix_res: i8) -> usize {
+    // Shifting left by a negative amount is an arithmetic right shift.
+    let shift = vdupq_n_s32(-(mix_bits as i32 & 31));
+    let res = vdupq_n_s32(mix_res as i32);
+
+    let mut len = 0;
+    for (u, v) in u.chunks_exact_mut(4).zip(v.chunks_exact_mut(4)) {
+        let x = vld1q_s32(u.as_ptr());
+        let y = vld1q_s32(v.as_ptr());
+
+        let r = vsubq_s32(x, vshlq_s32(vmulq_s32(y, res), shift));
+        let l = vaddq_s32(r, y);
+
+        vst1q_s32(u.as_mut_ptr(), l);
+        vst1q_s32(v.as_mut_ptr(), r);
+        len += 4;
+    }
+    len
+}
+
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn append_extra_bits_neon(buf: &mut [i32], extra_bits: &[u16], sample_shift: u8) -> usize {
+    let shift = vdupq_n_s32(sample_shift as i32);
+
+    let mut len = 0;
+    for (buf, extra_bits) in buf.chunks_exact_mut(4).zip(extra_bits.chunks_exact(4)) {
+        let x = vld1q_s32(buf.as_ptr());
+        let bits = vreinterpretq_s32_u32(vmovl_u16(vld1_u16(extra_bits.as_ptr())));
+        vst1q_s32(buf.as_mut_ptr(), vorrq_s32(vshlq_s32(x, shift), bits));
+        len += 4;
+    }
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    /// Returns pseudo-random samples including the extremes of `i32`.
+    fn samples(len: usize, seed: u32) -> Vec<i32> {
+        let mut state = seed;
+        let mut samples: Vec<i32> = (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as i32
+            })
+            .collect();
+        samples[0] = i32::MIN;
+        samples[1] = i32::MAX;
+        samples
+    }
+
+    fn unmix_stereo_scalar(u: &mut [i32], v: &mut [i32], mix_bits: u8, mix_res: i8) {
+        for (u, v) in u.iter_mut().zip(v.iter_mut()) {
+            let r = u.wrapping_sub(v.wrapping_mul(mix_res as i32).wrapping_shr(mix_bits as u32));
+            *u = r.wrapping_add(*v);
+            *v = r;
+        }
+    }
+
+    #[test]
+    fn unmix_stereo() {
+        for &mix_bits in &[0, 1, 7, 31, 32, 255] {
+            for &mix_res in &[-128, -3, 0, 1, 127] {
+                let (mut u, mut v) = (samples(67, 1), samples(67, 2));
+                let (mut expected_u, mut expected_v) = (u.clone(), v.clone());
+                unmix_stereo_scalar(&mut expected_u, &mut expected_v, mix_bits, mix_res);
+
+                let len = super::unmix_stereo(&mut u, &mut v, mix_bits, mix_res);
+                assert_eq!(u[..len], expected_u[..len]);
+                assert_eq!(v[..len], expected_v[..len]);
+            }
+        }
+    }
+
+    #[test]
+    fn append_extra_bits() {
+        let extra_bits: Vec<u16> = samples(67, 3).iter().map(|&bits| bits as u16).collect();
+        for &sample_shift in &[1, 8, 16] {
+            let mut buf = samples(67, 4);
+            let expected: Vec<i32> = buf
+                .iter()
+                .zip(&extra_bits)
+                .map(|(&sample, &bits)| {
+                    (sample << sample_shift) | (bits as i32 & ((1 << sample_shift) - 1))
+                })
+                .collect();
+            // Extra bits are never wider than the sample shift.
+            let extra_bits: Vec<u16> = extra_bits
+                .iter()
+                .map(|&bits| (bits as u32 & ((1 << sample_shift) - 1)) as u16)
+                .collect();
+
+            let len = super::append_extra_bits(&mut buf, &extra_bits, sample_shift);
+            assert_eq!(buf[..len], expected[..len]);
+        }
+    }
+
+    #[test]
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    fn sse2() {
+        if !is_x86_feature_detected!("sse2") {
+            return;
+        }
+        let (mut u, mut v) = (samples(67, 5), samples(67, 6));
+        let (mut expected_u, mut expected_v) = (u.clone(), v.clone());
+        unmix_stereo_scalar(&mut expected_u, &mut expected_v, 3, -5);
+        let len = unsafe { super::unmix_stereo_sse2(&mut u, &mut v, 3, -5) };
+        assert_eq!(len, 64);
+        assert_eq!(u[..len], expected_u[..len]);
+        assert_eq!(v[..len], expected_v[..len]);
+
+        let mut buf = samples(67, 7);
+        let expected: Vec<i32> = buf.iter().map(|&sample| (sample << 4) | 9).collect();
+        let len = unsafe { super::append_extra_bits_sse2(&mut buf, &[9; 67], 4) };
+        assert_eq!(len, 64);
+        assert_eq!(buf[..len], expected[..len]);
+    }
+}
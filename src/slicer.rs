@@ -0,0 +1,167 @@
+//! Sample and packet iterators for ALAC streams that are not stored in a supported container.
+//!
+//! A `PacketSlicer` pairs a magic cookie with a source of packets, for example packets received
+//! over RTP or extracted from a custom container, and provides the same `Samples` and `Packets`
+//! interface as `Reader`.
+
+use alloc::vec::Vec;
+use std::marker::PhantomData;
+
+use {Decoder, InvalidData, Sample, StreamInfo};
+
+/// A source of ALAC packets along with the magic cookie needed to decode them.
+///
+/// Packets are taken from any iterator of byte buffers. A callback can be used with
+/// `std::iter::from_fn`.
+pub struct PacketSlicer<I> {
+    packets: I,
+    decoder: Decoder,
+}
+
+impl<I> PacketSlicer<I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    /// Creates a `PacketSlicer` from a magic cookie and an iterator of packets.
+    pub fn new<P>(magic_cookie: &[u8], packets: P) -> Result<PacketSlicer<I>, InvalidData>
+    where
+        P: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
+        let stream_info = StreamInfo::from_cookie(magic_cookie)?;
+        Ok(PacketSlicer::with_stream_info(stream_info, packets))
+    }
+
+    /// Creates a `PacketSlicer` from a `StreamInfo` and an iterator of packets.
+    pub fn with_stream_info<P>(stream_info: StreamInfo, packets: P) -> PacketSlicer<I>
+    where
+        P: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
+        PacketSlicer {
+            packets: packets.into_iter(),
+            decoder: Decoder::new(stream_info),
+        }
+    }
+
+    /// Returns a `StreamInfo` describing the ALAC stream.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.decoder.stream_info()
+    }
+
+    /// Returns an iterator over the samples in the ALAC stream.
+    ///
+    /// Channels are interleaved, e.g. for a stereo stream they would be yielded in the order
+    /// `[left, right, left, right, ..]`.
+    pub fn into_samples<S: Sample>(self) -> Samples<I, S> {
+        Samples {
+            slicer: self,
+            samples: Vec::new(),
+            sample_len: 0,
+            sample_pos: 0,
+        }
+    }
+
+    /// Returns an iterator-like type that decodes packets into a user-provided buffer.
+    pub fn into_packets<S: Sample>(self) -> Packets<I, S> {
+        Packets {
+            slicer: self,
+            phantom: PhantomData,
+        }
+    }
+
+    fn decode_next_packet_into<'a, S: Sample>(
+        &mut self,
+        out: &'a mut [S],
+    ) -> Result<Option<&'a [S]>, InvalidData> {
+        // Packets containing no samples are skipped rather than ending the stream.
+        loop {
+            let packet = match self.packets.next() {
+                Some(packet) => packet,
+                None => return Ok(None),
+            };
+            let num_samples = self.decoder.decode_packet(packet.as_ref(), out)?.len();
+            if num_samples > 0 {
+                return Ok(Some(&out[..num_samples]));
+            }
+        }
+    }
+}
+
+/// An iterator that yields samples of type `S` decoded from a `PacketSlicer`.
+pub struct Samples<I, S> {
+    slicer: PacketSlicer<I>,
+    samples: Vec<S>,
+    sample_len: usize,
+    sample_pos: usize,
+}
+
+impl<I, S> Samples<I, S>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+    S: Sample,
+{
+    /// Returns a `StreamInfo` describing the ALAC stream.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.slicer.stream_info()
+    }
+}
+
+impl<I, S> Iterator for Samples<I, S>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+    S: Sample,
+{
+    type Item = Result<S, InvalidData>;
+
+    fn next(&mut self) -> Option<Result<S, InvalidData>> {
+        // Allocate sample buffer if required
+        if self.samples.is_empty() {
+            let max_samples = self.stream_info().max_samples_per_packet() as usize;
+            self.samples = vec![S::from_decoder(0, 16); max_samples];
+        }
+
+        // Decode the next packet if we're at the end of the current one.
+        if self.sample_pos == self.sample_len {
+            self.sample_len = match self.slicer.decode_next_packet_into(&mut self.samples) {
+                Ok(Some(s)) => s.len(),
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            self.sample_pos = 0;
+        }
+
+        let sample_pos = self.sample_pos;
+        self.sample_pos += 1;
+        Some(Ok(self.samples[sample_pos]))
+    }
+}
+
+/// An iterator-like type that decodes packets from a `PacketSlicer` into a user-provided buffer.
+pub struct Packets<I, S> {
+    slicer: PacketSlicer<I>,
+    phantom: PhantomData<[S]>,
+}
+
+impl<I, S> Packets<I, S>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+    S: Sample,
+{
+    /// Returns a `StreamInfo` describing the ALAC stream.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.slicer.stream_info()
+    }
+
+    /// Decodes the next packet into `out`.
+    ///
+    /// Channels are interleaved, e.g. for a stereo packet `out` would contains samples in the
+    /// order `[left, right, left, right, ..]`.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    pub fn next_into<'a>(&mut self, out: &'a mut [S]) -> Result<Option<&'a [S]>, InvalidData> {
+        self.slicer.decode_next_packet_into(out)
+    }
+}
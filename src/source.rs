@@ -0,0 +1,115 @@
+use std::io::{self, ErrorKind, Read, Seek};
+
+use {GaplessInfo, ReadError};
+
+/// A seekable byte stream, implemented for every type implementing `Read` and `Seek`.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// A source of ALAC packets stored in a container not supported by `Reader`.
+///
+/// Implementing `PacketSource` for a container such as Matroska or a custom network framing
+/// allows it to be read with `Reader::from_packet_source`, reusing `Samples`, `Packets` and the
+/// rest of the `Reader` API. The source is given the underlying reader each time it needs to read
+/// from it.
+///
+/// Only `magic_cookie` and `next_packet_into` are required. Without `packet_count` and
+/// `total_frames` the stream length is unknown and `Reader::total_samples` returns zero.
+pub trait PacketSource {
+    /// Returns the ALAC magic cookie describing the stream.
+    fn magic_cookie(&self) -> &[u8];
+
+    /// Reads the next packet from `reader` into `buf`, replacing its contents, or clears `buf` if
+    /// there are no more packets.
+    fn next_packet_into(&mut self, reader: &mut dyn ReadSeek, buf: &mut Vec<u8>) -> io::Result<()>;
+
+    /// Positions the source so the next packet read is packet `packet`, counting from zero.
+    ///
+    /// Every packet is assumed to hold `StreamInfo::max_frames_per_packet` frames when seeking.
+    /// By default seeking fails with an error of kind `ErrorKind::Unsupported`.
+    fn seek_to_packet(&mut self, reader: &mut dyn ReadSeek, packet: u64) -> io::Result<()> {
+        let _ = (reader, packet);
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "packet source does not support seeking",
+        ))
+    }
+
+    /// Returns the number of packets in the stream, if known.
+    fn packet_count(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the number of frames in the stream, if known.
+    fn total_frames(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the stream's encoder delay and length, if known.
+    fn gapless_info(&self) -> Option<GaplessInfo> {
+        None
+    }
+}
+
+pub struct SourcePacketReader<R> {
+    reader: R,
+    source: Box<dyn PacketSource + Send>,
+    packet_idx: u64,
+}
+
+impl<R: Read + Seek> SourcePacketReader<R> {
+    pub fn new(reader: R, source: Box<dyn PacketSource + Send>) -> SourcePacketReader<R> {
+        SourcePacketReader {
+            reader,
+            source,
+            packet_idx: 0,
+        }
+    }
+
+    pub fn magic_cookie(&self) -> &[u8] {
+        self.source.magic_cookie()
+    }
+
+    pub fn packet_count(&self) -> u64 {
+        self.source.packet_count().unwrap_or(0)
+    }
+
+    pub fn has_packet_count(&self) -> bool {
+        self.source.packet_count().is_some()
+    }
+
+    pub fn total_frames(&self) -> Option<u64> {
+        self.source.total_frames()
+    }
+
+    pub fn gapless_info(&self) -> Option<GaplessInfo> {
+        self.source.gapless_info()
+    }
+
+    pub fn packet_index(&self) -> u64 {
+        self.packet_idx
+    }
+
+    pub fn seek_to_packet(&mut self, packet: u64) -> Result<(), ReadError> {
+        self.source.seek_to_packet(&mut self.reader, packet)?;
+        self.packet_idx = packet;
+        Ok(())
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    pub fn next_packet_into(&mut self, buf: &mut Vec<u8>) -> Result<(), ReadError> {
+        self.source.next_packet_into(&mut self.reader, buf)?;
+        if !buf.is_empty() {
+            self.packet_idx += 1;
+        }
+        Ok(())
+    }
+}
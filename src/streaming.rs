@@ -0,0 +1,169 @@
+use std::cmp;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+
+/// The number of recently read bytes kept to allow short backward seeks.
+const WINDOW_LEN: usize = 64 * 1024;
+
+/// Adapts a non-seekable byte stream, such as a pipe or network socket, for use with `Reader`.
+///
+/// Forward seeks are performed by reading and discarding data. Backward seeks are only possible
+/// within the most recently read 64 KiB, and seeking relative to the end of the stream is not
+/// supported.
+///
+/// A `Streaming` reader is usually created with `Reader::new_streaming`.
+pub struct Streaming<R> {
+    reader: R,
+    // Bytes most recently read from `reader`, ending at `end`.
+    window: Vec<u8>,
+    // The number of bytes read from `reader`.
+    end: u64,
+    // The logical position of the stream, which may be behind or beyond `end`.
+    position: u64,
+}
+
+impl<R: Read> Streaming<R> {
+    /// Creates a `Streaming` reader from a stream positioned at the start of an ALAC file.
+    pub fn new(reader: R) -> Streaming<R> {
+        Streaming {
+            reader,
+            window: Vec::new(),
+            end: 0,
+            position: 0,
+        }
+    }
+
+    /// Returns a reference to the underlying stream.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+
+    /// Consumes the `Streaming` reader returning the underlying stream.
+    ///
+    /// Any data read from the stream but not yet consumed is lost.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn window_start(&self) -> u64 {
+        self.end - self.window.len() as u64
+    }
+
+    /// Reads and discards data until the underlying stream reaches `position` or its end.
+    fn skip_to(&mut self, position: u64) -> io::Result<()> {
+        let skip = position - self.end;
+        let skipped = io::copy(&mut (&mut self.reader).take(skip), &mut io::sink())?;
+        if skipped > 0 {
+            self.window.clear();
+            self.end += skipped;
+        }
+        Ok(())
+    }
+
+    fn push_window(&mut self, buf: &[u8]) {
+        self.window.extend_from_slice(buf);
+        self.end += buf.len() as u64;
+        // The window is trimmed in bulk so that each byte is only moved a bounded number of times.
+        if self.window.len() > 2 * WINDOW_LEN {
+            let excess = self.window.len() - WINDOW_LEN;
+            self.window.drain(..excess);
+        }
+    }
+}
+
+impl<R: Read> Read for Streaming<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.end {
+            let start = (self.position - self.window_start()) as usize;
+            let len = cmp::min(buf.len(), self.window.len() - start);
+            buf[..len].copy_from_slice(&self.window[start..start + len]);
+            self.position += len as u64;
+            return Ok(len);
+        }
+
+        if self.position > self.end {
+            self.skip_to(self.position)?;
+            if self.position > self.end {
+                return Ok(0);
+            }
+        }
+
+        let len = self.reader.read(buf)?;
+        self.push_window(&buf[..len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl<R: Read> Seek for Streaming<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => {
+                return Err(io::Error::new(
+                    ErrorKind::Unsupported,
+                    "cannot seek from the end of a streaming reader",
+                ))
+            }
+        };
+        let position = position.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+
+        if position < self.window_start() {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "cannot seek backwards beyond the data buffered by a streaming reader",
+            ));
+        }
+        // Skipping forward is deferred until the next read.
+        self.position = position;
+        Ok(position)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Streaming, WINDOW_LEN};
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn seek_within_window() {
+        let data: Vec<u8> = (0..4 * WINDOW_LEN).map(|i| i as u8).collect();
+        let mut reader = Streaming::new(&data[..]);
+
+        let mut buf = [0; 16];
+        reader.seek(SeekFrom::Start(1000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1000..1016]);
+
+        reader.seek(SeekFrom::Current(-8)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1008..1024]);
+
+        // Data skipped over by a forward seek can't be returned to.
+        assert!(reader.seek(SeekFrom::Start(0)).is_err());
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(&rest[..], &data[1024..]);
+        assert!(reader.seek(SeekFrom::Start(1024)).is_err());
+        reader
+            .seek(SeekFrom::Start((data.len() - WINDOW_LEN) as u64))
+            .unwrap();
+        assert!(reader.seek(SeekFrom::End(0)).is_err());
+
+        // Seeking beyond the end of the stream behaves like a seekable stream.
+        reader
+            .seek(SeekFrom::Start(data.len() as u64 + 10))
+            .unwrap();
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+}
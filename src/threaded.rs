@@ -0,0 +1,142 @@
+//! Decoding ahead of the consumer on a background thread.
+//!
+//! Reading and decoding a packet happen on a separate thread while the previous packet is being
+//! consumed, so playback from slow media doesn't stall on IO between packets.
+
+use std::io::{Read, Seek};
+use std::mem;
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use {Packets, ReadError, Sample, StreamInfo};
+
+/// The number of decoded packets that can wait to be consumed.
+const DECODE_AHEAD_PACKETS: usize = 2;
+
+/// An iterator over decoded packets that are read and decoded on a background thread.
+///
+/// This is created by `Packets::threaded`. The thread stops once the end of the stream is reached
+/// or the iterator is dropped.
+pub struct ThreadedPackets<S> {
+    stream_info: StreamInfo,
+    packets: Receiver<Result<Vec<S>, ReadError>>,
+    // Buffers returned by `recycle` for reuse by the decoding thread.
+    recycled: Sender<Vec<S>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<S: Sample + Send + 'static> ThreadedPackets<S> {
+    pub(crate) fn new<R>(mut packets: Packets<R, S>) -> ThreadedPackets<S>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        let stream_info = packets.stream_info().clone();
+        let (packet_sender, packet_receiver) = mpsc::sync_channel(DECODE_AHEAD_PACKETS);
+        let (recycled_sender, recycled_receiver) = mpsc::channel();
+
+        let thread = thread::spawn(move || loop {
+            for buf in recycled_receiver.try_iter() {
+                packets.recycle(buf);
+            }
+            let packet = match packets.next() {
+                Some(packet) => packet,
+                None => return,
+            };
+            // Sending fails once the iterator has been dropped.
+            if packet_sender.send(packet).is_err() {
+                return;
+            }
+        });
+
+        ThreadedPackets {
+            stream_info,
+            packets: packet_receiver,
+            recycled: recycled_sender,
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<S> ThreadedPackets<S> {
+    /// Returns a `StreamInfo` describing the ALAC stream in this file.
+    pub fn stream_info(&self) -> &StreamInfo {
+        &self.stream_info
+    }
+
+    /// Returns a buffer yielded by the iterator so it can be reused for a later packet, avoiding
+    /// an allocation.
+    pub fn recycle(&mut self, buf: Vec<S>) {
+        // The thread may already have stopped in which case the buffer is dropped.
+        let _ = self.recycled.send(buf);
+    }
+}
+
+impl<S> Iterator for ThreadedPackets<S> {
+    type Item = Result<Vec<S>, ReadError>;
+
+    fn next(&mut self) -> Option<Result<Vec<S>, ReadError>> {
+        match self.packets.recv() {
+            Ok(packet) => Some(packet),
+            Err(_) => {
+                // The thread has stopped, either at the end of the stream or because it panicked.
+                if let Some(thread) = self.thread.take() {
+                    if let Err(payload) = thread.join() {
+                        panic::resume_unwind(payload);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
+
+/// An iterator over samples that are read and decoded on a background thread.
+///
+/// This is created by `Samples::threaded`.
+pub struct ThreadedSamples<S> {
+    packets: ThreadedPackets<S>,
+    samples: Vec<S>,
+    sample_pos: usize,
+}
+
+impl<S: Sample + Send + 'static> ThreadedSamples<S> {
+    /// Creates a `ThreadedSamples` that yields `samples` before those in `packets`.
+    pub(crate) fn new<R>(packets: Packets<R, S>, samples: Vec<S>) -> ThreadedSamples<S>
+    where
+        R: Read + Seek + Send + 'static,
+    {
+        ThreadedSamples {
+            packets: ThreadedPackets::new(packets),
+            samples,
+            sample_pos: 0,
+        }
+    }
+}
+
+impl<S> ThreadedSamples<S> {
+    /// Returns a `StreamInfo` describing the ALAC stream in this file.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.packets.stream_info()
+    }
+}
+
+impl<S: Copy> Iterator for ThreadedSamples<S> {
+    type Item = Result<S, ReadError>;
+
+    fn next(&mut self) -> Option<Result<S, ReadError>> {
+        while self.sample_pos == self.samples.len() {
+            let samples = match self.packets.next()? {
+                Ok(samples) => samples,
+                Err(err) => return Some(Err(err)),
+            };
+            let consumed = mem::replace(&mut self.samples, samples);
+            self.packets.recycle(consumed);
+            self.sample_pos = 0;
+        }
+
+        let sample_pos = self.sample_pos;
+        self.sample_pos += 1;
+        Some(Ok(self.samples[sample_pos]))
+    }
+}
@@ -0,0 +1,592 @@
+//! An asynchronous `Reader` for tokio.
+//!
+//! Only the container metadata is parsed up front. Every chunk or box other than the one holding
+//! the audio data is read into memory and parsed by the same container parsers as the blocking
+//! `alac::Reader`, after which packets are read from the stream as they are decoded.
+
+pub extern crate futures_core;
+pub extern crate tokio;
+
+use self::futures_core::Stream;
+use self::tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+use std::cmp;
+use std::future::Future;
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom};
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use reader::max_packet_len;
+use {invalid_data, Decoder, Format, PacketIndex, PacketLocation, ReadError, Sample, StreamInfo};
+
+/// An asynchronous ALAC reader.
+pub struct Reader<R> {
+    source: Source<R>,
+    index: PacketIndex,
+    decoder: Decoder,
+    packet_buf: Vec<u8>,
+    packet_idx: u64,
+    skip_frames: u64,
+    frame_position: u64,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Reader<R> {
+    /// Attempts to create a `Reader` from a seekable byte stream.
+    ///
+    /// The returned future resolves once the container has been parsed.
+    pub fn open(reader: R) -> Open<R> {
+        Open {
+            source: Some(Source::new(reader)),
+            header: None,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Creates a `Reader` for a file previously indexed with `alac::Reader::packet_index` without
+    /// parsing the file's container.
+    ///
+    /// The index is not checked against the file so it must have been created from the same file.
+    pub fn from_packet_index(reader: R, index: PacketIndex) -> Result<Reader<R>, ReadError> {
+        Reader::from_source(Source::new(reader), index)
+    }
+
+    fn from_source(source: Source<R>, index: PacketIndex) -> Result<Reader<R>, ReadError> {
+        let stream_info =
+            StreamInfo::from_cookie(index.magic_cookie()).map_err(ReadError::Decoder)?;
+        // Priming frames are always at the start of the first packet.
+        let priming_frames = index.gapless_info().map_or(0, |info| info.priming_frames());
+        Ok(Reader {
+            source,
+            index,
+            decoder: Decoder::new(stream_info),
+            packet_buf: Vec::new(),
+            packet_idx: 0,
+            skip_frames: priming_frames,
+            frame_position: priming_frames,
+        })
+    }
+
+    /// Returns the container format of the file.
+    pub fn format(&self) -> Format {
+        self.index.format()
+    }
+
+    /// Returns a `StreamInfo` describing the ALAC stream.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.decoder.stream_info()
+    }
+
+    /// Returns the number of packets in the container's packet table.
+    pub fn packet_count(&self) -> u64 {
+        self.index.packet_count()
+    }
+
+    /// Returns the total number of samples per channel in the stream, excluding any priming and
+    /// padding frames.
+    pub fn total_samples(&self) -> u64 {
+        let (start, end) = self.frame_range();
+        end.saturating_sub(start)
+    }
+
+    /// Returns the untrimmed frame positions the stream starts and ends at.
+    fn frame_range(&self) -> (u64, u64) {
+        if let Some(trim) = self.index.gapless_info() {
+            let start = trim.priming_frames();
+            return (start, start + trim.valid_frames());
+        }
+
+        let end = self.index.total_frames().unwrap_or_else(|| {
+            self.packet_count() * self.stream_info().max_frames_per_packet() as u64
+        });
+        (0, end)
+    }
+
+    /// Decodes the next packet into `out`.
+    ///
+    /// Channels are interleaved, e.g. for a stereo packet `out` would contains samples in the
+    /// order `[left, right, left, right, ..]`.
+    ///
+    /// Panics if `out` is shorter than `StreamInfo::max_samples_per_packet`.
+    pub fn next_packet_into<'a, S: Sample>(&'a mut self, out: &'a mut [S]) -> NextPacket<'a, R, S> {
+        NextPacket {
+            reader: self,
+            out: Some(out),
+        }
+    }
+
+    /// Returns a `Stream` of decoded packets.
+    ///
+    /// Each item holds the interleaved samples of one packet.
+    pub fn into_stream<S: Sample>(self) -> Packets<R, S> {
+        Packets {
+            reader: self,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Decodes the next packet with any priming or padding frames removed, returning the number
+    /// of samples written to `out`, or `None` at the end of the stream.
+    fn poll_next_packet<S: Sample>(
+        &mut self,
+        cx: &mut Context,
+        out: &mut [S],
+    ) -> Poll<Result<Option<usize>, ReadError>> {
+        let channels = self.stream_info().channels() as usize;
+        // Padding is only trimmed when the end of the stream is known.
+        let end = self
+            .index
+            .gapless_info()
+            .map_or(u64::MAX, |_| self.frame_range().1);
+        loop {
+            if self.frame_position >= end {
+                return Poll::Ready(Ok(None));
+            }
+
+            // The packet index is only advanced once the whole packet has been read so a failed
+            // read can be retried.
+            let (offset, len) = match self.index.packet(self.packet_idx) {
+                Some(entry) => entry,
+                None => return Poll::Ready(Ok(None)),
+            };
+            if len > max_packet_len(self.stream_info()) as u64 {
+                return Poll::Ready(Err(ReadError::Format(
+                    self.format(),
+                    invalid_data(
+                        ::ErrorKind::InvalidContainer,
+                        "packet is longer than the stream allows",
+                    ),
+                )));
+            }
+            self.packet_buf.resize(len as usize, 0);
+            ready!(self.source.poll_read_at(cx, offset, &mut self.packet_buf))?;
+            self.packet_idx += 1;
+
+            let num_samples = match self.decoder.decode_packet(&self.packet_buf, out) {
+                Ok(samples) => samples.len(),
+                Err(err) => {
+                    let packet_start = self.frame_position.saturating_sub(self.skip_frames);
+                    let location = PacketLocation {
+                        packet_index: self.packet_idx - 1,
+                        byte_offset: Some(offset),
+                        sample_position: packet_start.saturating_sub(self.frame_range().0),
+                    };
+                    return Poll::Ready(Err(ReadError::Packet(location, err)));
+                }
+            };
+            if num_samples == 0 {
+                return Poll::Ready(Ok(None));
+            }
+
+            let skip = cmp::min(self.skip_frames, (num_samples / channels) as u64);
+            self.skip_frames -= skip;
+            let skip = skip as usize * channels;
+            if skip < num_samples {
+                let frames = cmp::min(
+                    ((num_samples - skip) / channels) as u64,
+                    end - self.frame_position,
+                );
+                let len = frames as usize * channels;
+                // Decoded samples always start at the beginning of `out`.
+                out.copy_within(skip..skip + len, 0);
+                self.frame_position += frames;
+                return Poll::Ready(Ok(Some(len)));
+            }
+        }
+    }
+}
+
+/// A future that resolves to a `Reader` once a file's container has been parsed.
+pub struct Open<R> {
+    source: Option<Source<R>>,
+    header: Option<HeaderParser>,
+    buf: Vec<u8>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Future for Open<R> {
+    type Output = Result<Reader<R>, ReadError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let source = this.source.as_mut().expect("polled after completion");
+
+        let header = match this.header {
+            Some(ref mut header) => header,
+            None => {
+                let len = ready!(source.poll_len(cx))?;
+                this.header.get_or_insert(HeaderParser::new(len))
+            }
+        };
+        while let Some((offset, len)) = header.next_read() {
+            this.buf.resize(len as usize, 0);
+            ready!(source.poll_read_at(cx, offset, &mut this.buf))?;
+            header.supply(mem::take(&mut this.buf))?;
+        }
+
+        let index = ::Reader::new(header.image())?.packet_index()?;
+        let source = this.source.take().expect("polled after completion");
+        Poll::Ready(Reader::from_source(source, index))
+    }
+}
+
+/// A future that decodes the next packet from a `Reader`.
+pub struct NextPacket<'a, R, S> {
+    reader: &'a mut Reader<R>,
+    out: Option<&'a mut [S]>,
+}
+
+impl<'a, R: AsyncRead + AsyncSeek + Unpin, S: Sample> Future for NextPacket<'a, R, S> {
+    type Output = Result<Option<&'a [S]>, ReadError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let out = this.out.as_mut().expect("polled after completion");
+        let len = ready!(this.reader.poll_next_packet(cx, out));
+        let out = this.out.take().expect("polled after completion");
+        Poll::Ready(len.map(move |len| len.map(move |len| &out[..len])))
+    }
+}
+
+/// A `Stream` of packets decoded from an asynchronous `Reader`.
+pub struct Packets<R, S> {
+    reader: Reader<R>,
+    buf: Vec<S>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin, S: Sample> Packets<R, S> {
+    /// Returns a `StreamInfo` describing the ALAC stream.
+    pub fn stream_info(&self) -> &StreamInfo {
+        self.reader.stream_info()
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin, S: Sample + Unpin> Stream for Packets<R, S> {
+    type Item = Result<Vec<S>, ReadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.buf.is_empty() {
+            let max_samples = this.stream_info().max_samples_per_packet() as usize;
+            this.buf = vec![S::from_decoder(0, 16); max_samples];
+        }
+
+        let len = ready!(this.reader.poll_next_packet(cx, &mut this.buf));
+        Poll::Ready(
+            len.transpose()
+                .map(|len| len.map(|len| this.buf[..len].to_vec())),
+        )
+    }
+}
+
+/// An asynchronous stream along with the progress of the current operation on it.
+struct Source<R> {
+    reader: R,
+    state: SourceState,
+    // The position of `reader`, or `None` if it is unknown after a failed read.
+    position: Option<u64>,
+}
+
+#[derive(Clone, Copy)]
+enum SourceState {
+    Idle,
+    SeekingEnd,
+    Seeking,
+    Reading(usize),
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Source<R> {
+    fn new(reader: R) -> Source<R> {
+        Source {
+            reader,
+            state: SourceState::Idle,
+            position: None,
+        }
+    }
+
+    /// Returns the length of the stream.
+    fn poll_len(&mut self, cx: &mut Context) -> Poll<io::Result<u64>> {
+        if let SourceState::Idle = self.state {
+            self.position = None;
+            self.state = SourceState::SeekingEnd;
+            if let Err(err) = Pin::new(&mut self.reader).start_seek(SeekFrom::End(0)) {
+                self.state = SourceState::Idle;
+                return Poll::Ready(Err(err));
+            }
+        }
+        let result = ready!(Pin::new(&mut self.reader).poll_complete(cx));
+        self.state = SourceState::Idle;
+        self.position = result.as_ref().ok().cloned();
+        Poll::Ready(result)
+    }
+
+    /// Fills `buf` with the data at `offset`, seeking only if the stream is elsewhere.
+    ///
+    /// The same `offset` and `buf` must be passed until the read completes.
+    fn poll_read_at(
+        &mut self,
+        cx: &mut Context,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let result = match self.state {
+                SourceState::Idle | SourceState::SeekingEnd => {
+                    // The stream position is unknown until the read succeeds.
+                    if self.position.take() == Some(offset) {
+                        self.state = SourceState::Reading(0);
+                        Ok(())
+                    } else {
+                        self.state = SourceState::Seeking;
+                        Pin::new(&mut self.reader).start_seek(SeekFrom::Start(offset))
+                    }
+                }
+                SourceState::Seeking => {
+                    let result = ready!(Pin::new(&mut self.reader).poll_complete(cx));
+                    self.state = SourceState::Reading(0);
+                    result.map(|_| ())
+                }
+                SourceState::Reading(filled) if filled == buf.len() => {
+                    self.state = SourceState::Idle;
+                    self.position = Some(offset + buf.len() as u64);
+                    return Poll::Ready(Ok(()));
+                }
+                SourceState::Reading(filled) => {
+                    let mut read_buf = ReadBuf::new(&mut buf[filled..]);
+                    match ready!(Pin::new(&mut self.reader).poll_read(cx, &mut read_buf)) {
+                        Ok(()) if read_buf.filled().is_empty() => {
+                            Err(io::Error::from(ErrorKind::UnexpectedEof))
+                        }
+                        Ok(()) => {
+                            self.state = SourceState::Reading(filled + read_buf.filled().len());
+                            Ok(())
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+            };
+            if let Err(err) = result {
+                self.state = SourceState::Idle;
+                return Poll::Ready(Err(err));
+            }
+        }
+    }
+}
+
+/// The next part of a file's container to be read by a `HeaderParser`.
+#[derive(Clone, Copy)]
+enum HeaderRead {
+    Magic,
+    ChunkHeader,
+    LargeSize,
+    Content { start: u64, len: u64 },
+    Done,
+}
+
+/// Determines which parts of a file make up its container metadata, i.e. every top-level CAF
+/// chunk or MP4 box except the audio data.
+struct HeaderParser {
+    len: u64,
+    caf: bool,
+    // The offset of the current chunk or box.
+    offset: u64,
+    // The offset of the chunk or box after the current one.
+    next_offset: u64,
+    next: HeaderRead,
+    regions: Vec<(u64, Vec<u8>)>,
+}
+
+impl HeaderParser {
+    fn new(len: u64) -> HeaderParser {
+        HeaderParser {
+            len,
+            caf: false,
+            offset: 0,
+            next_offset: 0,
+            next: HeaderRead::Magic,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Returns the offset and length of the next data needed, or `None` once the whole container
+    /// has been read.
+    fn next_read(&self) -> Option<(u64, u64)> {
+        let read = match self.next {
+            HeaderRead::Magic => (0, 8),
+            HeaderRead::ChunkHeader => (self.offset, if self.caf { 12 } else { 8 }),
+            HeaderRead::LargeSize => (self.offset + 8, 8),
+            HeaderRead::Content { start, len } => (start, len),
+            HeaderRead::Done => return None,
+        };
+        // A truncated final chunk or box is ignored, the container parsers will report it if it
+        // is needed.
+        if read.0.saturating_add(read.1) > self.len {
+            return None;
+        }
+        Some(read)
+    }
+
+    /// Accepts the data requested by `next_read`.
+    fn supply(&mut self, data: Vec<u8>) -> Result<(), ReadError> {
+        let offset = self.offset;
+        self.next = match self.next {
+            HeaderRead::Magic => match (&data[0..4], &data[4..8]) {
+                #[cfg(feature = "caf")]
+                (b"caff", _) => {
+                    self.caf = true;
+                    self.offset = 8;
+                    self.regions.push((0, data));
+                    HeaderRead::ChunkHeader
+                }
+                #[cfg(feature = "mp4")]
+                (_, b"ftyp") => HeaderRead::ChunkHeader,
+                _ => return Err(ReadError::UnsupportedFormat),
+            },
+            HeaderRead::ChunkHeader if self.caf => {
+                let mut size = [0; 8];
+                size.copy_from_slice(&data[4..12]);
+                let is_data = &data[0..4] == b"data";
+                self.regions.push((offset, data));
+                // Only the data chunk may have an unknown size, it then extends to the end of the
+                // file.
+                let size = match i64::from_be_bytes(size) {
+                    size if size < 0 => self.len - offset - 12,
+                    size => size as u64,
+                };
+                self.next_offset = (offset + 12).saturating_add(size);
+                // The data chunk begins with a 32 bit edit count.
+                let len = if is_data { cmp::min(size, 4) } else { size };
+                HeaderRead::Content {
+                    start: offset + 12,
+                    len,
+                }
+            }
+            HeaderRead::ChunkHeader => {
+                let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+                let is_data = &data[4..8] == b"mdat";
+                self.regions.push((offset, data));
+                match size {
+                    // The box extends to the end of the file.
+                    0 => self.box_content(8, self.len - offset, is_data),
+                    1 => HeaderRead::LargeSize,
+                    size => self.box_content(8, size as u64, is_data),
+                }
+            }
+            HeaderRead::LargeSize => {
+                let mut size = [0; 8];
+                size.copy_from_slice(&data);
+                let is_data = self
+                    .regions
+                    .last()
+                    .is_some_and(|(_, header)| &header[4..8] == b"mdat");
+                self.regions.push((offset + 8, data));
+                self.box_content(16, u64::from_be_bytes(size), is_data)
+            }
+            HeaderRead::Content { start, .. } => {
+                self.regions.push((start, data));
+                self.offset = self.next_offset;
+                HeaderRead::ChunkHeader
+            }
+            HeaderRead::Done => HeaderRead::Done,
+        };
+        Ok(())
+    }
+
+    fn box_content(&mut self, header_len: u64, size: u64, is_data: bool) -> HeaderRead {
+        if size < header_len {
+            return HeaderRead::Done;
+        }
+        self.next_offset = self.offset.saturating_add(size);
+        if is_data {
+            self.offset = self.next_offset;
+            return HeaderRead::ChunkHeader;
+        }
+        HeaderRead::Content {
+            start: self.offset + header_len,
+            len: size - header_len,
+        }
+    }
+
+    /// Returns a copy of the file containing only the container metadata.
+    fn image(&mut self) -> Header {
+        Header {
+            regions: mem::take(&mut self.regions),
+            len: self.len,
+            position: 0,
+        }
+    }
+}
+
+/// An in-memory copy of a file's container metadata.
+///
+/// Regions that were not read, such as the audio data, read as zeros.
+struct Header {
+    regions: Vec<(u64, Vec<u8>)>,
+    len: u64,
+    position: u64,
+}
+
+impl Read for Header {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let mut len = cmp::min(buf.len() as u64, remaining) as usize;
+
+        // Regions are sorted and don't overlap.
+        let next = self
+            .regions
+            .iter()
+            .find(|&&(start, ref data)| start + data.len() as u64 > self.position);
+        match next {
+            Some(&(start, ref data)) if start <= self.position => {
+                let data = &data[(self.position - start) as usize..];
+                len = cmp::min(len, data.len());
+                buf[..len].copy_from_slice(&data[..len]);
+            }
+            Some(&(start, _)) => {
+                len = cmp::min(len as u64, start - self.position) as usize;
+                buf[..len].iter_mut().for_each(|b| *b = 0);
+            }
+            None => buf[..len].iter_mut().for_each(|b| *b = 0),
+        }
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for Header {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Header;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn header_reads_zeros_between_regions() {
+        let mut header = Header {
+            regions: vec![(2, vec![1, 2]), (6, vec![3])],
+            len: 9,
+            position: 0,
+        };
+        let mut buf = Vec::new();
+        header.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, [0, 0, 1, 2, 0, 0, 3, 0, 0]);
+
+        header.seek(SeekFrom::Start(3)).unwrap();
+        let mut buf = [0; 4];
+        header.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [2, 0, 0, 3]);
+    }
+}
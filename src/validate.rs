@@ -0,0 +1,182 @@
+use std::io::{ErrorKind, Read, Seek};
+
+use {InvalidData, Progress, ReadError, Reader};
+
+/// A problem found while validating an ALAC file.
+#[derive(Debug)]
+pub enum Issue {
+    /// A packet could not be decoded.
+    Decoder { packet: u64, error: InvalidData },
+    /// A packet other than the last one contains fewer frames than the stream's frame length,
+    /// which will cause the decoded duration to differ from the expected duration.
+    PartialPacket { packet: u64, frames: u32 },
+    /// A packet is larger than the maximum packet size given in the stream's magic cookie.
+    OversizedPacket {
+        packet: u64,
+        bytes: usize,
+        max_bytes: u32,
+    },
+    /// The file ends part way through a packet.
+    Truncated { packet: u64 },
+    /// The stream's compatible version is newer than `MAX_COMPATIBLE_VERSION` so it may not be
+    /// decoded correctly.
+    UnknownCompatibleVersion { version: u8 },
+    /// The number of frames decoded differs from the number the container says the stream has.
+    DurationMismatch { expected: u64, decoded: u64 },
+    /// The container could not be read any further. No packets after `packet` were checked.
+    Format { packet: u64, error: ReadError },
+}
+
+/// The result of validating every packet in an ALAC file.
+#[derive(Debug)]
+pub struct ValidationReport {
+    packets: u64,
+    frames: u64,
+    issues: Vec<Issue>,
+}
+
+impl ValidationReport {
+    /// Returns the number of packets read from the file, including any that failed to decode.
+    pub fn packets(&self) -> u64 {
+        self.packets
+    }
+
+    /// Returns the number of frames successfully decoded.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Returns the problems found in the file in the order they were encountered.
+    pub fn issues(&self) -> &[Issue] {
+        &self.issues
+    }
+
+    /// Returns `true` if no problems were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Returns `true` if the file ended part way through a packet.
+    pub fn is_truncated(&self) -> bool {
+        self.issues
+            .iter()
+            .any(|issue| matches!(*issue, Issue::Truncated { .. }))
+    }
+}
+
+/// Checks the integrity of every packet in an ALAC file.
+///
+/// An error is only returned if the file cannot be opened at all. Problems found after that are
+/// collected in the returned `ValidationReport` and do not stop validation unless the container
+/// itself can no longer be read.
+pub fn validate_file<R: Read + Seek>(reader: R) -> Result<ValidationReport, ReadError> {
+    validate_file_with_progress(reader, |_| ())
+}
+
+/// Checks the integrity of every packet in an ALAC file, calling `progress` after each packet.
+///
+/// See `validate_file` for details.
+pub fn validate_file_with_progress<R, F>(
+    reader: R,
+    mut progress: F,
+) -> Result<ValidationReport, ReadError>
+where
+    R: Read + Seek,
+    F: FnMut(&Progress),
+{
+    let mut reader = Reader::new(reader)?;
+    // Validation reads packets directly so untrimmed frame counts are reported.
+    reader.set_gapless(false)?;
+    let stream_info = reader.stream_info().clone();
+    let frames_total = reader.total_samples();
+
+    let mut report = ValidationReport {
+        packets: 0,
+        frames: 0,
+        issues: Vec::new(),
+    };
+
+    if !stream_info.is_known_compatible_version() {
+        report.issues.push(Issue::UnknownCompatibleVersion {
+            version: stream_info.compatible_version(),
+        });
+    }
+
+    let mut out = vec![0i32; stream_info.max_samples_per_packet() as usize];
+    let mut partial_packet = None;
+    let mut bytes_read = 0;
+
+    loop {
+        let packet = report.packets;
+
+        match reader.read_next_packet() {
+            Ok(true) => (),
+            Ok(false) => {
+                check_duration(&reader, &mut report);
+                break;
+            }
+            Err(ReadError::Io(ref err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                report.issues.push(Issue::Truncated { packet });
+                break;
+            }
+            Err(error) => {
+                report.issues.push(Issue::Format { packet, error });
+                break;
+            }
+        }
+        report.packets += 1;
+
+        // Only the final packet of a stream may be shorter than the frame length.
+        if let Some((packet, frames)) = partial_packet.take() {
+            report.issues.push(Issue::PartialPacket { packet, frames });
+        }
+
+        let bytes = reader.current_packet().len();
+        bytes_read += bytes as u64;
+        let max_bytes = stream_info.max_frame_bytes();
+        if max_bytes != 0 && bytes > max_bytes as usize {
+            report.issues.push(Issue::OversizedPacket {
+                packet,
+                bytes,
+                max_bytes,
+            });
+        }
+
+        match reader.decode_current_packet(&mut out) {
+            Ok(samples) => {
+                let frames = (samples.len() / stream_info.channels() as usize) as u32;
+                report.frames += frames as u64;
+                if frames < stream_info.max_frames_per_packet() {
+                    partial_packet = Some((packet, frames));
+                }
+            }
+            Err(ReadError::Packet(_, error)) => report.issues.push(Issue::Decoder { packet, error }),
+            Err(error) => {
+                report.issues.push(Issue::Format { packet, error });
+                break;
+            }
+        }
+
+        progress(&Progress {
+            packets_done: report.packets,
+            frames_done: report.frames,
+            frames_total: Some(frames_total),
+            bytes_read,
+        });
+    }
+
+    Ok(report)
+}
+
+/// Reports a `DurationMismatch` if the frames decoded from a whole stream differ from the
+/// container's frame count.
+fn check_duration<R: Read + Seek>(reader: &Reader<R>, report: &mut ValidationReport) {
+    if let Some(expected) = reader.container_total_frames() {
+        if expected != report.frames {
+            report.issues.push(Issue::DurationMismatch {
+                expected,
+                decoded: report.frames,
+            });
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! JavaScript bindings built with `wasm-bindgen`.
+//!
+//! Samples are returned as interleaved `Float32Array`s scaled to `[-1.0, 1.0)`, ready to be copied
+//! into Web Audio buffers.
+
+pub extern crate wasm_bindgen;
+
+use self::wasm_bindgen::prelude::*;
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use std::io::Cursor;
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+use Reader;
+use {Decoder, StreamInfo};
+
+/// An ALAC packet decoder.
+#[wasm_bindgen]
+pub struct AlacDecoder {
+    decoder: Decoder,
+    buf: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl AlacDecoder {
+    /// Creates a decoder from the magic cookie of a stream.
+    #[wasm_bindgen(constructor)]
+    pub fn new(cookie: &[u8]) -> Result<AlacDecoder, JsError> {
+        let stream_info = StreamInfo::from_cookie(cookie)?;
+        Ok(AlacDecoder {
+            buf: vec![0.0; stream_info.max_samples_per_packet() as usize],
+            decoder: Decoder::new(stream_info),
+        })
+    }
+
+    /// The number of channels in the stream.
+    #[wasm_bindgen(getter)]
+    pub fn channels(&self) -> u8 {
+        self.decoder.stream_info().channels()
+    }
+
+    /// The sample rate of the stream in Hz.
+    #[wasm_bindgen(getter, js_name = sampleRate)]
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.stream_info().sample_rate()
+    }
+
+    /// The bit depth of the stream.
+    #[wasm_bindgen(getter, js_name = bitDepth)]
+    pub fn bit_depth(&self) -> u8 {
+        self.decoder.stream_info().bit_depth()
+    }
+
+    /// Decodes a packet into a new array of interleaved samples.
+    #[wasm_bindgen(js_name = decodePacket)]
+    pub fn decode_packet(&mut self, packet: &[u8]) -> Result<Vec<f32>, JsError> {
+        let samples = self.decoder.decode_packet(packet, &mut self.buf)?;
+        Ok(samples.to_vec())
+    }
+
+    /// Clears state carried between packets, for example after seeking.
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+    }
+}
+
+/// A fully decoded ALAC file.
+#[cfg(any(feature = "caf", feature = "mp4"))]
+#[wasm_bindgen]
+pub struct AlacFile {
+    channels: u8,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+#[cfg(any(feature = "caf", feature = "mp4"))]
+#[wasm_bindgen]
+impl AlacFile {
+    /// Decodes a complete CAF or MP4 file held in memory.
+    pub fn decode(data: &[u8]) -> Result<AlacFile, JsError> {
+        let reader = Reader::new(Cursor::new(data)).map_err(|e| JsError::new(&e.to_string()))?;
+        let channels = reader.stream_info().channels();
+        let sample_rate = reader.stream_info().sample_rate();
+        let samples = reader
+            .into_samples()
+            .collect::<Result<_, _>>()
+            .map_err(|e| JsError::new(&e.to_string()))?;
+        Ok(AlacFile {
+            channels,
+            sample_rate,
+            samples,
+        })
+    }
+
+    /// The number of channels in the file.
+    #[wasm_bindgen(getter)]
+    pub fn channels(&self) -> u8 {
+        self.channels
+    }
+
+    /// The sample rate of the file in Hz.
+    #[wasm_bindgen(getter, js_name = sampleRate)]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns a copy of the interleaved samples.
+    #[wasm_bindgen(getter)]
+    pub fn samples(&self) -> Vec<f32> {
+        self.samples.clone()
+    }
+}
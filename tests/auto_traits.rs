@@ -0,0 +1,13 @@
+// Checks at compile time that types used across threads implement the expected auto traits.
+extern crate alac;
+
+use alac::{DecodeOptions, Decoder, StreamInfo};
+
+fn assert_send_sync_clone<T: Send + Sync + Clone>() {}
+
+#[test]
+fn decoder_is_send_sync_clone() {
+    assert_send_sync_clone::<Decoder>();
+    assert_send_sync_clone::<StreamInfo>();
+    assert_send_sync_clone::<DecodeOptions>();
+}
@@ -0,0 +1,91 @@
+// Decodes packets directly into byte buffers in audio device formats.
+#![cfg(feature = "caf")]
+
+extern crate alac;
+
+use alac::{DecodeOptions, Decoder, I32Raw, Reader, Requantize, SampleFormat, StreamInfo};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_24_bit_afconvert.caf";
+
+/// Returns the stream info and first few packets of the test file.
+fn packets() -> (StreamInfo, Vec<Vec<u8>>) {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let stream_info = reader.stream_info().clone();
+    let index = reader.packet_index().unwrap();
+    let packets = (0..4)
+        .map(|i| {
+            let (offset, len) = index.packet(i).unwrap();
+            data[offset as usize..(offset + len) as usize].to_vec()
+        })
+        .collect();
+    (stream_info, packets)
+}
+
+#[test]
+fn packed_24_bit() {
+    let (stream_info, packets) = packets();
+    let max_samples = stream_info.max_samples_per_packet() as usize;
+    let mut decoder = Decoder::new(stream_info);
+    let mut samples = vec![I32Raw(0); max_samples];
+    // Trailing bytes that don't make up a whole sample are allowed.
+    let mut bytes = vec![0; max_samples * 3 + 2];
+
+    for packet in &packets {
+        let expected = decoder.decode_packet(packet, &mut samples).unwrap();
+        let decoded = decoder.decode_packet_s24le(packet, &mut bytes).unwrap();
+        assert_eq!(decoded.len(), expected.len() * 3);
+        for (bytes, &I32Raw(sample)) in decoded.chunks(3).zip(expected) {
+            let sample_bytes = sample.to_le_bytes();
+            assert_eq!(bytes, &sample_bytes[..3]);
+        }
+    }
+}
+
+#[test]
+fn device_formats() {
+    let (stream_info, packets) = packets();
+    let max_samples = stream_info.max_samples_per_packet() as usize;
+    let options = DecodeOptions::new().requantize(Requantize::Truncate);
+    let mut decoder = Decoder::with_options(stream_info, options);
+    let mut bytes = vec![0; max_samples * 4];
+
+    let mut i16_samples = vec![0i16; max_samples];
+    let mut i32_samples = vec![0i32; max_samples];
+    let mut f32_samples = vec![0f32; max_samples];
+    for packet in &packets {
+        let i16_samples = decoder.decode_packet(packet, &mut i16_samples).unwrap();
+        let decoded = decoder
+            .decode_packet_bytes(packet, &mut bytes, SampleFormat::S16Le)
+            .unwrap();
+        let expected: Vec<u8> = i16_samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(decoded, &expected[..]);
+
+        let i32_samples = decoder.decode_packet(packet, &mut i32_samples).unwrap();
+        let decoded = decoder
+            .decode_packet_bytes(packet, &mut bytes, SampleFormat::S32Le)
+            .unwrap();
+        let expected: Vec<u8> = i32_samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(decoded, &expected[..]);
+
+        // 24 bit samples in four bytes are right-aligned.
+        let decoded = decoder
+            .decode_packet_bytes(packet, &mut bytes, SampleFormat::S24Le)
+            .unwrap();
+        let expected: Vec<u8> = i32_samples
+            .iter()
+            .flat_map(|s| (s >> 8).to_le_bytes())
+            .collect();
+        assert_eq!(decoded, &expected[..]);
+
+        let f32_samples = decoder.decode_packet(packet, &mut f32_samples).unwrap();
+        let decoded = decoder
+            .decode_packet_bytes(packet, &mut bytes, SampleFormat::F32Le)
+            .unwrap();
+        let expected: Vec<u8> = f32_samples.iter().flat_map(|s| s.to_le_bytes()).collect();
+        assert_eq!(decoded, &expected[..]);
+    }
+}
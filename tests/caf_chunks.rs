@@ -0,0 +1,139 @@
+// Reads CAF files containing chunks that aren't needed to decode them.
+#![cfg(feature = "caf")]
+
+extern crate alac;
+
+use alac::{ErrorKind, Format, ReadError, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn decode(data: &[u8]) -> Vec<i32> {
+    let reader = Reader::new(Cursor::new(data)).unwrap();
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+fn caf_chunk(chunk_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut chunk = chunk_type.to_vec();
+    chunk.extend_from_slice(&(content.len() as u64).to_be_bytes());
+    chunk.extend_from_slice(content);
+    chunk
+}
+
+/// Splits a CAF file into its file header and chunks.
+fn split_chunks(data: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>) {
+    let mut chunks = Vec::new();
+    let mut offset = 8;
+    while offset < data.len() {
+        let mut size = [0; 8];
+        size.copy_from_slice(&data[offset + 4..offset + 12]);
+        let end = offset + 12 + u64::from_be_bytes(size) as usize;
+        chunks.push(data[offset..end].to_vec());
+        offset = end;
+    }
+    (data[..8].to_vec(), chunks)
+}
+
+/// The overview and peak chunks written by audio editors, and a chunk with an unregistered type.
+fn unknown_chunks() -> Vec<Vec<u8>> {
+    vec![
+        caf_chunk(b"ovvw", &[0; 36]),
+        caf_chunk(b"peak", &[0; 20]),
+        caf_chunk(b"XYZ1", b"custom chunk"),
+        caf_chunk(b"empt", &[]),
+    ]
+}
+
+#[test]
+fn skips_unknown_chunks() {
+    let data = read_file(PATH);
+    let expected = decode(&data);
+
+    // Unknown chunks are inserted before the description, before the audio data and at the end of
+    // the file.
+    let (header, mut chunks) = split_chunks(&data);
+    let data_chunk = chunks.pop().unwrap();
+    assert_eq!(&data_chunk[..4], b"data");
+    let mut file = header;
+    for chunk in unknown_chunks()
+        .into_iter()
+        .chain(chunks)
+        .chain(unknown_chunks())
+        .chain(Some(data_chunk))
+        .chain(unknown_chunks())
+    {
+        file.extend_from_slice(&chunk);
+    }
+
+    assert_eq!(decode(&file), expected);
+    let reader = Reader::new_streaming(&file[..]).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn reads_packet_table_after_audio_data() {
+    let data = read_file(PATH);
+    let expected = decode(&data);
+
+    let (header, mut chunks) = split_chunks(&data);
+    let pakt = chunks.remove(3);
+    assert_eq!(&pakt[..4], b"pakt");
+    let mut file = header;
+    for chunk in chunks.into_iter().chain(unknown_chunks()).chain(Some(pakt)) {
+        file.extend_from_slice(&chunk);
+    }
+    assert_eq!(decode(&file), expected);
+}
+
+#[test]
+fn rejects_invalid_chunk_size() {
+    let data = read_file(PATH);
+    let (header, chunks) = split_chunks(&data);
+    let mut file = header;
+    file.extend_from_slice(b"ovvw");
+    file.extend_from_slice(&(-2i64).to_be_bytes());
+    for chunk in chunks {
+        file.extend_from_slice(&chunk);
+    }
+    assert!(Reader::new(Cursor::new(&file)).is_err());
+}
+
+#[test]
+fn rejects_oversized_packet() {
+    let data = read_file(PATH);
+    let (header, mut chunks) = split_chunks(&data);
+
+    // The first packet's length is replaced with one far longer than any packet of the stream,
+    // and the audio data extends to the end of the file so the length isn't otherwise checked.
+    let pakt = chunks.remove(3);
+    assert_eq!(&pakt[..4], b"pakt");
+    let mut content = pakt[12..36].to_vec();
+    content.extend_from_slice(&[0x80 | 0x40, 0x80, 0x80, 0x80, 0x80, 0]);
+    let first_len = pakt[36..].iter().position(|&b| b & 0x80 == 0).unwrap() + 1;
+    content.extend_from_slice(&pakt[36 + first_len..]);
+    chunks.insert(3, caf_chunk(b"pakt", &content));
+    let data_chunk = chunks.last_mut().unwrap();
+    assert_eq!(&data_chunk[..4], b"data");
+    data_chunk[4..12].copy_from_slice(&(-1i64).to_be_bytes());
+
+    let mut file = header;
+    for chunk in chunks {
+        file.extend_from_slice(&chunk);
+    }
+    let mut reader = Reader::new(Cursor::new(&file)).unwrap();
+    match reader.next_raw_packet() {
+        Err(ReadError::Format(Format::Caf, err)) => {
+            assert_eq!(err.kind(), ErrorKind::InvalidContainer)
+        }
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("oversized packet was read"),
+    }
+}
@@ -0,0 +1,133 @@
+// Reads channel layouts stored in containers rather than magic cookies.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{ChannelLayout, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+// kAudioChannelLayoutTag_StereoHeadphones
+const HEADPHONES: u32 = 102 << 16 | 2;
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn channel_layout_tag(data: &[u8]) -> Option<u32> {
+    let reader = Reader::new(Cursor::new(data)).unwrap();
+    reader.stream_info().channel_layout_tag()
+}
+
+#[cfg(feature = "mp4")]
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// Appends a box to the end of the `alac` sample entry of `data`, shrinking the following free box
+/// so the media data doesn't move.
+#[cfg(feature = "mp4")]
+fn append_to_sample_entry(data: &[u8], box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    const PATH: &[&[u8; 4]] = &[
+        b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stsd", b"alac",
+    ];
+
+    fn append(mut data: &[u8], path: &[&[u8; 4]], new_box: &[u8], out: &mut Vec<u8>) {
+        while !data.is_empty() {
+            let size = read_u32(data) as usize;
+            let box_type = [data[4], data[5], data[6], data[7]];
+            let mut content = &data[8..size];
+            data = &data[size..];
+
+            let mut children = Vec::new();
+            if path.first() == Some(&&box_type) {
+                // The sample description's entry count and the sample entry's sound description
+                // fields precede their children.
+                let fields_len = match &box_type {
+                    b"stsd" => 8,
+                    b"alac" => 28,
+                    _ => 0,
+                };
+                children.extend_from_slice(&content[..fields_len]);
+                content = &content[fields_len..];
+                if path.len() == 1 {
+                    children.extend_from_slice(content);
+                    children.extend_from_slice(new_box);
+                } else {
+                    append(content, &path[1..], new_box, &mut children);
+                }
+            } else if &box_type == b"free" {
+                children.extend_from_slice(&content[new_box.len()..]);
+            } else {
+                children.extend_from_slice(content);
+            }
+            out.extend_from_slice(&(children.len() as u32 + 8).to_be_bytes());
+            out.extend_from_slice(&box_type);
+            out.extend_from_slice(&children);
+        }
+    }
+
+    let mut new_box = (content.len() as u32 + 8).to_be_bytes().to_vec();
+    new_box.extend_from_slice(box_type);
+    new_box.extend_from_slice(content);
+
+    let mut out = Vec::new();
+    append(data, PATH, &new_box, &mut out);
+    assert_eq!(out.len(), data.len());
+    out
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_chan_chunk() {
+    let data = read_file("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+    assert_eq!(channel_layout_tag(&data), None);
+
+    // The chunk is inserted after the audio description chunk.
+    let mut chan = HEADPHONES.to_be_bytes().to_vec();
+    chan.extend_from_slice(&[0; 8]);
+    let mut with_chan = data[..52].to_vec();
+    with_chan.extend_from_slice(b"chan");
+    with_chan.extend_from_slice(&(chan.len() as u64).to_be_bytes());
+    with_chan.extend_from_slice(&chan);
+    with_chan.extend_from_slice(&data[52..]);
+
+    let reader = Reader::new(Cursor::new(&with_chan)).unwrap();
+    let stream_info = reader.stream_info();
+    assert_eq!(stream_info.channel_layout_tag(), Some(HEADPHONES));
+    assert_eq!(
+        stream_info.channel_layout(),
+        Some(ChannelLayout::Other(HEADPHONES))
+    );
+
+    // Layouts given by channel descriptions are ignored.
+    let mut with_descriptions = with_chan.clone();
+    with_descriptions[64..68].copy_from_slice(&[0; 4]);
+    assert_eq!(channel_layout_tag(&with_descriptions), None);
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_chan_and_chnl_atoms() {
+    let data = read_file("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a");
+    assert_eq!(channel_layout_tag(&data), None);
+
+    let mut chan = vec![0; 4];
+    chan.extend_from_slice(&HEADPHONES.to_be_bytes());
+    chan.extend_from_slice(&[0; 8]);
+    let with_chan = append_to_sample_entry(&data, b"chan", &chan);
+    assert_eq!(channel_layout_tag(&with_chan), Some(HEADPHONES));
+
+    // A channel structured stream using the predefined stereo layout with no omitted channels.
+    let chnl = [0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0];
+    let with_chnl = append_to_sample_entry(&data, b"chnl", &chnl);
+    let tag = ChannelLayout::Stereo.tag();
+    assert_eq!(channel_layout_tag(&with_chnl), Some(tag));
+
+    let mut omitted = chnl;
+    omitted[13] = 1;
+    let with_omitted = append_to_sample_entry(&data, b"chnl", &omitted);
+    assert_eq!(channel_layout_tag(&with_omitted), None);
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "caf")]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+fn read(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn decode(data: &[u8]) -> Vec<i32> {
+    let reader = Reader::new(Cursor::new(data)).unwrap();
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+#[test]
+fn concatenated_caf_files() {
+    let first = read("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+    let second = read("tests/data/decode_comparison/synth_44100_24_bit_afconvert.caf");
+
+    let mut data = first.clone();
+    data.extend_from_slice(&second);
+
+    let mut samples = Reader::new(Cursor::new(&data)).unwrap().into_samples();
+    assert_eq!(samples.stream_info().bit_depth(), 16);
+    let first_samples: Vec<i32> = samples.by_ref().map(|s| s.unwrap()).collect();
+    assert_eq!(first_samples, decode(&first));
+
+    let mut samples = samples.next_stream().unwrap().expect("missing second file");
+    assert_eq!(samples.stream_info().bit_depth(), 24);
+    let second_samples: Vec<i32> = samples.by_ref().map(|s| s.unwrap()).collect();
+    assert_eq!(second_samples, decode(&second));
+
+    assert!(samples.next_stream().unwrap().is_none());
+}
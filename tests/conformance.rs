@@ -0,0 +1,30 @@
+// Runs every test vector in `tests/data/decode_comparison` and in any directories listed in the
+// `ALAC_CONFORMANCE_DIRS` environment variable. See `tests/support/mod.rs` for the vector layout.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+mod support;
+
+use std::env;
+
+#[test]
+fn conformance_vectors() {
+    let mut dirs = vec!["tests/data/decode_comparison".into()];
+    if let Some(extra) = env::var_os("ALAC_CONFORMANCE_DIRS") {
+        dirs.extend(env::split_paths(&extra));
+    }
+
+    let mut failures = Vec::new();
+    let mut count = 0;
+    for dir in dirs {
+        for vector in support::load_vectors(dir) {
+            count += 1;
+            println!("checking {}", vector.alac.display());
+            if let Err(err) = support::run_vector(&vector) {
+                failures.push(format!("{}: {}", vector.alac.display(), err));
+            }
+        }
+    }
+
+    assert!(count > 0, "no test vectors found");
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
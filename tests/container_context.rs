@@ -0,0 +1,45 @@
+#![cfg(feature = "container-context")]
+
+extern crate alac;
+
+use alac::container::ContainerContext;
+use std::fs::File;
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_container_context() {
+    let file = File::open("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf").unwrap();
+    let reader = alac::Reader::new(file).unwrap();
+    match reader.container_context() {
+        Some(ContainerContext::Caf(context)) => {
+            assert_eq!(context.audio_description().sample_rate, 44100.0);
+            assert_eq!(&context.audio_description().format_id, b"alac");
+            assert_eq!(context.magic_cookie().len(), 24);
+            let gapless_info = reader.gapless_info().unwrap();
+            assert_eq!(
+                context.packet_table().priming_frames as u64,
+                gapless_info.priming_frames()
+            );
+            assert_eq!(
+                context.packet_table().lengths.len() as u64,
+                reader.packet_count()
+            );
+        }
+        _ => panic!("expected a caf context"),
+    }
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_container_context() {
+    let file = File::open("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a").unwrap();
+    let reader = alac::Reader::new(file).unwrap();
+    match reader.container_context() {
+        Some(ContainerContext::Mp4(context)) => {
+            assert_eq!(context.major_brand(), Some(*b"M4A "));
+            assert_eq!(context.tracks().len(), 1);
+            assert_eq!(context.tracks()[0].timescale(), Some(44100));
+        }
+        _ => panic!("expected an mp4 context"),
+    }
+}
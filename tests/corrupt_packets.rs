@@ -0,0 +1,122 @@
+// Checks how readers report packets that fail to decode.
+#![cfg(feature = "mp4")]
+
+extern crate alac;
+
+use alac::{ErrorKind, ErrorPolicy, ReadError, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+
+/// Returns the test file with packet `packet` replaced by one containing only an end element.
+fn corrupt_packet(packet: u64) -> (Vec<u8>, u64) {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    let index = Reader::new(Cursor::new(&data))
+        .unwrap()
+        .packet_index()
+        .unwrap();
+    let (offset, _) = index.packet(packet).unwrap();
+    data[offset as usize] = 0xe0;
+    (data, offset)
+}
+
+#[test]
+fn error_locates_corrupt_packet() {
+    let (data, offset) = corrupt_packet(3);
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let frames_per_packet = reader.stream_info().max_frames_per_packet() as u64;
+    let priming_frames = reader
+        .gapless_info()
+        .map_or(0, |info| info.priming_frames());
+
+    let err = reader.into_samples::<i16>().find_map(|s| s.err()).unwrap();
+    let (location, err) = match err {
+        ReadError::Packet(location, err) => (location, err),
+        err => panic!("unexpected error {:?}", err),
+    };
+    assert_eq!(location.packet_index(), 3);
+    assert_eq!(location.byte_offset(), Some(offset));
+    assert_eq!(
+        location.sample_position(),
+        3 * frames_per_packet - priming_frames
+    );
+    assert_eq!(err.kind(), ErrorKind::ChannelCountMismatch);
+}
+
+#[test]
+fn error_location_follows_seek() {
+    let (data, offset) = corrupt_packet(5);
+    let mut reader = Reader::new(Cursor::new(&data)).unwrap();
+    let frames_per_packet = reader.stream_info().max_frames_per_packet() as u64;
+    let priming_frames = reader
+        .gapless_info()
+        .map_or(0, |info| info.priming_frames());
+
+    // Seek part way into the packet before the corrupt one.
+    let packet_start = 5 * frames_per_packet - priming_frames;
+    reader.seek_to_sample(packet_start - 100).unwrap();
+    let mut packets = reader.into_packets::<i16>();
+    let mut out = vec![0; packets.stream_info().max_samples_per_packet() as usize];
+    assert_eq!(packets.next_into(&mut out).unwrap().unwrap().len(), 200);
+
+    match packets.next_into(&mut out) {
+        Err(ReadError::Packet(location, _)) => {
+            assert_eq!(location.packet_index(), 5);
+            assert_eq!(location.byte_offset(), Some(offset));
+            assert_eq!(location.sample_position(), packet_start);
+        }
+        result => panic!(
+            "unexpected result {:?}",
+            result.map(|s| s.map(<[i16]>::len))
+        ),
+    }
+}
+
+#[test]
+fn error_policies() {
+    let (data, _) = corrupt_packet(3);
+    let original: Vec<i16> = Reader::new(Cursor::new(&data))
+        .unwrap()
+        .into_samples()
+        .filter_map(|s| s.ok())
+        .collect();
+
+    let samples = |policy| -> Vec<Result<i16, ReadError>> {
+        let reader = Reader::new(Cursor::new(&data)).unwrap();
+        reader.into_samples().with_error_policy(policy).collect()
+    };
+
+    // The corrupt packet's samples in the stream without it.
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let samples_per_packet = reader.stream_info().max_samples_per_packet() as usize;
+    let priming_frames = reader
+        .gapless_info()
+        .map_or(0, |info| info.priming_frames());
+    let start = 3 * samples_per_packet - priming_frames as usize * 2;
+
+    let report = samples(ErrorPolicy::Report);
+    assert_eq!(report.len(), original.len() + 1);
+    assert!(matches!(report[start], Err(ReadError::Packet(..))));
+
+    let skip: Vec<i16> = samples(ErrorPolicy::Skip)
+        .into_iter()
+        .map(|s| s.unwrap())
+        .collect();
+    assert_eq!(skip, original);
+
+    let silence_fill: Vec<i16> = samples(ErrorPolicy::SilenceFill)
+        .into_iter()
+        .map(|s| s.unwrap())
+        .collect();
+    let end = start + samples_per_packet;
+    assert_eq!(silence_fill.len(), original.len() + samples_per_packet);
+    assert_eq!(&silence_fill[..start], &original[..start]);
+    assert!(silence_fill[start..end].iter().all(|&s| s == 0));
+    assert_eq!(&silence_fill[end..], &original[start..]);
+
+    let stop = samples(ErrorPolicy::Stop);
+    assert_eq!(stop.len(), start + 1);
+    assert!(matches!(stop[start], Err(ReadError::Packet(..))));
+}
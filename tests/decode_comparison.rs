@@ -6,22 +6,42 @@ extern crate hound;
 use std::fmt;
 use std::fs::File;
 
-static ROOT: &'static str = "tests/data/decode_comparison";
+static ROOT: &str = "tests/data/decode_comparison";
 
-static COMPARE_MP4_I16: &'static [(&'static str, &'static str)] =
+#[cfg(feature = "mp4")]
+static COMPARE_MP4_I16: &[(&str, &str)] =
     &[("synth_44100_16_bit.wav", "synth_44100_16_bit_afconvert.m4a")];
 
-static COMPARE_MP4_I32: &'static [(&'static str, &'static str)] = &[
+#[cfg(feature = "mp4")]
+static COMPARE_MP4_I32: &[(&str, &str)] = &[
     ("synth_44100_16_bit.wav", "synth_44100_16_bit_afconvert.m4a"),
     ("synth_44100_24_bit.wav", "synth_44100_24_bit_afconvert.m4a"),
 ];
 
-static COMPARE_CAF_I16: &'static [(&'static str, &'static str)] =
-    &[("synth_44100_16_bit.wav", "synth_44100_16_bit_afconvert.caf")];
+// The 5.1 files declare a channel layout in WAVE order (L R C LFE Ls Rs) in a CAF chan chunk or
+// their magic cookie, while ALAC stores the channels in the order C L R Ls Rs LFE.
+#[cfg(feature = "caf")]
+static COMPARE_CAF_I16: &[(&str, &str)] = &[
+    ("synth_44100_16_bit.wav", "synth_44100_16_bit_afconvert.caf"),
+    (
+        "multichannel_48000_16_bit_5_1.wav",
+        "multichannel_48000_16_bit_5_1_chan.caf",
+    ),
+    (
+        "multichannel_48000_16_bit_5_1.wav",
+        "multichannel_48000_16_bit_5_1_cookie.caf",
+    ),
+];
 
-static COMPARE_CAF_I32: &'static [(&'static str, &'static str)] = &[
+#[cfg(feature = "caf")]
+static COMPARE_CAF_I32: &[(&str, &str)] = &[
     ("synth_44100_16_bit.wav", "synth_44100_16_bit_afconvert.caf"),
     ("synth_44100_24_bit.wav", "synth_44100_24_bit_afconvert.caf"),
+    (
+        "multichannel_48000_16_bit_5_1.wav",
+        "multichannel_48000_16_bit_5_1_chan.caf",
+    ),
+    ("wide_44100_32_bit.wav", "wide_44100_32_bit.caf"),
 ];
 
 #[test]
@@ -46,6 +66,21 @@ fn caf() {
     }
 }
 
+#[test]
+#[cfg(feature = "caf")]
+fn caf_right_aligned() {
+    for &(wav, alac) in COMPARE_CAF_I32 {
+        let wav = format!("{}/{}", ROOT, wav);
+        let alac = format!("{}/{}", ROOT, alac);
+
+        let alac = alac::Reader::new(File::open(alac).unwrap()).unwrap();
+        let alac = alac.into_samples::<alac::I32Raw>().map(|s| s.unwrap().0);
+        let wav = hound::WavReader::open(wav).unwrap();
+        let wav = wav.into_samples::<i32>().map(|s| s.unwrap());
+        assert!(alac.eq(wav));
+    }
+}
+
 fn compare<S: Sample>(root: &str, wav: &str, alac: &str) {
     println!("comparing {} to {}", wav, alac);
 
@@ -78,27 +113,18 @@ fn compare<S: Sample>(root: &str, wav: &str, alac: &str) {
 }
 
 trait Sample: alac::Sample + hound::Sample + Clone + Copy + fmt::Display + PartialEq {
-    fn zero() -> Self;
     /// Hound samples are right aligned and need to be shifted to compare with alac samples if the
     /// stream bit depth is lower than the sample type bit depth.
     fn hound_left_align(self, bit_depth: u8) -> Self;
 }
 
 impl Sample for i16 {
-    fn zero() -> Self {
-        0
-    }
-
     fn hound_left_align(self, bit_depth: u8) -> Self {
         self << (16 - bit_depth)
     }
 }
 
 impl Sample for i32 {
-    fn zero() -> Self {
-        0
-    }
-
     fn hound_left_align(self, bit_depth: u8) -> Self {
         self << (32 - bit_depth)
     }
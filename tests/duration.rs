@@ -0,0 +1,64 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{PacketIndex, Reader};
+use std::fs::File;
+
+fn test_duration(path: &str) {
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    let sample_rate = reader.stream_info().sample_rate() as f64;
+    let total_samples = reader.total_samples();
+    let duration = reader.duration();
+    let index = reader.packet_index().unwrap();
+
+    let mut samples = reader.into_samples::<i32>();
+    let len = total_samples as usize * channels;
+    assert_eq!(samples.size_hint(), (len, Some(len)));
+    samples.next().unwrap().unwrap();
+    assert_eq!(samples.size_hint(), (len - 1, Some(len - 1)));
+    assert_eq!(samples.count(), total_samples as usize * channels - 1);
+
+    let secs = duration.as_secs_f64();
+    assert!((secs - total_samples as f64 / sample_rate).abs() < 1e-6);
+
+    let reader = Reader::from_packet_index(File::open(path).unwrap(), index).unwrap();
+    assert_eq!(reader.total_samples(), total_samples);
+    assert_eq!(
+        PacketIndex::from_bytes(&reader.packet_index().unwrap().to_bytes())
+            .unwrap()
+            .packet_count(),
+        reader.packet_count()
+    );
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_duration() {
+    test_duration("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_duration() {
+    test_duration("tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn duration_with_sample_rate_override() {
+    let path = "tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a";
+    let mut reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let total_samples = reader.total_samples();
+    let duration = reader.duration();
+    let gapless_info = reader.gapless_info();
+
+    reader.set_sample_rate(88200).unwrap();
+    assert_eq!(reader.stream_info().sample_rate(), 88200);
+    assert_eq!(reader.total_samples(), total_samples);
+    assert_eq!(reader.duration(), duration / 2);
+    assert_eq!(reader.gapless_info(), gapless_info);
+    assert_eq!(reader.tracks()[0].stream_info().sample_rate(), 44100);
+    assert_eq!(reader.tracks()[0].duration(), Some(duration));
+}
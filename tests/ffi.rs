@@ -0,0 +1,55 @@
+// Decodes a single packet through the C interface.
+#![cfg(feature = "ffi")]
+
+extern crate alac;
+
+use alac::ffi::*;
+use std::ptr;
+
+#[test]
+fn decode_packet() {
+    let cookie = include_bytes!("data/magic_cookie.bin");
+    let packet = include_bytes!("data/packet_16_bit.bin");
+    let out_comp_bin = include_bytes!("data/out_16_bit.bin");
+
+    unsafe {
+        let mut info = AlacStreamInfo::default();
+        let res = alac_stream_info_from_cookie(cookie.as_ptr(), cookie.len(), &mut info);
+        assert_eq!(res, ALAC_OK);
+        assert_eq!(info.channels, 2);
+        assert_eq!(info.bit_depth, 16);
+        assert_eq!(info.max_samples_per_packet, 8192);
+
+        let res = alac_stream_info_from_cookie(cookie.as_ptr(), 4, &mut info);
+        assert_eq!(res, ALAC_ERROR_INVALID_DATA);
+        assert!(alac_decoder_new(ptr::null(), 0).is_null());
+
+        let decoder = alac_decoder_new(cookie.as_ptr(), cookie.len());
+        assert!(!decoder.is_null());
+
+        let mut out = vec![0i16; 8192];
+        let len = alac_decode_packet_s16(decoder, packet.as_ptr(), 8581, out.as_mut_ptr(), 8192);
+        assert_eq!(len, 8192);
+        for (i, &sample) in out.iter().enumerate() {
+            let comp = (out_comp_bin[i * 2] as i16) + ((out_comp_bin[i * 2 + 1] as i16) << 8);
+            assert_eq!(sample, comp);
+        }
+
+        let mut out = vec![0i32; 8192];
+        let len = alac_decode_packet(decoder, packet.as_ptr(), 8581, out.as_mut_ptr(), 8192);
+        assert_eq!(len, 8192);
+        for (i, &sample) in out.iter().enumerate() {
+            let comp = (out_comp_bin[i * 2] as i16) + ((out_comp_bin[i * 2 + 1] as i16) << 8);
+            assert_eq!(sample, (comp as i32) << 16);
+        }
+
+        // A buffer too short for a full packet is rejected rather than panicking.
+        let len = alac_decode_packet(decoder, packet.as_ptr(), 8581, out.as_mut_ptr(), 100);
+        assert_eq!(len, ALAC_ERROR_INVALID_ARGUMENT as isize);
+        let len = alac_decode_packet(decoder, packet.as_ptr(), 10, out.as_mut_ptr(), 8192);
+        assert_eq!(len, ALAC_ERROR_INVALID_DATA as isize);
+
+        alac_decoder_free(decoder);
+        alac_decoder_free(ptr::null_mut());
+    }
+}
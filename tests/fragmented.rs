@@ -0,0 +1,173 @@
+// Reads a fragmented MP4 file created by moving the packets of an ordinary MP4 file into movie
+// fragments.
+#![cfg(feature = "mp4")]
+
+extern crate alac;
+
+use alac::{ErrorKind, Format, ReadError, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+const CONTAINERS: &[&[u8; 4]] = &[b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+fn write_box(out: &mut Vec<u8>, box_type: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(&(content.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(box_type);
+    out.extend_from_slice(content);
+}
+
+fn full_box(fields: &[u32]) -> Vec<u8> {
+    let mut content = vec![0; 4];
+    for field in fields {
+        content.extend_from_slice(&field.to_be_bytes());
+    }
+    content
+}
+
+/// Copies boxes from `data` to `out`, emptying the sample tables and adding `extra` to the moov
+/// box.
+fn rewrite_boxes(mut data: &[u8], out: &mut Vec<u8>, extra: &[u8]) {
+    while !data.is_empty() {
+        let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let box_type = [data[4], data[5], data[6], data[7]];
+        let content = &data[8..size];
+        data = &data[size..];
+
+        match &box_type {
+            b"mdat" | b"free" => (),
+            b"stts" | b"stsc" | b"stco" => write_box(out, &box_type, &full_box(&[0])),
+            b"stsz" => write_box(out, &box_type, &full_box(&[0, 0])),
+            t if CONTAINERS.contains(&t) => {
+                let mut children = Vec::new();
+                rewrite_boxes(content, &mut children, &[]);
+                if t == b"moov" {
+                    children.extend_from_slice(extra);
+                }
+                write_box(out, &box_type, &children);
+            }
+            _ => write_box(out, &box_type, content),
+        }
+    }
+}
+
+#[test]
+fn fragmented_mp4() {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let frame_length = reader.stream_info().max_frames_per_packet();
+    let total_samples = reader.total_samples();
+    let index = reader.packet_index().unwrap();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let packets: Vec<&[u8]> = (0..index.packet_count())
+        .map(|i| {
+            let (offset, len) = index.packet(i).unwrap();
+            &data[offset as usize..(offset + len) as usize]
+        })
+        .collect();
+
+    // Packets default to a full frame length, only the final packet is shorter.
+    let mut mvex = Vec::new();
+    write_box(&mut mvex, b"trex", &full_box(&[1, 1, frame_length, 0, 0]));
+    let mut extra = Vec::new();
+    write_box(&mut extra, b"mvex", &mvex);
+
+    let mut fmp4 = Vec::new();
+    rewrite_boxes(&data, &mut fmp4, &extra);
+
+    let last_packet_frames = total_samples as u32 - frame_length * (packets.len() as u32 - 1);
+    for (i, fragment) in packets.chunks(20).enumerate() {
+        // Only the final fragment gives each sample's duration.
+        let last = (i + 1) * 20 >= packets.len();
+        let flags: u32 = if last { 0x301 } else { 0x201 };
+
+        let moof = |data_offset: u32| {
+            let mut trun = vec![fragment.len() as u32, data_offset];
+            for (j, packet) in fragment.iter().enumerate() {
+                if last && j == fragment.len() - 1 {
+                    trun.push(last_packet_frames);
+                } else if last {
+                    trun.push(frame_length);
+                }
+                trun.push(packet.len() as u32);
+            }
+            let mut trun = full_box(&trun);
+            trun[0..4].copy_from_slice(&flags.to_be_bytes());
+
+            let mut traf = Vec::new();
+            write_box(&mut traf, b"tfhd", &full_box(&[1]));
+            write_box(&mut traf, b"trun", &trun);
+            let mut moof = Vec::new();
+            write_box(&mut moof, b"mfhd", &full_box(&[i as u32 + 1]));
+            write_box(&mut moof, b"traf", &traf);
+            moof
+        };
+
+        // Data offsets are relative to the start of the moof box by default.
+        let moof_len = moof(0).len() as u32 + 8;
+        write_box(&mut fmp4, b"moof", &moof(moof_len + 8));
+        write_box(&mut fmp4, b"mdat", &fragment.concat());
+    }
+
+    let reader = Reader::new(Cursor::new(&fmp4)).unwrap();
+    assert_eq!(reader.packet_count(), packets.len() as u64);
+    assert_eq!(reader.total_samples(), total_samples);
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    let mut reader = Reader::new(Cursor::new(&fmp4)).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    reader.seek_to_sample(frame_length as u64 * 30 + 5).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(
+        samples,
+        &expected[(frame_length as usize * 30 + 5) * channels..]
+    );
+}
+
+#[test]
+fn rejects_out_of_range_data_offset() {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    let frame_length = Reader::new(Cursor::new(&data))
+        .unwrap()
+        .stream_info()
+        .max_frames_per_packet();
+
+    let mut mvex = Vec::new();
+    write_box(&mut mvex, b"trex", &full_box(&[1, 1, frame_length, 0, 0]));
+    let mut extra = Vec::new();
+    write_box(&mut extra, b"mvex", &mvex);
+
+    // The run's data offset, or the offset of its second sample, is past the end of a `u64`.
+    let base_data_offset = u64::MAX - 10;
+    for &(data_offset, sample_size) in &[(100, 1), (0, 100)] {
+        let mut tfhd = full_box(&[1]);
+        tfhd[3] = 1;
+        tfhd.extend_from_slice(&base_data_offset.to_be_bytes());
+        let mut trun = full_box(&[2, data_offset, sample_size, sample_size]);
+        trun[0..4].copy_from_slice(&0x201u32.to_be_bytes());
+
+        let mut traf = Vec::new();
+        write_box(&mut traf, b"tfhd", &tfhd);
+        write_box(&mut traf, b"trun", &trun);
+        let mut moof = Vec::new();
+        write_box(&mut moof, b"mfhd", &full_box(&[1]));
+        write_box(&mut moof, b"traf", &traf);
+
+        let mut fmp4 = Vec::new();
+        rewrite_boxes(&data, &mut fmp4, &extra);
+        write_box(&mut fmp4, b"moof", &moof);
+
+        match Reader::new(Cursor::new(&fmp4)) {
+            Err(ReadError::Format(Format::Mp4, err)) => {
+                assert_eq!(err.kind(), ErrorKind::InvalidContainer)
+            }
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("out of range data offset was accepted"),
+        }
+    }
+}
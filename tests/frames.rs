@@ -0,0 +1,44 @@
+// Reads streams a frame at a time.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+
+fn test_frames(path: &str) {
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    let mut frames = reader.into_frames::<i32>();
+    let mut samples = Vec::new();
+    while let Some(frame) = frames.next_frame() {
+        let frame = frame.unwrap();
+        assert_eq!(frame.len(), channels);
+        samples.extend_from_slice(frame);
+    }
+    assert_eq!(samples, expected);
+
+    // Seeking positions the next frame.
+    let frame = 4096 + 17;
+    frames.seek_to_sample(frame as u64).unwrap();
+    let start = frame * channels;
+    assert_eq!(
+        frames.next_frame().unwrap().unwrap(),
+        &expected[start..start + channels]
+    );
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf() {
+    test_frames("tests/data/decode_comparison/multichannel_48000_16_bit_5_1_chan.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4() {
+    test_frames("tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a");
+}
@@ -1,6 +1,6 @@
 extern crate alac;
 
-const COOKIE_A: &'static [u8] = b"\x00\x00\x10\x00\x00\x10\x28\x0a\x0e\x02\x00\xff\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xac\x44\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x2c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x0c\x00\x68\xc5\x06\x00\x01\x00\x00\x00\xff\xff\xff\xff\xff\xff\xff\xff\xef\xcd\xab\x89\xff\xff\xff\xff\x40\xd3\x06\x00\x01\x00\x00\x00\xb0\x02\x20\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x22\x00\x00\x00\x07\x06\x00\x00\x00\x00\x00\x00\x00\x00\x00\x20\x21\x43\x50\x58\x05\x00\x00\x00\x00\xf0\x00\x00\x04\x00\x00\x00\x00\x00\x00";
+const COOKIE_A: &[u8] = b"\x00\x00\x10\x00\x00\x10\x28\x0a\x0e\x02\x00\xff\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\xac\x44\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x2c\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x00\x0c\x00\x68\xc5\x06\x00\x01\x00\x00\x00\xff\xff\xff\xff\xff\xff\xff\xff\xef\xcd\xab\x89\xff\xff\xff\xff\x40\xd3\x06\x00\x01\x00\x00\x00\xb0\x02\x20\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x22\x00\x00\x00\x07\x06\x00\x00\x00\x00\x00\x00\x00\x00\x00\x20\x21\x43\x50\x58\x05\x00\x00\x00\x00\xf0\x00\x00\x04\x00\x00\x00\x00\x00\x00";
 
 #[allow(non_snake_case)]
 #[test]
@@ -45,6 +45,43 @@ fn rice_limit_too_large() {
     assert_stream_info_decode_error(data);
 }
 
+#[test]
+fn rice_limit_zero() {
+    let mut cookie = COOKIE_A[..24].to_vec();
+    cookie[8] = 0;
+    assert_stream_info_decode_error(&cookie);
+}
+
+#[test]
+fn rice_limit_below_zero_block_parameter() {
+    // A mono packet of four samples whose first residual is zero, which with the initial rice
+    // history of 10 is followed by a zero block coded with a rice parameter of 4. Smaller rice
+    // limits clamp the parameter, so read the zero block and the samples after it differently.
+    let packet = [
+        0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x03, 0x00, 0x27,
+    ];
+    let decode = |kb: u8| {
+        let mut cookie = COOKIE_A[..24].to_vec();
+        cookie[8] = kb;
+        cookie[9] = 1;
+        let stream_info = alac::StreamInfo::from_cookie(&cookie).unwrap();
+        let mut decoder = alac::Decoder::new(stream_info);
+        let mut out = vec![0i16; decoder.stream_info().max_samples_per_packet() as usize];
+        decoder.decode_packet(&packet, &mut out).map(|s| s.to_vec())
+    };
+
+    for kb in 4..8 {
+        assert_eq!(decode(kb).unwrap(), [0, 0, 0, 0]);
+    }
+    // A limit of 1 reads an empty zero block, after which each residual is -1.
+    assert_eq!(decode(1).unwrap(), [0, -1, -1, -2]);
+    // With the other clamped limits the channel reads past the end of the packet.
+    for kb in 2..4 {
+        let err = decode(kb).unwrap_err();
+        assert_eq!(err.kind(), alac::ErrorKind::TruncatedPacket);
+    }
+}
+
 fn assert_decode_err(cookie: &[u8], packet: &[u8]) {
     let stream_info = alac::StreamInfo::from_cookie(cookie).expect("error reading cookie");
     let mut decoder = alac::Decoder::new(stream_info);
@@ -0,0 +1,136 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+fn decode<R: Read + std::io::Seek>(reader: Reader<R>) -> Vec<i32> {
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_itunes_gapless_info() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let info = reader.gapless_info().unwrap();
+    assert_eq!((info.priming_frames(), info.valid_frames()), (0, 302400));
+    assert_eq!(reader.total_samples(), 302400);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_priming_frames_are_trimmed() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+
+    let reader = Reader::new(Cursor::new(data.clone())).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    let info = reader.gapless_info().unwrap();
+    assert_eq!((info.priming_frames(), info.valid_frames()), (0, 302400));
+    let untrimmed = decode(reader);
+
+    // Mark the first 5000 frames as priming and the last 1000 as padding.
+    let pakt = data.windows(4).position(|w| w == b"pakt").unwrap() + 12;
+    data[pakt + 8..pakt + 16].copy_from_slice(&296400i64.to_be_bytes());
+    data[pakt + 16..pakt + 20].copy_from_slice(&5000i32.to_be_bytes());
+
+    let reader = Reader::new(Cursor::new(data.clone())).unwrap();
+    assert_eq!(reader.total_samples(), 296400);
+    let samples = reader.into_samples::<i32>();
+    let len = 296400 * channels;
+    assert_eq!(samples.size_hint(), (len, Some(len)));
+    let trimmed: Vec<i32> = samples.map(|s| s.unwrap()).collect();
+    assert_eq!(trimmed, &untrimmed[5000 * channels..301400 * channels]);
+
+    let mut reader = Reader::new(Cursor::new(data.clone())).unwrap();
+    reader.seek_to_sample(10).unwrap();
+    assert_eq!(
+        decode(reader),
+        &untrimmed[5010 * channels..301400 * channels]
+    );
+
+    let mut reader = Reader::new(Cursor::new(data)).unwrap();
+    reader.set_gapless(false).unwrap();
+    assert_eq!(reader.total_samples(), 301400);
+    assert_eq!(decode(reader), untrimmed);
+}
+
+#[cfg(feature = "mp4")]
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// Inserts `edts` into the first trak atom of `data`, shrinking the free atom following the moov
+/// atom so the media data doesn't move.
+#[cfg(feature = "mp4")]
+fn insert_edts(data: &[u8], edts: &[u8]) -> Vec<u8> {
+    let moov = 28;
+    assert_eq!(&data[moov + 4..moov + 8], b"moov");
+    let moov_len = read_u32(&data[moov..]) as usize;
+    let trak = data.windows(4).position(|w| w == b"trak").unwrap() - 4;
+    let tkhd_end = trak + 8 + read_u32(&data[trak + 8..]) as usize;
+    let free = moov + moov_len;
+    assert_eq!(&data[free + 4..free + 8], b"free");
+    let free_len = read_u32(&data[free..]) as usize;
+
+    let grow = |data: &[u8]| read_u32(data) + edts.len() as u32;
+    let mut out = data[..moov].to_vec();
+    out.extend_from_slice(&grow(&data[moov..]).to_be_bytes());
+    out.extend_from_slice(&data[moov + 4..trak]);
+    out.extend_from_slice(&grow(&data[trak..]).to_be_bytes());
+    out.extend_from_slice(&data[trak + 4..tkhd_end]);
+    out.extend_from_slice(edts);
+    out.extend_from_slice(&data[tkhd_end..free]);
+    out.extend_from_slice(&(free_len as u32 - edts.len() as u32).to_be_bytes());
+    out.extend_from_slice(&data[free + 4..free + free_len - edts.len()]);
+    out.extend_from_slice(&data[free + free_len..]);
+    assert_eq!(out.len(), data.len());
+    out
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_edit_list() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+
+    let reader = Reader::new(Cursor::new(data.clone())).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    let untrimmed = decode(reader);
+
+    // An empty edit followed by an edit starting 5000 frames into the media and lasting 6
+    // seconds in the movie timescale.
+    let mvhd = data.windows(4).position(|w| w == b"mvhd").unwrap() + 4;
+    let movie_timescale = read_u32(&data[mvhd + 12..]);
+    let mut edts = Vec::new();
+    for field in &[48, 0x6564_7473, 40, 0x656c_7374, 0, 2] {
+        edts.extend_from_slice(&(*field as u32).to_be_bytes());
+    }
+    for field in &[
+        movie_timescale,
+        u32::MAX,
+        0x10000,
+        6 * movie_timescale,
+        5000,
+        0x10000,
+    ] {
+        edts.extend_from_slice(&field.to_be_bytes());
+    }
+    let data = insert_edts(&data, &edts);
+
+    let reader = Reader::new(Cursor::new(data)).unwrap();
+    let info = reader.gapless_info().unwrap();
+    assert_eq!(
+        (info.priming_frames(), info.valid_frames()),
+        (5000, 6 * 44100)
+    );
+    assert_eq!(
+        decode(reader),
+        &untrimmed[5000 * channels..(5000 + 6 * 44100) * channels]
+    );
+}
@@ -0,0 +1,165 @@
+// Reads tags stored in ALAC files.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+#[cfg(feature = "caf")]
+const CAF_PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+#[cfg(feature = "mp4")]
+const MP4_PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[cfg(feature = "mp4")]
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+#[cfg(feature = "mp4")]
+fn mp4_box(box_type: &[u8; 4], content: &[u8]) -> Vec<u8> {
+    let mut out = (content.len() as u32 + 8).to_be_bytes().to_vec();
+    out.extend_from_slice(box_type);
+    out.extend_from_slice(content);
+    out
+}
+
+/// Returns an ilst item holding a data box with the given type indicator and value.
+#[cfg(feature = "mp4")]
+fn ilst_item(item_type: &[u8; 4], data_type: u32, value: &[u8]) -> Vec<u8> {
+    let mut data = data_type.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(value);
+    mp4_box(item_type, &mp4_box(b"data", &data))
+}
+
+/// Appends `items` to the `moov/udta/meta/ilst` box of `data`, shrinking the following free box so
+/// the media data doesn't move.
+#[cfg(feature = "mp4")]
+fn append_ilst_items(data: &[u8], items: &[u8]) -> Vec<u8> {
+    fn append(mut data: &[u8], path: &[&[u8; 4]], items: &[u8], out: &mut Vec<u8>) {
+        while !data.is_empty() {
+            let size = read_u32(data) as usize;
+            let box_type = [data[4], data[5], data[6], data[7]];
+            let mut content = &data[8..size];
+            data = &data[size..];
+
+            if path.first() != Some(&&box_type) {
+                if &box_type == b"free" {
+                    content = &content[items.len()..];
+                }
+                out.extend_from_slice(&mp4_box(&box_type, content));
+                continue;
+            }
+
+            let mut children = Vec::new();
+            if path.len() == 1 {
+                children.extend_from_slice(content);
+                children.extend_from_slice(items);
+            } else {
+                // The meta box is a full box with a version and flags before its children.
+                if &box_type == b"meta" {
+                    children.extend_from_slice(&content[..4]);
+                    content = &content[4..];
+                }
+                append(content, &path[1..], items, &mut children);
+            }
+            out.extend_from_slice(&mp4_box(&box_type, &children));
+        }
+    }
+
+    let mut out = Vec::new();
+    append(data, &[b"moov", b"udta", b"meta", b"ilst"], items, &mut out);
+    assert_eq!(out.len(), data.len());
+    out
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_info_chunk() {
+    let data = read_file(CAF_PATH);
+
+    // The file's existing info chunk only holds its duration and source bit depth.
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    assert_eq!(*reader.metadata(), Default::default());
+
+    let mut info = 5u32.to_be_bytes().to_vec();
+    for string in &[
+        "title",
+        "Sine Sweep",
+        "artist",
+        "Synth \u{e9}",
+        "album",
+        "Test Tones",
+        "track number",
+        "3/12",
+        "year",
+        "2018",
+    ] {
+        info.extend_from_slice(string.as_bytes());
+        info.push(0);
+    }
+
+    // The original info chunk is replaced. Packets are located relative to the data chunk so it
+    // can move.
+    let (info_start, info_end) = (88, 88 + 12 + 62);
+    assert_eq!(&data[info_start..info_start + 4], b"info");
+    let mut tagged = data[..info_start].to_vec();
+    tagged.extend_from_slice(b"info");
+    tagged.extend_from_slice(&(info.len() as u64).to_be_bytes());
+    tagged.extend_from_slice(&info);
+    tagged.extend_from_slice(&data[info_end..]);
+
+    let reader = Reader::new(Cursor::new(&tagged)).unwrap();
+    let metadata = reader.metadata();
+    assert_eq!(metadata.title(), Some("Sine Sweep"));
+    assert_eq!(metadata.artist(), Some("Synth \u{e9}"));
+    assert_eq!(metadata.album(), Some("Test Tones"));
+    assert_eq!(metadata.track_number(), Some(3));
+    assert_eq!(metadata.track_count(), Some(12));
+    assert_eq!(metadata.cover_art(), None);
+
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    let original = Reader::new(Cursor::new(&data)).unwrap();
+    let expected: Vec<i32> = original.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_ilst_tags() {
+    let data = read_file(MP4_PATH);
+
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    assert_eq!(reader.metadata().title(), None);
+    assert_eq!(reader.metadata().cover_art(), None);
+
+    let cover_art = b"\x89PNG\r\n\x1a\n";
+    let mut items = Vec::new();
+    items.extend_from_slice(&ilst_item(b"\xa9nam", 1, "Sine Sweep".as_bytes()));
+    items.extend_from_slice(&ilst_item(b"\xa9ART", 1, "Synth \u{e9}".as_bytes()));
+    items.extend_from_slice(&ilst_item(b"\xa9alb", 1, b"Test Tones"));
+    items.extend_from_slice(&ilst_item(b"trkn", 0, &[0, 0, 0, 3, 0, 12, 0, 0]));
+    items.extend_from_slice(&ilst_item(b"covr", 14, cover_art));
+    let tagged = append_ilst_items(&data, &items);
+
+    let reader = Reader::new(Cursor::new(&tagged)).unwrap();
+    let metadata = reader.metadata();
+    assert_eq!(metadata.title(), Some("Sine Sweep"));
+    assert_eq!(metadata.artist(), Some("Synth \u{e9}"));
+    assert_eq!(metadata.album(), Some("Test Tones"));
+    assert_eq!(metadata.track_number(), Some(3));
+    assert_eq!(metadata.track_count(), Some(12));
+    assert_eq!(metadata.cover_art(), Some(&cover_art[..]));
+
+    // The existing iTunSMPB tag is still read.
+    let original = Reader::new(Cursor::new(&data)).unwrap();
+    assert_eq!(reader.gapless_info(), original.gapless_info());
+}
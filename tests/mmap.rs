@@ -0,0 +1,61 @@
+// Decodes memory-mapped files. The test data is never modified so mapping it is safe.
+#![cfg(all(feature = "mmap", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a";
+
+fn expected_samples() -> Vec<i32> {
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+#[test]
+fn decodes_mapped_file() {
+    let reader = unsafe { Reader::from_mmap(PATH) }.unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected_samples());
+}
+
+#[test]
+fn raw_packets_are_borrowed_from_mapping() {
+    let mut file_reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let mut reader = unsafe { Reader::from_mmap(PATH) }.unwrap();
+    let data = reader.get_ref().clone();
+
+    while let Some(packet) = reader.next_raw_packet().unwrap() {
+        let expected = file_reader.next_raw_packet().unwrap().unwrap();
+        assert_eq!(packet.data(), expected.data());
+        assert_eq!(packet.location(), expected.location());
+
+        let offset = packet.location().byte_offset().unwrap() as usize;
+        let mapped = &data.as_bytes()[offset..offset + packet.data().len()];
+        assert_eq!(packet.data().as_ptr(), mapped.as_ptr());
+    }
+    assert!(file_reader.next_raw_packet().unwrap().is_none());
+}
+
+#[test]
+fn seeks_in_mapped_file() {
+    let expected = expected_samples();
+
+    let mut reader = unsafe { Reader::from_mmap(PATH) }.unwrap();
+    reader.seek_to_sample(10_000).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, &expected[10_000 * 2..]);
+}
+
+#[cfg(feature = "caf")]
+#[test]
+fn decodes_mapped_caf_file() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let reader = unsafe { Reader::from_mmap(path) }.unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
@@ -0,0 +1,84 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use std::fs::File;
+
+fn test_packet_count(path: &str) {
+    let reader = alac::Reader::new(File::open(path).unwrap()).unwrap();
+    let max_samples = reader.stream_info().max_samples_per_packet() as usize;
+    let packet_count = reader.packet_count();
+    assert!(packet_count > 0);
+    assert_eq!(reader.packets_remaining(), packet_count);
+
+    let mut packets = reader.into_packets::<i16>();
+    let mut buf = vec![0; max_samples];
+    let mut decoded = 0;
+    let mut bytes = 0;
+    while let Some(samples) = packets.next_into(&mut buf).unwrap() {
+        decoded += 1;
+        assert_eq!(packets.packets_remaining(), packet_count - decoded);
+
+        // Samples are only trimmed from the first and last packets.
+        let stats = packets.last_packet_stats().unwrap();
+        if decoded > 1 && decoded < packet_count {
+            assert_eq!(stats.samples(), samples.len() as u64);
+        }
+        assert!(!stats.uncompressed());
+        assert!(stats.bit_rate().unwrap() > 0.0);
+        bytes += stats.bytes();
+    }
+    assert_eq!(decoded, packet_count);
+    assert_eq!(bytes, packets.throughput().bytes_in());
+    assert_eq!(packets.last_packet_stats(), None);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_packet_count() {
+    test_packet_count("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_packet_count() {
+    test_packet_count("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a");
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn uncompressed_packet_stats() {
+    let path = "tests/data/decode_comparison/multichannel_48000_16_bit_5_1_cookie.caf";
+    let reader = alac::Reader::new(File::open(path).unwrap()).unwrap();
+    let channels = reader.stream_info().channels() as u64;
+    let mut buf = vec![0i16; reader.stream_info().max_samples_per_packet() as usize];
+
+    let mut packets = reader.into_packets();
+    packets.next_into(&mut buf).unwrap().unwrap();
+    let stats = packets.last_packet_stats().unwrap();
+    assert!(stats.uncompressed());
+    assert_eq!(stats.samples(), stats.frames() as u64 * channels);
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn packets_iterator() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+    let reader = alac::Reader::new(File::open(path).unwrap()).unwrap();
+    let expected: Vec<i16> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let reader = alac::Reader::new(File::open(path).unwrap()).unwrap();
+    let packet_count = reader.packet_count() as usize;
+    let packets: Vec<Vec<i16>> = reader.into_packets().map(|p| p.unwrap()).collect();
+    assert_eq!(packets.len(), packet_count);
+    assert_eq!(packets.concat(), expected);
+
+    // Recycled buffers are reused.
+    let reader = alac::Reader::new(File::open(path).unwrap()).unwrap();
+    let mut packets = reader.into_packets::<i16>();
+    let first = packets.next().unwrap().unwrap();
+    let ptr = first.as_ptr();
+    packets.recycle(first);
+    let second = packets.next().unwrap().unwrap();
+    assert_eq!(second.as_ptr(), ptr);
+}
@@ -0,0 +1,32 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{PacketIndex, Reader};
+use std::fs::File;
+
+fn test_packet_index(path: &str) {
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let index = reader.packet_index().unwrap();
+    assert_eq!(index.packet_count(), reader.packet_count());
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let index = PacketIndex::from_bytes(&index.to_bytes()).unwrap();
+    let reader = Reader::from_packet_index(File::open(path).unwrap(), index.clone()).unwrap();
+    assert_eq!(reader.packet_index().unwrap(), index);
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    assert_eq!(samples, expected);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_packet_index() {
+    test_packet_index("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_packet_index() {
+    test_packet_index("tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a");
+}
@@ -0,0 +1,132 @@
+// Reads packets from a container implemented outside the crate.
+#![cfg(feature = "caf")]
+
+extern crate alac;
+
+use alac::{Format, PacketSource, ReadSeek, Reader};
+use std::fs::File;
+use std::io::{self, Cursor, SeekFrom};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+
+/// Packets each preceded by their length as a big endian `u32`.
+struct LengthPrefixed {
+    magic_cookie: Vec<u8>,
+    offsets: Vec<u64>,
+    frames: u64,
+}
+
+impl PacketSource for LengthPrefixed {
+    fn magic_cookie(&self) -> &[u8] {
+        &self.magic_cookie
+    }
+
+    fn next_packet_into(&mut self, reader: &mut dyn ReadSeek, buf: &mut Vec<u8>) -> io::Result<()> {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                buf.clear();
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+        buf.resize(u32::from_be_bytes(len) as usize, 0);
+        reader.read_exact(buf)
+    }
+
+    fn seek_to_packet(&mut self, reader: &mut dyn ReadSeek, packet: u64) -> io::Result<()> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        let offset = self.offsets.get(packet as usize).cloned().unwrap_or(end);
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+
+    fn packet_count(&self) -> Option<u64> {
+        Some(self.offsets.len() as u64)
+    }
+
+    fn total_frames(&self) -> Option<u64> {
+        Some(self.frames)
+    }
+}
+
+/// Returns the samples of the test file and its packets rewritten with length prefixes.
+fn length_prefixed() -> (Vec<i32>, Vec<u8>, LengthPrefixed) {
+    let mut reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.set_gapless(false).unwrap();
+    let mut data = Vec::new();
+    let mut offsets = Vec::new();
+    let mut frames = 0;
+    while let Some(packet) = reader.next_raw_packet().unwrap() {
+        offsets.push(data.len() as u64);
+        data.extend_from_slice(&(packet.data().len() as u32).to_be_bytes());
+        data.extend_from_slice(packet.data());
+        frames += packet.frames() as u64;
+    }
+
+    let source = LengthPrefixed {
+        magic_cookie: reader.stream_info().to_cookie(),
+        offsets,
+        frames,
+    };
+    let mut reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.set_gapless(false).unwrap();
+    let samples = reader.into_samples().map(|s| s.unwrap()).collect();
+    (samples, data, source)
+}
+
+#[test]
+fn reads_custom_container() {
+    let (expected, data, source) = length_prefixed();
+    let packet_count = source.offsets.len() as u64;
+    let reader = Reader::from_packet_source(Cursor::new(data), source).unwrap();
+    assert_eq!(reader.format(), Format::Custom);
+    assert_eq!(reader.packet_count(), packet_count);
+    assert!(reader.packet_index().is_err());
+
+    let channels = reader.stream_info().channels() as u64;
+    assert_eq!(reader.total_samples(), expected.len() as u64 / channels);
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn seeks_custom_container() {
+    let (expected, data, source) = length_prefixed();
+    let mut reader = Reader::from_packet_source(Cursor::new(data), source).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+
+    let sample = 10_000;
+    reader.seek_to_sample(sample).unwrap();
+    assert_eq!(reader.sample_position(), sample);
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, &expected[sample as usize * channels..]);
+}
+
+#[test]
+fn seeking_unsupported_by_default() {
+    struct Unseekable(LengthPrefixed);
+
+    impl PacketSource for Unseekable {
+        fn magic_cookie(&self) -> &[u8] {
+            &self.0.magic_cookie
+        }
+
+        fn next_packet_into(
+            &mut self,
+            reader: &mut dyn ReadSeek,
+            buf: &mut Vec<u8>,
+        ) -> io::Result<()> {
+            self.0.next_packet_into(reader, buf)
+        }
+    }
+
+    let (expected, data, source) = length_prefixed();
+    let mut reader = Reader::from_packet_source(Cursor::new(data), Unseekable(source)).unwrap();
+    assert_eq!(reader.total_samples(), 0);
+    assert!(reader.seek_to_sample(1000).is_err());
+
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
@@ -0,0 +1,67 @@
+// Decodes whole files on rayon's thread pool.
+#![cfg(all(feature = "rayon", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{ReadError, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a";
+
+fn expected_samples<S: alac::Sample>() -> Vec<S> {
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+#[test]
+fn decode_all_parallel() {
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let samples: Vec<i32> = reader.decode_all_parallel().unwrap();
+    assert_eq!(samples, expected_samples::<i32>());
+
+    // Narrow sample types are truncated as they are by `into_samples`.
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let samples: Vec<i16> = reader.decode_all_parallel().unwrap();
+    assert_eq!(samples, expected_samples::<i16>());
+}
+
+#[test]
+fn decode_all_parallel_after_seek() {
+    let expected = expected_samples::<i32>();
+    let channels = 2;
+
+    let mut reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.seek_to_sample(10_000).unwrap();
+    let samples: Vec<i32> = reader.decode_all_parallel().unwrap();
+    assert_eq!(samples, &expected[10_000 * channels..]);
+}
+
+#[test]
+fn decode_all_parallel_with_corrupt_packet() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    for b in &mut data[20000..20064] {
+        *b = 0xff;
+    }
+
+    // The first error is returned as `into_samples` would report it.
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let expected = reader.into_samples::<i32>().find_map(|s| s.err()).unwrap();
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    match (reader.decode_all_parallel::<i32>().unwrap_err(), expected) {
+        (ReadError::Packet(location, _), ReadError::Packet(expected, _)) => {
+            assert_eq!(location, expected)
+        }
+        (err, _) => panic!("unexpected error {:?}", err),
+    }
+
+    // Resync decodes the same samples as `into_samples`.
+    let mut reader = Reader::new(Cursor::new(&data)).unwrap();
+    reader.set_resync(true);
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    let mut reader = Reader::new(Cursor::new(&data)).unwrap();
+    reader.set_resync(true);
+    assert_eq!(reader.decode_all_parallel::<i32>().unwrap(), expected);
+}
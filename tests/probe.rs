@@ -0,0 +1,40 @@
+// Identifies container formats without opening a reader.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{probe, Format};
+use std::fs::File;
+use std::io::{Cursor, Seek};
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+    let mut file = File::open(path).unwrap();
+    assert_eq!(probe(&mut file).unwrap(), Some(Format::Caf));
+    assert_eq!(file.stream_position().unwrap(), 0);
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+    let mut file = File::open(path).unwrap();
+    assert_eq!(probe(&mut file).unwrap(), Some(Format::Mp4));
+
+    // The reader is left where it was.
+    assert_eq!(file.stream_position().unwrap(), 0);
+    alac::Reader::new(file).unwrap();
+}
+
+#[test]
+fn unsupported() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit.wav";
+    assert_eq!(probe(&mut File::open(path).unwrap()).unwrap(), None);
+
+    // Files too short to identify are not supported.
+    let mut short = Cursor::new(b"caff");
+    assert_eq!(probe(&mut short).unwrap(), None);
+    assert_eq!(short.position(), 0);
+}
@@ -0,0 +1,56 @@
+// Reads compressed packets without decoding them.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{Decoder, Reader};
+use std::fs::File;
+
+fn test_raw_packets(path: &str) {
+    let mut reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let index = reader.packet_index().unwrap();
+    let stream_info = reader.stream_info().clone();
+    let channels = stream_info.channels() as usize;
+    let priming_frames = reader
+        .gapless_info()
+        .map_or(0, |info| info.priming_frames());
+    let mut decoder = Decoder::new(stream_info.clone());
+    let mut out = vec![0i32; stream_info.max_samples_per_packet() as usize];
+
+    let max_packet_size = reader.max_packet_size();
+    let mut longest_packet = 0;
+    let mut packets = 0;
+    let mut position = 0u64;
+    while let Some(packet) = reader.next_raw_packet().unwrap() {
+        let location = packet.location();
+        assert_eq!(location.packet_index(), packets);
+        let (offset, len) = index.packet(packets).unwrap();
+        assert_eq!(location.byte_offset(), Some(offset));
+        assert_eq!(packet.data().len() as u64, len);
+        assert_eq!(
+            location.sample_position(),
+            position.saturating_sub(priming_frames)
+        );
+
+        let samples = decoder.decode_packet(packet.data(), &mut out).unwrap();
+        assert_eq!(samples.len(), packet.frames() as usize * channels);
+
+        longest_packet = longest_packet.max(packet.data().len());
+        packets += 1;
+        position += packet.frames() as u64;
+    }
+    assert_eq!(packets, index.packet_count());
+    assert_eq!(max_packet_size, longest_packet);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf() {
+    test_raw_packets("tests/data/decode_comparison/wide_44100_32_bit.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4() {
+    test_raw_packets("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a");
+}
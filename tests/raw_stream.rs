@@ -0,0 +1,79 @@
+// Reads length-prefixed packet streams without a container.
+#![cfg(feature = "caf")]
+
+extern crate alac;
+
+use alac::{write_raw_packet, Format, ReadError, Reader, StreamInfo, Streaming};
+use std::fs::File;
+use std::io::Cursor;
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+
+/// Returns the samples of the test file, its stream info and its packets as a raw stream.
+fn raw_stream() -> (Vec<i32>, StreamInfo, Vec<u8>) {
+    let mut reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.set_gapless(false).unwrap();
+    let mut data = Vec::new();
+    while let Some(packet) = reader.next_raw_packet().unwrap() {
+        write_raw_packet(&mut data, packet.data()).unwrap();
+    }
+    let stream_info = reader.stream_info().clone();
+
+    let mut reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.set_gapless(false).unwrap();
+    let samples = reader.into_samples().map(|s| s.unwrap()).collect();
+    (samples, stream_info, data)
+}
+
+#[test]
+fn reads_raw_stream() {
+    let (expected, stream_info, data) = raw_stream();
+    let reader = Reader::new_raw(Cursor::new(&data), stream_info).unwrap();
+    assert_eq!(reader.format(), Format::Raw);
+    assert_eq!(reader.total_samples(), 0);
+    let mut samples = reader.into_samples::<i32>();
+    assert_eq!(samples.size_hint(), (0, None));
+    let first = samples.next().unwrap().unwrap();
+    let (buffered, upper) = samples.size_hint();
+    assert!(buffered > 0);
+    assert_eq!(upper, None);
+    let rest = samples.map(|s| s.unwrap());
+    let samples: Vec<i32> = Some(first).into_iter().chain(rest).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn reads_raw_stream_from_pipe() {
+    let (expected, stream_info, data) = raw_stream();
+    let reader = Reader::new_raw(Streaming::new(&data[..]), stream_info).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
+
+#[test]
+fn seeks_raw_stream() {
+    let (expected, stream_info, data) = raw_stream();
+    let channels = stream_info.channels() as usize;
+
+    // Seeking forwards skips packets and seeking backwards starts again from the first packet.
+    for &(packets_read, sample) in &[(0, 20_000), (10, 5_000)] {
+        let mut reader = Reader::new_raw(Cursor::new(&data), stream_info.clone()).unwrap();
+        for _ in 0..packets_read {
+            reader.next_raw_packet().unwrap().unwrap();
+        }
+        reader.seek_to_sample(sample).unwrap();
+        let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+        assert_eq!(samples, &expected[sample as usize * channels..]);
+    }
+}
+
+#[test]
+fn rejects_invalid_lengths() {
+    let (_, stream_info, mut data) = raw_stream();
+    data[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+    let reader = Reader::new_raw(Cursor::new(&data), stream_info).unwrap();
+    match reader.into_samples::<i32>().next() {
+        Some(Err(ReadError::Format(Format::Raw, _))) => (),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
@@ -0,0 +1,112 @@
+// Decodes a 24 bit stream into 16 bit samples.
+#![cfg(feature = "caf")]
+
+extern crate alac;
+
+use alac::{DecodeError, DecodeOptions, Decoder, Reader, Requantize, Sample, StreamInfo};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_24_bit_afconvert.caf";
+
+/// Returns the stream info and first few packets of the test file.
+fn packets() -> (StreamInfo, Vec<Vec<u8>>) {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let stream_info = reader.stream_info().clone();
+    let index = reader.packet_index().unwrap();
+    let packets = (0..4)
+        .map(|i| {
+            let (offset, len) = index.packet(i).unwrap();
+            data[offset as usize..(offset + len) as usize].to_vec()
+        })
+        .collect();
+    (stream_info, packets)
+}
+
+fn decode<S: Sample + Default + Clone>(decoder: &mut Decoder, packets: &[Vec<u8>]) -> Vec<S> {
+    let mut out = vec![S::default(); decoder.stream_info().max_samples_per_packet() as usize];
+    let mut samples = Vec::new();
+    for packet in packets {
+        samples.extend_from_slice(decoder.decode_packet(packet, &mut out).unwrap());
+    }
+    samples
+}
+
+#[test]
+fn narrow_output_requires_requantization() {
+    let (stream_info, packets) = packets();
+    assert_eq!(stream_info.bit_depth(), 24);
+
+    let mut decoder = Decoder::new(stream_info);
+    let mut out = vec![0i16; decoder.stream_info().max_samples_per_packet() as usize];
+    match decoder.try_decode_packet(&packets[0], &mut out) {
+        Err(DecodeError::SampleTooNarrow {
+            sample_bits: 16,
+            bit_depth: 24,
+        }) => (),
+        result => panic!("unexpected result {:?}", result.map(<[i16]>::len)),
+    }
+}
+
+#[test]
+fn truncate() {
+    let (stream_info, packets) = packets();
+    let expected: Vec<i32> = decode(&mut Decoder::new(stream_info.clone()), &packets);
+
+    let options = DecodeOptions::new().requantize(Requantize::Truncate);
+    let mut decoder = Decoder::with_options(stream_info, options);
+    let samples: Vec<i16> = decode(&mut decoder, &packets);
+
+    assert_eq!(samples.len(), expected.len());
+    for (&sample, &expected) in samples.iter().zip(expected.iter()) {
+        assert_eq!(sample, (expected >> 16) as i16);
+    }
+}
+
+#[test]
+fn dither() {
+    let (stream_info, packets) = packets();
+    let expected: Vec<i32> = decode(&mut Decoder::new(stream_info.clone()), &packets);
+
+    let options = DecodeOptions::new().requantize(Requantize::Dither);
+    let mut decoder = Decoder::with_options(stream_info, options);
+    let samples: Vec<i16> = decode(&mut decoder, &packets);
+
+    // Dither moves samples by at most one least significant bit from the rounded sample.
+    assert_eq!(samples.len(), expected.len());
+    let mut changed = 0;
+    for (&sample, &expected) in samples.iter().zip(expected.iter()) {
+        let exact = expected as f64 / 65536.0;
+        assert!((sample as f64 - exact).abs() < 1.5);
+        if sample != exact.round() as i16 {
+            changed += 1;
+        }
+    }
+    assert!(changed > 0);
+}
+
+#[test]
+fn samples_with_dither() {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    let samples = |dither| -> Vec<i16> {
+        let reader = Reader::new(Cursor::new(&data)).unwrap();
+        let samples = reader.into_samples().with_dither(dither);
+        samples.map(|s| s.unwrap()).collect()
+    };
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    // Samples are truncated by default.
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let truncated: Vec<i16> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(truncated, samples(false));
+    let shifted: Vec<i16> = expected.iter().map(|&s| (s >> 16) as i16).collect();
+    assert_eq!(truncated, shifted);
+
+    let dithered = samples(true);
+    assert_eq!(dithered.len(), expected.len());
+    assert_ne!(dithered, truncated);
+}
@@ -0,0 +1,59 @@
+extern crate alac;
+
+use alac::{Decoder, StreamInfo};
+
+#[test]
+fn decoder_finds_packet_after_garbage() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+
+    let mut data = vec![0x20, 0x00, 0x00, 0xff, 0x12, 0x34, 0x56];
+    data.extend_from_slice(packet);
+
+    let mut dec = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+    let mut expected = vec![0i16; 8192];
+    dec.decode_packet(packet, &mut expected).unwrap();
+
+    let mut out = vec![0i16; 8192];
+    let (range, samples) = dec.decode_next_valid_packet(&data, &mut out).unwrap();
+    assert_eq!(range, 7..7 + 8581);
+    assert_eq!(samples, &expected[..]);
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn reader_recovers_from_corrupt_packet() {
+    use std::fs::File;
+    use std::io::{Cursor, Read};
+
+    let mut data = Vec::new();
+    File::open("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+
+    let original: Vec<i16> = alac::Reader::new(Cursor::new(&data))
+        .unwrap()
+        .into_samples()
+        .map(|s| s.unwrap())
+        .collect();
+
+    for b in &mut data[20000..20064] {
+        *b = 0xff;
+    }
+
+    let mut reader = alac::Reader::new(Cursor::new(&data)).unwrap();
+    reader.set_resync(true);
+    let max_samples = reader.stream_info().max_samples_per_packet() as usize;
+    let recovered: Vec<i16> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    // At most the corrupt packet and the one following it are lost.
+    assert!(recovered.len() >= original.len() - 2 * max_samples);
+    assert!(recovered.len() < original.len());
+
+    let tail = original.len() / 2;
+    assert_eq!(
+        &recovered[recovered.len() - tail..],
+        &original[original.len() - tail..]
+    );
+}
@@ -0,0 +1,85 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::{ReadError, Reader};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+/// A reader that fails with `WouldBlock` on every other read once `flaky` is set.
+struct FlakyReader {
+    inner: Cursor<Vec<u8>>,
+    flaky: Rc<Cell<bool>>,
+    reads: usize,
+}
+
+impl Read for FlakyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reads += 1;
+        if self.flaky.get() && self.reads & 1 == 0 {
+            // Consume a little data before failing as a partial network read would.
+            let len = buf.len().min(3);
+            self.inner.read(&mut buf[..len])?;
+            return Err(io::Error::new(ErrorKind::WouldBlock, "would block"));
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for FlakyReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+fn test_retry(path: &str) {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+
+    let expected: Vec<i32> = Reader::new(Cursor::new(&data))
+        .unwrap()
+        .into_samples()
+        .map(|s| s.unwrap())
+        .collect();
+
+    // Open the file before introducing failures as headers are not retryable.
+    let flaky = Rc::new(Cell::new(false));
+    let reader = Reader::new(FlakyReader {
+        inner: Cursor::new(data),
+        flaky: flaky.clone(),
+        reads: 0,
+    })
+    .unwrap();
+    let stream_info = reader.stream_info().clone();
+    let mut packets = reader.into_packets();
+    flaky.set(true);
+
+    let mut buf = vec![0i32; stream_info.max_samples_per_packet() as usize];
+    let mut decoded = Vec::new();
+    let mut failures = 0;
+    loop {
+        match packets.next_into(&mut buf) {
+            Ok(Some(samples)) => decoded.extend_from_slice(samples),
+            Ok(None) => break,
+            Err(ReadError::Io(ref err)) if err.kind() == ErrorKind::WouldBlock => failures += 1,
+            Err(err) => panic!("unexpected error: {}", err),
+        }
+    }
+
+    assert!(failures > 0);
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_retry_after_would_block() {
+    test_retry("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_retry_after_would_block() {
+    test_retry("tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a");
+}
@@ -0,0 +1,273 @@
+// Property-based round trip tests.
+//
+// The crate does not yet include an encoder so these tests use a minimal encoder that only emits
+// uncompressed (escape) elements. This still exercises element sequencing, partial frames, and
+// output conversion for every supported bit depth and channel count.
+extern crate alac;
+extern crate proptest;
+
+use alac::float::FloatScale;
+use alac::{DecodeOptions, Decoder, StreamInfo};
+use proptest::prelude::*;
+
+const ID_SCE: u32 = 0;
+const ID_CPE: u32 = 1;
+const ID_END: u32 = 7;
+
+struct BitWriter {
+    buf: Vec<u8>,
+    bit_len: usize,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            buf: Vec::new(),
+            bit_len: 0,
+        }
+    }
+
+    fn write(&mut self, bits: usize, value: u32) {
+        for i in (0..bits).rev() {
+            let bit_pos = self.bit_len & 7;
+            if bit_pos == 0 {
+                self.buf.push(0);
+            }
+            let bit = ((value >> i) & 1) as u8;
+            *self.buf.last_mut().unwrap() |= bit << (7 - bit_pos);
+            self.bit_len += 1;
+        }
+    }
+
+    fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Returns the ALAC element channel counts for a stream with `channels` channels.
+fn elements(channels: u8) -> Vec<u8> {
+    match channels {
+        1 => vec![1],
+        2 => vec![2],
+        3 => vec![1, 2],
+        4 => vec![1, 2, 1],
+        5 => vec![1, 2, 2],
+        6 => vec![1, 2, 2, 1],
+        7 => vec![1, 2, 2, 1, 1],
+        8 => vec![1, 2, 2, 2, 1],
+        // There are no standard layouts above 8 channels so use as many pairs as possible.
+        _ => {
+            let mut elements = vec![2; channels as usize / 2];
+            if channels % 2 == 1 {
+                elements.push(1);
+            }
+            elements
+        }
+    }
+}
+
+fn cookie(frame_length: u32, bit_depth: u8, channels: u8) -> Vec<u8> {
+    let mut cookie = Vec::new();
+    cookie.extend_from_slice(&frame_length.to_be_bytes());
+    cookie.extend_from_slice(&[0, bit_depth, 40, 10, 14, channels]);
+    cookie.extend_from_slice(&255u16.to_be_bytes());
+    cookie.extend_from_slice(&[0; 8]);
+    cookie.extend_from_slice(&44100u32.to_be_bytes());
+    cookie
+}
+
+/// Encodes interleaved right-aligned samples as uncompressed ALAC packets.
+fn encode(samples: &[i32], frame_length: u32, bit_depth: u8, channels: u8) -> Vec<Vec<u8>> {
+    let mask = if bit_depth == 32 {
+        u32::MAX
+    } else {
+        (1 << bit_depth) - 1
+    };
+
+    samples
+        .chunks(frame_length as usize * channels as usize)
+        .map(|packet| {
+            let frames = packet.len() / channels as usize;
+            let partial = frames != frame_length as usize;
+
+            let mut writer = BitWriter::new();
+            let mut channel_index = 0;
+            for element_channels in elements(channels) {
+                let tag = if element_channels == 1 { ID_SCE } else { ID_CPE };
+                writer.write(3, tag);
+                writer.write(4, 0); // element instance tag
+                writer.write(12, 0); // unused
+                writer.write(1, partial as u32);
+                writer.write(2, 0); // sample shift
+                writer.write(1, 1); // uncompressed
+                if partial {
+                    writer.write(32, frames as u32);
+                }
+                for frame in packet.chunks(channels as usize) {
+                    for j in 0..element_channels as usize {
+                        writer.write(bit_depth as usize, frame[channel_index + j] as u32 & mask);
+                    }
+                }
+                channel_index += element_channels as usize;
+            }
+            writer.write(3, ID_END);
+            writer.into_bytes()
+        })
+        .collect()
+}
+
+fn decode<S: alac::Sample + PartialEq + std::fmt::Debug>(packets: &[Vec<u8>], stream_info: StreamInfo) -> Vec<S> {
+    decode_with_options(packets, stream_info, DecodeOptions::new())
+}
+
+fn decode_with_options<S: alac::Sample + PartialEq + std::fmt::Debug>(
+    packets: &[Vec<u8>],
+    stream_info: StreamInfo,
+    options: DecodeOptions,
+) -> Vec<S> {
+    let mut decoder = Decoder::with_options(stream_info, options);
+    let mut buf =
+        vec![S::from_decoder(0, 16); decoder.stream_info().max_samples_per_packet() as usize];
+    let mut out = Vec::new();
+    for packet in packets {
+        let samples = decoder
+            .decode_packet(packet, &mut buf)
+            .expect("failed to decode packet");
+        out.extend_from_slice(samples);
+    }
+
+    let all = decoder
+        .decode_all(packets.iter().map(|p| &p[..]))
+        .expect("failed to decode packets");
+    assert_eq!(out, all);
+
+    out
+}
+
+fn pcm(max_bit_depth: u8) -> impl Strategy<Value = (u8, u8, u32, Vec<i32>)> {
+    let bit_depths = match max_bit_depth {
+        16 => vec![16],
+        _ => vec![16, 20, 24, 32],
+    };
+    (
+        prop::sample::select(bit_depths),
+        prop_oneof![4 => 1u8..=8, 1 => 9u8..=24],
+        1u32..=512,
+        0usize..1500,
+    )
+        .prop_flat_map(|(bit_depth, channels, frame_length, frames)| {
+            let min = -(1i64 << (bit_depth - 1));
+            let max = (1i64 << (bit_depth - 1)) - 1;
+            let samples = prop::collection::vec(
+                (min..=max).prop_map(|s| s as i32),
+                frames * channels as usize,
+            );
+            (Just(bit_depth), Just(channels), Just(frame_length), samples)
+        })
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn roundtrip_i32((bit_depth, channels, frame_length, samples) in pcm(32)) {
+        let cookie = cookie(frame_length, bit_depth, channels);
+        let stream_info = StreamInfo::from_cookie(&cookie).unwrap();
+
+        let packets = encode(&samples, frame_length, bit_depth, channels);
+        let decoded = decode::<i32>(&packets, stream_info);
+
+        let expected: Vec<i32> = samples.iter().map(|&s| s << (32 - bit_depth)).collect();
+        prop_assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn roundtrip_i16((bit_depth, channels, frame_length, samples) in pcm(16)) {
+        let cookie = cookie(frame_length, bit_depth, channels);
+        let stream_info = StreamInfo::from_cookie(&cookie).unwrap();
+
+        let packets = encode(&samples, frame_length, bit_depth, channels);
+        let decoded = decode::<i16>(&packets, stream_info);
+
+        let expected: Vec<i16> = samples.iter().map(|&s| s as i16).collect();
+        prop_assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn roundtrip_float((bit_depth, channels, frame_length, samples) in pcm(32)) {
+        let cookie = cookie(frame_length, bit_depth, channels);
+        let stream_info = StreamInfo::from_cookie(&cookie).unwrap();
+
+        let packets = encode(&samples, frame_length, bit_depth, channels);
+        let decoded_i32 = decode::<i32>(&packets, stream_info.clone());
+        let decoded_f32 = decode::<f32>(&packets, stream_info.clone());
+        let decoded_f64 = decode::<f64>(&packets, stream_info);
+
+        // Left-aligned samples are scaled to the same range as every other bit depth.
+        let expected: Vec<f32> = decoded_i32.iter().map(|&s| s as f32 / 2147483648.0).collect();
+        prop_assert_eq!(decoded_f32, expected);
+        let expected: Vec<f64> = decoded_i32.iter().map(|&s| s as f64 / 2147483648.0).collect();
+        prop_assert_eq!(decoded_f64, expected);
+    }
+
+    #[test]
+    fn roundtrip_float_scales((bit_depth, channels, frame_length, samples) in pcm(32)) {
+        let cookie = cookie(frame_length, bit_depth, channels);
+        let stream_info = StreamInfo::from_cookie(&cookie).unwrap();
+
+        let packets = encode(&samples, frame_length, bit_depth, channels);
+        let decoded_i32 = decode::<i32>(&packets, stream_info.clone());
+
+        // Decoding to floats gives the same samples as converting integer samples.
+        for &scale in &[FloatScale::FullScale, FloatScale::UnityAtMax] {
+            let options = DecodeOptions::new().scale_floats(scale);
+            let decoded_f32 = decode_with_options::<f32>(&packets, stream_info.clone(), options);
+            let decoded_f64 = decode_with_options::<f64>(&packets, stream_info.clone(), options);
+
+            let mut expected = vec![0f32; decoded_i32.len()];
+            scale.convert_f32(&decoded_i32, bit_depth, &mut expected);
+            prop_assert_eq!(decoded_f32, expected);
+            let mut expected = vec![0f64; decoded_i32.len()];
+            scale.convert_f64(&decoded_i32, bit_depth, &mut expected);
+            prop_assert_eq!(decoded_f64, expected);
+        }
+    }
+}
+
+#[test]
+fn roundtrip_255_channels() {
+    let samples: Vec<i32> = (0..255 * 3).map(|s| s - 300).collect();
+    let cookie = cookie(3, 16, 255);
+    let stream_info = StreamInfo::from_cookie(&cookie).unwrap();
+
+    let packets = encode(&samples, 3, 16, 255);
+    let decoded = decode::<i32>(&packets, stream_info);
+
+    let expected: Vec<i32> = samples.iter().map(|&s| s << 16).collect();
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn too_many_channels_in_255_channel_stream() {
+    // 128 channel pairs is one more channel than the stream contains.
+    let samples = vec![0; 256];
+    let cookie = cookie(1, 16, 255);
+    let mut decoder = Decoder::new(StreamInfo::from_cookie(&cookie).unwrap());
+
+    let mut writer = BitWriter::new();
+    for pair in samples.chunks(2) {
+        writer.write(3, ID_CPE);
+        writer.write(4, 0);
+        writer.write(12, 0);
+        writer.write(4, 1); // not partial, no shift, uncompressed
+        writer.write(16, pair[0] as u32);
+        writer.write(16, pair[1] as u32);
+    }
+    writer.write(3, ID_END);
+
+    let mut out = vec![0i16; 255];
+    let err = decoder
+        .decode_packet(&writer.into_bytes(), &mut out)
+        .unwrap_err();
+    assert_eq!((err.expected(), err.found()), (Some(255), Some(256)));
+}
@@ -0,0 +1,390 @@
+// Reads MP4 files whose sample tables have been rewritten using alternative box types.
+#![cfg(feature = "mp4")]
+
+extern crate alac;
+
+use alac::{ErrorKind, Format, PacketIndex, ReadError, Reader};
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+const CONTAINERS: &[&[u8; 4]] = &[b"moov", b"trak", b"mdia", b"minf", b"stbl"];
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// Calls `rewrite` with the type and content of each box in the sample tables of `data`, which
+/// returns a replacement box type and content.
+///
+/// `rewrite` is also given the number of bytes the boxes before the media data have grown or
+/// shrunk by, which chunk offsets need to be adjusted by.
+fn rewrite_sample_tables<F>(data: &[u8], rewrite: F) -> Vec<u8>
+where
+    F: Fn(&[u8; 4], &[u8], i64) -> ([u8; 4], Vec<u8>),
+{
+    fn rewrite_boxes<F>(mut data: &[u8], out: &mut Vec<u8>, rewrite: &F, shift: i64, in_stbl: bool)
+    where
+        F: Fn(&[u8; 4], &[u8], i64) -> ([u8; 4], Vec<u8>),
+    {
+        while !data.is_empty() {
+            let size = read_u32(data) as usize;
+            let box_type = [data[4], data[5], data[6], data[7]];
+            let content = &data[8..size];
+            data = &data[size..];
+
+            let (box_type, content) = if CONTAINERS.contains(&&box_type) {
+                let mut children = Vec::new();
+                let in_stbl = &box_type == b"stbl";
+                rewrite_boxes(content, &mut children, rewrite, shift, in_stbl);
+                (box_type, children)
+            } else if in_stbl {
+                rewrite(&box_type, content, shift)
+            } else {
+                (box_type, content.to_vec())
+            };
+            out.extend_from_slice(&(content.len() as u32 + 8).to_be_bytes());
+            out.extend_from_slice(&box_type);
+            out.extend_from_slice(&content);
+        }
+    }
+
+    // The first pass finds how much the boxes grow by, which is independent of the shift.
+    let mut out = Vec::new();
+    rewrite_boxes(data, &mut out, &rewrite, 0, false);
+    let shift = out.len() as i64 - data.len() as i64;
+    out.clear();
+    rewrite_boxes(data, &mut out, &rewrite, shift, false);
+    out
+}
+
+/// Returns the content of a stco box with its offsets adjusted by `shift`.
+fn shift_stco(content: &[u8], shift: i64) -> Vec<u8> {
+    let mut stco = content[..8].to_vec();
+    for offset in content[8..].chunks(4) {
+        stco.extend_from_slice(&((read_u32(offset) as i64 + shift) as u32).to_be_bytes());
+    }
+    stco
+}
+
+/// Returns the top level boxes of `data` other than the media data.
+fn without_media_data(mut data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while !data.is_empty() {
+        let size = read_u32(data) as usize;
+        if &data[4..8] != b"mdat" && &data[4..8] != b"free" {
+            out.extend_from_slice(&data[..size]);
+        }
+        data = &data[size..];
+    }
+    out
+}
+
+/// Returns the packets of `data` in order.
+fn packets(data: &[u8]) -> Vec<&[u8]> {
+    let index = Reader::new(Cursor::new(data))
+        .unwrap()
+        .packet_index()
+        .unwrap();
+    (0..index.packet_count())
+        .map(|i| {
+            let (offset, len) = index.packet(i).unwrap();
+            &data[offset as usize..(offset + len) as usize]
+        })
+        .collect()
+}
+
+fn check_decodes_same(data: &[u8], rewritten: &[u8]) {
+    let reader = Reader::new(Cursor::new(data)).unwrap();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let reader = Reader::new(Cursor::new(rewritten)).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+}
+
+fn read_file() -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn co64() {
+    let data = read_file();
+    let rewritten = rewrite_sample_tables(&data, |box_type, content, shift| match box_type {
+        b"stco" => {
+            let mut co64 = content[..8].to_vec();
+            for offset in content[8..].chunks(4) {
+                co64.extend_from_slice(&((read_u32(offset) as i64 + shift) as u64).to_be_bytes());
+            }
+            (*b"co64", co64)
+        }
+        _ => (*box_type, content.to_vec()),
+    });
+    check_decodes_same(&data, &rewritten);
+}
+
+#[test]
+fn constant_sample_size() {
+    let data = read_file();
+    let packets = packets(&data);
+    let packet_len = packets.iter().map(|p| p.len()).max().unwrap();
+
+    // Every packet is padded to the same length and stored in a single chunk following the moov
+    // box.
+    let header = without_media_data(&data);
+    let mut rewritten = rewrite_sample_tables(&header, |box_type, content, shift| {
+        let fields = match box_type {
+            b"stsz" => vec![0, packet_len as u32, packets.len() as u32],
+            b"stsc" => vec![0, 1, 1, packets.len() as u32, 1],
+            b"stco" => vec![0, 1, (header.len() as i64 + shift) as u32 + 8],
+            _ => return (*box_type, content.to_vec()),
+        };
+        let content = fields.iter().flat_map(|f| f.to_be_bytes().to_vec());
+        (*box_type, content.collect())
+    });
+
+    let mut mdat = Vec::new();
+    for packet in &packets {
+        mdat.extend_from_slice(packet);
+        mdat.resize(mdat.len() + packet_len - packet.len(), 0);
+    }
+    rewritten.extend_from_slice(&(mdat.len() as u32 + 8).to_be_bytes());
+    rewritten.extend_from_slice(b"mdat");
+    rewritten.extend_from_slice(&mdat);
+
+    check_decodes_same(&data, &rewritten);
+}
+
+#[test]
+fn stz2() {
+    let data = read_file();
+    let rewritten = rewrite_sample_tables(&data, |box_type, content, shift| match box_type {
+        b"stsz" => {
+            // Packets are short enough to use 16 bit fields.
+            let mut stz2 = vec![0, 0, 0, 0, 0, 0, 0, 16];
+            stz2.extend_from_slice(&content[8..12]);
+            for size in content[12..].chunks(4) {
+                stz2.extend_from_slice(&(read_u32(size) as u16).to_be_bytes());
+            }
+            (*b"stz2", stz2)
+        }
+        b"stco" => (*box_type, shift_stco(content, shift)),
+        _ => (*box_type, content.to_vec()),
+    });
+    check_decodes_same(&data, &rewritten);
+}
+
+#[test]
+fn quicktime_wave_cookie() {
+    let data = read_file();
+    let rewritten = rewrite_sample_tables(&data, |box_type, content, shift| match box_type {
+        b"stsd" => {
+            // Convert the version 0 sample entry to version 1 and move the alac atom containing
+            // the magic cookie into a wave atom.
+            let entry = &content[8..];
+            let fields = &entry[8..36];
+            let alac = &entry[36..read_u32(entry) as usize];
+
+            let mut wave = vec![0, 0, 0, 12];
+            wave.extend_from_slice(b"frmaalac");
+            wave.extend_from_slice(alac);
+            wave.extend_from_slice(&[0, 0, 0, 8, 0, 0, 0, 0]);
+
+            let mut new_entry = fields.to_vec();
+            new_entry[8..10].copy_from_slice(&1u16.to_be_bytes());
+            new_entry.extend_from_slice(&[0; 16]);
+            new_entry.extend_from_slice(&(wave.len() as u32 + 8).to_be_bytes());
+            new_entry.extend_from_slice(b"wave");
+            new_entry.extend_from_slice(&wave);
+
+            let mut stsd = content[..8].to_vec();
+            stsd.extend_from_slice(&(new_entry.len() as u32 + 8).to_be_bytes());
+            stsd.extend_from_slice(b"alac");
+            stsd.extend_from_slice(&new_entry);
+            (*box_type, stsd)
+        }
+        b"stco" => (*box_type, shift_stco(content, shift)),
+        _ => (*box_type, content.to_vec()),
+    });
+    check_decodes_same(&data, &rewritten);
+}
+
+/// Returns `data` with its packets stored in chunks of `chunk_sizes` packets, with gaps between
+/// the chunks.
+fn rechunk(data: &[u8], chunk_sizes: &[usize]) -> Vec<u8> {
+    let packets = packets(data);
+    assert_eq!(chunk_sizes.iter().sum::<usize>(), packets.len());
+
+    let mut stsc = vec![0, 0];
+    for (chunk, &size) in chunk_sizes.iter().enumerate() {
+        if chunk == 0 || chunk_sizes[chunk - 1] != size {
+            stsc.extend_from_slice(&[chunk as u32 + 1, size as u32, 1]);
+            stsc[1] += 1;
+        }
+    }
+
+    let header = without_media_data(data);
+    let mut mdat = Vec::new();
+    let mut chunk_offsets = Vec::new();
+    let mut packets_iter = packets.iter();
+    for &size in chunk_sizes {
+        mdat.extend_from_slice(&[0; 7]);
+        chunk_offsets.push(mdat.len() as u32);
+        for packet in packets_iter.by_ref().take(size) {
+            mdat.extend_from_slice(packet);
+        }
+    }
+
+    let mut rewritten = rewrite_sample_tables(&header, |box_type, content, shift| {
+        let fields = match box_type {
+            b"stsc" => stsc.clone(),
+            b"stco" => {
+                let mdat_start = (header.len() as i64 + shift) as u32 + 8;
+                let mut stco = vec![0, chunk_offsets.len() as u32];
+                stco.extend(chunk_offsets.iter().map(|offset| offset + mdat_start));
+                stco
+            }
+            _ => return (*box_type, content.to_vec()),
+        };
+        let content = fields.iter().flat_map(|f| f.to_be_bytes().to_vec());
+        (*box_type, content.collect())
+    });
+    rewritten.extend_from_slice(&(mdat.len() as u32 + 8).to_be_bytes());
+    rewritten.extend_from_slice(b"mdat");
+    rewritten.extend_from_slice(&mdat);
+    rewritten
+}
+
+#[test]
+fn varying_chunk_sizes() {
+    let data = read_file();
+    let packet_count = packets(&data).len();
+
+    // Chunks of one, five and then two packets with gaps between them, described by a run of
+    // entries in the sample to chunk table.
+    let mut chunk_sizes = vec![1, 1, 1];
+    let mut remaining = packet_count - 3;
+    while remaining > 0 {
+        let size = if chunk_sizes.len() < 8 { 5 } else { 2 };
+        chunk_sizes.push(size.min(remaining));
+        remaining -= chunk_sizes[chunk_sizes.len() - 1];
+    }
+    let rewritten = rechunk(&data, &chunk_sizes);
+
+    check_decodes_same(&data, &rewritten);
+
+    // Seeking into the middle of a chunk and back.
+    let mut expected = Reader::new(Cursor::new(&data)).unwrap();
+    let mut reader = Reader::new(Cursor::new(&rewritten)).unwrap();
+    for &sample in &[70_000, 10_000, 30_000] {
+        expected.seek_to_sample(sample).unwrap();
+        reader.seek_to_sample(sample).unwrap();
+        for _ in 0..4 {
+            let expected = expected
+                .next_raw_packet()
+                .unwrap()
+                .map(|p| p.data().to_vec());
+            let packet = reader.next_raw_packet().unwrap().map(|p| p.data().to_vec());
+            assert_eq!(packet, expected);
+        }
+    }
+}
+
+#[test]
+fn lazy_sample_tables() {
+    let data = read_file();
+    let original = Reader::new(Cursor::new(&data)).unwrap();
+    let index = original.packet_index().unwrap();
+    let expected: Vec<i32> = original.into_samples().map(|s| s.unwrap()).collect();
+
+    let mut reader = Reader::new(Cursor::new(&data)).unwrap();
+    reader.set_lazy_sample_tables(true).unwrap();
+    assert!(reader.packet_index().is_err());
+    let channels = reader.stream_info().channels() as usize;
+    assert_eq!(reader.total_samples() as usize * channels, expected.len());
+
+    reader.seek_to_sample(50_000).unwrap();
+    let packet = reader.next_raw_packet().unwrap().unwrap();
+    let (offset, len) = index.packet(packet.location().packet_index()).unwrap();
+    assert_eq!(packet.location().byte_offset(), Some(offset));
+    assert_eq!(packet.data().len() as u64, len);
+
+    reader.seek_to_sample(0).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // Tables are read back into memory when disabled.
+    let mut reader = Reader::new(Cursor::new(&data)).unwrap();
+    reader.set_lazy_sample_tables(true).unwrap();
+    reader.set_lazy_sample_tables(false).unwrap();
+    assert_eq!(reader.packet_index().unwrap(), index);
+}
+
+#[test]
+fn sample_to_chunk_entries() {
+    let data = read_file();
+    let packets = packets(&data);
+
+    // Each entry in the sample to chunk table covers several chunks holding a different number of
+    // packets to the chunks of the entry before it.
+    let mut chunk_sizes = vec![3, 3, 2, 2, 2, 4];
+    let mut remaining = packets.len() - chunk_sizes.iter().sum::<usize>();
+    while remaining > 0 {
+        chunk_sizes.push(remaining.min(3));
+        remaining -= chunk_sizes[chunk_sizes.len() - 1];
+    }
+    let rewritten = rechunk(&data, &chunk_sizes);
+
+    let index = Reader::new(Cursor::new(&rewritten))
+        .unwrap()
+        .packet_index()
+        .unwrap();
+    assert_eq!(index.packet_count(), packets.len() as u64);
+    for (i, packet) in packets.iter().enumerate() {
+        let (offset, len) = index.packet(i as u64).unwrap();
+        let range = offset as usize..(offset + len) as usize;
+        assert_eq!(&rewritten[range], *packet);
+    }
+    check_decodes_same(&data, &rewritten);
+}
+
+#[test]
+fn oversized_packet() {
+    let data = read_file();
+    let rewritten = rewrite_sample_tables(&data, |box_type, content, _| match box_type {
+        b"stsz" => {
+            // The fourth packet is far longer than any packet of the stream can be.
+            let mut stsz = content.to_vec();
+            stsz[24..28].copy_from_slice(&0x7fff_ffffu32.to_be_bytes());
+            (*box_type, stsz)
+        }
+        _ => (*box_type, content.to_vec()),
+    });
+
+    let check_error = |result: Result<_, ReadError>| match result {
+        Err(ReadError::Format(Format::Mp4, err)) => {
+            assert_eq!(err.kind(), ErrorKind::InvalidContainer)
+        }
+        Err(err) => panic!("unexpected error {:?}", err),
+        Ok(_) => panic!("oversized packet was read"),
+    };
+
+    let mut reader = Reader::new(Cursor::new(&rewritten)).unwrap();
+    for _ in 0..3 {
+        reader.next_raw_packet().unwrap().unwrap();
+    }
+    check_error(reader.next_raw_packet().map(|_| ()));
+
+    // Packet indexes are checked when they are read and when they are deserialized.
+    let index = Reader::new(Cursor::new(&rewritten))
+        .unwrap()
+        .packet_index()
+        .unwrap();
+    let mut reader = Reader::from_packet_index(Cursor::new(&rewritten), index.clone()).unwrap();
+    reader.seek_to_sample(3 * 4096).unwrap();
+    check_error(reader.next_raw_packet().map(|_| ()));
+    let err = PacketIndex::from_bytes(&index.to_bytes()).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidContainer);
+}
@@ -0,0 +1,73 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+use std::io::{Seek, SeekFrom};
+
+fn test_seek(path: &str) {
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    let frame_length = reader.stream_info().max_frames_per_packet() as u64;
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    let total_frames = (expected.len() / channels) as u64;
+
+    let targets = [
+        0,
+        1,
+        frame_length - 1,
+        frame_length,
+        frame_length * 3 + 17,
+        total_frames - 1,
+        total_frames,
+        total_frames + frame_length,
+    ];
+
+    for &target in &targets {
+        let mut reader = Reader::new(File::open(path).unwrap()).unwrap();
+        reader.seek_to_sample(target).unwrap();
+        let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+        let start = ((target * channels as u64) as usize).min(expected.len());
+        assert_eq!(samples, &expected[start..], "seek to {}", target);
+    }
+
+    // Seek backwards part way through a packet.
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    let mut samples = reader.into_samples::<i32>();
+    for _ in 0..frame_length * 5 {
+        samples.next().unwrap().unwrap();
+    }
+    assert_eq!(
+        samples.sample_position(),
+        frame_length * 5 / channels as u64
+    );
+    samples.seek_to_sample(frame_length + 3).unwrap();
+    assert_eq!(samples.sample_position(), frame_length + 3);
+    let first = samples.next().unwrap().unwrap();
+    assert_eq!(first, expected[(frame_length as usize + 3) * channels]);
+    assert_eq!(samples.sample_position(), frame_length + 3);
+
+    // The underlying reader can be reused.
+    let mut file = samples.into_inner();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    let reader = Reader::new(file).unwrap();
+    let mut packets = reader.into_packets::<i32>();
+    assert_eq!(packets.sample_position(), 0);
+    let mut out = vec![0; packets.stream_info().max_samples_per_packet() as usize];
+    let len = packets.next_into(&mut out).unwrap().unwrap().len();
+    assert_eq!(packets.sample_position(), (len / channels) as u64);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn caf_seek() {
+    test_seek("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn mp4_seek() {
+    test_seek("tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a");
+}
@@ -1,7 +1,7 @@
 // Compares a single 16 bit 2 channel packet against the reference decoder 4th frame of alac.caf
 extern crate alac;
 
-use alac::{Decoder, StreamInfo};
+use alac::{Concealment, DecodeError, DecodeOptions, Decoder, ErrorKind, SampleFormat, StreamInfo};
 
 #[test]
 fn main() {
@@ -20,3 +20,219 @@ fn main() {
 
     assert_eq!(out, out_comp);
 }
+
+#[test]
+fn channel_gains() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = include_bytes!("data/packet_16_bit.bin");
+
+    let mut dec = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+    dec.set_channel_gains(&[0.5, 4.0]);
+    let mut out = vec![0i16; 8192];
+    dec.decode_packet(&packet[..8581], &mut out).unwrap();
+
+    let out_comp_bin = include_bytes!("data/out_16_bit.bin");
+    for (i, &sample) in out.iter().enumerate() {
+        let comp = (out_comp_bin[i * 2] as i16) + ((out_comp_bin[i * 2 + 1] as i16) << 8);
+        let gain = if i % 2 == 0 { 0.5 } else { 4.0 };
+        let expected = (comp as f64 * gain).round().clamp(-32768.0, 32767.0) as i16;
+        assert_eq!(sample, expected);
+    }
+}
+
+#[test]
+fn conceal_lost_packet() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = include_bytes!("data/packet_16_bit.bin");
+
+    let mut dec = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+    let mut expected = vec![0i16; 8192];
+    dec.decode_packet(&packet[..8581], &mut expected).unwrap();
+
+    // Silence is the default.
+    let mut out = vec![1i16; 8192];
+    assert_eq!(dec.conceal_lost_packet(&mut out), &[0; 8192][..]);
+
+    dec.set_concealment(Concealment::RepeatLastPacket);
+    assert_eq!(dec.conceal_lost_packet(&mut out), &[0; 8192][..]);
+    dec.decode_packet(&packet[..8581], &mut out).unwrap();
+    let mut out = vec![0i16; 8192];
+    assert_eq!(dec.conceal_lost_packet(&mut out), &expected[..]);
+
+    dec.reset();
+    assert_eq!(dec.conceal_lost_packet(&mut out), &[0; 8192][..]);
+}
+
+#[test]
+fn try_decode_packet_mismatched_output() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = include_bytes!("data/packet_16_bit.bin");
+
+    let mut dec = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+    let mut short = vec![0i16; 8191];
+    match dec.try_decode_packet(&packet[..8581], &mut short) {
+        Err(DecodeError::OutputTooShort { required, len }) => {
+            assert_eq!((required, len), (8192, 8191));
+        }
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let mut out = vec![0i16; 8192];
+    let len = dec
+        .try_decode_packet(&packet[..8581], &mut out)
+        .unwrap()
+        .len();
+    assert_eq!(len, 8192);
+
+    match dec.try_decode_packet(&packet[..16], &mut out) {
+        Err(DecodeError::InvalidData(_)) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn back_to_back_packets() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+
+    let mut dec = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+    let mut expected = vec![0i16; 8192];
+    dec.decode_packet(packet, &mut expected).unwrap();
+
+    let mut data = packet.to_vec();
+    data.extend_from_slice(packet);
+    let mut data = &data[..];
+    let mut out = vec![0i16; 8192];
+    for _ in 0..2 {
+        let (samples, len) = dec.decode_packet_with_len(data, &mut out).unwrap();
+        assert_eq!(samples, &expected[..]);
+        assert_eq!(len, packet.len());
+        data = &data[len..];
+    }
+    assert!(data.is_empty());
+}
+
+#[test]
+fn strict_decoding_rejects_trailing_data() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+    let mut with_trailing_data = packet.to_vec();
+    with_trailing_data.push(0);
+
+    let stream_info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+    let mut out = vec![0i16; 8192];
+    let mut lenient = Decoder::new(stream_info.clone());
+    assert!(lenient.decode_packet(&with_trailing_data, &mut out).is_ok());
+
+    let options = DecodeOptions::new().strict(true);
+    let mut strict = Decoder::with_options(stream_info, options);
+    assert!(strict.options().is_strict());
+    assert!(strict.decode_packet(packet, &mut out).is_ok());
+    let err = strict
+        .decode_packet(&with_trailing_data, &mut out)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TrailingData);
+    assert_eq!((err.expected(), err.found()), (Some(8581), Some(8582)));
+
+    // Packets in a byte stream are followed by the next packet.
+    let (_, len) = strict
+        .decode_packet_with_len(&with_trailing_data, &mut out)
+        .unwrap();
+    assert_eq!(len, 8581);
+}
+
+#[test]
+fn strict_decoding_entry_points() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+    let mut with_trailing_data = packet.to_vec();
+    with_trailing_data.push(0);
+
+    let stream_info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+    let options = DecodeOptions::new().strict(true);
+    let mut strict = Decoder::with_options(stream_info, options);
+
+    let mut out = vec![0i16; 8192];
+    assert!(strict.try_decode_packet(packet, &mut out).is_ok());
+    match strict.try_decode_packet(&with_trailing_data, &mut out) {
+        Err(DecodeError::InvalidData(err)) => assert_eq!(err.kind(), ErrorKind::TrailingData),
+        other => panic!("unexpected result {:?}", other),
+    }
+
+    let mut bytes = vec![0u8; 8192 * 4];
+    assert!(strict.decode_packet_s24le(packet, &mut bytes).is_ok());
+    let err = strict
+        .decode_packet_s24le(&with_trailing_data, &mut bytes)
+        .unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TrailingData);
+
+    let formats = [
+        SampleFormat::S16Le,
+        SampleFormat::S24Le,
+        SampleFormat::S24Le3,
+        SampleFormat::S32Le,
+        SampleFormat::F32Le,
+    ];
+    for &format in &formats {
+        assert!(strict
+            .decode_packet_bytes(packet, &mut bytes, format)
+            .is_ok());
+        let err = strict
+            .decode_packet_bytes(&with_trailing_data, &mut bytes, format)
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::TrailingData);
+    }
+
+    // Recovery finds the packet at the start of the data however much follows it.
+    let (range, samples) = strict
+        .decode_next_valid_packet(&with_trailing_data, &mut out)
+        .unwrap();
+    assert_eq!((range, samples.len()), (0..8581, 8192));
+}
+
+#[test]
+fn decode_all() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+    let mut with_trailing_data = packet.to_vec();
+    with_trailing_data.push(0);
+
+    let stream_info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+    let mut dec = Decoder::new(stream_info.clone());
+    let mut expected = vec![0i16; 8192];
+    dec.decode_packet(packet, &mut expected).unwrap();
+
+    dec.set_concealment(Concealment::RepeatLastPacket);
+    let samples: Vec<i16> = dec.decode_all(vec![packet, packet]).unwrap();
+    assert_eq!(samples, [&expected[..], &expected[..]].concat());
+    let mut out = vec![0i16; 8192];
+    assert_eq!(dec.conceal_lost_packet(&mut out), &expected[..]);
+
+    let options = DecodeOptions::new().strict(true);
+    let mut strict = Decoder::with_options(stream_info, options);
+    let packets = vec![packet, &with_trailing_data[..]];
+    let err = strict.decode_all::<_, i16>(packets).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TrailingData);
+}
+
+#[test]
+fn cloned_decoder() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+
+    let mut dec = Decoder::new(StreamInfo::from_cookie(cookie_bytes).unwrap());
+    dec.set_channel_gains(&[0.5, 4.0]);
+    let mut expected = vec![0i16; 8192];
+    dec.decode_packet(packet, &mut expected).unwrap();
+
+    // Clones keep the channel gains and can decode on another thread.
+    let mut clone = dec.clone();
+    let out = std::thread::spawn(move || {
+        let mut out = vec![0i16; 8192];
+        clone.decode_packet(packet, &mut out).unwrap();
+        out
+    })
+    .join()
+    .unwrap();
+    assert_eq!(out, expected);
+}
@@ -0,0 +1,40 @@
+// Decodes raw packets without a container using a PacketSlicer.
+extern crate alac;
+
+use alac::PacketSlicer;
+
+#[test]
+fn samples() {
+    let cookie = include_bytes!("data/magic_cookie.bin");
+    let packet = &include_bytes!("data/packet_16_bit.bin")[..8581];
+
+    let samples: Vec<i16> = PacketSlicer::new(cookie, vec![packet, packet])
+        .unwrap()
+        .into_samples()
+        .map(|s| s.unwrap())
+        .collect();
+
+    let out_comp_bin = include_bytes!("data/out_16_bit.bin");
+    let expected: Vec<i16> = out_comp_bin
+        .chunks(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]))
+        .collect();
+
+    assert_eq!(samples.len(), expected.len() * 2);
+    assert_eq!(&samples[..expected.len()], &expected[..]);
+    assert_eq!(&samples[expected.len()..], &expected[..]);
+}
+
+#[test]
+fn packets() {
+    let cookie = include_bytes!("data/magic_cookie.bin");
+    let packet = include_bytes!("data/packet_16_bit.bin")[..8581].to_vec();
+
+    let mut source = Some(packet);
+    let slicer = PacketSlicer::new(cookie, std::iter::from_fn(|| source.take())).unwrap();
+    let mut packets = slicer.into_packets::<i32>();
+
+    let mut out = vec![0; packets.stream_info().max_samples_per_packet() as usize];
+    assert_eq!(packets.next_into(&mut out).unwrap().unwrap().len(), 8192);
+    assert!(packets.next_into(&mut out).unwrap().is_none());
+}
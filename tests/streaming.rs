@@ -0,0 +1,90 @@
+// Decodes files from streams that cannot seek.
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+use std::io::{self, Cursor, Read};
+
+/// A stream that returns data in small chunks and doesn't implement `Seek`.
+struct Pipe<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Read for Pipe<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(1000).min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+fn check_streaming(path: &str) {
+    let data = read_file(path);
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let reader = Reader::new_streaming(Pipe { data: &data }).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    // Seeking forwards skips data.
+    let mut reader = Reader::new_streaming(Pipe { data: &data }).unwrap();
+    let channels = reader.stream_info().channels() as usize;
+    reader.seek_to_sample(100_000).unwrap();
+    let mut samples = reader.into_samples::<i32>();
+    assert_eq!(
+        samples.next().unwrap().unwrap(),
+        expected[100_000 * channels]
+    );
+    let rest: Vec<i32> = samples.by_ref().map(|s| s.unwrap()).collect();
+    assert_eq!(rest, &expected[100_000 * channels + 1..]);
+
+    // Skipped data can't be returned to.
+    samples.seek_to_sample(10).unwrap();
+    assert!(samples.next().unwrap().is_err());
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn streaming_caf() {
+    check_streaming("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn streaming_mp4() {
+    check_streaming("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn streaming_mp4_moov_after_mdat() {
+    let data = read_file("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a");
+
+    // Move the moov box to the end of the file.
+    let mut boxes = Vec::new();
+    let mut rest = &data[..];
+    while !rest.is_empty() {
+        let size = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        boxes.push(&rest[..size]);
+        rest = &rest[size..];
+    }
+    let moov = boxes.remove(1);
+    assert_eq!(&moov[4..8], b"moov");
+    boxes.push(moov);
+    let data = boxes.concat();
+
+    match Reader::new_streaming(Pipe { data: &data }) {
+        Err(alac::ReadError::Format(..)) => (),
+        _ => panic!("expected a format error"),
+    }
+}
@@ -0,0 +1,116 @@
+//! Support for running bit-exact comparisons against directories of reference test vectors.
+//!
+//! A vector directory contains ALAC files (`.m4a` or `.caf`) alongside the PCM they are expected to
+//! decode to stored as `.wav` files. An ALAC file named `<name>.m4a` or `<name>_<suffix>.m4a` is
+//! compared against `<name>.wav`, so several encodings of the same source can share one reference,
+//! e.g. `synth_44100_16_bit_afconvert.m4a` is compared against `synth_44100_16_bit.wav`.
+
+#![allow(dead_code)]
+
+extern crate alac;
+extern crate hound;
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// A pair of an ALAC file and the reference PCM it must decode to.
+pub struct Vector {
+    pub alac: PathBuf,
+    pub expected: PathBuf,
+}
+
+/// Finds all test vectors in `dir` whose container format is supported by the enabled features.
+///
+/// Panics if an ALAC file has no matching reference file.
+pub fn load_vectors<P: AsRef<Path>>(dir: P) -> Vec<Vector> {
+    let mut vectors = Vec::new();
+
+    for entry in fs::read_dir(dir.as_ref()).expect("failed to read vector directory") {
+        let path = entry.expect("failed to read vector directory").path();
+
+        let extension = path.extension().and_then(|e| e.to_str());
+        let supported = (extension == Some("m4a") && cfg!(feature = "mp4"))
+            || (extension == Some("caf") && cfg!(feature = "caf"));
+        if !supported {
+            continue;
+        }
+
+        let expected = expected_path(&path)
+            .unwrap_or_else(|| panic!("no reference wav file for {}", path.display()));
+        vectors.push(Vector {
+            alac: path,
+            expected,
+        });
+    }
+
+    vectors.sort_by(|a, b| a.alac.cmp(&b.alac));
+    vectors
+}
+
+fn expected_path(alac: &Path) -> Option<PathBuf> {
+    let stem = alac.file_stem()?.to_str()?;
+    let mut candidates = vec![stem];
+    if let Some(i) = stem.rfind('_') {
+        candidates.push(&stem[..i]);
+    }
+
+    candidates
+        .into_iter()
+        .map(|name| alac.with_file_name(format!("{}.wav", name)))
+        .find(|path| path.is_file())
+}
+
+/// Decodes `vector.alac` and checks every sample is identical to `vector.expected`.
+///
+/// Samples are compared as left-aligned `i32` values so streams of any bit depth up to 32 bits are
+/// compared exactly. Returns a description of the first mismatch on failure.
+pub fn run_vector(vector: &Vector) -> Result<(), String> {
+    let alac = File::open(&vector.alac).map_err(|e| e.to_string())?;
+    let alac = alac::Reader::new(alac).map_err(|e| e.to_string())?;
+
+    let wav = hound::WavReader::open(&vector.expected).map_err(|e| e.to_string())?;
+    let spec = wav.spec();
+
+    let stream_info = alac.stream_info().clone();
+    if spec.channels != stream_info.channels() as u16 {
+        return Err(format!(
+            "channel count mismatch. wav: {}, alac: {}",
+            spec.channels,
+            stream_info.channels()
+        ));
+    }
+    if spec.bits_per_sample != stream_info.bit_depth() as u16 {
+        return Err(format!(
+            "bit depth mismatch. wav: {}, alac: {}",
+            spec.bits_per_sample,
+            stream_info.bit_depth()
+        ));
+    }
+
+    let shift = 32 - spec.bits_per_sample as u32;
+    let mut wav = wav.into_samples::<i32>().map(|r| r.map(|s| s << shift));
+    let mut alac = alac.into_samples::<i32>();
+
+    for i in 0u64.. {
+        match (wav.next(), alac.next()) {
+            (Some(Ok(w)), Some(Ok(a))) if w == a => (),
+            (Some(Ok(w)), Some(Ok(a))) => {
+                return Err(format!(
+                    "sample {} (frame {}, channel {}) does not match. wav: {}, alac: {}",
+                    i,
+                    i / spec.channels as u64,
+                    i % spec.channels as u64,
+                    w,
+                    a
+                ))
+            }
+            (None, None) => break,
+            (Some(_), None) => return Err(format!("alac ends early at sample {}", i)),
+            (None, Some(_)) => return Err(format!("alac longer than wav at sample {}", i)),
+            (Some(Err(e)), _) => return Err(format!("wav read error at {}: {}", i, e)),
+            (_, Some(Err(e))) => return Err(format!("alac read error at {}: {}", i, e)),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+// Decodes packets ahead of the consumer on a background thread.
+#![cfg(all(feature = "threaded", feature = "mp4"))]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_24_bit_afconvert.m4a";
+
+fn expected_samples() -> Vec<i32> {
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+#[test]
+fn threaded_samples() {
+    let expected = expected_samples();
+
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let samples: Vec<i32> = reader
+        .into_samples()
+        .threaded()
+        .map(|s| s.unwrap())
+        .collect();
+    assert_eq!(samples, expected);
+
+    // Samples already decoded on the calling thread are yielded first.
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let mut samples = reader.into_samples::<i32>();
+    let mut partial: Vec<i32> = samples.by_ref().take(1000).map(|s| s.unwrap()).collect();
+    partial.extend(samples.threaded().map(|s| s.unwrap()));
+    assert_eq!(partial, expected);
+}
+
+#[test]
+fn threaded_packets() {
+    let expected = expected_samples();
+
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let mut packets = reader.into_packets::<i32>().threaded();
+    assert_eq!(packets.stream_info().bit_depth(), 24);
+    let mut samples = Vec::new();
+    while let Some(packet) = packets.next() {
+        let packet = packet.unwrap();
+        samples.extend_from_slice(&packet);
+        packets.recycle(packet);
+    }
+    assert_eq!(samples, expected);
+    assert!(packets.next().is_none());
+}
+
+#[test]
+fn drop_before_end() {
+    let reader = Reader::new(File::open(PATH).unwrap()).unwrap();
+    let mut packets = reader.into_packets::<i16>().threaded();
+    packets.next().unwrap().unwrap();
+    // Dropping the iterator stops the thread once its next packet is decoded.
+    drop(packets);
+}
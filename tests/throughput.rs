@@ -0,0 +1,39 @@
+#![cfg(feature = "mp4")]
+
+extern crate alac;
+
+use std::fs::File;
+use std::time::Duration;
+
+#[test]
+fn throughput() {
+    let file = File::open("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a").unwrap();
+    let reader = alac::Reader::new(file).unwrap();
+    let channels = reader.stream_info().channels() as u64;
+
+    let mut samples = reader.into_samples::<i16>();
+    let mut num_samples = 0;
+    for sample in &mut samples {
+        sample.unwrap();
+        num_samples += 1;
+    }
+
+    let throughput = samples.throughput();
+    assert!(throughput.packets() > 0);
+    assert!(throughput.bytes_in() > 0);
+    assert_eq!(throughput.frames_out(), num_samples / channels);
+    assert!(throughput.elapsed() > Duration::from_secs(0));
+    assert!(throughput.packets_per_second().unwrap() > 0.0);
+    assert!(throughput.realtime_factor().unwrap() > 0.0);
+}
+
+#[test]
+fn throughput_before_decoding() {
+    let file = File::open("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a").unwrap();
+    let reader = alac::Reader::new(file).unwrap();
+
+    let throughput = reader.throughput();
+    assert_eq!(throughput.elapsed(), Duration::from_secs(0));
+    assert_eq!(throughput.packets_per_second(), None);
+    assert_eq!(throughput.realtime_factor(), None);
+}
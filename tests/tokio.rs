@@ -0,0 +1,58 @@
+// Decodes files with the asynchronous reader.
+#![cfg(all(feature = "async", any(feature = "caf", feature = "mp4")))]
+
+extern crate alac;
+extern crate tokio;
+
+use alac::tokio::futures_core::Stream;
+use alac::Reader;
+use std::fs::File;
+use std::future;
+use std::pin::Pin;
+use tokio::runtime::{Builder, Runtime};
+
+fn decode_blocking(path: &str) -> Vec<i32> {
+    let reader = Reader::new(File::open(path).unwrap()).unwrap();
+    reader.into_samples().map(|s| s.unwrap()).collect()
+}
+
+fn open(runtime: &Runtime, path: &str) -> alac::tokio::Reader<tokio::fs::File> {
+    let file = runtime.block_on(tokio::fs::File::open(path)).unwrap();
+    runtime.block_on(alac::tokio::Reader::open(file)).unwrap()
+}
+
+fn check_async(path: &str) {
+    let expected = decode_blocking(path);
+    let runtime = Builder::new_current_thread().build().unwrap();
+
+    let mut reader = open(&runtime, path);
+    let channels = reader.stream_info().channels() as usize;
+    assert_eq!(reader.total_samples() as usize * channels, expected.len());
+    let mut buf = vec![0; reader.stream_info().max_samples_per_packet() as usize];
+    let mut samples = Vec::new();
+    while let Some(packet) = runtime.block_on(reader.next_packet_into(&mut buf)).unwrap() {
+        samples.extend_from_slice(packet);
+    }
+    assert_eq!(samples, expected);
+
+    let mut packets = open(&runtime, path).into_stream::<i32>();
+    let mut samples = Vec::new();
+    while let Some(packet) =
+        runtime.block_on(future::poll_fn(|cx| Pin::new(&mut packets).poll_next(cx)))
+    {
+        samples.extend(packet.unwrap());
+    }
+    assert_eq!(samples, expected);
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn async_caf() {
+    check_async("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+}
+
+#[test]
+#[cfg(feature = "mp4")]
+fn async_mp4() {
+    check_async("tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a");
+}
@@ -0,0 +1,91 @@
+// Selects between multiple ALAC tracks in an MP4 file.
+#![cfg(feature = "mp4")]
+
+extern crate alac;
+
+use alac::Reader;
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+const PATH: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_be_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// Returns the offset of the first child box of type `box_type` in `data`.
+fn find_box(data: &[u8], box_type: &[u8; 4]) -> usize {
+    let mut offset = 0;
+    while &data[offset + 4..offset + 8] != box_type {
+        offset += read_u32(&data[offset..]) as usize;
+    }
+    offset
+}
+
+/// Sets the packed ISO 639-2/T language code in a track's media header.
+fn set_language(trak: &mut [u8], language: &[u8; 3]) {
+    let mdia = find_box(&trak[8..], b"mdia") + 8;
+    let mdhd = mdia + 8 + find_box(&trak[mdia + 8..], b"mdhd");
+    let code = language
+        .iter()
+        .fold(0u16, |code, &c| (code << 5) | (c - 0x60) as u16);
+    trak[mdhd + 28..mdhd + 30].copy_from_slice(&code.to_be_bytes());
+}
+
+/// Adds a copy of the file's only track, which shares its media data.
+fn add_track(data: &[u8]) -> Vec<u8> {
+    let moov = find_box(data, b"moov");
+    let moov_len = read_u32(&data[moov..]) as usize;
+    let trak = moov + 8 + find_box(&data[moov + 8..], b"trak");
+    let trak_len = read_u32(&data[trak..]) as usize;
+    let mut first = data[trak..trak + trak_len].to_vec();
+    set_language(&mut first, b"eng");
+    let mut second = first.clone();
+    set_language(&mut second, b"fra");
+    // Set the track ID in the track header.
+    second[8 + 8 + 12..8 + 8 + 16].copy_from_slice(&2u32.to_be_bytes());
+
+    // The free box following the moov box is shrunk so the media data doesn't move.
+    let free = moov + moov_len;
+    assert_eq!(&data[free + 4..free + 8], b"free");
+    let free_len = read_u32(&data[free..]) as usize;
+
+    let mut out = data[..moov].to_vec();
+    out.extend_from_slice(&(moov_len as u32 + trak_len as u32).to_be_bytes());
+    out.extend_from_slice(&data[moov + 4..trak]);
+    out.extend_from_slice(&first);
+    out.extend_from_slice(&second);
+    out.extend_from_slice(&data[trak + trak_len..free]);
+    out.extend_from_slice(&(free_len as u32 - trak_len as u32).to_be_bytes());
+    out.extend_from_slice(&data[free + 4..free + free_len - trak_len]);
+    out.extend_from_slice(&data[free + free_len..]);
+    assert_eq!(out.len(), data.len());
+    out
+}
+
+#[test]
+fn select_track() {
+    let mut data = Vec::new();
+    File::open(PATH).unwrap().read_to_end(&mut data).unwrap();
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let stream_info = reader.stream_info().clone();
+    let expected: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+
+    let data = add_track(&data);
+    let reader = Reader::new(Cursor::new(&data)).unwrap();
+    let tracks = reader.tracks();
+    assert_eq!(tracks.len(), 2);
+    assert_eq!(tracks[0].language(), Some("eng"));
+    assert_eq!(tracks[1].language(), Some("fra"));
+    for track in tracks {
+        assert_eq!(track.stream_info(), &stream_info);
+        assert_eq!(track.total_samples(), tracks[0].total_samples());
+        assert!(track.duration().unwrap().as_secs_f64() > 1.0);
+    }
+
+    let reader = Reader::new_with_track(Cursor::new(&data), 1).unwrap();
+    let samples: Vec<i32> = reader.into_samples().map(|s| s.unwrap()).collect();
+    assert_eq!(samples, expected);
+
+    assert!(Reader::new_with_track(Cursor::new(&data), 2).is_err());
+}
@@ -0,0 +1,36 @@
+// Decodes into a sample type defined outside the crate.
+#![cfg(feature = "unsealed-sample")]
+
+extern crate alac;
+
+use alac::{Decoder, Sample, StreamInfo};
+
+/// A Q15 fixed point sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Q15(i16);
+
+impl Sample for Q15 {
+    fn from_decoder(sample: i32, bits: u8) -> Self {
+        Q15(((sample << (32 - bits)) >> 16) as i16)
+    }
+
+    fn bits() -> u8 {
+        16
+    }
+}
+
+#[test]
+fn decode_into_user_type() {
+    let cookie_bytes = include_bytes!("data/magic_cookie.bin");
+    let packet = include_bytes!("data/packet_16_bit.bin");
+    let stream_info = StreamInfo::from_cookie(cookie_bytes).unwrap();
+
+    let mut dec = Decoder::new(stream_info);
+    let mut expected = vec![0i16; 8192];
+    dec.decode_packet(&packet[..8581], &mut expected).unwrap();
+    let mut out = vec![Q15(0); 8192];
+    dec.decode_packet(&packet[..8581], &mut out).unwrap();
+
+    let out: Vec<i16> = out.iter().map(|s| s.0).collect();
+    assert_eq!(out, expected);
+}
@@ -0,0 +1,100 @@
+#![cfg(any(feature = "caf", feature = "mp4"))]
+
+extern crate alac;
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+
+#[cfg(feature = "mp4")]
+static FILE: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.m4a";
+#[cfg(not(feature = "mp4"))]
+static FILE: &str = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+
+fn read_file(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path)
+        .expect("failed to open alac file")
+        .read_to_end(&mut data)
+        .unwrap();
+    data
+}
+
+#[test]
+fn valid_file() {
+    let file = File::open(FILE).expect("failed to open alac file");
+    let report = alac::validate_file(file).expect("failed to open alac reader");
+
+    assert!(report.is_valid(), "{:?}", report.issues());
+    assert!(report.packets() > 0);
+    assert!(report.frames() > 0);
+}
+
+#[test]
+fn progress() {
+    let file = File::open(FILE).expect("failed to open alac file");
+    let mut calls = 0;
+    let mut last = None;
+    let report = alac::validate_file_with_progress(file, |progress| {
+        calls += 1;
+        last = Some(*progress);
+    })
+    .expect("failed to open alac reader");
+
+    let last = last.unwrap();
+    assert_eq!(calls, report.packets());
+    assert_eq!(last.packets_done(), report.packets());
+    assert_eq!(last.frames_done(), report.frames());
+    assert!(last.bytes_read() > 0);
+}
+
+#[test]
+fn corrupt_packet() {
+    let mut data = read_file(FILE);
+    for b in &mut data[20000..20064] {
+        *b = 0xff;
+    }
+
+    let report = alac::validate_file(Cursor::new(data)).expect("failed to open alac reader");
+    assert!(report
+        .issues()
+        .iter()
+        .any(|issue| matches!(*issue, alac::Issue::Decoder { .. })));
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn truncated_file() {
+    let mut data = read_file("tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf");
+    let len = data.len();
+    data.truncate(len - 100);
+
+    let report = alac::validate_file(Cursor::new(data)).expect("failed to open alac reader");
+    assert!(report.is_truncated());
+}
+
+#[test]
+#[cfg(feature = "caf")]
+fn duration_mismatch() {
+    let path = "tests/data/decode_comparison/synth_44100_16_bit_afconvert.caf";
+    let report = alac::validate_file(File::open(path).unwrap()).unwrap();
+    assert!(report.is_valid(), "{:?}", report.issues());
+    let frames_decoded = report.frames();
+
+    // Claim 100 more valid frames in the packet table than the packets contain.
+    let mut data = read_file(path);
+    let pakt = data.windows(4).position(|w| w == b"pakt").unwrap();
+    let valid_frames = pakt + 20..pakt + 28;
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[valid_frames.clone()]);
+    let frames = i64::from_be_bytes(bytes) + 100;
+    data[valid_frames].copy_from_slice(&frames.to_be_bytes());
+
+    let report = alac::validate_file(Cursor::new(data)).unwrap();
+    match report.issues() {
+        [alac::Issue::DurationMismatch { expected, decoded }] => {
+            assert_eq!(*expected, frames_decoded + 100);
+            assert_eq!(*decoded, frames_decoded);
+        }
+        issues => panic!("unexpected issues {:?}", issues),
+    }
+}